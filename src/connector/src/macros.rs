@@ -112,6 +112,18 @@ macro_rules! impl_split_reader {
                 }
             }
 
+            pub fn finalize(&mut self) -> Result<ConnectorStateV2> {
+                match self {
+                    $( Self::$variant_name(inner) => inner.finalize(), )*
+                }
+            }
+
+            pub async fn commit(&mut self, splits: &[SplitImpl]) -> Result<()> {
+                match self {
+                    $( Self::$variant_name(inner) => inner.commit(splits).await, )*
+                }
+            }
+
              pub async fn create(
                 config: ConnectorProperties,
                 state: ConnectorState,
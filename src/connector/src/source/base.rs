@@ -31,11 +31,15 @@ use crate::source::datagen::{
     DatagenProperties, DatagenSplit, DatagenSplitEnumerator, DatagenSplitReader, DATAGEN_CONNECTOR,
 };
 use crate::source::dummy_connector::DummySplitReader;
+use crate::source::dynamodb_streams::enumerator::DynamoDbStreamsSplitEnumerator;
+use crate::source::dynamodb_streams::source::DynamoDbStreamsSplitReader;
+use crate::source::dynamodb_streams::DYNAMODB_STREAMS_CONNECTOR;
 use crate::source::filesystem::s3::{S3Properties, S3_CONNECTOR};
 use crate::source::kafka::enumerator::KafkaSplitEnumerator;
 use crate::source::kafka::source::KafkaSplitReader;
 use crate::source::kafka::{KafkaProperties, KafkaSplit, KAFKA_CONNECTOR};
 use crate::source::kinesis::enumerator::client::KinesisSplitEnumerator;
+use crate::source::kinesis::source::message::KinesisMeta;
 use crate::source::kinesis::source::reader::KinesisMultiSplitReader;
 use crate::source::kinesis::split::KinesisSplit;
 use crate::source::kinesis::{KinesisProperties, KINESIS_CONNECTOR};
@@ -76,6 +80,19 @@ pub trait SplitReader: Sized {
     ) -> Result<Self>;
 
     async fn next(&mut self) -> Result<Option<Vec<SourceMessage>>>;
+
+    /// Stops reading on graceful shutdown and returns the splits positioned after the last
+    /// messages returned by `next`. Readers which do not track their positions return no split,
+    /// leaving the state to the checkpoints.
+    fn finalize(&mut self) -> Result<ConnectorStateV2> {
+        Ok(ConnectorStateV2::default())
+    }
+
+    /// Commits the positions of `splits` once they are checkpointed, e.g. to an external
+    /// checkpoint store. Splits not read by this reader are ignored.
+    async fn commit(&mut self, _splits: &[SplitImpl]) -> Result<()> {
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, EnumAsInner, PartialEq, Hash)]
@@ -83,12 +100,14 @@ pub enum SplitImpl {
     Kafka(KafkaSplit),
     Pulsar(PulsarSplit),
     Kinesis(KinesisSplit),
+    DynamoDbStreams(KinesisSplit),
     Nexmark(NexmarkSplit),
     Datagen(DatagenSplit),
 }
 
 pub enum SplitReaderImpl {
     Kinesis(Box<KinesisMultiSplitReader>),
+    DynamoDbStreams(Box<DynamoDbStreamsSplitReader>),
     Kafka(Box<KafkaSplitReader>),
     Dummy(Box<DummySplitReader>),
     Nexmark(Box<NexmarkSplitReader>),
@@ -100,6 +119,7 @@ pub enum SplitEnumeratorImpl {
     Kafka(KafkaSplitEnumerator),
     Pulsar(PulsarSplitEnumerator),
     Kinesis(KinesisSplitEnumerator),
+    DynamoDbStreams(DynamoDbStreamsSplitEnumerator),
     Nexmark(NexmarkSplitEnumerator),
     Datagen(DatagenSplitEnumerator),
 }
@@ -109,6 +129,7 @@ pub enum ConnectorProperties {
     Kafka(KafkaProperties),
     Pulsar(PulsarProperties),
     Kinesis(KinesisProperties),
+    DynamoDbStreams(KinesisProperties),
     Nexmark(NexmarkProperties),
     Datagen(DatagenProperties),
     S3(S3Properties),
@@ -120,6 +141,7 @@ impl_connector_properties! {
     { Kafka, KAFKA_CONNECTOR },
     { Pulsar, PULSAR_CONNECTOR },
     { Kinesis, KINESIS_CONNECTOR },
+    { DynamoDbStreams, DYNAMODB_STREAMS_CONNECTOR },
     { Nexmark, NEXMARK_CONNECTOR },
     { Datagen, DATAGEN_CONNECTOR },
     { S3, S3_CONNECTOR }
@@ -130,6 +152,7 @@ impl_split_enumerator! {
     { Kafka, KafkaSplitEnumerator },
     { Pulsar, PulsarSplitEnumerator },
     { Kinesis, KinesisSplitEnumerator },
+    { DynamoDbStreams, DynamoDbStreamsSplitEnumerator },
     { Nexmark, NexmarkSplitEnumerator },
     { Datagen, DatagenSplitEnumerator }
 }
@@ -139,6 +162,7 @@ impl_split! {
     { Kafka, KAFKA_CONNECTOR, KafkaSplit },
     { Pulsar, PULSAR_CONNECTOR, PulsarSplit },
     { Kinesis, KINESIS_CONNECTOR, KinesisSplit },
+    { DynamoDbStreams, DYNAMODB_STREAMS_CONNECTOR, KinesisSplit },
     { Nexmark, NEXMARK_CONNECTOR, NexmarkSplit },
     { Datagen, DATAGEN_CONNECTOR, DatagenSplit }
 }
@@ -148,6 +172,7 @@ impl_split_reader! {
     { Kafka, KafkaSplitReader },
     { Pulsar, PulsarSplitReader },
     { Kinesis, KinesisMultiSplitReader },
+    { DynamoDbStreams, DynamoDbStreamsSplitReader },
     { Nexmark, NexmarkSplitReader },
     { Datagen, DatagenSplitReader },
     { Dummy, DummySplitReader }
@@ -171,6 +196,40 @@ pub struct SourceMessage {
     pub payload: Option<Bytes>,
     pub offset: String,
     pub split_id: SplitId,
+    pub meta: SourceMeta,
+    /// Connector specific attributes of the message, e.g. the partition key of a Kinesis record,
+    /// so that downstream transforms need not parse them from the payload.
+    pub attributes: HashMap<String, Bytes>,
+    /// Where the message comes from, `None` if the connector does not track it.
+    pub provenance: Option<Provenance>,
+    /// The offset to record as the state of the split instead of `offset`, set by connectors which
+    /// coalesce state updates. A message without payload only updates the state if it is set.
+    pub state_offset: Option<String>,
+}
+
+/// The origin of a [`SourceMessage`] for lineage tracking, carried to downstream catalogs. Only
+/// the Kinesis reader populates it for now, other connectors leave it `None`. The fields are not
+/// specific to Kinesis, so that other connectors can populate it in the same way.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Provenance {
+    /// The connector type, e.g. `kinesis`.
+    pub connector: &'static str,
+    /// The stream or topic read, by name.
+    pub source: String,
+    /// The split within the source, e.g. a Kinesis shard or a Kafka partition.
+    pub split_id: SplitId,
+    /// The position of the message within the split, e.g. a Kinesis sequence number or a Kafka
+    /// offset, before any connector specific encoding of the offset.
+    pub position: String,
+    /// When the message was read from the source, in milliseconds since epoch.
+    pub ingested_at: i64,
+}
+
+/// Connector-specific metadata attached to a [`SourceMessage`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SourceMeta {
+    Kinesis(KinesisMeta),
+    Empty,
 }
 
 /// The metadata of a split.
@@ -186,6 +245,20 @@ pub trait SplitMetaData: Sized {
 /// split readers.
 pub type ConnectorState = Option<Vec<SplitImpl>>;
 
+/// The durable state returned by a split reader on graceful shutdown, to restart from.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConnectorStateV2 {
+    /// The splits positioned where the reader stopped.
+    pub splits: Vec<SplitImpl>,
+}
+
+impl ConnectorStateV2 {
+    /// Converts into the [`ConnectorState`] a split reader is created with, `None` without splits.
+    pub fn into_state(self) -> ConnectorState {
+        (!self.splits.is_empty()).then_some(self.splits)
+    }
+}
+
 /// Used for acquiring the generated data for [`spawn_data_generation_stream`].
 pub type DataGenerationReceiver = mpsc::Receiver<Result<Vec<SourceMessage>>>;
 
@@ -21,7 +21,7 @@ use risingwave_common::field_generator::FieldGeneratorImpl;
 use serde_json::{Map, Value};
 
 use super::DEFAULT_DATAGEN_INTERVAL;
-use crate::source::{SourceMessage, SplitId};
+use crate::source::{SourceMessage, SourceMeta, SplitId};
 
 pub struct DatagenEventGenerator {
     pub fields_map: HashMap<String, FieldGeneratorImpl>,
@@ -82,6 +82,10 @@ impl DatagenEventGenerator {
                 payload: Some(Bytes::from(value.to_string())),
                 offset: offset.to_string(),
                 split_id: self.split_id.clone(),
+                meta: SourceMeta::Empty,
+                attributes: Default::default(),
+                provenance: None,
+                state_offset: None,
             };
             generated_count += 1;
             res.push(msg);
@@ -22,8 +22,8 @@ use super::generator::DatagenEventGenerator;
 use crate::source::datagen::source::SEQUENCE_FIELD_KIND;
 use crate::source::datagen::{DatagenProperties, DatagenSplit};
 use crate::source::{
-    spawn_data_generation_stream, Column, ConnectorState, DataGenerationReceiver, DataType,
-    SourceMessage, SplitId, SplitImpl, SplitMetaData, SplitReader,
+    spawn_data_generation_stream, Column, ConnectorState, ConnectorStateV2, DataGenerationReceiver,
+    DataType, SourceMessage, SplitId, SplitImpl, SplitMetaData, SplitReader,
 };
 
 const KAFKA_MAX_FETCH_MESSAGES: usize = 1024;
@@ -32,6 +32,8 @@ pub struct DatagenSplitReader {
     generation_rx: DataGenerationReceiver,
 
     assigned_split: DatagenSplit,
+    /// The offset of the last event returned by `next`.
+    latest_offset: Option<String>,
 }
 
 #[async_trait]
@@ -201,11 +203,28 @@ impl SplitReader for DatagenSplitReader {
         Ok(DatagenSplitReader {
             generation_rx,
             assigned_split,
+            latest_offset: None,
         })
     }
 
     async fn next(&mut self) -> Result<Option<Vec<SourceMessage>>> {
-        self.generation_rx.recv().await.transpose()
+        let events = self.generation_rx.recv().await.transpose()?;
+        if let Some(event) = events.as_ref().and_then(|events| events.last()) {
+            self.latest_offset = Some(event.offset.clone());
+        }
+        Ok(events)
+    }
+
+    fn finalize(&mut self) -> Result<ConnectorStateV2> {
+        // Stops the generation.
+        self.generation_rx.close();
+        let split = match &self.latest_offset {
+            Some(offset) => self.assigned_split.copy_with_offset(offset.clone()),
+            None => self.assigned_split.clone(),
+        };
+        Ok(ConnectorStateV2 {
+            splits: vec![SplitImpl::Datagen(split)],
+        })
     }
 }
 
@@ -307,4 +326,34 @@ mod tests {
         assert_eq!(v1, v2);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_finalize() -> Result<()> {
+        let state = Some(vec![SplitImpl::Datagen(DatagenSplit {
+            split_index: 0,
+            split_num: 1,
+            start_offset: None,
+        })]);
+        let properties = DatagenProperties {
+            split_num: None,
+            rows_per_second: "10".to_string(),
+            fields: HashMap::new(),
+        };
+        let columns = vec![Column {
+            name: "random_int".to_string(),
+            data_type: DataType::Int32,
+        }];
+        let mut reader = DatagenSplitReader::new(properties, state, Some(columns)).await?;
+        let events = reader.next().await?.unwrap();
+        assert_eq!(events.last().unwrap().offset, "9");
+        assert_eq!(
+            reader.finalize()?.into_state(),
+            Some(vec![SplitImpl::Datagen(DatagenSplit {
+                split_index: 0,
+                split_num: 1,
+                start_offset: Some(9),
+            })])
+        );
+        Ok(())
+    }
 }
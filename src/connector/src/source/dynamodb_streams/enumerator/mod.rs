@@ -0,0 +1,215 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use aws_sdk_dynamodbstreams::model::Shard;
+use aws_sdk_dynamodbstreams::Client;
+
+use crate::source::dynamodb_streams::{
+    build_dynamodb_streams_client, describe_shards, resolve_stream_arn, KinesisProperties,
+};
+use crate::source::kinesis::clock::TokioClock;
+use crate::source::kinesis::retry::{RetryPolicy, SharedBackoff};
+use crate::source::kinesis::split::{KinesisOffset, KinesisSplit};
+use crate::source::SplitEnumerator;
+
+/// Resolves where the shards listed when the source starts are read from by `scan.startup.mode`,
+/// either `earliest` (default) or `latest`.
+fn startup_offset(properties: &KinesisProperties) -> Result<KinesisOffset> {
+    match properties
+        .scan_startup_mode
+        .as_ref()
+        .map(|s| s.to_lowercase())
+        .as_deref()
+    {
+        Some("earliest") | None => Ok(KinesisOffset::Earliest),
+        Some("latest") => Ok(KinesisOffset::Latest),
+        _ => Err(anyhow!(
+            "properties `scan.startup.mode` of dynamodb streams only support earliest and latest \
+             or leave it empty"
+        )),
+    }
+}
+
+/// Returns whether the shard is closed, i.e. has an ending sequence number.
+fn is_closed(shard: &Shard) -> bool {
+    shard
+        .sequence_number_range()
+        .and_then(|range| range.ending_sequence_number())
+        .is_some()
+}
+
+pub struct DynamoDbStreamsSplitEnumerator {
+    stream_arn: String,
+    client: Client,
+    retry_policy: RetryPolicy,
+    start_offset: KinesisOffset,
+    /// The shards of the first listing, which start at `start_offset`. Shards created later are
+    /// read from their beginning, so that no record of a new child shard is missed.
+    initial_shards: Option<HashSet<String>>,
+}
+
+#[async_trait]
+impl SplitEnumerator for DynamoDbStreamsSplitEnumerator {
+    type Properties = KinesisProperties;
+    type Split = KinesisSplit;
+
+    async fn new(properties: KinesisProperties) -> Result<Self> {
+        let (properties, stream_arn) = resolve_stream_arn(properties)?;
+        let start_offset = startup_offset(&properties)?;
+        let retry_policy = RetryPolicy::from_properties(&properties)?;
+        let client = build_dynamodb_streams_client(properties).await?;
+        Ok(Self {
+            stream_arn,
+            client,
+            retry_policy,
+            start_offset,
+            initial_shards: None,
+        })
+    }
+
+    async fn list_splits(&mut self) -> Result<Vec<KinesisSplit>> {
+        let backoff = SharedBackoff::new(Arc::new(TokioClock));
+        let shards =
+            describe_shards(&self.client, &self.stream_arn, &self.retry_policy, &backoff).await?;
+        let initial_shards = self.initial_shards.get_or_insert_with(|| {
+            shards
+                .iter()
+                .filter_map(|shard| shard.shard_id().map(String::from))
+                .collect()
+        });
+        let latest = self.start_offset == KinesisOffset::Latest;
+        Ok(shards
+            .iter()
+            .filter_map(|shard| {
+                let shard_id = shard.shard_id().unwrap_or_default();
+                let start_offset = if !initial_shards.contains(shard_id) {
+                    KinesisOffset::Earliest
+                } else if latest && is_closed(shard) {
+                    // A closed shard has no records after the latest ones.
+                    return None;
+                } else {
+                    self.start_offset.clone()
+                };
+                Some(KinesisSplit::new(
+                    shard_id.to_string().into(),
+                    start_offset,
+                    KinesisOffset::None,
+                ))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::source::dynamodb_streams::test_utils::{
+        json_response, mock_properties, mount_api_matching, MOCK_STREAM_ARN,
+    };
+    use crate::source::kinesis::test_utils::SequenceResponder;
+
+    fn describe_stream_output(shards: serde_json::Value) -> serde_json::Value {
+        json!({ "StreamDescription": { "Shards": shards } })
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_startup_mode() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        let closed = json!({
+            "ShardId": "shardId-0",
+            "SequenceNumberRange": { "StartingSequenceNumber": "1", "EndingSequenceNumber": "9" },
+        });
+        let open = json!({
+            "ShardId": "shardId-1",
+            "ParentShardId": "shardId-0",
+            "SequenceNumberRange": { "StartingSequenceNumber": "10" },
+        });
+        let new = json!({
+            "ShardId": "shardId-2",
+            "ParentShardId": "shardId-1",
+            "SequenceNumberRange": { "StartingSequenceNumber": "20" },
+        });
+        mount_api_matching(
+            &server,
+            "DescribeStream",
+            json!({ "StreamArn": MOCK_STREAM_ARN }),
+            SequenceResponder::new(vec![
+                json_response(describe_stream_output(json!([closed, open]))),
+                json_response(describe_stream_output(json!([closed, open, new]))),
+            ]),
+        )
+        .await;
+
+        let positions = |splits: Vec<KinesisSplit>| {
+            splits
+                .into_iter()
+                .map(|split| (split.shard_id.to_string(), split.start_position))
+                .collect::<Vec<_>>()
+        };
+        let mut enumerator = DynamoDbStreamsSplitEnumerator::new(KinesisProperties {
+            scan_startup_mode: Some("latest".to_string()),
+            ..mock_properties(&server)
+        })
+        .await?;
+        assert_eq!(enumerator.stream_arn, MOCK_STREAM_ARN);
+        // The closed shard is skipped and the open one starts at the latest records, while the
+        // shard created later is read from its beginning.
+        assert_eq!(
+            positions(enumerator.list_splits().await?),
+            vec![("shardId-1".to_string(), KinesisOffset::Latest)]
+        );
+        assert_eq!(
+            positions(enumerator.list_splits().await?),
+            vec![
+                ("shardId-1".to_string(), KinesisOffset::Latest),
+                ("shardId-2".to_string(), KinesisOffset::Earliest),
+            ]
+        );
+
+        // All shards start at the earliest records by default.
+        let mut enumerator = DynamoDbStreamsSplitEnumerator::new(mock_properties(&server)).await?;
+        assert_eq!(
+            positions(enumerator.list_splits().await?),
+            vec![
+                ("shardId-0".to_string(), KinesisOffset::Earliest),
+                ("shardId-1".to_string(), KinesisOffset::Earliest),
+                ("shardId-2".to_string(), KinesisOffset::Earliest),
+            ]
+        );
+
+        assert!(DynamoDbStreamsSplitEnumerator::new(KinesisProperties {
+            scan_startup_mode: Some("tail".to_string()),
+            ..mock_properties(&server)
+        })
+        .await
+        .is_err());
+        // The ARN is read from `stream.arn` only.
+        assert!(DynamoDbStreamsSplitEnumerator::new(KinesisProperties {
+            stream_name: MOCK_STREAM_ARN.to_string(),
+            stream_arn: None,
+            ..mock_properties(&server)
+        })
+        .await
+        .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! DynamoDB Streams share the consumption model of Kinesis: shards, shard iterators and sequence
+//! numbers. This connector reuses [`KinesisProperties`], [`KinesisSplit`] and [`KinesisOffset`],
+//! where `stream.arn` is the ARN of the table's stream, and only differs in the SDK and the shape
+//! of records, whose keys and images are emitted as JSON.
+//!
+//! [`KinesisSplit`]: crate::source::kinesis::split::KinesisSplit
+//! [`KinesisOffset`]: crate::source::kinesis::split::KinesisOffset
+
+pub mod enumerator;
+pub mod source;
+#[cfg(test)]
+pub(crate) mod test_utils;
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use aws_sdk_dynamodbstreams::model::Shard;
+use aws_sdk_dynamodbstreams::Client;
+use http::Uri;
+
+use crate::source::kinesis::config::{parse_property, AwsConfigInfo};
+use crate::source::kinesis::retry::{with_retry, RetryPolicy, SharedBackoff};
+pub use crate::source::kinesis::KinesisProperties;
+
+pub const DYNAMODB_STREAMS_CONNECTOR: &str = "dynamodb-streams";
+
+/// The ARN of the stream of a DynamoDB table,
+/// `arn:<partition>:dynamodb:<region>:<account id>:table/<table name>/stream/<label>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DynamoDbStreamArn {
+    pub arn: String,
+    pub region: String,
+}
+
+impl FromStr for DynamoDbStreamArn {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts = s.splitn(6, ':').collect::<Vec<_>>();
+        if let ["arn", _, "dynamodb", region, _, resource] = parts.as_slice() {
+            if !region.is_empty() && resource.starts_with("table/") && resource.contains("/stream/")
+            {
+                return Ok(Self {
+                    arn: s.to_string(),
+                    region: region.to_string(),
+                });
+            }
+        }
+        Err(anyhow!(
+            "expect arn:<partition>:dynamodb:<region>:<account id>:table/<table name>/stream/<label>"
+        ))
+    }
+}
+
+/// Takes the ARN of the stream from `stream.arn`, the key of the ARN of the Kinesis connector, and
+/// fills the region from it unless `aws.region` is set. The ARN is cleared from the returned
+/// properties, as it is not the ARN of a Kinesis stream.
+pub fn resolve_stream_arn(
+    mut properties: KinesisProperties,
+) -> Result<(KinesisProperties, String)> {
+    let arn = parse_property::<DynamoDbStreamArn>("stream.arn", properties.stream_arn.as_deref())?
+        .ok_or_else(|| anyhow!("stream.arn should be provided"))?;
+    if properties.stream_region.is_empty() {
+        properties.stream_region = arn.region;
+    }
+    properties.stream_arn = None;
+    Ok((properties, arn.arn))
+}
+
+/// Builds the client from properties resolved by [`resolve_stream_arn`].
+pub async fn build_dynamodb_streams_client(properties: KinesisProperties) -> Result<Client> {
+    let config = AwsConfigInfo::build(properties)?;
+    let aws_config = config.load().await?;
+    let mut builder = aws_sdk_dynamodbstreams::config::Builder::from(&aws_config);
+    if let Some(endpoint) = &config.endpoint {
+        let uri = endpoint
+            .parse::<Uri>()
+            .map_err(|e| anyhow!("invalid endpoint {}: {}", endpoint, e))?;
+        builder = builder.endpoint_resolver(aws_smithy_http::endpoint::Endpoint::immutable(uri));
+    }
+    Ok(Client::from_conf(builder.build()))
+}
+
+/// Describes the shards of the stream page by page, retrying throttled calls under `policy`.
+pub async fn describe_shards(
+    client: &Client,
+    stream_arn: &str,
+    policy: &RetryPolicy,
+    backoff: &SharedBackoff,
+) -> Result<Vec<Shard>> {
+    let mut exclusive_start_shard_id: Option<String> = None;
+    let mut shards = Vec::new();
+    loop {
+        let output = with_retry(policy, backoff, || {
+            let exclusive_start_shard_id = exclusive_start_shard_id.clone();
+            async move {
+                backoff.wait().await;
+                client
+                    .describe_stream()
+                    .stream_arn(stream_arn)
+                    .set_exclusive_start_shard_id(exclusive_start_shard_id)
+                    .send()
+                    .await
+            }
+        })
+        .await?;
+        let description = output
+            .stream_description()
+            .ok_or_else(|| anyhow!("no description of stream {}", stream_arn))?;
+        shards.extend(description.shards().unwrap_or_default().iter().cloned());
+        match description.last_evaluated_shard_id() {
+            Some(shard_id) => exclusive_start_shard_id = Some(shard_id.to_string()),
+            None => break,
+        }
+    }
+    Ok(shards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_invalid_endpoint() {
+        let err = build_dynamodb_streams_client(KinesisProperties {
+            stream_name: "arn".to_string(),
+            stream_region: "us-east-1".to_string(),
+            endpoint: Some("not a uri".to_string()),
+            credentials_access_key: Some("access_key".to_string()),
+            credentials_secret_access_key: Some("secret_key".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("invalid endpoint"));
+    }
+    #[test]
+    fn test_resolve_stream_arn() {
+        let arn =
+            "arn:aws:dynamodb:us-west-2:123456789012:table/orders/stream/2023-01-01T00:00:00.000";
+        let (properties, stream_arn) = resolve_stream_arn(KinesisProperties {
+            stream_arn: Some(arn.to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(stream_arn, arn);
+        assert_eq!(properties.stream_region, "us-west-2");
+        assert_eq!(properties.stream_arn, None);
+
+        // An explicit region takes precedence.
+        let (properties, _) = resolve_stream_arn(KinesisProperties {
+            stream_region: "us-east-1".to_string(),
+            stream_arn: Some(arn.to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(properties.stream_region, "us-east-1");
+
+        for invalid in [
+            None,
+            Some("arn:aws:kinesis:us-west-2:123456789012:stream/orders"),
+            Some("arn:aws:dynamodb:us-west-2:123456789012:table/orders"),
+        ] {
+            assert!(resolve_stream_arn(KinesisProperties {
+                stream_name: arn.to_string(),
+                stream_arn: invalid.map(String::from),
+                ..Default::default()
+            })
+            .is_err());
+        }
+    }
+}
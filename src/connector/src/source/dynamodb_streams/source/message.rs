@@ -0,0 +1,151 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use aws_sdk_dynamodbstreams::model::{AttributeValue, Record};
+use aws_smithy_types::base64;
+use bytes::Bytes;
+use serde_json::{Map, Number, Value};
+
+use crate::source::kinesis::source::message::{datetime_to_millis, KinesisMeta};
+use crate::source::{SourceMessage, SourceMeta, SplitId};
+
+/// Converts a DynamoDB stream record to a [`SourceMessage`], whose payload is a JSON object with
+/// the event name, keys and the old and new images of the item, e.g.
+/// `{"event_name": "MODIFY", "keys": {"id": 1}, "old_image": {...}, "new_image": {...}}`. Images
+/// absent in the stream view type are `null`.
+pub fn dynamodb_record_to_message(split_id: SplitId, record: &Record) -> SourceMessage {
+    let stream_record = record.dynamodb();
+    let mut payload = Map::new();
+    payload.insert(
+        "event_name".to_string(),
+        record
+            .event_name()
+            .map_or(Value::Null, |name| Value::String(name.as_str().to_string())),
+    );
+    payload.insert(
+        "keys".to_string(),
+        item_to_json(stream_record.and_then(|r| r.keys())),
+    );
+    payload.insert(
+        "old_image".to_string(),
+        item_to_json(stream_record.and_then(|r| r.old_image())),
+    );
+    payload.insert(
+        "new_image".to_string(),
+        item_to_json(stream_record.and_then(|r| r.new_image())),
+    );
+
+    SourceMessage {
+        payload: Some(Bytes::from(Value::Object(payload).to_string())),
+        offset: stream_record
+            .and_then(|r| r.sequence_number())
+            .unwrap_or_default()
+            .to_string(),
+        split_id,
+        meta: SourceMeta::Kinesis(KinesisMeta {
+            timestamp: stream_record
+                .and_then(|r| r.approximate_creation_date_time())
+                .map(datetime_to_millis),
+            ..Default::default()
+        }),
+        attributes: Default::default(),
+        provenance: None,
+        state_offset: None,
+    }
+}
+
+fn item_to_json(item: Option<&HashMap<String, AttributeValue>>) -> Value {
+    match item {
+        Some(item) => Value::Object(
+            item.iter()
+                .map(|(name, value)| (name.clone(), attribute_to_json(value)))
+                .collect(),
+        ),
+        None => Value::Null,
+    }
+}
+
+fn number_to_json(number: &str) -> Value {
+    number
+        .parse::<Number>()
+        .map_or_else(|_| Value::String(number.to_string()), Value::Number)
+}
+
+fn attribute_to_json(value: &AttributeValue) -> Value {
+    match value {
+        AttributeValue::S(s) => Value::String(s.clone()),
+        AttributeValue::N(n) => number_to_json(n),
+        AttributeValue::B(b) => Value::String(base64::encode(b.as_ref())),
+        AttributeValue::Bool(b) => Value::Bool(*b),
+        AttributeValue::Null(_) => Value::Null,
+        AttributeValue::L(l) => Value::Array(l.iter().map(attribute_to_json).collect()),
+        AttributeValue::M(m) => Value::Object(
+            m.iter()
+                .map(|(name, value)| (name.clone(), attribute_to_json(value)))
+                .collect(),
+        ),
+        AttributeValue::Ss(ss) => Value::Array(ss.iter().cloned().map(Value::String).collect()),
+        AttributeValue::Ns(ns) => Value::Array(ns.iter().map(|n| number_to_json(n)).collect()),
+        AttributeValue::Bs(bs) => Value::Array(
+            bs.iter()
+                .map(|b| Value::String(base64::encode(b.as_ref())))
+                .collect(),
+        ),
+        _ => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_dynamodbstreams::model::{OperationType, StreamRecord};
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_dynamodb_record_to_message() {
+        let record = Record::builder()
+            .event_name(OperationType::Modify)
+            .dynamodb(
+                StreamRecord::builder()
+                    .keys("id", AttributeValue::N("1".to_string()))
+                    .old_image("id", AttributeValue::N("1".to_string()))
+                    .old_image("name", AttributeValue::S("old".to_string()))
+                    .new_image("id", AttributeValue::N("1".to_string()))
+                    .new_image("name", AttributeValue::S("new".to_string()))
+                    .new_image(
+                        "tags",
+                        AttributeValue::Ss(vec!["a".to_string(), "b".to_string()]),
+                    )
+                    .sequence_number("100")
+                    .build(),
+            )
+            .build();
+
+        let message = dynamodb_record_to_message("shardId-1".to_string().into(), &record);
+        assert_eq!(message.offset, "100");
+        let payload: Value = serde_json::from_slice(&message.payload.unwrap()).unwrap();
+        assert_eq!(
+            payload,
+            json!({
+                "event_name": "MODIFY",
+                "keys": { "id": 1 },
+                "old_image": { "id": 1, "name": "old" },
+                "new_image": { "id": 1, "name": "new", "tags": ["a", "b"] },
+            })
+        );
+    }
+}
@@ -0,0 +1,349 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use aws_sdk_dynamodbstreams::model::ShardIteratorType;
+use aws_sdk_dynamodbstreams::types::SdkError;
+use aws_sdk_dynamodbstreams::Client;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use futures_async_stream::try_stream;
+
+use crate::source::dynamodb_streams::source::message::dynamodb_record_to_message;
+use crate::source::dynamodb_streams::{
+    build_dynamodb_streams_client, describe_shards, resolve_stream_arn, KinesisProperties,
+};
+use crate::source::kinesis::clock::TokioClock;
+use crate::source::kinesis::config::{parse_property, MissingParentPolicy};
+use crate::source::kinesis::retry::{with_retry, RetryPolicy, SharedBackoff};
+use crate::source::kinesis::source::lineage::LineageMerge;
+use crate::source::kinesis::split::{unpack_sequence_number, KinesisOffset};
+use crate::source::{
+    Column, ConnectorState, SourceMessage, SplitId, SplitImpl, SplitMetaData, SplitReader,
+};
+
+/// The interval between polls of a shard without new records.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+type ShardStream = BoxStream<'static, Result<Vec<SourceMessage>>>;
+
+struct ShardReader {
+    client: Client,
+    stream_arn: String,
+    retry_policy: RetryPolicy,
+    /// Shared by the shards of the reader, so that a throttled shard holds back the others.
+    backoff: Arc<SharedBackoff>,
+    split_id: SplitId,
+    shard_id: SplitId,
+    shard_iter: Option<String>,
+    latest_offset: Option<String>,
+    start_position: KinesisOffset,
+    /// Set once the shard is closed.
+    finished: bool,
+}
+
+impl ShardReader {
+    async fn new_shard_iter(&mut self) -> Result<()> {
+        let (iter_type, sequence_number) = match (&self.latest_offset, &self.start_position) {
+            (Some(seq), _) | (None, KinesisOffset::SequenceNumber(seq)) => {
+                (ShardIteratorType::AfterSequenceNumber, Some(seq.clone()))
+            }
+            (None, KinesisOffset::PackedSequenceNumber(packed)) => (
+                ShardIteratorType::AfterSequenceNumber,
+                Some(unpack_sequence_number(packed)?),
+            ),
+            (None, KinesisOffset::Latest) => (ShardIteratorType::Latest, None),
+            (None, KinesisOffset::Timestamp(_)) => {
+                return Err(anyhow!(
+                    "DynamoDB Streams do not support starting from a timestamp"
+                ));
+            }
+            (None, _) => (ShardIteratorType::TrimHorizon, None),
+        };
+
+        let backoff = self.backoff.as_ref();
+        let client = &self.client;
+        let stream_arn = self.stream_arn.as_str();
+        let shard_id = self.shard_id.as_str();
+        let resp = with_retry(&self.retry_policy, backoff, || {
+            let iter_type = iter_type.clone();
+            let sequence_number = sequence_number.clone();
+            async move {
+                backoff.wait().await;
+                client
+                    .get_shard_iterator()
+                    .stream_arn(stream_arn)
+                    .shard_id(shard_id)
+                    .shard_iterator_type(iter_type)
+                    .set_sequence_number(sequence_number)
+                    .send()
+                    .await
+            }
+        })
+        .await?;
+        self.shard_iter = resp.shard_iterator().map(String::from);
+        Ok(())
+    }
+
+    /// Calls `GetRecords` on the shard once, retrying throttled calls.
+    async fn poll(&mut self) -> Result<Vec<SourceMessage>> {
+        if self.shard_iter.is_none() {
+            self.new_shard_iter().await?;
+        }
+        let shard_iter = self.shard_iter.take();
+        let backoff = self.backoff.as_ref();
+        let client = &self.client;
+        let result = with_retry(&self.retry_policy, backoff, || {
+            let shard_iter = shard_iter.clone();
+            async move {
+                backoff.wait().await;
+                client
+                    .get_records()
+                    .set_shard_iterator(shard_iter)
+                    .send()
+                    .await
+            }
+        })
+        .await;
+        match result {
+            Ok(resp) => {
+                self.shard_iter = resp.next_shard_iterator().map(String::from);
+                // A closed shard has no next iterator.
+                self.finished = self.shard_iter.is_none();
+                let chunk = resp
+                    .records()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|r| dynamodb_record_to_message(self.split_id.clone(), r))
+                    .collect::<Vec<_>>();
+                if let Some(msg) = chunk.last() {
+                    self.latest_offset = Some(msg.offset.clone());
+                }
+                Ok(chunk)
+            }
+            // The iterator is renewed after the latest offset on the next poll.
+            Err(SdkError::ServiceError { err, .. }) if err.is_expired_iterator_exception() => {
+                Ok(vec![])
+            }
+            Err(e) => Err(anyhow!(e)),
+        }
+    }
+}
+
+/// Polls the shard until it is closed, yielding the non-empty batches.
+#[try_stream(ok = Vec<SourceMessage>, error = anyhow::Error)]
+async fn shard_reader_into_stream(mut reader: ShardReader) {
+    loop {
+        let chunk = reader.poll().await?;
+        let idle = chunk.is_empty();
+        if !idle {
+            yield chunk;
+        }
+        if reader.finished {
+            break;
+        }
+        if idle {
+            tokio::time::sleep(IDLE_POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Reads the assigned shards of a DynamoDB stream concurrently, where a child shard is read only
+/// once its parent shards are closed, see [`LineageMerge`].
+pub struct DynamoDbStreamsSplitReader {
+    shards: LineageMerge<ShardStream>,
+}
+
+#[async_trait]
+impl SplitReader for DynamoDbStreamsSplitReader {
+    type Properties = KinesisProperties;
+
+    async fn new(
+        properties: KinesisProperties,
+        state: ConnectorState,
+        _columns: Option<Vec<Column>>,
+    ) -> Result<Self> {
+        let splits = state
+            .unwrap()
+            .into_iter()
+            .map(|split| match split {
+                SplitImpl::DynamoDbStreams(split) => Ok(split),
+                _ => Err(anyhow!("expect DynamoDB stream split, got {:?}", split)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let (properties, stream_arn) = resolve_stream_arn(properties)?;
+        let retry_policy = RetryPolicy::from_properties(&properties)?;
+        let missing_parent = parse_property::<MissingParentPolicy>(
+            "on_missing_parent",
+            properties.on_missing_parent.as_deref(),
+        )?
+        .unwrap_or_default();
+        let client = build_dynamodb_streams_client(properties).await?;
+        let backoff = Arc::new(SharedBackoff::new(Arc::new(TokioClock)));
+
+        // Parents assigned to other readers can not be waited for, while those no longer in the
+        // stream have expired and are left to `on_missing_parent`.
+        let described = describe_shards(&client, &stream_arn, &retry_policy, &backoff).await?;
+        let existing = described
+            .iter()
+            .filter_map(|shard| shard.shard_id())
+            .collect::<HashSet<_>>();
+        let assigned = splits
+            .iter()
+            .map(|split| split.shard_id.as_str())
+            .collect::<HashSet<_>>();
+        let parents = |shard_id: &str| -> Vec<SplitId> {
+            described
+                .iter()
+                .filter(|shard| shard.shard_id() == Some(shard_id))
+                .filter_map(|shard| shard.parent_shard_id())
+                .filter(|parent| assigned.contains(parent) || !existing.contains(parent))
+                .map(|parent| parent.to_string().into())
+                .collect()
+        };
+
+        let shards = splits
+            .iter()
+            .map(|split| {
+                let reader = ShardReader {
+                    client: client.clone(),
+                    stream_arn: stream_arn.clone(),
+                    retry_policy: retry_policy.clone(),
+                    backoff: backoff.clone(),
+                    split_id: split.id(),
+                    shard_id: split.shard_id.clone(),
+                    shard_iter: None,
+                    latest_offset: None,
+                    start_position: split.start_position.clone(),
+                    finished: false,
+                };
+                let stream: ShardStream = shard_reader_into_stream(reader).boxed();
+                (split.id(), parents(&split.shard_id), stream)
+            })
+            .collect();
+        Ok(Self {
+            shards: LineageMerge::new(shards, missing_parent)?,
+        })
+    }
+
+    async fn next(&mut self) -> Result<Option<Vec<SourceMessage>>> {
+        self.shards.next().await.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::source::dynamodb_streams::test_utils::{
+        error_response, json_response, mock_properties, mount_api_matching as mount,
+    };
+    use crate::source::kinesis::test_utils::{mock_split, SequenceResponder};
+
+    fn records_output(sequence_number: &str) -> ResponseTemplate {
+        json_response(json!({
+            "Records": [{
+                "eventName": "INSERT",
+                "dynamodb": {
+                    "Keys": { "id": { "N": sequence_number } },
+                    "SequenceNumber": sequence_number,
+                },
+            }],
+        }))
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_lineage_and_retry() -> Result<()> {
+        let server = MockServer::start().await;
+        mount(
+            &server,
+            "DescribeStream",
+            json!({}),
+            SequenceResponder::new(vec![json_response(json!({
+                "StreamDescription": {
+                    "Shards": [
+                        { "ShardId": "shardId-0" },
+                        { "ShardId": "shardId-1", "ParentShardId": "shardId-0" },
+                    ],
+                },
+            }))]),
+        )
+        .await;
+        for shard_id in ["shardId-0", "shardId-1"] {
+            mount(
+                &server,
+                "GetShardIterator",
+                json!({ "ShardId": shard_id }),
+                SequenceResponder::new(vec![json_response(json!({ "ShardIterator": shard_id }))]),
+            )
+            .await;
+        }
+        // The parent is throttled once, and both shards are closed after a record.
+        let throttled = error_response("LimitExceededException");
+        mount(
+            &server,
+            "GetRecords",
+            json!({ "ShardIterator": "shardId-0" }),
+            SequenceResponder::new(vec![throttled, records_output("1")]),
+        )
+        .await;
+        mount(
+            &server,
+            "GetRecords",
+            json!({ "ShardIterator": "shardId-1" }),
+            SequenceResponder::new(vec![records_output("2")]),
+        )
+        .await;
+
+        let properties = KinesisProperties {
+            retry_base_delay: Some("10ms".to_string()),
+            ..mock_properties(&server)
+        };
+        let mut reader = DynamoDbStreamsSplitReader::new(
+            properties,
+            Some(vec![
+                SplitImpl::DynamoDbStreams(mock_split("shardId-1")),
+                SplitImpl::DynamoDbStreams(mock_split("shardId-0")),
+            ]),
+            None,
+        )
+        .await?;
+
+        // The child is read only after its parent is closed, even though the parent is throttled.
+        let offsets = |chunk: Vec<SourceMessage>| {
+            chunk
+                .into_iter()
+                .map(|msg| (msg.split_id.to_string(), msg.offset))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            offsets(reader.next().await?.unwrap()),
+            vec![("shardId-0".to_string(), "1".to_string())]
+        );
+        assert_eq!(
+            offsets(reader.next().await?.unwrap()),
+            vec![("shardId-1".to_string(), "2".to_string())]
+        );
+        assert!(reader.next().await?.is_none());
+        Ok(())
+    }
+}
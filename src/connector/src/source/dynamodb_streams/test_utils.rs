@@ -0,0 +1,70 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A mock DynamoDB Streams service built on [`wiremock`], speaking the JSON protocol used by the
+//! SDK.
+
+use serde_json::Value;
+use wiremock::matchers::{body_partial_json, header, method};
+use wiremock::{Mock, MockServer, Respond, ResponseTemplate};
+
+use crate::source::kinesis::test_utils::mock_properties as mock_kinesis_properties;
+use crate::source::kinesis::KinesisProperties;
+
+const DYNAMODB_STREAMS_TARGET_PREFIX: &str = "DynamoDBStreams_20120810";
+const DYNAMODB_STREAMS_CONTENT_TYPE: &str = "application/x-amz-json-1.0";
+
+pub const MOCK_STREAM_ARN: &str =
+    "arn:aws:dynamodb:us-east-1:123456789012:table/orders/stream/2023-01-01T00:00:00.000";
+
+pub fn mock_properties(server: &MockServer) -> KinesisProperties {
+    KinesisProperties {
+        stream_name: String::new(),
+        stream_region: String::new(),
+        stream_arn: Some(MOCK_STREAM_ARN.to_string()),
+        ..mock_kinesis_properties(server)
+    }
+}
+
+/// Mounts `responder` for the requests of the DynamoDB Streams API `operation` whose JSON body
+/// contains `body`.
+pub async fn mount_api_matching(
+    server: &MockServer,
+    operation: &str,
+    body: Value,
+    responder: impl Respond + 'static,
+) {
+    let target = format!("{}.{}", DYNAMODB_STREAMS_TARGET_PREFIX, operation);
+    Mock::given(method("POST"))
+        .and(header("x-amz-target", target.as_str()))
+        .and(body_partial_json(body))
+        .respond_with(responder)
+        .mount(server)
+        .await;
+}
+
+pub fn json_response(body: Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_raw(body.to_string(), DYNAMODB_STREAMS_CONTENT_TYPE)
+}
+
+pub fn error_response(error_type: &str) -> ResponseTemplate {
+    ResponseTemplate::new(400).set_body_raw(
+        serde_json::json!({
+            "__type": format!("com.amazonaws.dynamodb.v20120810#{}", error_type),
+            "message": "mock error",
+        })
+        .to_string(),
+        DYNAMODB_STREAMS_CONTENT_TYPE,
+    )
+}
@@ -33,7 +33,7 @@ use tokio_util::io::ReaderStream;
 use tracing::{error, info};
 
 use crate::aws_utils::{default_conn_config, s3_client, AwsConfigV2, AwsCredentialV2};
-use crate::source::base::{SourceMessage, SplitReader};
+use crate::source::base::{SourceMessage, SourceMeta, SplitReader};
 use crate::source::filesystem::file_common::{EntryStat, StatusWatch};
 use crate::source::filesystem::s3::s3_dir::FileSystemOptError::IllegalS3FilePath;
 use crate::source::filesystem::s3::s3_dir::{
@@ -365,6 +365,10 @@ impl SplitReader for S3FileReader {
                         payload: Some(msg.payload),
                         offset: new_offset.to_string(),
                         split_id: msg_id.into(),
+                        meta: SourceMeta::Empty,
+                        attributes: Default::default(),
+                        provenance: None,
+                        state_offset: None,
                     }
                 })
                 .collect_vec(),
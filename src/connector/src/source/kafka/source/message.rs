@@ -16,7 +16,7 @@ use bytes::Bytes;
 use rdkafka::message::BorrowedMessage;
 use rdkafka::Message;
 
-use crate::source::base::SourceMessage;
+use crate::source::base::{SourceMessage, SourceMeta};
 
 impl<'a> From<BorrowedMessage<'a>> for SourceMessage {
     fn from(message: BorrowedMessage<'a>) -> Self {
@@ -25,6 +25,10 @@ impl<'a> From<BorrowedMessage<'a>> for SourceMessage {
             payload: message.payload().map(Bytes::copy_from_slice),
             offset: message.offset().to_string(),
             split_id: message.partition().to_string().into(),
+            meta: SourceMeta::Empty,
+            attributes: Default::default(),
+            provenance: None,
+            state_offset: None,
         }
     }
 }
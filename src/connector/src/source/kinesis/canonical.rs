@@ -0,0 +1,255 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! The canonical form of the properties of a Kinesis source, so that equivalent configurations,
+//! e.g. with aliased or padded keys and differently cased keywords, produce identical internal
+//! state and checkpoints.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+use crate::source::kinesis::config::resolve_stream_arn;
+use crate::source::kinesis::KinesisProperties;
+
+/// The legacy keys accepted by [`KinesisProperties`], along with their canonical keys.
+const ALIASES: &[(&str, &str)] = &[
+    ("kinesis.stream.name", "stream"),
+    ("kinesis.stream.region", "aws.region"),
+    ("kinesis.scan.startup.mode", "scan.startup.mode"),
+    ("kinesis.endpoint", "endpoint"),
+    (
+        "kinesis.credentials.access",
+        "aws.credentials.access_key_id",
+    ),
+    (
+        "kinesis.credentials.secret",
+        "aws.credentials.secret_access_key",
+    ),
+    (
+        "kinesis.credentials.session_token",
+        "aws.credentials.session_token",
+    ),
+    ("kinesis.assumerole.arn", "aws.credentials.role.arn"),
+    (
+        "kinesis.assumerole.external_id",
+        "aws.credentials.role.external_id",
+    ),
+    ("on_stream_error", "enumerate.partial.failure"),
+];
+
+/// The properties whose values are case-insensitive keywords or booleans, lowercased in the
+/// canonical form.
+const KEYWORD_PROPERTIES: &[&str] = &[
+    "bounded.to_latest",
+    "client.shared",
+    "consumer.mode",
+    "dry_run",
+    "endpoint.flavor",
+    "enumerate.partial.failure",
+    "get_records.retry.cache",
+    "get_records.validation",
+    "iterator.acquisition",
+    "latest.gap.detection",
+    "max.shards.per.reader.policy",
+    "on_corrupted_aggregate",
+    "on_empty_payload",
+    "on_missing_parent",
+    "on_no_shards",
+    "on_shard_error",
+    "payload.framing",
+    "payload.transforms",
+    "preflight.check.shard_limit",
+    "response.compression",
+    "retry.jitter",
+    "retry.on",
+    "scan.startup.mode",
+    "state.sequence_number.format",
+];
+
+/// Properties with trimmed, lowercased and unaliased keys, and trimmed values, lowercased for
+/// keywords. Two configurations are equivalent if and only if their canonical forms are equal.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CanonicalKinesisConfig {
+    entries: BTreeMap<String, String>,
+}
+
+impl CanonicalKinesisConfig {
+    /// Canonicalizes the properties of a `WITH` clause, failing if two keys are the same property
+    /// with different values, e.g. `stream` and its alias `kinesis.stream.name`.
+    pub fn from_map(properties: &HashMap<String, String>) -> Result<Self> {
+        let mut entries = BTreeMap::new();
+        for (key, value) in properties {
+            let key = key.trim().to_lowercase();
+            let key = ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == key)
+                .map_or(key.clone(), |(_, canonical)| canonical.to_string());
+            let value = if KEYWORD_PROPERTIES.contains(&key.as_str()) {
+                value.trim().to_lowercase()
+            } else {
+                value.trim().to_string()
+            };
+            if let Some(previous) = entries.get(&key) {
+                if *previous != value {
+                    return Err(anyhow!(
+                        "conflicting values '{}' and '{}' of kinesis property {}",
+                        previous,
+                        value,
+                        key
+                    ));
+                }
+            }
+            entries.insert(key, value);
+        }
+        let config = Self { entries };
+        // Fails early on values of the wrong types.
+        config.properties()?;
+        Ok(config)
+    }
+
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Self> {
+        let value = serde_json::to_value(properties)?;
+        let properties = value
+            .as_object()
+            .unwrap()
+            .iter()
+            .filter_map(|(key, value)| match value {
+                Value::String(value) => Some((key.clone(), value.clone())),
+                _ => None,
+            })
+            .collect();
+        Self::from_map(&properties)
+    }
+
+    /// The canonical keys and values, in the order of the keys.
+    pub fn entries(&self) -> &BTreeMap<String, String> {
+        &self.entries
+    }
+
+    pub fn properties(&self) -> Result<KinesisProperties> {
+        serde_json::from_value(serde_json::to_value(&self.entries)?)
+            .map_err(|e| anyhow!("invalid kinesis properties: {}", e))
+    }
+
+    /// Returns the canonical form of `properties`. Canonicalizing canonical properties leaves them
+    /// as is.
+    pub fn canonicalize(properties: &KinesisProperties) -> Result<KinesisProperties> {
+        Self::from_properties(properties)?.properties()
+    }
+
+    /// Canonicalizes `properties` and fills the stream from `stream.arn`, see
+    /// [`resolve_stream_arn`]. Applied once by the public constructors of the readers and the
+    /// enumerator, which pass the resolved properties down. Both steps are idempotent, so
+    /// resolving resolved properties again leaves them as is.
+    pub fn resolve(properties: &KinesisProperties) -> Result<KinesisProperties> {
+        resolve_stream_arn(Self::canonicalize(properties)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::btreemap;
+
+    use super::*;
+
+    fn map(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_canonical_aliases() -> Result<()> {
+        let config = CanonicalKinesisConfig::from_map(&map(&[
+            ("kinesis.stream.name", " orders "),
+            (" Kinesis.Stream.Region", "us-east-1"),
+            ("kinesis.scan.startup.mode", "LATEST"),
+            ("on_stream_error", "Skip_Failed"),
+        ]))?;
+        assert_eq!(
+            config.entries(),
+            &btreemap! {
+                "stream".to_string() => "orders".to_string(),
+                "aws.region".to_string() => "us-east-1".to_string(),
+                "scan.startup.mode".to_string() => "latest".to_string(),
+                "enumerate.partial.failure".to_string() => "skip_failed".to_string(),
+            }
+        );
+        let properties = config.properties()?;
+        assert_eq!(properties.stream_name, "orders");
+        assert_eq!(properties.scan_startup_mode.as_deref(), Some("latest"));
+
+        let err = CanonicalKinesisConfig::from_map(&map(&[
+            ("stream", "orders"),
+            ("kinesis.stream.name", "payments"),
+        ]))
+        .unwrap_err();
+        assert!(err.to_string().contains("stream"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn test_equivalent_properties() -> Result<()> {
+        let legacy = CanonicalKinesisConfig::from_map(&map(&[
+            ("kinesis.stream.name", "orders"),
+            ("kinesis.stream.region", "us-east-1"),
+            ("kinesis.credentials.access", "access_key"),
+            ("client.shared", "TRUE"),
+            ("max_chunk_records", " 100"),
+        ]))?;
+        let current = CanonicalKinesisConfig::from_map(&map(&[
+            ("stream", "orders "),
+            ("aws.region", "us-east-1"),
+            ("aws.credentials.access_key_id", "access_key"),
+            ("CLIENT.SHARED", "true"),
+            ("max_chunk_records", "100"),
+        ]))?;
+        assert_eq!(legacy, current);
+        // Round trips through the properties.
+        assert_eq!(
+            CanonicalKinesisConfig::from_properties(&legacy.properties()?)?,
+            current
+        );
+
+        let other = CanonicalKinesisConfig::from_map(&map(&[
+            ("stream", "orders"),
+            ("aws.region", "us-west-2"),
+        ]))?;
+        assert_ne!(other, current);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_idempotent() -> Result<()> {
+        let properties = CanonicalKinesisConfig::from_map(&map(&[
+            (
+                "stream.arn",
+                "arn:aws:kinesis:us-east-1:123456789012:stream/orders",
+            ),
+            ("SCAN.STARTUP.MODE", "Latest"),
+        ]))?
+        .properties()?;
+        let resolved = CanonicalKinesisConfig::resolve(&properties)?;
+        assert_eq!(resolved.stream_name, "orders");
+        assert_eq!(resolved.stream_region, "us-east-1");
+        assert_eq!(resolved.stream_arn, None);
+        assert_eq!(
+            CanonicalKinesisConfig::from_properties(&CanonicalKinesisConfig::resolve(&resolved)?)?,
+            CanonicalKinesisConfig::from_properties(&resolved)?
+        );
+        Ok(())
+    }
+}
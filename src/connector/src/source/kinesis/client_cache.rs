@@ -0,0 +1,178 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use aws_sdk_kinesis::Client;
+
+use crate::source::kinesis::config::{build_client_config, client_from_conf, HttpSettings};
+use crate::source::kinesis::KinesisProperties;
+
+/// The properties which determine the configuration of a client. Clients with the same key are
+/// interchangeable.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct ClientKey {
+    region: String,
+    endpoint: Option<String>,
+    access_key: Option<String>,
+    secret_key: Option<String>,
+    session_token: Option<String>,
+    assume_role_arn: Option<String>,
+    assume_role_external_id: Option<String>,
+    user_agent_suffix: Option<String>,
+    http: HttpSettings,
+}
+
+impl ClientKey {
+    fn new(properties: &KinesisProperties, http: HttpSettings) -> Self {
+        Self {
+            region: properties.stream_region.clone(),
+            endpoint: properties.endpoint.clone(),
+            access_key: properties.credentials_access_key.clone(),
+            secret_key: properties.credentials_secret_access_key.clone(),
+            session_token: properties.session_token.clone(),
+            assume_role_arn: properties.assume_role_arn.clone(),
+            assume_role_external_id: properties.assume_role_external_id.clone(),
+            user_agent_suffix: properties.client_user_agent_suffix.clone(),
+            http,
+        }
+    }
+}
+
+/// A shared client along with how many times it has been refreshed.
+#[derive(Clone, Debug)]
+struct SharedClient {
+    client: Client,
+    generation: u64,
+}
+
+lazy_static::lazy_static! {
+    /// The clients shared in the process, which are never evicted as there are only as many as
+    /// distinct connection configurations. The lock is never held while building a client, which
+    /// may take a slow call to STS.
+    static ref SHARED_CLIENTS: Mutex<HashMap<ClientKey, SharedClient>> = Mutex::new(HashMap::new());
+}
+
+/// Returns whether `a` and `b` are clones of the same client.
+fn same_client(a: &Client, b: &Client) -> bool {
+    std::ptr::eq(a.conf(), b.conf())
+}
+
+/// Returns the client shared by the readers and enumerators in the process with the same region,
+/// endpoint and credentials, building it on first use. The clones of a client share its
+/// connection pool and credentials provider, so that credentials are cached and refreshed once
+/// for all of them.
+pub async fn shared_client(properties: KinesisProperties) -> Result<Client> {
+    let http = HttpSettings::from_properties(&properties)?;
+    let key = ClientKey::new(&properties, http);
+    let cached = SHARED_CLIENTS.lock().unwrap().get(&key).cloned();
+    if let Some(shared) = cached {
+        return Ok(shared.client);
+    }
+    let client = client_from_conf(build_client_config(properties).await?, http);
+    // Callers racing on the first use keep the client built first.
+    let mut clients = SHARED_CLIENTS.lock().unwrap();
+    let shared = clients.entry(key).or_insert(SharedClient {
+        client,
+        generation: 0,
+    });
+    Ok(shared.client.clone())
+}
+
+/// Builds the shared client again after the call of `stale` was rejected for its expired
+/// credentials, replacing it for the callers of [`shared_client`] from then on. The client is only
+/// built once for the callers failing together: if the shared client is no longer `stale`, or is
+/// replaced while building, the replacement is returned instead.
+pub async fn refresh_shared_client(
+    properties: KinesisProperties,
+    stale: &Client,
+) -> Result<Client> {
+    let http = HttpSettings::from_properties(&properties)?;
+    let key = ClientKey::new(&properties, http);
+    let cached = SHARED_CLIENTS.lock().unwrap().get(&key).cloned();
+    let generation = match cached {
+        Some(shared) if !same_client(&shared.client, stale) => return Ok(shared.client),
+        Some(shared) => Some(shared.generation),
+        None => None,
+    };
+    let client = client_from_conf(build_client_config(properties).await?, http);
+    let mut clients = SHARED_CLIENTS.lock().unwrap();
+    match clients.get(&key) {
+        Some(shared) if Some(shared.generation) != generation => Ok(shared.client.clone()),
+        _ => {
+            clients.insert(
+                key,
+                SharedClient {
+                    client: client.clone(),
+                    generation: generation.map_or(0, |generation| generation + 1),
+                },
+            );
+            Ok(client)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_refresh_shared_client() -> Result<()> {
+        let properties = KinesisProperties {
+            stream_name: "stream".to_string(),
+            stream_region: "us-east-1".to_string(),
+            // Not shared with the clients of other tests.
+            endpoint: Some("http://refresh-shared-client.localhost".to_string()),
+            credentials_access_key: Some("access_key".to_string()),
+            credentials_secret_access_key: Some("secret_key".to_string()),
+            ..Default::default()
+        };
+        let stale = shared_client(properties.clone()).await?;
+        assert!(same_client(
+            &stale,
+            &shared_client(properties.clone()).await?
+        ));
+
+        // The first caller with the stale client builds another one, which the others get.
+        let refreshed = refresh_shared_client(properties.clone(), &stale).await?;
+        assert!(!same_client(&stale, &refreshed));
+        assert!(same_client(
+            &refreshed,
+            &refresh_shared_client(properties.clone(), &stale).await?
+        ));
+        assert!(same_client(
+            &refreshed,
+            &shared_client(properties.clone()).await?
+        ));
+
+        // Once the refreshed client fails as well, it is built again.
+        let again = refresh_shared_client(properties.clone(), &refreshed).await?;
+        assert!(!same_client(&refreshed, &again));
+        assert_eq!(
+            SHARED_CLIENTS
+                .lock()
+                .unwrap()
+                .get(&ClientKey::new(
+                    &properties,
+                    HttpSettings::from_properties(&properties)?
+                ))
+                .unwrap()
+                .generation,
+            2
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+
+/// The source of time of the reader, so that tests can exercise backoff, idle polls and
+/// heartbeats in virtual time without waiting.
+#[async_trait]
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Milliseconds since the Unix epoch, to compare with the arrival timestamps of records.
+    fn now_millis(&self) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64
+    }
+
+    async fn sleep(&self, duration: Duration);
+}
+
+pub type ClockRef = Arc<dyn Clock>;
+
+/// The clock backed by tokio, used in production.
+#[derive(Debug, Default)]
+pub struct TokioClock;
+
+#[async_trait]
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await;
+    }
+}
+
+/// A clock whose `sleep` advances virtual time instantly and records the duration, or in the
+/// manual mode waits until the test advances the time past its deadline.
+#[cfg(test)]
+#[derive(Debug)]
+pub struct MockClock {
+    state: std::sync::Mutex<(Instant, Vec<Duration>)>,
+    /// The virtual time at creation, along with the wall clock time then in milliseconds.
+    origin: (Instant, i64),
+    /// Set in the manual mode, publishing the virtual time on each `advance`.
+    advanced: Option<tokio::sync::watch::Sender<Instant>>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            state: std::sync::Mutex::new((now, vec![])),
+            origin: (now, TokioClock.now_millis()),
+            advanced: None,
+        }
+    }
+
+    /// Creates a clock whose time only moves by [`MockClock::advance`].
+    pub fn manual() -> Self {
+        let clock = Self::new();
+        let now = clock.now();
+        Self {
+            advanced: Some(tokio::sync::watch::channel(now).0),
+            ..clock
+        }
+    }
+
+    /// Moves the virtual time of a manual clock forward, waking the sleeps that are due.
+    pub fn advance(&self, duration: Duration) {
+        let now = {
+            let mut state = self.state.lock().unwrap();
+            state.0 += duration;
+            state.0
+        };
+        self.advanced
+            .as_ref()
+            .expect("only a manual clock is advanced")
+            .send_replace(now);
+    }
+
+    /// Returns the durations slept so far, in order.
+    pub fn sleeps(&self) -> Vec<Duration> {
+        self.state.lock().unwrap().1.clone()
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.state.lock().unwrap().0
+    }
+
+    fn now_millis(&self) -> i64 {
+        self.origin.1 + (self.now() - self.origin.0).as_millis() as i64
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        if let Some(advanced) = &self.advanced {
+            let deadline = {
+                let mut state = self.state.lock().unwrap();
+                state.1.push(duration);
+                state.0 + duration
+            };
+            let mut advanced = advanced.subscribe();
+            while *advanced.borrow() < deadline {
+                if advanced.changed().await.is_err() {
+                    return;
+                }
+            }
+            return;
+        }
+        {
+            let mut state = self.state.lock().unwrap();
+            state.0 += duration;
+            state.1.push(duration);
+        }
+        // Lets other tasks run as a real sleep would.
+        tokio::task::yield_now().await;
+    }
+}
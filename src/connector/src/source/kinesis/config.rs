@@ -13,17 +13,36 @@
 // limitations under the License.
 
 use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use aws_config::default_provider::credentials::DefaultCredentialsChain;
 use aws_config::sts::AssumeRoleProvider;
-use aws_sdk_kinesis::Client;
+use aws_sdk_kinesis::{Client, Config};
+use aws_types::app_name::AppName;
 use aws_types::credentials::SharedCredentialsProvider;
 use aws_types::region::Region;
 use http::Uri;
 use maplit::hashmap;
 use serde::{Deserialize, Serialize};
 
+use crate::source::kinesis::client_cache::shared_client;
+use crate::source::kinesis::retry::RetryPolicy;
+use crate::source::kinesis::source::checkpoint::OffsetCoalescer;
+use crate::source::kinesis::source::chunk::{BatchWindow, ChunkSplitter};
+use crate::source::kinesis::source::circuit_breaker::CircuitBreakerConfig;
+use crate::source::kinesis::source::clock_skew::ClockSkewCheck;
+use crate::source::kinesis::source::fetch_limit::AdaptiveLimit;
+use crate::source::kinesis::source::freshness::FreshnessSla;
+use crate::source::kinesis::source::hash_key::HashKeyRange;
+use crate::source::kinesis::source::safety_lag::SafetyLag;
+use crate::source::kinesis::source::transform::{
+    parse_transforms, PayloadFraming, PayloadTransform,
+};
+use crate::source::kinesis::split::{pack_sequence_number, PACKED_OFFSET_PREFIX};
+use crate::source::kinesis::telemetry::CountingConnector;
 use crate::source::kinesis::KinesisProperties;
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
@@ -92,8 +111,14 @@ impl AwsConfigInfo {
     }
 
     pub fn build(properties: KinesisProperties) -> Result<Self> {
+        let properties = resolve_stream_arn(properties)?;
         let stream_name = properties.stream_name;
         let region = properties.stream_region;
+        if region.is_empty() {
+            return Err(anyhow!(
+                "aws.region should be provided, or stream.arn to derive it from"
+            ));
+        }
 
         let mut credentials: Option<AwsCredentials> = None;
         let mut assume_role: Option<AwsAssumeRole> = None;
@@ -131,6 +156,478 @@ impl AwsConfigInfo {
     }
 }
 
+/// Parses an optional property with [`FromStr`], naming the property on failure.
+pub fn parse_property<T>(name: &str, value: Option<&str>) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .map(|value| {
+            value
+                .trim()
+                .parse::<T>()
+                .map_err(|e| anyhow!("invalid {} '{}': {}", name, value, e))
+        })
+        .transpose()
+}
+
+/// A Kinesis stream ARN, `arn:<partition>:kinesis:<region>:<account id>:stream/<stream name>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamArn {
+    pub region: String,
+    pub account_id: String,
+    pub stream_name: String,
+}
+
+impl FromStr for StreamArn {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts = s.splitn(6, ':').collect::<Vec<_>>();
+        if let ["arn", _, "kinesis", region, account_id, resource] = parts.as_slice() {
+            if let Some(stream_name) = resource.strip_prefix("stream/") {
+                if !region.is_empty() && !stream_name.is_empty() {
+                    return Ok(Self {
+                        region: region.to_string(),
+                        account_id: account_id.to_string(),
+                        stream_name: stream_name.to_string(),
+                    });
+                }
+            }
+        }
+        Err(anyhow!(
+            "expect arn:<partition>:kinesis:<region>:<account id>:stream/<stream name>"
+        ))
+    }
+}
+
+/// Fills the stream name and the region from `stream.arn` unless they are set. An explicit region
+/// different from that of the ARN takes precedence with a warning, while a different stream name
+/// is an error. The ARN is cleared once resolved, so that resolving again is a no-op.
+pub fn resolve_stream_arn(mut properties: KinesisProperties) -> Result<KinesisProperties> {
+    let arn = match parse_property::<StreamArn>("stream.arn", properties.stream_arn.as_deref())? {
+        Some(arn) => arn,
+        None => return Ok(properties),
+    };
+    if properties.stream_name.is_empty() {
+        properties.stream_name = arn.stream_name;
+    } else if properties.stream_name != arn.stream_name {
+        return Err(anyhow!(
+            "stream {} does not match the stream {} of stream.arn",
+            properties.stream_name,
+            arn.stream_name
+        ));
+    }
+    if properties.stream_region.is_empty() {
+        properties.stream_region = arn.region;
+    } else if properties.stream_region != arn.region {
+        tracing::warn!(
+            "aws.region {} overrides the region {} of stream.arn, which is expected only when \
+             reaching the stream through an endpoint in another region",
+            properties.stream_region,
+            arn.region
+        );
+    }
+    properties.stream_arn = None;
+    Ok(properties)
+}
+
+/// Parses an optional human readable duration property, e.g. `500ms` or `1m`.
+pub fn parse_duration_property(name: &str, value: Option<&str>) -> Result<Option<Duration>> {
+    value
+        .map(|value| {
+            humantime::parse_duration(value.trim())
+                .map_err(|e| anyhow!("invalid {} '{}': {}", name, value, e))
+        })
+        .transpose()
+}
+
+/// Parses an RFC 3339 timestamp like `2023-01-01T00:00:00.123+08:00` to milliseconds since epoch.
+pub fn parse_rfc3339_millis(name: &str, value: &str) -> Result<i64> {
+    chrono::DateTime::parse_from_rfc3339(value.trim())
+        .map(|datetime| datetime.timestamp_millis())
+        .map_err(|e| {
+            anyhow!(
+                "invalid {} '{}', expect an RFC 3339 timestamp like 2023-01-01T00:00:00Z: {}",
+                name,
+                value,
+                e
+            )
+        })
+}
+
+/// Whether `scan.startup.mode` is `tail`, which reads the last `scan.tail.records` records of each
+/// shard.
+pub fn is_tail_mode(properties: &KinesisProperties) -> bool {
+    properties
+        .scan_startup_mode
+        .as_deref()
+        .map_or(false, |mode| mode.eq_ignore_ascii_case("tail"))
+}
+
+/// Returns whether the source reads from a startup timestamp up to the tip and stops.
+pub fn is_backfill_mode(properties: &KinesisProperties) -> bool {
+    properties
+        .scan_startup_mode
+        .as_deref()
+        .map_or(false, |mode| mode.eq_ignore_ascii_case("backfill"))
+}
+
+/// What the multi split reader does when one of its shards fails.
+#[derive(Copy, Default, Debug, Clone, PartialEq, Eq)]
+pub enum ShardErrorPolicy {
+    /// Fail the whole reader.
+    #[default]
+    FailAll,
+    /// Log the error and stop reading the failed shard, while the others keep flowing.
+    DropShard,
+    /// Re-acquire the shard iterator after a backoff and keep reading the shard.
+    Retry,
+}
+
+impl FromStr for ShardErrorPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("fail_all") {
+            Ok(Self::FailAll)
+        } else if s.eq_ignore_ascii_case("drop_shard") {
+            Ok(Self::DropShard)
+        } else if s.eq_ignore_ascii_case("retry") {
+            Ok(Self::Retry)
+        } else {
+            Err(anyhow!("expect one of fail_all, drop_shard or retry"))
+        }
+    }
+}
+
+/// What the enumerator does when the shards of some of its streams fail to be listed, see
+/// `enumerate.partial.failure`.
+#[derive(Copy, Default, Debug, Clone, PartialEq, Eq)]
+pub enum StreamErrorPolicy {
+    /// Fail the whole enumeration.
+    #[default]
+    FailAll,
+    /// Report the errors and enumerate the other streams.
+    SkipFailed,
+    /// List the failed streams again after a backoff, a few times, before failing the whole
+    /// enumeration on those still failing, e.g. while a stream is briefly `UPDATING`.
+    RetryFailed,
+}
+
+impl FromStr for StreamErrorPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("fail_all") {
+            Ok(Self::FailAll)
+        } else if s.eq_ignore_ascii_case("skip_failed") || s.eq_ignore_ascii_case("skip") {
+            Ok(Self::SkipFailed)
+        } else if s.eq_ignore_ascii_case("retry_failed") {
+            Ok(Self::RetryFailed)
+        } else {
+            Err(anyhow!(
+                "expect one of fail_all, skip_failed or retry_failed"
+            ))
+        }
+    }
+}
+
+/// What the enumerator does when a stream has no shards to list.
+#[derive(Copy, Default, Debug, Clone, PartialEq, Eq)]
+pub enum NoShardsPolicy {
+    /// Fail to list the stream, subject to `enumerate.partial.failure`.
+    #[default]
+    Error,
+    /// Warn and list the stream again at the next enumeration.
+    Retry,
+}
+
+impl FromStr for NoShardsPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("error") {
+            Ok(Self::Error)
+        } else if s.eq_ignore_ascii_case("retry") {
+            Ok(Self::Retry)
+        } else {
+            Err(anyhow!("expect one of error or retry"))
+        }
+    }
+}
+
+/// What a reader does when it is assigned more shards than `max.shards.per.reader`.
+#[derive(Copy, Default, Debug, Clone, PartialEq, Eq)]
+pub enum ShardCapPolicy {
+    /// Fail to create the reader, so that the scheduler distributes the shards to more readers.
+    #[default]
+    Error,
+    /// Log a warning and read all the shards anyway.
+    Warn,
+}
+
+impl FromStr for ShardCapPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("error") {
+            Ok(Self::Error)
+        } else if s.eq_ignore_ascii_case("warn") {
+            Ok(Self::Warn)
+        } else {
+            Err(anyhow!("expect one of error or warn"))
+        }
+    }
+}
+
+/// How the reader validates `GetRecords` responses, see `get_records.validation`.
+#[derive(Copy, Default, Debug, Clone, PartialEq, Eq)]
+pub enum ResponseValidation {
+    /// Trust the responses.
+    #[default]
+    Off,
+    /// Log the violations and read the records anyway.
+    Warn,
+    /// Fail the shard on any violation.
+    Strict,
+}
+
+impl FromStr for ResponseValidation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("off") {
+            Ok(Self::Off)
+        } else if s.eq_ignore_ascii_case("warn") {
+            Ok(Self::Warn)
+        } else if s.eq_ignore_ascii_case("strict") {
+            Ok(Self::Strict)
+        } else {
+            Err(anyhow!("expect one of off, warn or strict"))
+        }
+    }
+}
+
+/// What the reader does with records of empty payloads, e.g. keep-alives written by producers,
+/// see `on_empty_payload`.
+#[derive(Copy, Default, Debug, Clone, PartialEq, Eq)]
+pub enum EmptyPayloadPolicy {
+    /// Emit them as messages of empty payloads.
+    #[default]
+    Emit,
+    /// Drop them, advancing the offset past them.
+    Skip,
+    /// Emit them as heartbeats without payloads, which advance the watermark to their arrival
+    /// timestamps.
+    Heartbeat,
+}
+
+impl FromStr for EmptyPayloadPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("emit") {
+            Ok(Self::Emit)
+        } else if s.eq_ignore_ascii_case("skip") {
+            Ok(Self::Skip)
+        } else if s.eq_ignore_ascii_case("heartbeat") {
+            Ok(Self::Heartbeat)
+        } else {
+            Err(anyhow!("expect one of emit, skip or heartbeat"))
+        }
+    }
+}
+
+/// What the reader does with a record starting with the KPL magic number whose aggregation framing
+/// is malformed, e.g. truncated or failing its MD5 digest, see `on_corrupted_aggregate`.
+#[derive(Copy, Default, Debug, Clone, PartialEq, Eq)]
+pub enum CorruptedAggregatePolicy {
+    /// Fail the shard.
+    #[default]
+    Fail,
+    /// Emit the record as a single plain record, transformed by the other payload transforms.
+    Passthrough,
+    /// Hand the record off to the dead-letter sink of the reader, advancing the offset past it.
+    DeadLetter,
+}
+
+impl FromStr for CorruptedAggregatePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("fail") {
+            Ok(Self::Fail)
+        } else if s.eq_ignore_ascii_case("passthrough") {
+            Ok(Self::Passthrough)
+        } else if s.eq_ignore_ascii_case("dead_letter") {
+            Ok(Self::DeadLetter)
+        } else {
+            Err(anyhow!("expect one of fail, passthrough or dead_letter"))
+        }
+    }
+}
+
+/// What happens to a child shard whose parent shards are missing from its lineage, e.g. trimmed
+/// after the retention period, so that they can not be read before it, see `on_missing_parent`.
+#[derive(Copy, Default, Debug, Clone, PartialEq, Eq)]
+pub enum MissingParentPolicy {
+    /// Read the child anyway, accepting that the records of a partition key may be out of order.
+    Proceed,
+    /// Fail the reader.
+    Fail,
+    /// Log a warning and read the child anyway.
+    #[default]
+    Warn,
+}
+
+impl FromStr for MissingParentPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("proceed") {
+            Ok(Self::Proceed)
+        } else if s.eq_ignore_ascii_case("fail") {
+            Ok(Self::Fail)
+        } else if s.eq_ignore_ascii_case("warn") {
+            Ok(Self::Warn)
+        } else {
+            Err(anyhow!("expect one of proceed, fail or warn"))
+        }
+    }
+}
+
+/// The service behind the endpoint, which toggles compatibility shims for the quirks of
+/// Kinesis-compatible services, see `endpoint.flavor`.
+#[derive(Copy, Default, Debug, Clone, PartialEq, Eq)]
+pub enum EndpointFlavor {
+    #[default]
+    Aws,
+    /// Localstack, which may omit `Shards` or return an empty `NextToken` on the last page of
+    /// `ListShards`, and may omit `MillisBehindLatest` from `GetRecords`.
+    Localstack,
+}
+
+impl EndpointFlavor {
+    /// Whether a `ListShards` page without shards or with an empty next token ends the listing,
+    /// instead of failing it.
+    pub fn lenient_pagination(&self) -> bool {
+        *self == Self::Localstack
+    }
+
+    /// Whether a `GetRecords` response without `MillisBehindLatest` means the reader is caught
+    /// up, instead of the lag being unknown.
+    pub fn absent_lag_is_zero(&self) -> bool {
+        *self == Self::Localstack
+    }
+}
+
+impl FromStr for EndpointFlavor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("aws") {
+            Ok(Self::Aws)
+        } else if s.eq_ignore_ascii_case("localstack") {
+            Ok(Self::Localstack)
+        } else {
+            Err(anyhow!("expect one of aws or localstack"))
+        }
+    }
+}
+
+/// When the readers acquire the shard iterators, see `iterator.acquisition`.
+#[derive(Copy, Default, Debug, Clone, PartialEq, Eq)]
+pub enum IteratorAcquisition {
+    /// On the first poll of each shard, which spreads the `GetShardIterator` calls.
+    #[default]
+    Lazy,
+    /// For all the shards up front when the reader is created, with bounded concurrency.
+    Eager,
+}
+
+impl FromStr for IteratorAcquisition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("lazy") {
+            Ok(Self::Lazy)
+        } else if s.eq_ignore_ascii_case("eager") {
+            Ok(Self::Eager)
+        } else {
+            Err(anyhow!("expect one of lazy or eager"))
+        }
+    }
+}
+
+/// How records are consumed from Kinesis. Only [`ConsumerMode::Polling`] is supported by the
+/// reader so far.
+#[derive(Copy, Default, Debug, Clone, PartialEq, Eq)]
+pub enum ConsumerMode {
+    /// Poll with `GetRecords`, sharing the read throughput of each shard with other consumers.
+    #[default]
+    Polling,
+    /// Enhanced fan-out, where a registered consumer gets dedicated read throughput.
+    FanOut,
+    /// Select fan-out or polling per stream.
+    Auto,
+}
+
+impl FromStr for ConsumerMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("polling") {
+            Ok(Self::Polling)
+        } else if s.eq_ignore_ascii_case("efo") {
+            Ok(Self::FanOut)
+        } else if s.eq_ignore_ascii_case("auto") {
+            Ok(Self::Auto)
+        } else {
+            Err(anyhow!("expect one of polling, efo or auto"))
+        }
+    }
+}
+
+/// How the sequence numbers of emitted messages are persisted in the split state.
+#[derive(Copy, Default, Debug, Clone, PartialEq, Eq)]
+pub enum SequenceNumberFormat {
+    /// The decimal string returned by Kinesis.
+    #[default]
+    String,
+    /// The packed format of [`pack_sequence_number`], which is shorter.
+    Packed,
+}
+
+impl SequenceNumberFormat {
+    /// Formats the sequence number as the offset of a message.
+    pub fn format_offset(&self, sequence_number: String) -> Result<String> {
+        match self {
+            Self::String => Ok(sequence_number),
+            Self::Packed => Ok(format!(
+                "{}{}",
+                PACKED_OFFSET_PREFIX,
+                pack_sequence_number(&sequence_number)?
+            )),
+        }
+    }
+}
+
+impl FromStr for SequenceNumberFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("string") {
+            Ok(Self::String)
+        } else if s.eq_ignore_ascii_case("packed") {
+            Ok(Self::Packed)
+        } else {
+            Err(anyhow!("expect one of string or packed"))
+        }
+    }
+}
+
 /// This function provides a minimum configuration for testing kinesis
 pub fn kinesis_demo_properties() -> HashMap<String, String> {
     let properties: HashMap<String, String> = hashmap! {
@@ -141,13 +638,808 @@ pub fn kinesis_demo_properties() -> HashMap<String, String> {
     properties
 }
 
-pub async fn build_client(properties: KinesisProperties) -> Result<Client> {
+/// The app name reported in the user agent of Kinesis API calls, with an optional suffix
+/// configured by `client.user_agent_suffix` for usage attribution.
+pub fn user_agent_app_name(suffix: Option<&str>) -> Result<AppName> {
+    let name = match suffix {
+        Some(suffix) => format!("risingwave-{}-{}", env!("CARGO_PKG_VERSION"), suffix.trim()),
+        None => format!("risingwave-{}", env!("CARGO_PKG_VERSION")),
+    };
+    AppName::new(name.clone()).map_err(|e| anyhow!("invalid user agent app name {}: {}", name, e))
+}
+
+pub async fn build_client_config(properties: KinesisProperties) -> Result<Config> {
+    let app_name = user_agent_app_name(properties.client_user_agent_suffix.as_deref())?;
     let config = AwsConfigInfo::build(properties)?;
     let aws_config = config.load().await?;
-    let mut builder = aws_sdk_kinesis::config::Builder::from(&aws_config);
+    let mut builder = aws_sdk_kinesis::config::Builder::from(&aws_config).app_name(app_name);
     if let Some(endpoint) = &config.endpoint {
-        let uri = endpoint.clone().parse::<Uri>().unwrap();
+        let uri = endpoint
+            .parse::<Uri>()
+            .map_err(|e| anyhow!("invalid endpoint {}: {}", endpoint, e))?;
         builder = builder.endpoint_resolver(aws_smithy_http::endpoint::Endpoint::immutable(uri));
     }
-    Ok(Client::from_conf(builder.build()))
+    Ok(builder.build())
+}
+
+/// The settings of the HTTP connections of the clients. The defaults suit the long-lived HTTP/2
+/// streams of enhanced fan-out, where `SubscribeToShard` pushes up to 2 MB per second per shard
+/// for 5 minutes: pings keep idle connections from being dropped by load balancers and NATs, and
+/// wide windows keep the push from stalling on flow control.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HttpSettings {
+    /// How often HTTP/2 connections are pinged, even when idle.
+    pub keep_alive_interval: Duration,
+    /// How long to wait for the ack of a ping before closing the connection.
+    pub keep_alive_timeout: Duration,
+    /// The initial HTTP/2 flow control window of each stream, in bytes.
+    pub stream_window_size: u32,
+    /// The initial HTTP/2 flow control window of each connection, in bytes.
+    pub connection_window_size: u32,
+    /// The compression asked for the responses of `GetRecords`.
+    pub response_compression: ResponseCompression,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            keep_alive_interval: Duration::from_secs(30),
+            keep_alive_timeout: Duration::from_secs(20),
+            stream_window_size: 4 << 20,
+            connection_window_size: 16 << 20,
+            response_compression: ResponseCompression::None,
+        }
+    }
+}
+
+impl HttpSettings {
+    /// The largest flow control window allowed by HTTP/2.
+    const MAX_WINDOW_SIZE: u32 = (1 << 31) - 1;
+    /// The initial flow control window of HTTP/2, below which the windows can't be set.
+    const MIN_WINDOW_SIZE: u32 = 65_535;
+
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Self> {
+        let default = Self::default();
+        let window_size = |name: &str, value: Option<&str>, default: u32| -> Result<u32> {
+            let size = parse_property(name, value)?.unwrap_or(default);
+            if !(Self::MIN_WINDOW_SIZE..=Self::MAX_WINDOW_SIZE).contains(&size) {
+                return Err(anyhow!(
+                    "invalid {} {}, expect between {} and {}",
+                    name,
+                    size,
+                    Self::MIN_WINDOW_SIZE,
+                    Self::MAX_WINDOW_SIZE
+                ));
+            }
+            Ok(size)
+        };
+        Ok(Self {
+            keep_alive_interval: parse_duration_property(
+                "client.http2.keep_alive.interval",
+                properties.client_keep_alive_interval.as_deref(),
+            )?
+            .unwrap_or(default.keep_alive_interval),
+            keep_alive_timeout: parse_duration_property(
+                "client.http2.keep_alive.timeout",
+                properties.client_keep_alive_timeout.as_deref(),
+            )?
+            .unwrap_or(default.keep_alive_timeout),
+            stream_window_size: window_size(
+                "client.http2.stream_window_size",
+                properties.client_stream_window_size.as_deref(),
+                default.stream_window_size,
+            )?,
+            connection_window_size: window_size(
+                "client.http2.connection_window_size",
+                properties.client_connection_window_size.as_deref(),
+                default.connection_window_size,
+            )?,
+            response_compression: parse_property(
+                "response.compression",
+                properties.response_compression.as_deref(),
+            )?
+            .unwrap_or(default.response_compression),
+        })
+    }
+
+    /// The builder of the hyper client underneath the connector.
+    pub fn hyper_builder(&self) -> hyper::client::Builder {
+        let mut builder = hyper::Client::builder();
+        builder
+            .http2_keep_alive_interval(self.keep_alive_interval)
+            .http2_keep_alive_timeout(self.keep_alive_timeout)
+            .http2_keep_alive_while_idle(true)
+            .http2_initial_stream_window_size(self.stream_window_size)
+            .http2_initial_connection_window_size(self.connection_window_size);
+        builder
+    }
+}
+
+/// The compression of the responses of `GetRecords` asked for by the clients, see
+/// `response.compression`. Payloads compressed by producers are decompressed by the `decompress`
+/// step of `payload.transforms` instead.
+#[derive(Copy, Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ResponseCompression {
+    #[default]
+    None,
+    /// Send `Accept-Encoding: gzip`, and decompress the responses encoded with gzip.
+    Gzip,
+}
+
+impl FromStr for ResponseCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("none") {
+            Ok(Self::None)
+        } else if s.eq_ignore_ascii_case("gzip") {
+            Ok(Self::Gzip)
+        } else {
+            Err(anyhow!("expect one of none or gzip"))
+        }
+    }
+}
+
+/// Builds a client, or returns the one shared in the process if `client.shared` is enabled.
+pub async fn build_client(properties: KinesisProperties) -> Result<Client> {
+    let properties = resolve_stream_arn(properties)?;
+    if parse_property("client.shared", properties.client_shared.as_deref())?.unwrap_or(false) {
+        return shared_client(properties).await;
+    }
+    let http = HttpSettings::from_properties(&properties)?;
+    Ok(client_from_conf(
+        build_client_config(properties).await?,
+        http,
+    ))
+}
+
+/// Builds a client whose requests are counted by
+/// [`count_attempts`](crate::source::kinesis::telemetry::count_attempts).
+pub fn client_from_conf(config: Config, http: HttpSettings) -> Client {
+    Client::from_conf_conn(config, CountingConnector::https(http))
+}
+
+const DEFAULT_BUFFER_CAPACITY: usize = 16;
+const DEFAULT_PREFETCH_DEPTH: usize = 1;
+const MAX_PREFETCH_DEPTH: usize = 16;
+const DEFAULT_MAX_CONSECUTIVE_RENEWS: usize = 10;
+const DEFAULT_DEDUP_WINDOW_SIZE: usize = 10_000;
+const DEFAULT_ITERATOR_ACQUISITION_PARALLELISM: usize = 8;
+const DEFAULT_PROGRESS_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_STREAM_ACTIVE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The settings of the readers, parsed once from the properties, so that the readers only see
+/// typed settings. The shard readers of a multi split reader share them, and build their own state
+/// from them.
+#[derive(Debug, Clone)]
+pub struct ReaderConfig {
+    /// The stream of the splits which do not name theirs.
+    pub stream_name: String,
+    pub retry: RetryConfig,
+    pub batch: BatchConfig,
+    pub validation: ValidationConfig,
+    /// Set by `dedup.id_path`.
+    pub dedup: Option<DedupConfig>,
+    /// Set by `idle.heartbeat.interval`.
+    pub heartbeat_interval: Option<Duration>,
+    pub transforms: Vec<PayloadTransform>,
+    pub framing: PayloadFraming,
+    pub sequence_number_format: SequenceNumberFormat,
+    /// Set by `get_records.target_bytes`.
+    pub adaptive_limit: Option<AdaptiveLimit>,
+    /// Set by `capture.dir`.
+    pub capture_dir: Option<String>,
+    /// Set by `replay.dir`.
+    pub replay_dir: Option<String>,
+    /// Set by `hash_key.range`.
+    pub hash_key_range: Option<HashKeyRange>,
+    /// Set by `checkpoint.min.interval`.
+    pub offset_coalescer: Option<OffsetCoalescer>,
+    /// Set by `read.safety.lag.ms`.
+    pub safety_lag: Option<SafetyLag>,
+    pub clock_skew: ClockSkewCheck,
+    /// Set by `freshness.sla.ms`.
+    pub freshness_sla: Option<FreshnessSla>,
+    /// Set by `stall.watchdog.timeout`.
+    pub stall_timeout: Option<Duration>,
+    /// Set by `startup.skip.to.latest.if.lag.exceeds`.
+    pub skip_to_latest_lag: Option<Duration>,
+    pub progress_report_interval: Duration,
+    /// Set by `preview.truncate.bytes`.
+    pub truncate_bytes: Option<usize>,
+    pub iterator_acquisition: IteratorAcquisition,
+    pub acquisition_parallelism: usize,
+    /// Set by `max.shards.per.reader`.
+    pub max_shards: Option<usize>,
+    pub max_shards_policy: ShardCapPolicy,
+    pub stream_active_timeout: Duration,
+}
+
+impl ReaderConfig {
+    /// Parses the properties resolved by
+    /// [`CanonicalKinesisConfig::resolve`](crate::source::kinesis::canonical::CanonicalKinesisConfig::resolve).
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Self> {
+        let acquisition_parallelism = parse_property::<usize>(
+            "iterator.acquisition.parallelism",
+            properties.iterator_acquisition_parallelism.as_deref(),
+        )?
+        .unwrap_or(DEFAULT_ITERATOR_ACQUISITION_PARALLELISM);
+        if acquisition_parallelism == 0 {
+            return Err(anyhow!(
+                "iterator.acquisition.parallelism should be positive"
+            ));
+        }
+        Ok(Self {
+            stream_name: properties.stream_name.clone(),
+            retry: RetryConfig::from_properties(properties)?,
+            batch: BatchConfig::from_properties(properties)?,
+            validation: ValidationConfig::from_properties(properties)?,
+            dedup: DedupConfig::from_properties(properties)?,
+            heartbeat_interval: parse_duration_property(
+                "idle.heartbeat.interval",
+                properties.idle_heartbeat_interval.as_deref(),
+            )?,
+            transforms: parse_transforms(properties)?,
+            framing: parse_property("payload.framing", properties.payload_framing.as_deref())?
+                .unwrap_or_default(),
+            sequence_number_format: parse_property(
+                "state.sequence_number.format",
+                properties.state_sequence_number_format.as_deref(),
+            )?
+            .unwrap_or_default(),
+            adaptive_limit: AdaptiveLimit::from_properties(properties)?,
+            capture_dir: properties.capture_dir.clone(),
+            replay_dir: properties.replay_dir.clone(),
+            hash_key_range: HashKeyRange::from_properties(properties)?,
+            offset_coalescer: OffsetCoalescer::from_properties(properties)?,
+            safety_lag: SafetyLag::from_properties(properties)?,
+            clock_skew: ClockSkewCheck::from_properties(properties)?,
+            freshness_sla: FreshnessSla::from_properties(properties)?,
+            stall_timeout: parse_duration_property(
+                "stall.watchdog.timeout",
+                properties.stall_watchdog_timeout.as_deref(),
+            )?,
+            skip_to_latest_lag: parse_duration_property(
+                "startup.skip.to.latest.if.lag.exceeds",
+                properties.startup_skip_to_latest_lag.as_deref(),
+            )?,
+            progress_report_interval: parse_duration_property(
+                "progress.report.interval",
+                properties.progress_report_interval.as_deref(),
+            )?
+            .unwrap_or(DEFAULT_PROGRESS_REPORT_INTERVAL),
+            truncate_bytes: parse_property::<usize>(
+                "preview.truncate.bytes",
+                properties.preview_truncate_bytes.as_deref(),
+            )?,
+            iterator_acquisition: parse_property(
+                "iterator.acquisition",
+                properties.iterator_acquisition.as_deref(),
+            )?
+            .unwrap_or_default(),
+            acquisition_parallelism,
+            max_shards: parse_property::<usize>(
+                "max.shards.per.reader",
+                properties.max_shards_per_reader.as_deref(),
+            )?,
+            max_shards_policy: parse_property(
+                "max.shards.per.reader.policy",
+                properties.max_shards_per_reader_policy.as_deref(),
+            )?
+            .unwrap_or_default(),
+            stream_active_timeout: parse_duration_property(
+                "stream.active.timeout",
+                properties.stream_active_timeout.as_deref(),
+            )?
+            .unwrap_or(DEFAULT_STREAM_ACTIVE_TIMEOUT),
+        })
+    }
+}
+
+/// How the readers retry failed calls and react to failing shards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryConfig {
+    /// Set by the `retry.*` properties.
+    pub policy: RetryPolicy,
+    /// Set by `get_records.retry.cache`.
+    pub response_cache: bool,
+    /// Set by `max.consecutive.renews`.
+    pub max_consecutive_renews: usize,
+    /// Set by `on_shard_error`.
+    pub shard_error_policy: ShardErrorPolicy,
+    /// Set by `circuit_breaker.failure_threshold`.
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+}
+
+impl RetryConfig {
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Self> {
+        Ok(Self {
+            policy: RetryPolicy::from_properties(properties)?,
+            response_cache: parse_property(
+                "get_records.retry.cache",
+                properties.get_records_retry_cache.as_deref(),
+            )?
+            .unwrap_or(false),
+            max_consecutive_renews: parse_property::<usize>(
+                "max.consecutive.renews",
+                properties.max_consecutive_renews.as_deref(),
+            )?
+            .unwrap_or(DEFAULT_MAX_CONSECUTIVE_RENEWS),
+            shard_error_policy: parse_property(
+                "on_shard_error",
+                properties.on_shard_error.as_deref(),
+            )?
+            .unwrap_or_default(),
+            circuit_breaker: CircuitBreakerConfig::from_properties(properties)?,
+        })
+    }
+}
+
+/// How the readers buffer and batch the records.
+#[derive(Debug, Clone, Copy)]
+pub struct BatchConfig {
+    /// Set by `buffer.capacity`.
+    pub buffer_capacity: usize,
+    /// Set by `prefetch.depth`.
+    pub prefetch_depth: usize,
+    /// Set by `max.inflight.bytes`.
+    pub max_inflight_bytes: Option<usize>,
+    /// Set by `max.total.records`, the number of records to emit before the reader stops.
+    pub max_total_records: Option<usize>,
+    /// Set by `batch.max_runtime`, how long the reader runs from the first `next`.
+    pub max_runtime: Option<Duration>,
+    /// Set by `batch.window.ms`.
+    pub window: Option<BatchWindow>,
+    /// Set by `max_chunk_records` or `max_chunk_bytes`.
+    pub chunk_splitter: Option<ChunkSplitter>,
+    /// Set by `scan.tail.records` in the tail mode, where only the last records up to the end
+    /// position are emitted.
+    pub tail_records: Option<usize>,
+}
+
+impl BatchConfig {
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Self> {
+        let buffer_capacity =
+            parse_property::<usize>("buffer.capacity", properties.buffer_capacity.as_deref())?
+                .unwrap_or(DEFAULT_BUFFER_CAPACITY);
+        if buffer_capacity == 0 {
+            return Err(anyhow!("buffer.capacity should be positive"));
+        }
+        let prefetch_depth =
+            parse_property::<usize>("prefetch.depth", properties.prefetch_depth.as_deref())?
+                .unwrap_or(DEFAULT_PREFETCH_DEPTH);
+        if prefetch_depth == 0 || prefetch_depth > MAX_PREFETCH_DEPTH {
+            return Err(anyhow!(
+                "prefetch.depth should be between 1 and {}",
+                MAX_PREFETCH_DEPTH
+            ));
+        }
+        let max_inflight_bytes = parse_property::<usize>(
+            "max.inflight.bytes",
+            properties.max_inflight_bytes.as_deref(),
+        )?;
+        if let Some(max) = max_inflight_bytes {
+            if max == 0 || max > u32::MAX as usize {
+                return Err(anyhow!(
+                    "max.inflight.bytes should be positive and at most {}",
+                    u32::MAX
+                ));
+            }
+        }
+        let tail_records = if is_tail_mode(properties) {
+            match parse_property::<usize>(
+                "scan.tail.records",
+                properties.scan_tail_records.as_deref(),
+            )? {
+                Some(n) if n > 0 => Some(n),
+                _ => {
+                    return Err(anyhow!(
+                        "scan.tail.records should be positive in the tail mode"
+                    ))
+                }
+            }
+        } else {
+            None
+        };
+        Ok(Self {
+            buffer_capacity,
+            prefetch_depth,
+            max_inflight_bytes,
+            max_total_records: parse_property::<usize>(
+                "max.total.records",
+                properties.max_total_records.as_deref(),
+            )?,
+            max_runtime: parse_duration_property(
+                "batch.max_runtime",
+                properties.batch_max_runtime.as_deref(),
+            )?,
+            window: BatchWindow::from_properties(properties)?,
+            chunk_splitter: ChunkSplitter::from_properties(properties)?,
+            tail_records,
+        })
+    }
+}
+
+/// How the readers check the responses and records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidationConfig {
+    /// Set by `get_records.validation`.
+    pub response: ResponseValidation,
+    /// Set by `endpoint.flavor`.
+    pub endpoint_flavor: EndpointFlavor,
+    /// Set by `on_empty_payload`.
+    pub empty_payload_policy: EmptyPayloadPolicy,
+    /// Set by `on_corrupted_aggregate`.
+    pub corrupted_aggregate_policy: CorruptedAggregatePolicy,
+}
+
+impl ValidationConfig {
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Self> {
+        Ok(Self {
+            response: parse_property(
+                "get_records.validation",
+                properties.get_records_validation.as_deref(),
+            )?
+            .unwrap_or_default(),
+            endpoint_flavor: parse_property(
+                "endpoint.flavor",
+                properties.endpoint_flavor.as_deref(),
+            )?
+            .unwrap_or_default(),
+            empty_payload_policy: parse_property(
+                "on_empty_payload",
+                properties.on_empty_payload.as_deref(),
+            )?
+            .unwrap_or_default(),
+            corrupted_aggregate_policy: parse_property(
+                "on_corrupted_aggregate",
+                properties.on_corrupted_aggregate.as_deref(),
+            )?
+            .unwrap_or_default(),
+        })
+    }
+}
+
+/// How the readers drop the records duplicating recent ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DedupConfig {
+    /// A JSON pointer to the id of the records, e.g. `/order/id`, set by `dedup.id_path`.
+    pub id_path: String,
+    /// The number of recent ids remembered, set by `dedup.window.size`.
+    pub window_size: usize,
+}
+
+impl DedupConfig {
+    /// Returns `None` if `dedup.id_path` is not set, which disables deduplication.
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Option<Self>> {
+        let id_path = match &properties.dedup_id_path {
+            Some(id_path) => id_path.clone(),
+            None => return Ok(None),
+        };
+        if !id_path.starts_with('/') {
+            return Err(anyhow!(
+                "dedup.id_path should be a JSON pointer like /id, got '{}'",
+                id_path
+            ));
+        }
+        let window_size =
+            parse_property::<usize>("dedup.window.size", properties.dedup_window_size.as_deref())?
+                .unwrap_or(DEFAULT_DEDUP_WINDOW_SIZE);
+        if window_size == 0 {
+            return Err(anyhow!("dedup.window.size should be positive"));
+        }
+        Ok(Some(Self {
+            id_path,
+            window_size,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use wiremock::matchers::{header, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::source::kinesis::test_utils::{
+        capture_events, get_records_output, mock_properties, mock_record,
+    };
+
+    #[test]
+    fn test_reader_config() -> Result<()> {
+        let config = ReaderConfig::from_properties(&KinesisProperties {
+            stream_name: "stream".to_string(),
+            retry_max_attempts: Some("5".to_string()),
+            get_records_retry_cache: Some("true".to_string()),
+            on_shard_error: Some("drop_shard".to_string()),
+            buffer_capacity: Some("4".to_string()),
+            batch_max_runtime: Some("1m".to_string()),
+            get_records_validation: Some("off".to_string()),
+            dedup_id_path: Some("/id".to_string()),
+            ..Default::default()
+        })?;
+        assert_eq!(config.stream_name, "stream");
+        assert_eq!(config.retry.policy.max_attempts, 5);
+        assert!(config.retry.response_cache);
+        assert_eq!(config.retry.shard_error_policy, ShardErrorPolicy::DropShard);
+        assert_eq!(config.batch.buffer_capacity, 4);
+        assert_eq!(config.batch.prefetch_depth, DEFAULT_PREFETCH_DEPTH);
+        assert_eq!(config.batch.max_runtime, Some(Duration::from_secs(60)));
+        assert_eq!(config.validation.response, ResponseValidation::Off);
+        assert_eq!(
+            config.dedup,
+            Some(DedupConfig {
+                id_path: "/id".to_string(),
+                window_size: DEFAULT_DEDUP_WINDOW_SIZE,
+            })
+        );
+
+        for (properties, name) in [
+            (
+                KinesisProperties {
+                    buffer_capacity: Some("0".to_string()),
+                    ..Default::default()
+                },
+                "buffer.capacity",
+            ),
+            (
+                KinesisProperties {
+                    max_inflight_bytes: Some("0".to_string()),
+                    ..Default::default()
+                },
+                "max.inflight.bytes",
+            ),
+            (
+                KinesisProperties {
+                    dedup_id_path: Some("id".to_string()),
+                    ..Default::default()
+                },
+                "dedup.id_path",
+            ),
+            (
+                KinesisProperties {
+                    scan_startup_mode: Some("tail".to_string()),
+                    ..Default::default()
+                },
+                "scan.tail.records",
+            ),
+        ] {
+            let err = ReaderConfig::from_properties(&properties).unwrap_err();
+            assert!(err.to_string().contains(name), "{}", err);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rfc3339_millis() {
+        assert_eq!(
+            parse_rfc3339_millis("ts", "2023-01-01T00:00:00Z").unwrap(),
+            1672531200000
+        );
+        assert_eq!(
+            parse_rfc3339_millis("ts", "2023-01-01T00:00:00.123Z").unwrap(),
+            1672531200123
+        );
+        assert_eq!(
+            parse_rfc3339_millis("ts", "2023-01-01T08:00:00+08:00").unwrap(),
+            1672531200000
+        );
+        assert_eq!(
+            parse_rfc3339_millis("ts", "2022-12-31T19:00:00.5-05:00").unwrap(),
+            1672531200500
+        );
+        assert!(parse_rfc3339_millis("ts", "2023-01-01").is_err());
+        assert!(parse_rfc3339_millis("ts", "1672531200").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_stream_arn() -> Result<()> {
+        let arn = "arn:aws:kinesis:eu-west-1:123456789012:stream/orders";
+        let (events, _guard) = capture_events();
+
+        // ARN only.
+        let properties = resolve_stream_arn(KinesisProperties {
+            stream_arn: Some(arn.to_string()),
+            ..Default::default()
+        })?;
+        assert_eq!(properties.stream_name, "orders");
+        assert_eq!(properties.stream_region, "eu-west-1");
+        assert_eq!(
+            resolve_stream_arn(properties.clone())?.stream_region,
+            "eu-west-1"
+        );
+        let config = build_client_config(KinesisProperties {
+            stream_arn: Some(arn.to_string()),
+            credentials_access_key: Some("access_key".to_string()),
+            credentials_secret_access_key: Some("secret_key".to_string()),
+            ..Default::default()
+        })
+        .await?;
+        assert_eq!(config.region().unwrap().as_ref(), "eu-west-1");
+
+        // Explicit region only.
+        let properties = resolve_stream_arn(KinesisProperties {
+            stream_name: "orders".to_string(),
+            stream_region: "us-east-1".to_string(),
+            ..Default::default()
+        })?;
+        assert_eq!(properties.stream_region, "us-east-1");
+        assert!(events.lock().unwrap().is_empty());
+
+        // The explicit region overrides that of the ARN with a warning.
+        let properties = resolve_stream_arn(KinesisProperties {
+            stream_arn: Some(arn.to_string()),
+            stream_region: "us-east-1".to_string(),
+            ..Default::default()
+        })?;
+        assert_eq!(properties.stream_name, "orders");
+        assert_eq!(properties.stream_region, "us-east-1");
+        assert!(events.lock().unwrap()[0].contains("overrides the region eu-west-1"));
+
+        // A different stream name, an invalid ARN, and no region at all.
+        assert!(resolve_stream_arn(KinesisProperties {
+            stream_name: "payments".to_string(),
+            stream_arn: Some(arn.to_string()),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(resolve_stream_arn(KinesisProperties {
+            stream_arn: Some("arn:aws:sqs:eu-west-1:123456789012:orders".to_string()),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(AwsConfigInfo::build(KinesisProperties {
+            stream_name: "orders".to_string(),
+            ..Default::default()
+        })
+        .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_user_agent_suffix() -> Result<()> {
+        let properties = KinesisProperties {
+            stream_name: "stream".to_string(),
+            stream_region: "us-east-1".to_string(),
+            credentials_access_key: Some("access_key".to_string()),
+            credentials_secret_access_key: Some("secret_key".to_string()),
+            ..Default::default()
+        };
+        let config = build_client_config(properties.clone()).await?;
+        let app_name = config.app_name().unwrap().to_string();
+        assert!(app_name.starts_with("risingwave-"));
+
+        let config = build_client_config(KinesisProperties {
+            client_user_agent_suffix: Some("cluster-1".to_string()),
+            ..properties
+        })
+        .await?;
+        let app_name = config.app_name().unwrap().to_string();
+        assert!(app_name.starts_with("risingwave-"));
+        assert!(app_name.ends_with("-cluster-1"));
+
+        assert!(user_agent_app_name(Some("invalid suffix")).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_endpoint() {
+        let err = build_client_config(KinesisProperties {
+            stream_name: "stream".to_string(),
+            stream_region: "us-east-1".to_string(),
+            endpoint: Some("not a uri".to_string()),
+            credentials_access_key: Some("access_key".to_string()),
+            credentials_secret_access_key: Some("secret_key".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("invalid endpoint"));
+    }
+
+    #[test]
+    fn test_http_settings() -> Result<()> {
+        let connector =
+            CountingConnector::https(HttpSettings::from_properties(&KinesisProperties::default())?);
+        assert_eq!(connector.settings(), HttpSettings::default());
+
+        let properties = KinesisProperties {
+            client_keep_alive_interval: Some("10s".to_string()),
+            client_keep_alive_timeout: Some("5s".to_string()),
+            client_stream_window_size: Some("1048576".to_string()),
+            client_connection_window_size: Some("8388608".to_string()),
+            ..Default::default()
+        };
+        let connector = CountingConnector::https(HttpSettings::from_properties(&properties)?);
+        assert_eq!(
+            connector.settings(),
+            HttpSettings {
+                keep_alive_interval: Duration::from_secs(10),
+                keep_alive_timeout: Duration::from_secs(5),
+                stream_window_size: 1 << 20,
+                connection_window_size: 8 << 20,
+                response_compression: ResponseCompression::None,
+            }
+        );
+        let properties = KinesisProperties {
+            response_compression: Some("GZIP".to_string()),
+            ..Default::default()
+        };
+        let connector = CountingConnector::https(HttpSettings::from_properties(&properties)?);
+        assert_eq!(
+            connector.settings().response_compression,
+            ResponseCompression::Gzip
+        );
+        assert!(HttpSettings::from_properties(&KinesisProperties {
+            response_compression: Some("zstd".to_string()),
+            ..Default::default()
+        })
+        .is_err());
+
+        for window_size in ["1024", "4294967295"] {
+            let err = HttpSettings::from_properties(&KinesisProperties {
+                client_stream_window_size: Some(window_size.to_string()),
+                ..Default::default()
+            })
+            .unwrap_err();
+            assert!(
+                err.to_string().contains("client.http2.stream_window_size"),
+                "{}",
+                err
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_response_compression() -> Result<()> {
+        let server = MockServer::start().await;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(
+            get_records_output(vec![mock_record("1", b"payload", 0)], 0)
+                .to_string()
+                .as_bytes(),
+        )?;
+        // Only answers the requests asking for gzip.
+        Mock::given(method("POST"))
+            .and(header("x-amz-target", "Kinesis_20131202.GetRecords"))
+            .and(header("accept-encoding", "gzip"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-encoding", "gzip")
+                    .set_body_raw(encoder.finish()?, "application/x-amz-json-1.1"),
+            )
+            .mount(&server)
+            .await;
+
+        let client = build_client(KinesisProperties {
+            response_compression: Some("gzip".to_string()),
+            ..mock_properties(&server)
+        })
+        .await?;
+        let output = client
+            .get_records()
+            .shard_iterator("mock_shard_iterator")
+            .send()
+            .await?;
+        let records = output.records().unwrap_or_default();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].sequence_number(), Some("1"));
+        assert_eq!(records[0].data().unwrap().as_ref(), b"payload");
+
+        // Without the property, no compression is asked for.
+        let client = build_client(mock_properties(&server)).await?;
+        assert!(client
+            .get_records()
+            .shard_iterator("mock_shard_iterator")
+            .send()
+            .await
+            .is_err());
+        Ok(())
+    }
 }
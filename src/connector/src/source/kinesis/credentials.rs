@@ -0,0 +1,83 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use aws_sdk_kinesis::types::SdkError;
+use aws_sdk_kinesis::Client as KinesisClient;
+use aws_smithy_types::retry::ProvideErrorKind;
+
+use crate::source::kinesis::client_cache::refresh_shared_client;
+use crate::source::kinesis::config::{
+    build_client_config, client_from_conf, parse_property, HttpSettings,
+};
+use crate::source::kinesis::KinesisProperties;
+
+/// The error codes of calls signed with expired credentials, e.g. the temporary credentials of an
+/// assumed role past their expiration. `UnrecognizedClientException` is left out: it is returned
+/// for an invalid access key, which fresh credentials from the same configuration do not fix, so it
+/// fails the call at once.
+const EXPIRED_CREDENTIALS_CODES: &[&str] = &["ExpiredTokenException", "ExpiredToken"];
+
+/// Returns whether the call failed as its credentials expired, which a client with fresh
+/// credentials may retry.
+pub fn is_expired_credentials<E: ProvideErrorKind>(e: &SdkError<E>) -> bool {
+    match e {
+        SdkError::ServiceError { err, .. } => err
+            .code()
+            .map_or(false, |code| EXPIRED_CREDENTIALS_CODES.contains(&code)),
+        _ => false,
+    }
+}
+
+/// Provides a client with fresh credentials to replace one whose credentials expired.
+#[async_trait]
+pub trait ClientRefresher: Debug + Send + Sync {
+    /// Returns a client to replace `stale`, whose call was rejected for its expired credentials.
+    async fn refresh(&self, stale: &KinesisClient) -> Result<KinesisClient>;
+}
+
+pub type ClientRefresherRef = Arc<dyn ClientRefresher>;
+
+/// Builds the client again from the connection properties, which reloads the default credential
+/// chain or assumes the role again. A shared client, see `client.shared`, is replaced for the
+/// whole process.
+#[derive(Debug)]
+pub struct PropertiesClientRefresher {
+    properties: KinesisProperties,
+}
+
+impl PropertiesClientRefresher {
+    pub fn new(properties: KinesisProperties) -> Self {
+        Self { properties }
+    }
+}
+
+#[async_trait]
+impl ClientRefresher for PropertiesClientRefresher {
+    async fn refresh(&self, stale: &KinesisClient) -> Result<KinesisClient> {
+        let properties = self.properties.clone();
+        if parse_property("client.shared", properties.client_shared.as_deref())?.unwrap_or(false) {
+            return refresh_shared_client(properties, stale).await;
+        }
+        let http = HttpSettings::from_properties(&properties)?;
+        Ok(client_from_conf(
+            build_client_config(properties).await?,
+            http,
+        ))
+    }
+}
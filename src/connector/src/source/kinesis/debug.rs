@@ -0,0 +1,291 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Helpers for debugging the records of a Kinesis stream, which ingestion does not use.
+
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::time::Duration;
+
+use anyhow::Result;
+use bytes::Bytes;
+use futures::{pin_mut, TryStreamExt};
+
+use crate::source::kinesis::clock::{Clock, TokioClock};
+use crate::source::kinesis::enumerator::client::KinesisSplitEnumerator;
+use crate::source::kinesis::source::message::{ATTR_PARTITION_KEY, ATTR_TRUNCATED_FROM};
+use crate::source::kinesis::source::reader::{KinesisMultiSplitReader, KinesisSplitReader};
+use crate::source::kinesis::KinesisProperties;
+use crate::source::{SourceMessage, SourceMeta, SplitEnumerator, SplitId, SplitImpl, SplitReader};
+
+/// The payload bytes shown by [`follow`] unless `preview.truncate.bytes` is set.
+const DEFAULT_FOLLOW_PREVIEW_BYTES: usize = 256;
+
+/// Emulates looking up the last record of each partition key backwards from the tip, e.g. to
+/// find the last event of a key, which Kinesis can not do since shards are only read forward.
+///
+/// Every shard is read forward from `window` ago up to the tip when called, keeping the last
+/// record of each partition key among those `matches` accepts. It costs as much as reading the
+/// whole window, in `GetRecords` calls and bytes fetched, however few records match, so keep the
+/// window short on busy streams. The records are returned in the order of their arrival.
+pub async fn reverse_scan(
+    properties: KinesisProperties,
+    window: Duration,
+    matches: impl Fn(&SourceMessage) -> bool,
+) -> Result<Vec<SourceMessage>> {
+    let since = TokioClock.now_millis() - window.as_millis() as i64;
+    let properties = KinesisProperties {
+        scan_startup_mode: Some("backfill".to_string()),
+        scan_startup_timestamp_millis: Some(since.to_string()),
+        scan_startup_timestamp: None,
+        scan_startup_relative: None,
+        ..properties
+    };
+    let splits = KinesisSplitEnumerator::new(properties.clone())
+        .await?
+        .list_splits()
+        .await?;
+    let mut latest: HashMap<Bytes, SourceMessage> = HashMap::new();
+    for split in splits {
+        let mut reader = KinesisSplitReader::new(properties.clone(), split).await?;
+        while let Some(chunk) = reader.next().await? {
+            // Heartbeats and shard ends have no payloads.
+            for msg in chunk.into_iter().filter(|msg| msg.payload.is_some()) {
+                let key = match msg.attributes.get(ATTR_PARTITION_KEY) {
+                    Some(key) if matches(&msg) => key.clone(),
+                    _ => continue,
+                };
+                // A key moves to the children of a resharded shard, which may be read first.
+                if latest
+                    .get(&key)
+                    .map_or(true, |last| arrival(last) <= arrival(&msg))
+                {
+                    latest.insert(key, msg);
+                }
+            }
+        }
+    }
+    let mut records = latest.into_values().collect::<Vec<_>>();
+    records.sort_by_key(arrival);
+    Ok(records)
+}
+
+/// A record followed by [`follow`], handed to its formatting callback.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FollowedRecord {
+    pub split_id: SplitId,
+    pub sequence_number: String,
+    /// The approximate arrival timestamp in milliseconds.
+    pub arrival_timestamp: Option<i64>,
+    pub partition_key: Option<String>,
+    /// The payload truncated to `preview.truncate.bytes`.
+    pub payload_preview: Bytes,
+    /// The length of the payload before truncation.
+    pub payload_len: usize,
+}
+
+impl FollowedRecord {
+    fn new(msg: SourceMessage) -> Self {
+        let payload_preview = msg.payload.unwrap_or_default();
+        let payload_len = msg
+            .attributes
+            .get(ATTR_TRUNCATED_FROM)
+            .and_then(|len| std::str::from_utf8(len).ok()?.parse().ok())
+            .unwrap_or(payload_preview.len());
+        Self {
+            arrival_timestamp: arrival(&msg),
+            partition_key: msg
+                .attributes
+                .get(ATTR_PARTITION_KEY)
+                .map(|key| String::from_utf8_lossy(key).into_owned()),
+            split_id: msg.split_id,
+            sequence_number: msg.offset,
+            payload_preview,
+            payload_len,
+        }
+    }
+}
+
+/// Follows the records of a stream like `tail -f`, e.g. for a `kinesis tail` command: reads all
+/// its shards continuously from the startup mode, `latest` unless set, and hands each record to
+/// `format` to print it, until `format` breaks. Payloads are truncated to
+/// `preview.truncate.bytes`, 256 bytes unless set. Heartbeats and shard ends are not followed.
+pub async fn follow(
+    properties: KinesisProperties,
+    mut format: impl FnMut(FollowedRecord) -> ControlFlow<()>,
+) -> Result<()> {
+    let properties = KinesisProperties {
+        scan_startup_mode: Some(
+            properties
+                .scan_startup_mode
+                .unwrap_or_else(|| "latest".to_string()),
+        ),
+        preview_truncate_bytes: Some(
+            properties
+                .preview_truncate_bytes
+                .unwrap_or_else(|| DEFAULT_FOLLOW_PREVIEW_BYTES.to_string()),
+        ),
+        ..properties
+    };
+    let splits = KinesisSplitEnumerator::new(properties.clone())
+        .await?
+        .list_splits()
+        .await?
+        .into_iter()
+        .map(SplitImpl::Kinesis)
+        .collect();
+    let messages = KinesisMultiSplitReader::new(properties, Some(splits), None)
+        .await?
+        .messages();
+    pin_mut!(messages);
+    while let Some(msg) = messages.try_next().await? {
+        if msg.payload.is_none() {
+            continue;
+        }
+        if format(FollowedRecord::new(msg)).is_break() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn arrival(msg: &SourceMessage) -> Option<i64> {
+    match &msg.meta {
+        SourceMeta::Kinesis(meta) => meta.timestamp,
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::source::kinesis::test_utils::*;
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_reverse_scan() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(
+            &server,
+            "ListShards",
+            json_response(list_shards_output(&[
+                "shardId-000000000000",
+                "shardId-000000000001",
+            ])),
+        )
+        .await;
+        mount_api_matching(
+            &server,
+            "GetShardIterator",
+            json!({ "ShardIteratorType": "AT_TIMESTAMP" }),
+            ShardIteratorResponder,
+        )
+        .await;
+        let now = TokioClock.now_millis();
+        let record = |seq: &str, key: &str, data: &[u8], ago: i64| {
+            let mut record = mock_record(seq, data, now - ago);
+            record["PartitionKey"] = json!(key);
+            record
+        };
+        let shards = [
+            vec![
+                record("1", "a", b"a1", 50_000),
+                record("2", "b", b"b1", 40_000),
+                record("3", "a", b"a2", 30_000),
+                record("4", "c", b"c1", 20_000),
+            ],
+            vec![
+                record("5", "b", b"b2", 10_000),
+                record("6", "a", b"a0", 60_000),
+            ],
+        ];
+        for (i, records) in shards.into_iter().enumerate() {
+            mount_api_matching(
+                &server,
+                "GetRecords",
+                json!({ "ShardIterator": format!("shardId-00000000000{}/0", i) }),
+                json_response(json!({ "Records": records, "MillisBehindLatest": 0 })),
+            )
+            .await;
+        }
+
+        let records = reverse_scan(
+            mock_properties(&server),
+            Duration::from_secs(60 * 60),
+            |msg| msg.payload.as_deref() != Some(b"c1".as_slice()),
+        )
+        .await?;
+        let payloads = records
+            .iter()
+            .map(|msg| msg.payload.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(payloads, vec![b"a2".as_slice(), b"b2".as_slice()]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_follow() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        let shard_ids = ["shardId-000000000000", "shardId-000000000001"];
+        mount_api(
+            &server,
+            "ListShards",
+            json_response(list_shards_output(&shard_ids)),
+        )
+        .await;
+        mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+        mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+        let properties = KinesisProperties {
+            preview_truncate_bytes: Some("3".to_string()),
+            ..mock_properties(&server)
+        };
+
+        let mut followed = vec![];
+        follow(properties, |record| {
+            followed.push(record);
+            if followed.len() < 10 {
+                ControlFlow::Continue(())
+            } else {
+                ControlFlow::Break(())
+            }
+        })
+        .await?;
+        assert_eq!(followed.len(), 10);
+        for shard_id in shard_ids {
+            let sequence_numbers = followed
+                .iter()
+                .filter(|record| record.split_id.as_str() == shard_id)
+                .map(|record| record.sequence_number.parse::<u64>().unwrap())
+                .collect::<Vec<_>>();
+            assert!(!sequence_numbers.is_empty());
+            assert!(sequence_numbers
+                .iter()
+                .zip(1..)
+                .all(|(sequence_number, expected)| *sequence_number == expected));
+        }
+        for record in &followed {
+            assert_eq!(record.arrival_timestamp, Some(0));
+            assert_eq!(record.partition_key.as_deref(), Some("mock_partition_key"));
+            assert_eq!(record.payload_preview, Bytes::from_static(b"pay"));
+            assert_eq!(record.payload_len, b"payload".len());
+        }
+        let bodies = received_bodies(&server, "GetShardIterator").await;
+        assert!(bodies
+            .iter()
+            .all(|body| body["ShardIteratorType"] == "LATEST"));
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Validates the configuration of a Kinesis source end to end without ingesting, for
+//! `CREATE SOURCE ... WITH (dry_run = 'true')`.
+
+use std::fmt::{Display, Formatter};
+
+use aws_sdk_kinesis::model::{ShardIteratorType, StreamStatus};
+use aws_sdk_kinesis::types::SdkError;
+use aws_smithy_types::retry::ProvideErrorKind;
+use aws_types::credentials::ProvideCredentials;
+
+use crate::source::kinesis::config::{resolve_stream_arn, AwsConfigInfo};
+use crate::source::kinesis::{build_client, KinesisProperties};
+
+/// Error codes with which the service rejects the credentials of a request.
+const CREDENTIALS_ERROR_CODES: &[&str] = &[
+    "UnrecognizedClientException",
+    "InvalidSignatureException",
+    "ExpiredTokenException",
+    "IncompleteSignature",
+    "MissingAuthenticationToken",
+];
+
+/// The checks of a dry run, in the order they run.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum DryRunCheck {
+    /// The credentials are resolved and accepted by the service.
+    Credentials,
+    /// The stream exists and is readable, by `DescribeStreamSummary`.
+    Stream,
+    /// The stream has shards, by `ListShards`.
+    Shards,
+    /// A shard iterator is acquired for the first shard, by `GetShardIterator`.
+    ShardIterator,
+    /// A single record is fetched from the first shard, by `GetRecords`.
+    Fetch,
+}
+
+const CHECKS: [DryRunCheck; 5] = [
+    DryRunCheck::Credentials,
+    DryRunCheck::Stream,
+    DryRunCheck::Shards,
+    DryRunCheck::ShardIterator,
+    DryRunCheck::Fetch,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckOutcome {
+    Passed(String),
+    Failed(String),
+    /// Not run because an earlier check failed.
+    Skipped,
+}
+
+/// The outcome of every check of a dry run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DryRunReport {
+    pub checks: Vec<(DryRunCheck, CheckOutcome)>,
+}
+
+impl DryRunReport {
+    pub fn passed(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|(_, outcome)| matches!(outcome, CheckOutcome::Passed(_)))
+    }
+
+    pub fn outcome(&self, check: DryRunCheck) -> Option<&CheckOutcome> {
+        self.checks
+            .iter()
+            .find(|(c, _)| *c == check)
+            .map(|(_, outcome)| outcome)
+    }
+
+    fn pass(&mut self, check: DryRunCheck, detail: impl Into<String>) {
+        self.checks
+            .push((check, CheckOutcome::Passed(detail.into())));
+    }
+}
+
+impl Display for DryRunReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (check, outcome) in &self.checks {
+            match outcome {
+                CheckOutcome::Passed(detail) => writeln!(f, "{:?}: passed, {}", check, detail)?,
+                CheckOutcome::Failed(detail) => writeln!(f, "{:?}: failed, {}", check, detail)?,
+                CheckOutcome::Skipped => writeln!(f, "{:?}: skipped", check)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Describes a failed API call by its error code, which the generic message of [`SdkError`]
+/// omits.
+fn describe_error<E: ProvideErrorKind + std::error::Error>(e: &SdkError<E>) -> String {
+    match e {
+        SdkError::ServiceError { err, .. } => {
+            format!("{}: {}", err.code().unwrap_or("unknown error"), err)
+        }
+        e => e.to_string(),
+    }
+}
+
+fn is_credentials_error<E: ProvideErrorKind>(e: &SdkError<E>) -> bool {
+    match e {
+        SdkError::ServiceError { err, .. } => err
+            .code()
+            .map_or(false, |code| CREDENTIALS_ERROR_CODES.contains(&code)),
+        _ => false,
+    }
+}
+
+/// Runs the checks in order until one fails, reporting the rest as skipped.
+pub async fn dry_run(properties: KinesisProperties) -> DryRunReport {
+    let mut report = DryRunReport::default();
+    if let Err((check, detail)) = run_checks(properties, &mut report).await {
+        report.checks.push((check, CheckOutcome::Failed(detail)));
+        report.checks.extend(
+            CHECKS
+                .iter()
+                .skip_while(|c| **c != check)
+                .skip(1)
+                .map(|c| (*c, CheckOutcome::Skipped)),
+        );
+    }
+    report
+}
+
+async fn run_checks(
+    properties: KinesisProperties,
+    report: &mut DryRunReport,
+) -> Result<(), (DryRunCheck, String)> {
+    use DryRunCheck::*;
+
+    let properties = resolve_stream_arn(properties).map_err(|e| (Stream, e.to_string()))?;
+    let stream_name = properties.stream_name.clone();
+    if stream_name.is_empty() {
+        return Err((
+            Stream,
+            "dry_run requires the stream to be named".to_string(),
+        ));
+    }
+    let sdk_config = AwsConfigInfo::build(properties.clone())
+        .map_err(|e| (Credentials, e.to_string()))?
+        .load()
+        .await
+        .map_err(|e| (Credentials, e.to_string()))?;
+    sdk_config
+        .credentials_provider()
+        .ok_or((Credentials, "no credentials provider".to_string()))?
+        .provide_credentials()
+        .await
+        .map_err(|e| (Credentials, e.to_string()))?;
+    let client = build_client(properties)
+        .await
+        .map_err(|e| (Credentials, e.to_string()))?;
+
+    // The credentials are only verified by the service.
+    let summary = client
+        .describe_stream_summary()
+        .stream_name(&stream_name)
+        .send()
+        .await
+        .map_err(|e| {
+            let check = if is_credentials_error(&e) {
+                Credentials
+            } else {
+                Stream
+            };
+            (check, describe_error(&e))
+        })?;
+    report.pass(Credentials, "accepted by the service");
+    let status = summary
+        .stream_description_summary()
+        .and_then(|summary| summary.stream_status())
+        .cloned();
+    match status {
+        Some(status @ (StreamStatus::Creating | StreamStatus::Deleting)) => {
+            return Err((
+                Stream,
+                format!("stream {} is {}", stream_name, status.as_str()),
+            ));
+        }
+        _ => report.pass(Stream, format!("stream {} is readable", stream_name)),
+    }
+
+    let shards = client
+        .list_shards()
+        .stream_name(&stream_name)
+        .send()
+        .await
+        .map_err(|e| (Shards, describe_error(&e)))?;
+    let shard_id = match shards.shards().and_then(|shards| shards.first()) {
+        Some(shard) => shard.shard_id().unwrap_or_default().to_string(),
+        None => return Err((Shards, format!("stream {} has no shards", stream_name))),
+    };
+    report.pass(
+        Shards,
+        format!(
+            "{} shards listed{}",
+            shards.shards().unwrap_or_default().len(),
+            if shards.next_token().is_some() {
+                " on the first page"
+            } else {
+                ""
+            }
+        ),
+    );
+
+    let iterator = client
+        .get_shard_iterator()
+        .stream_name(&stream_name)
+        .shard_id(&shard_id)
+        .shard_iterator_type(ShardIteratorType::TrimHorizon)
+        .send()
+        .await
+        .map_err(|e| (ShardIterator, describe_error(&e)))?;
+    report.pass(ShardIterator, format!("acquired for shard {}", shard_id));
+
+    let records = client
+        .get_records()
+        .set_shard_iterator(iterator.shard_iterator().map(String::from))
+        .limit(1)
+        .send()
+        .await
+        .map_err(|e| (Fetch, describe_error(&e)))?;
+    report.pass(
+        Fetch,
+        format!(
+            "{} records fetched from shard {}",
+            records.records().unwrap_or_default().len(),
+            shard_id
+        ),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::source::kinesis::test_utils::*;
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_dry_run() {
+        let server = wiremock::MockServer::start().await;
+        mount_api(
+            &server,
+            "DescribeStreamSummary",
+            json_response(json!({
+                "StreamDescriptionSummary": {
+                    "StreamName": "mock_stream",
+                    "StreamStatus": "ACTIVE",
+                },
+            })),
+        )
+        .await;
+        mount_api(
+            &server,
+            "ListShards",
+            json_response(list_shards_output(&["shardId-000000000000"])),
+        )
+        .await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(vec![mock_record("1", b"a", 0)], 0)),
+        )
+        .await;
+
+        let report = dry_run(mock_properties(&server)).await;
+        assert!(report.passed(), "{}", report);
+        assert_eq!(report.checks.len(), CHECKS.len());
+        assert_eq!(
+            report.outcome(DryRunCheck::Fetch),
+            Some(&CheckOutcome::Passed(
+                "1 records fetched from shard shardId-000000000000".to_string()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_dry_run_rejected_credentials() {
+        let server = wiremock::MockServer::start().await;
+        mount_api(
+            &server,
+            "DescribeStreamSummary",
+            error_response("UnrecognizedClientException"),
+        )
+        .await;
+
+        let report = dry_run(mock_properties(&server)).await;
+        assert!(!report.passed());
+        assert!(matches!(
+            report.outcome(DryRunCheck::Credentials),
+            Some(CheckOutcome::Failed(detail)) if detail.contains("UnrecognizedClientException")
+        ));
+        for check in &CHECKS[1..] {
+            assert_eq!(report.outcome(*check), Some(&CheckOutcome::Skipped));
+        }
+    }
+}
@@ -12,79 +12,1646 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use anyhow::Result;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use aws_sdk_kinesis::model::Shard;
+use aws_sdk_kinesis::model::{MetricsName, Shard};
 use aws_sdk_kinesis::Client as kinesis_client;
+use futures::stream::{self, Stream, StreamExt};
+use regex::Regex;
 
+use crate::source::kinesis::canonical::CanonicalKinesisConfig;
+use crate::source::kinesis::clock::{Clock, TokioClock};
+use crate::source::kinesis::config::{
+    is_backfill_mode, is_tail_mode, parse_duration_property, parse_property, parse_rfc3339_millis,
+    ConsumerMode, EndpointFlavor, NoShardsPolicy, StreamErrorPolicy,
+};
+use crate::source::kinesis::dry_run::dry_run;
+use crate::source::kinesis::enumerator::report::EnumerationReport;
+use crate::source::kinesis::enumerator::reshard::{ReshardListenerRef, StreamResharded};
+use crate::source::kinesis::error::{enumeration_error, sdk_error, KinesisEnumerationError};
+use crate::source::kinesis::lease::LeaseTable;
+use crate::source::kinesis::manifest::ResumeManifest;
+use crate::source::kinesis::retry::{with_retry, RetryPolicy, SharedBackoff};
 use crate::source::kinesis::split::{KinesisOffset, KinesisSplit};
 use crate::source::kinesis::*;
-use crate::source::SplitEnumerator;
+use crate::source::{SplitEnumerator, SplitId, SplitMetaData};
+
+const DEFAULT_STREAM_DISCOVERY_INTERVAL: Duration = Duration::from_secs(60);
+const DEFAULT_STREAM_DISCOVERY_PARALLELISM: usize = 8;
+const DEFAULT_SHARD_LIMIT_THRESHOLD: f64 = 0.8;
+const DEFAULT_TAIL_LOOKBACK: Duration = Duration::from_secs(5 * 60);
+/// How many times `retry_failed` lists the failed streams again, and the backoff before the first
+/// time, doubled after each.
+const STREAM_RETRY_ATTEMPTS: usize = 3;
+const STREAM_RETRY_BACKOFF: Duration = Duration::from_millis(200);
 
 pub struct KinesisSplitEnumerator {
     stream_name: String,
+    region: String,
     client: kinesis_client,
+    start_offset: KinesisOffset,
+    end_offset: KinesisOffset,
+    /// Set when the streams to consume are discovered by name pattern.
+    stream_pattern: Option<Regex>,
+    discovery_interval: Duration,
+    /// The streams found by the last discovery, and when it happened.
+    discovered_streams: Option<(Instant, Vec<String>)>,
+    /// How many streams to list the shards of concurrently.
+    stream_parallelism: usize,
+    stream_error_policy: StreamErrorPolicy,
+    no_shards_policy: NoShardsPolicy,
+    endpoint_flavor: EndpointFlavor,
+    /// The fraction of the account shard limit above which the preflight check warns. `None` if
+    /// the preflight check is disabled or has already run.
+    shard_limit_threshold: Option<f64>,
+    retry_policy: RetryPolicy,
+    /// The shard-level metrics to enable on the streams. `None` if not configured or they have
+    /// already been enabled.
+    enhanced_monitoring_metrics: Option<Vec<MetricsName>>,
+    /// Whether to stamp the splits starting at `Latest` with their discovery time, see
+    /// `latest.gap.detection`.
+    latest_gap_detection: bool,
+    /// The KCL lease table to start the shards from. `None` if not configured or the shards have
+    /// already been listed.
+    lease_table: Option<LeaseTable>,
+    /// Set by `resume.manifest` until the shards are first listed.
+    resume_manifest: Option<ResumeManifest>,
+    reshard_listener: Option<ReshardListenerRef>,
+    last_report: Option<EnumerationReport>,
+    /// The splits of the last complete listing, to find reshards. `None` before the first one.
+    listed_splits: Option<HashSet<SplitId>>,
 }
 
-impl KinesisSplitEnumerator {}
+/// Parses comma separated shard-level metric names like `IncomingBytes,IncomingRecords`.
+fn parse_shard_level_metrics(value: &str) -> Result<Vec<MetricsName>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| match MetricsName::from(name) {
+            MetricsName::Unknown(_) => Err(anyhow!(
+                "invalid enhanced_monitoring.metrics '{}', expect some of {}",
+                name,
+                MetricsName::values().join(", ")
+            )),
+            metric => Ok(metric),
+        })
+        .collect()
+}
 
-#[async_trait]
-impl SplitEnumerator for KinesisSplitEnumerator {
-    type Properties = KinesisProperties;
-    type Split = KinesisSplit;
+/// Returns a warning if a stream with `stream_shards` shards takes more than `threshold` of the
+/// account level `shard_limit`, which risks throttling.
+fn shard_limit_warning(
+    stream_shards: usize,
+    open_shard_count: i32,
+    shard_limit: i32,
+    threshold: f64,
+) -> Option<String> {
+    if shard_limit <= 0 {
+        return None;
+    }
+    let fraction = stream_shards as f64 / shard_limit as f64;
+    if fraction <= threshold {
+        return None;
+    }
+    Some(format!(
+        "stream has {} shards, {:.0}% of the account shard limit {} ({} shards open in total), \
+         which risks throttling",
+        stream_shards,
+        fraction * 100.0,
+        shard_limit,
+        open_shard_count
+    ))
+}
 
-    async fn new(properties: KinesisProperties) -> Result<Self> {
-        let client = build_client(properties.clone()).await?;
+/// Resolves where new shards start to be consumed from `scan.startup.*` properties.
+fn startup_offset(properties: &KinesisProperties, now_millis: i64) -> Result<KinesisOffset> {
+    let timestamp = match (
+        &properties.scan_startup_timestamp_millis,
+        &properties.scan_startup_timestamp,
+        &properties.scan_startup_relative,
+    ) {
+        (Some(millis), None, None) => Some(
+            parse_property::<i64>("scan.startup.timestamp_millis", Some(millis.as_str()))?.unwrap(),
+        ),
+        (None, Some(timestamp), None) => {
+            Some(parse_rfc3339_millis("scan.startup.timestamp", timestamp)?)
+        }
+        (None, None, Some(relative)) => {
+            let duration =
+                parse_duration_property("scan.startup.relative", Some(relative.as_str()))?.unwrap();
+            Some(now_millis - duration.as_millis() as i64)
+        }
+        (None, None, None) => None,
+        _ => {
+            return Err(anyhow!(
+                "only one of scan.startup.timestamp_millis, scan.startup.timestamp and \
+                 scan.startup.relative can be set"
+            ));
+        }
+    };
+    if let Some(timestamp) = timestamp {
+        return Ok(KinesisOffset::Timestamp(timestamp));
+    }
+    if is_backfill_mode(properties) {
+        return Err(anyhow!(
+            "scan.startup.mode backfill requires one of scan.startup.timestamp_millis, \
+             scan.startup.timestamp and scan.startup.relative"
+        ));
+    }
+
+    match properties
+        .scan_startup_mode
+        .as_ref()
+        .map(|s| s.to_lowercase())
+        .as_deref()
+    {
+        // Left to the reader, which reads unset start positions from the trim horizon.
+        None => Ok(KinesisOffset::None),
+        Some("earliest") => Ok(KinesisOffset::Earliest),
+        Some("latest") => Ok(KinesisOffset::Latest),
+        Some("tail") => {
+            let lookback =
+                parse_duration_property("scan.tail.lookback", properties.scan_tail_lookback.as_deref())?
+                    .unwrap_or(DEFAULT_TAIL_LOOKBACK);
+            Ok(KinesisOffset::Timestamp(
+                now_millis - lookback.as_millis() as i64,
+            ))
+        }
+        _ => Err(anyhow!(
+            "properties `scan.startup.mode` only support earliest, latest, tail and backfill or leave it empty"
+        )),
+    }
+}
+
+impl KinesisSplitEnumerator {
+    /// Creates the enumerator with a client built by the caller instead of from the connection
+    /// properties, e.g. by `Client::new(&sdk_config)` from an `aws_config::SdkConfig` with custom
+    /// credential providers.
+    pub fn new_with_client(properties: KinesisProperties, client: kinesis_client) -> Result<Self> {
+        Self::from_resolved(CanonicalKinesisConfig::resolve(&properties)?, client)
+    }
+
+    /// Creates the enumerator from properties resolved by [`CanonicalKinesisConfig::resolve`].
+    fn from_resolved(properties: KinesisProperties, client: kinesis_client) -> Result<Self> {
+        let stream_pattern = properties
+            .stream_pattern
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| anyhow!("invalid stream.pattern: {}", e))?;
+        if stream_pattern.is_none() && properties.stream_name.is_empty() {
+            return Err(anyhow!(
+                "either stream or stream.pattern should be provided"
+            ));
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        let start_offset = startup_offset(&properties, now)?;
+        // Bounded to the tip when the source starts, i.e. the records have arrived by now.
+        let end_offset =
+            if parse_property("bounded.to_latest", properties.bounded_to_latest.as_deref())?
+                .unwrap_or(false)
+                || is_tail_mode(&properties)
+                || is_backfill_mode(&properties)
+            {
+                KinesisOffset::Timestamp(now)
+            } else {
+                KinesisOffset::None
+            };
+        let discovery_interval = parse_duration_property(
+            "stream.discovery.interval",
+            properties.stream_discovery_interval.as_deref(),
+        )?
+        .unwrap_or(DEFAULT_STREAM_DISCOVERY_INTERVAL);
+        let stream_parallelism = parse_property::<usize>(
+            "stream.discovery.parallelism",
+            properties.stream_discovery_parallelism.as_deref(),
+        )?
+        .unwrap_or(DEFAULT_STREAM_DISCOVERY_PARALLELISM);
+        if stream_parallelism == 0 {
+            return Err(anyhow!("stream.discovery.parallelism should be positive"));
+        }
+        let stream_error_policy = parse_property(
+            "enumerate.partial.failure",
+            properties.enumerate_partial_failure.as_deref(),
+        )?
+        .unwrap_or_default();
+        let no_shards_policy =
+            parse_property("on_no_shards", properties.on_no_shards.as_deref())?.unwrap_or_default();
+        let endpoint_flavor =
+            parse_property("endpoint.flavor", properties.endpoint_flavor.as_deref())?
+                .unwrap_or_default();
+        let check_shard_limit = parse_property(
+            "preflight.check.shard_limit",
+            properties.preflight_check_shard_limit.as_deref(),
+        )?
+        .unwrap_or(false);
+        let shard_limit_threshold = if check_shard_limit {
+            Some(
+                parse_property(
+                    "preflight.shard_limit.threshold",
+                    properties.preflight_shard_limit_threshold.as_deref(),
+                )?
+                .unwrap_or(DEFAULT_SHARD_LIMIT_THRESHOLD),
+            )
+        } else {
+            None
+        };
+
+        // The reader only polls with `GetRecords`, so reject the modes it can not honor rather
+        // than silently polling.
+        match parse_property("consumer.mode", properties.consumer_mode.as_deref())?
+            .unwrap_or_default()
+        {
+            ConsumerMode::Polling => {}
+            mode => {
+                return Err(anyhow!(
+                    "consumer.mode {:?} is not supported yet, expect polling",
+                    mode
+                ));
+            }
+        }
+
+        let enhanced_monitoring_metrics = properties
+            .enhanced_monitoring_metrics
+            .as_deref()
+            .map(parse_shard_level_metrics)
+            .transpose()?;
+
+        let latest_gap_detection = parse_property(
+            "latest.gap.detection",
+            properties.latest_gap_detection.as_deref(),
+        )?
+        .unwrap_or(false);
+
+        let retry_policy = RetryPolicy::from_properties(&properties)?;
         let stream_name = properties.stream_name.clone();
         Ok(Self {
             stream_name,
+            region: properties.stream_region.clone(),
             client,
+            start_offset,
+            end_offset,
+            stream_pattern,
+            discovery_interval,
+            discovered_streams: None,
+            stream_parallelism,
+            stream_error_policy,
+            no_shards_policy,
+            endpoint_flavor,
+            shard_limit_threshold,
+            retry_policy,
+            enhanced_monitoring_metrics,
+            latest_gap_detection,
+            lease_table: None,
+            resume_manifest: ResumeManifest::from_properties(&properties)?,
+            reshard_listener: None,
+            last_report: None,
+            listed_splits: None,
         })
     }
 
-    async fn list_splits(&mut self) -> Result<Vec<KinesisSplit>> {
+    /// Starts the shards from the checkpoints of `lease_table` when they are first listed.
+    pub fn with_lease_table(self, lease_table: LeaseTable) -> Self {
+        Self {
+            lease_table: Some(lease_table),
+            ..self
+        }
+    }
+
+    /// Reports to `reshard_listener` when the splits listed change from the previous listing.
+    pub fn with_reshard_listener(self, reshard_listener: ReshardListenerRef) -> Self {
+        Self {
+            reshard_listener: Some(reshard_listener),
+            ..self
+        }
+    }
+
+    /// Returns which streams the last enumeration listed and which failed, if any ran.
+    pub fn last_report(&self) -> Option<&EnumerationReport> {
+        self.last_report.as_ref()
+    }
+
+    /// Compares the splits listed with the previous listing, and reports the change if any. The
+    /// splits of the streams in `failed` are carried over from the previous listing rather than
+    /// taken as removed.
+    fn detect_reshard(&mut self, splits: &[KinesisSplit], failed: &[String]) {
+        let mut current = splits
+            .iter()
+            .map(|split| split.id())
+            .collect::<HashSet<_>>();
+        if let Some(previous) = &self.listed_splits {
+            current.extend(
+                previous
+                    .iter()
+                    .filter(|id| failed.iter().any(|stream| self.is_split_of(id, stream)))
+                    .cloned(),
+            );
+        }
+        let previous = match self.listed_splits.replace(current) {
+            Some(previous) => previous,
+            None => return,
+        };
+        let current = self.listed_splits.as_ref().unwrap();
+        if let Some(event) = StreamResharded::between(&previous, current, TokioClock.now_millis()) {
+            tracing::info!(
+                added = ?event.added,
+                removed = ?event.removed,
+                "kinesis streams resharded"
+            );
+            if let Some(listener) = &self.reshard_listener {
+                listener.on_resharded(&event);
+            }
+        }
+    }
+
+    /// Whether the split `id` belongs to `stream_name`. The split ids are only qualified by their
+    /// streams when the streams are discovered by pattern, otherwise a single stream is listed.
+    fn is_split_of(&self, id: &SplitId, stream_name: &str) -> bool {
+        match &self.stream_pattern {
+            Some(_) => id
+                .strip_prefix(stream_name)
+                .map_or(false, |shard_id| shard_id.starts_with(':')),
+            None => true,
+        }
+    }
+
+    /// Starts the splits from the checkpoints of their KCL leases, dropping the shards KCL has
+    /// read to their ends.
+    async fn apply_leases(
+        lease_table: &LeaseTable,
+        splits: Vec<KinesisSplit>,
+    ) -> Result<Vec<KinesisSplit>> {
+        let mut leased = Vec::with_capacity(splits.len());
+        for mut split in splits {
+            match lease_table.read(&split.shard_id).await? {
+                Some(lease) => match lease.start_offset() {
+                    Some(offset) => {
+                        split.start_position = offset;
+                        leased.push(split);
+                    }
+                    None => tracing::info!(
+                        "skip kinesis shard {} which kcl has read to its end",
+                        split.shard_id
+                    ),
+                },
+                None => leased.push(split),
+            }
+        }
+        Ok(leased)
+    }
+
+    /// Warns if the stream takes a large fraction of the account shard limit reported by
+    /// `DescribeLimits`.
+    async fn check_shard_limit(
+        &self,
+        stream_shards: usize,
+        threshold: f64,
+    ) -> Result<Option<String>> {
+        let limits = self
+            .client
+            .describe_limits()
+            .send()
+            .await
+            .map_err(sdk_error)?;
+        Ok(shard_limit_warning(
+            stream_shards,
+            limits.open_shard_count().unwrap_or_default(),
+            limits.shard_limit().unwrap_or_default(),
+            threshold,
+        ))
+    }
+
+    /// Enables the shard-level CloudWatch metrics on the stream with `EnableEnhancedMonitoring`,
+    /// returning the metrics enabled after the call.
+    pub async fn enable_enhanced_monitoring(
+        &self,
+        stream_name: &str,
+        shard_level_metrics: &[MetricsName],
+    ) -> Result<Vec<MetricsName>> {
+        let output = self
+            .client
+            .enable_enhanced_monitoring()
+            .stream_name(stream_name)
+            .set_shard_level_metrics(Some(shard_level_metrics.to_vec()))
+            .send()
+            .await
+            .map_err(sdk_error)?;
+        Ok(output
+            .desired_shard_level_metrics()
+            .unwrap_or_default()
+            .to_vec())
+    }
+
+    /// Lists the streams failed in `listed` again under `retry_failed`, until they succeed or the
+    /// attempts run out.
+    async fn retry_failed_streams(
+        &self,
+        streams: &[String],
+        listed: &mut [Result<Vec<Shard>>],
+        backoff: &SharedBackoff,
+    ) {
+        let mut delay = STREAM_RETRY_BACKOFF;
+        for _ in 0..STREAM_RETRY_ATTEMPTS {
+            let failed = (0..streams.len())
+                .filter(|i| listed[*i].is_err())
+                .collect::<Vec<_>>();
+            if failed.is_empty() {
+                return;
+            }
+            tracing::warn!(
+                "list the shards of {} failed kinesis streams again in {:?}",
+                failed.len(),
+                delay
+            );
+            TokioClock.sleep(delay).await;
+            for i in failed {
+                listed[i] = self.list_shards(&streams[i], backoff).await;
+            }
+            delay *= 2;
+        }
+    }
+
+    /// Lists the shards of the stream, backing off together with the other streams listed
+    /// concurrently. A stream without shards is handled by `on_no_shards`.
+    async fn list_shards(&self, stream_name: &str, backoff: &SharedBackoff) -> Result<Vec<Shard>> {
         let mut next_token: Option<String> = None;
         let mut shard_collect: Vec<Shard> = Vec::new();
 
         loop {
-            let list_shard_output = self
+            let (shards, token) = self
+                .list_shards_page(stream_name, next_token, backoff)
+                .await?;
+            shard_collect.extend(shards);
+            match token {
+                Some(token) => next_token = Some(token),
+                None => break,
+            }
+        }
+        if shard_collect.is_empty() {
+            self.on_no_shards(stream_name)?;
+        }
+        Ok(shard_collect)
+    }
+
+    /// Handles a stream listed without any shard according to `on_no_shards`.
+    fn on_no_shards(&self, stream_name: &str) -> Result<()> {
+        match self.no_shards_policy {
+            NoShardsPolicy::Error => Err(KinesisEnumerationError::NoShards {
+                stream: stream_name.to_string(),
+                region: self.region.clone(),
+            }
+            .into()),
+            NoShardsPolicy::Retry => {
+                tracing::warn!(
+                    "kinesis stream {} has no listable shards, list it again at the next \
+                     enumeration",
+                    stream_name
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Lists a page of the shards of the stream starting from `next_token`, returning the token of
+    /// the next page if any.
+    async fn list_shards_page(
+        &self,
+        stream_name: &str,
+        next_token: Option<String>,
+        backoff: &SharedBackoff,
+    ) -> Result<(Vec<Shard>, Option<String>)> {
+        let list_shard_output = with_retry(&self.retry_policy, backoff, || {
+            let next_token = next_token.clone();
+            async move {
+                backoff.wait().await;
+                self.client
+                    .list_shards()
+                    .set_next_token(next_token)
+                    .stream_name(stream_name)
+                    .send()
+                    .await
+            }
+        })
+        .await
+        .map_err(|e| enumeration_error(e, stream_name, &self.region, "kinesis:ListShards"))?;
+        let lenient = self.endpoint_flavor.lenient_pagination();
+        // A missing shard list is taken as a stream without shards, which ends the listing under
+        // lenient pagination and is then handled by `on_no_shards` like any empty listing.
+        let (shards, next_token) = match list_shard_output.shards {
+            Some(shards) => (shards, list_shard_output.next_token),
+            None if lenient => (vec![], None),
+            None => (vec![], list_shard_output.next_token),
+        };
+        let next_token = next_token.filter(|token| !(token.is_empty() && lenient));
+        Ok((shards, next_token))
+    }
+
+    fn new_split(&self, stream_name: &str, shard: &Shard) -> KinesisSplit {
+        // A shard already closed when bounded to the tip stops right at its last record.
+        let ending_sequence_number = shard
+            .sequence_number_range()
+            .and_then(|range| range.ending_sequence_number());
+        let end_offset = match (&self.end_offset, ending_sequence_number) {
+            (KinesisOffset::Timestamp(_), Some(ending)) => {
+                KinesisOffset::SequenceNumber(ending.to_string())
+            }
+            (end_offset, _) => end_offset.clone(),
+        };
+        let mut split = KinesisSplit::new(
+            shard.shard_id().unwrap_or_default().to_string().into(),
+            self.start_offset.clone(),
+            end_offset,
+        );
+        if self.latest_gap_detection && self.start_offset == KinesisOffset::Latest {
+            split.discovered_at = Some(TokioClock.now_millis());
+        }
+        if self.stream_pattern.is_some() {
+            split.with_stream_name(stream_name.to_string())
+        } else {
+            split
+        }
+    }
+
+    /// Lists the splits page by page of `ListShards`, so that only a page of shards is held in
+    /// memory at a time for streams with tens of thousands of shards. Unlike `list_splits`, a
+    /// stream failing to be listed fails the pages, and neither KCL leases nor the preflight
+    /// checks are applied.
+    pub async fn list_split_pages(
+        &mut self,
+    ) -> Result<impl Stream<Item = Result<Vec<KinesisSplit>>> + '_> {
+        let streams = self.streams().await?;
+        let this = &*self;
+        let backoff = Arc::new(SharedBackoff::new(Arc::new(TokioClock)));
+        // The streams left to list, and the stream being listed with the token of its next page.
+        let state: (VecDeque<String>, Option<(String, Option<String>)>) = (streams.into(), None);
+        Ok(stream::try_unfold(state, move |(mut streams, listing)| {
+            let backoff = backoff.clone();
+            async move {
+                let (stream_name, next_token) = match listing {
+                    Some(listing) => listing,
+                    None => match streams.pop_front() {
+                        Some(stream_name) => (stream_name, None),
+                        None => return Ok(None),
+                    },
+                };
+                let first_page = next_token.is_none();
+                let (shards, next_token) = this
+                    .list_shards_page(&stream_name, next_token, &backoff)
+                    .await
+                    .map_err(|e| {
+                        e.context(format!(
+                            "failed to list shards of kinesis stream {}",
+                            stream_name
+                        ))
+                    })?;
+                if first_page && shards.is_empty() && next_token.is_none() {
+                    this.on_no_shards(&stream_name)?;
+                }
+                let splits = shards
+                    .iter()
+                    .map(|shard| this.new_split(&stream_name, shard))
+                    .collect();
+                let listing = next_token.map(|token| (stream_name, Some(token)));
+                Ok(Some((splits, (streams, listing))))
+            }
+        }))
+    }
+
+    /// Lists all streams whose names match `pattern` with `ListStreams`.
+    async fn discover_streams(&self, pattern: &Regex) -> Result<Vec<String>> {
+        let mut streams = Vec::new();
+        let mut exclusive_start_stream_name: Option<String> = None;
+
+        loop {
+            let list_streams_output = self
                 .client
-                .list_shards()
-                .set_next_token(next_token)
-                .stream_name(&self.stream_name)
+                .list_streams()
+                .set_exclusive_start_stream_name(exclusive_start_stream_name.take())
                 .send()
-                .await?;
-            match list_shard_output.shards {
-                Some(shard) => shard_collect.extend(shard),
-                None => {
-                    return Err(anyhow::Error::msg(format!(
-                        "no shards in stream {}",
-                        &self.stream_name
-                    )));
+                .await
+                .map_err(|e| {
+                    enumeration_error(e, pattern.as_str(), &self.region, "kinesis:ListStreams")
+                })?;
+            let stream_names = list_streams_output.stream_names().unwrap_or_default();
+            streams.extend(
+                stream_names
+                    .iter()
+                    .filter(|name| pattern.is_match(name))
+                    .cloned(),
+            );
+
+            match (list_streams_output.has_more_streams(), stream_names.last()) {
+                (Some(true), Some(last)) => exclusive_start_stream_name = Some(last.clone()),
+                _ => break,
+            }
+        }
+        Ok(streams)
+    }
+
+    /// Returns the streams to enumerate, re-discovering them if the last discovery is older than
+    /// the discovery interval.
+    async fn streams(&mut self) -> Result<Vec<String>> {
+        let pattern = match &self.stream_pattern {
+            Some(pattern) => pattern,
+            None => return Ok(vec![self.stream_name.clone()]),
+        };
+        if let Some((discovered_at, streams)) = &self.discovered_streams {
+            if discovered_at.elapsed() < self.discovery_interval {
+                return Ok(streams.clone());
+            }
+        }
+
+        let streams = self.discover_streams(pattern).await?;
+        if streams.is_empty() {
+            tracing::warn!("no kinesis stream matches pattern {}", pattern);
+        }
+        self.discovered_streams = Some((Instant::now(), streams.clone()));
+        Ok(streams)
+    }
+}
+
+#[async_trait]
+impl SplitEnumerator for KinesisSplitEnumerator {
+    type Properties = KinesisProperties;
+    type Split = KinesisSplit;
+
+    async fn new(properties: KinesisProperties) -> Result<Self> {
+        let properties = CanonicalKinesisConfig::resolve(&properties)?;
+        if parse_property("dry_run", properties.dry_run.as_deref())?.unwrap_or(false) {
+            let report = dry_run(properties).await;
+            return Err(anyhow!(
+                "dry run of the kinesis source {}, the source is not created:\n{}",
+                if report.passed() { "passed" } else { "failed" },
+                report
+            ));
+        }
+        let client = build_client(properties.clone()).await?;
+        let lease_table = LeaseTable::from_properties(&properties).await?;
+        let enumerator = Self::from_resolved(properties, client)?;
+        Ok(match lease_table {
+            Some(lease_table) => enumerator.with_lease_table(lease_table),
+            None => enumerator,
+        })
+    }
+
+    async fn list_splits(&mut self) -> Result<Vec<KinesisSplit>> {
+        let mut splits = Vec::new();
+        let enhanced_monitoring_metrics = self.enhanced_monitoring_metrics.take();
+        let streams = self.streams().await?;
+        let backoff = SharedBackoff::new(Arc::new(TokioClock));
+        let mut listed = stream::iter(&streams)
+            .map(|stream_name| self.list_shards(stream_name, &backoff))
+            .buffered(self.stream_parallelism)
+            .collect::<Vec<_>>()
+            .await;
+        if self.stream_error_policy == StreamErrorPolicy::RetryFailed {
+            self.retry_failed_streams(&streams, &mut listed, &backoff)
+                .await;
+        }
+        let report = EnumerationReport::new(&streams, &listed);
+        let failures = report.failed.len();
+        self.last_report = Some(report);
+        for (stream_name, shards) in streams.iter().zip(listed) {
+            let shards = match shards {
+                Ok(shards) => shards,
+                Err(e) => match self.stream_error_policy {
+                    StreamErrorPolicy::FailAll => {
+                        return Err(e.context(format!(
+                            "failed to list shards of kinesis stream {}",
+                            stream_name
+                        )));
+                    }
+                    StreamErrorPolicy::RetryFailed => {
+                        return Err(e.context(format!(
+                            "failed to list shards of kinesis stream {} after {} retries",
+                            stream_name, STREAM_RETRY_ATTEMPTS
+                        )));
+                    }
+                    StreamErrorPolicy::SkipFailed => continue,
+                },
+            };
+            if let Some(metrics) = &enhanced_monitoring_metrics {
+                match self.enable_enhanced_monitoring(stream_name, metrics).await {
+                    Ok(enabled) => tracing::info!(
+                        "enabled shard-level metrics {:?} on kinesis stream {}",
+                        enabled,
+                        stream_name
+                    ),
+                    Err(e) => tracing::warn!(
+                        "failed to enable enhanced monitoring on kinesis stream {}: {}",
+                        stream_name,
+                        e
+                    ),
                 }
             }
+            splits.extend(
+                shards
+                    .iter()
+                    .map(|shard| self.new_split(stream_name, shard)),
+            );
+        }
+        if failures > 0 {
+            let described = self.last_report.as_ref().unwrap().describe_failures();
+            tracing::warn!(
+                "skip {} kinesis streams failed to list shards: {}",
+                failures,
+                described
+            );
+        }
 
-            match list_shard_output.next_token {
-                Some(token) => next_token = Some(token),
-                None => break,
+        // Kept until a listing passes the validation, so that a failed one applies it on retry.
+        if let Some(manifest) = &self.resume_manifest {
+            manifest.validate(&splits)?;
+        }
+        let resume_manifest = self.resume_manifest.take();
+        if let Some(lease_table) = self.lease_table.take() {
+            splits = Self::apply_leases(&lease_table, splits).await?;
+        }
+        if let Some(manifest) = resume_manifest {
+            splits = splits
+                .into_iter()
+                .map(|split| manifest.apply(split))
+                .collect();
+        }
+        // The shards of a stream failing to be listed are not taken as removed.
+        let failed = self
+            .last_report
+            .as_ref()
+            .unwrap()
+            .failed
+            .iter()
+            .map(|(stream_name, _)| stream_name.clone())
+            .collect::<Vec<_>>();
+        self.detect_reshard(&splits, &failed);
+
+        if let Some(threshold) = self.shard_limit_threshold.take() {
+            match self.check_shard_limit(splits.len(), threshold).await {
+                Ok(Some(warning)) => tracing::warn!("{}", warning),
+                Ok(None) => {}
+                Err(e) => tracing::warn!("failed to check kinesis shard limit: {}", e),
             }
         }
-        Ok(shard_collect
-            .into_iter()
-            .map(|x| KinesisSplit {
-                shard_id: x.shard_id().unwrap_or_default().to_string().into(),
-                start_position: KinesisOffset::None,
-                end_position: KinesisOffset::None,
-            })
-            .collect())
+        Ok(splits)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use aws_sdk_kinesis::Region;
+    use futures::TryStreamExt;
+    use serde_json::json;
 
     use super::*;
+    use crate::source::kinesis::enumerator::reshard::ReshardListener;
+    use crate::source::kinesis::source::reader::KinesisSplitReader;
+    use crate::source::kinesis::test_utils::*;
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_discover_streams_by_pattern() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(
+            &server,
+            "ListStreams",
+            json_response(json!({
+                "StreamNames": ["events-20230101", "audit", "events-20230102"],
+                "HasMoreStreams": false,
+            })),
+        )
+        .await;
+        mount_api(
+            &server,
+            "ListShards",
+            json_response(list_shards_output(&["shardId-000000000000"])),
+        )
+        .await;
+
+        let properties = KinesisProperties {
+            stream_name: String::new(),
+            stream_pattern: Some("^events-\\d+$".to_string()),
+            ..mock_properties(&server)
+        };
+        let mut enumerator = KinesisSplitEnumerator::new(properties).await?;
+        let splits = enumerator.list_splits().await?;
+
+        assert!(splits
+            .iter()
+            .all(|split| split.end_position == KinesisOffset::None));
+        let streams = splits
+            .iter()
+            .map(|split| split.stream_name.clone().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(streams, vec!["events-20230101", "events-20230102"]);
+        assert_ne!(splits[0].id(), splits[1].id());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_stream_error_policy() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(
+            &server,
+            "ListStreams",
+            json_response(json!({
+                "StreamNames": ["events-a", "events-b", "events-c", "events-d"],
+                "HasMoreStreams": false,
+            })),
+        )
+        .await;
+        mount_api_matching(
+            &server,
+            "ListShards",
+            json!({ "StreamName": "events-b" }),
+            error_response("AccessDeniedException"),
+        )
+        .await;
+        mount_api(
+            &server,
+            "ListShards",
+            json_response(list_shards_output(&["shardId-000000000000"])),
+        )
+        .await;
+        let properties = KinesisProperties {
+            stream_name: String::new(),
+            stream_pattern: Some("^events-".to_string()),
+            stream_discovery_parallelism: Some("2".to_string()),
+            ..mock_properties(&server)
+        };
+
+        let mut enumerator = KinesisSplitEnumerator::new(properties.clone()).await?;
+        let err = enumerator.list_splits().await.unwrap_err();
+        assert!(err.to_string().contains("events-b"), "{}", err);
+
+        // The failed stream is skipped while the others are enumerated.
+        let mut enumerator = KinesisSplitEnumerator::new(KinesisProperties {
+            enumerate_partial_failure: Some("skip_failed".to_string()),
+            ..properties
+        })
+        .await?;
+        let streams = enumerator
+            .list_splits()
+            .await?
+            .into_iter()
+            .map(|split| split.stream_name.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(streams, vec!["events-a", "events-c", "events-d"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_enumerate_partial_failure() -> Result<()> {
+        // Of three streams, `events-b` is `UPDATING` for the first `updating` listings.
+        let enumerate = |policy: &'static str, updating: usize| async move {
+            let server = wiremock::MockServer::start().await;
+            mount_api(
+                &server,
+                "ListStreams",
+                json_response(json!({
+                    "StreamNames": ["events-a", "events-b", "events-c"],
+                    "HasMoreStreams": false,
+                })),
+            )
+            .await;
+            let shards = json_response(list_shards_output(&["shardId-000000000000"]));
+            let mut responses = vec![error_response("ResourceInUseException"); updating];
+            responses.push(shards.clone());
+            mount_api_matching(
+                &server,
+                "ListShards",
+                json!({ "StreamName": "events-b" }),
+                SequenceResponder::new(responses),
+            )
+            .await;
+            mount_api(&server, "ListShards", shards).await;
+            let mut enumerator = KinesisSplitEnumerator::new(KinesisProperties {
+                stream_name: String::new(),
+                stream_pattern: Some("^events-".to_string()),
+                enumerate_partial_failure: Some(policy.to_string()),
+                ..mock_properties(&server)
+            })
+            .await?;
+            let streams = enumerator.list_splits().await.map(|splits| {
+                splits
+                    .into_iter()
+                    .map(|split| split.stream_name.unwrap())
+                    .collect::<Vec<_>>()
+            });
+            Ok::<_, anyhow::Error>((streams, enumerator.last_report().unwrap().clone()))
+        };
+        let failed = |succeeded: &[&str]| {
+            move |report: &EnumerationReport| {
+                report.succeeded == succeeded
+                    && report.failed.len() == 1
+                    && report.failed[0].0 == "events-b"
+            }
+        };
+
+        let (streams, report) = enumerate("fail_all", 1).await?;
+        assert!(streams.unwrap_err().to_string().contains("events-b"));
+        assert!(failed(&["events-a", "events-c"])(&report), "{:?}", report);
+
+        let (streams, report) = enumerate("skip_failed", 1).await?;
+        assert_eq!(streams?, vec!["events-a", "events-c"]);
+        assert!(failed(&["events-a", "events-c"])(&report), "{:?}", report);
+
+        let (streams, report) = enumerate("retry_failed", 1).await?;
+        assert_eq!(streams?, vec!["events-a", "events-b", "events-c"]);
+        assert_eq!(report.succeeded, vec!["events-a", "events-b", "events-c"]);
+        assert!(report.failed.is_empty());
+
+        // A stream still failing after the retries fails the enumeration.
+        let (streams, report) = enumerate("retry_failed", STREAM_RETRY_ATTEMPTS + 1).await?;
+        assert!(streams.unwrap_err().to_string().contains("events-b"));
+        assert!(failed(&["events-a", "events-c"])(&report), "{:?}", report);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_skip_failed_streams() -> Result<()> {
+        #[derive(Debug, Default)]
+        struct MockListener(std::sync::Mutex<Vec<StreamResharded>>);
+
+        impl ReshardListener for MockListener {
+            fn on_resharded(&self, event: &StreamResharded) {
+                self.0.lock().unwrap().push(event.clone());
+            }
+        }
+
+        let server = wiremock::MockServer::start().await;
+        mount_api(
+            &server,
+            "ListStreams",
+            json_response(json!({
+                "StreamNames": ["events-a", "events-b"],
+                "HasMoreStreams": false,
+            })),
+        )
+        .await;
+        // `events-a` fails after its first listing, while shard 0 of `events-b` is split into
+        // shards 1 and 2.
+        mount_api_matching(
+            &server,
+            "ListShards",
+            json!({ "StreamName": "events-a" }),
+            SequenceResponder::new(vec![
+                json_response(list_shards_output(&["shardId-000000000000"])),
+                error_response("AccessDeniedException"),
+            ]),
+        )
+        .await;
+        mount_api(
+            &server,
+            "ListShards",
+            SequenceResponder::new(vec![
+                json_response(list_shards_output(&["shardId-000000000000"])),
+                json_response(list_shards_output(&[
+                    "shardId-000000000001",
+                    "shardId-000000000002",
+                ])),
+            ]),
+        )
+        .await;
+        let listener = Arc::new(MockListener::default());
+        let mut enumerator = KinesisSplitEnumerator::new(KinesisProperties {
+            stream_name: String::new(),
+            stream_pattern: Some("^events-".to_string()),
+            enumerate_partial_failure: Some("skip_failed".to_string()),
+            ..mock_properties(&server)
+        })
+        .await?
+        .with_reshard_listener(listener.clone());
+        assert_eq!(enumerator.list_splits().await?.len(), 2);
+        assert_eq!(enumerator.list_splits().await?.len(), 2);
+
+        // The reshard of `events-b` is reported, without taking the shards of `events-a` as
+        // removed.
+        let events = listener.0.lock().unwrap().clone();
+        assert_eq!(events.len(), 1);
+        let ids = |ids: &[SplitId]| ids.iter().map(|id| id.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            ids(&events[0].added),
+            vec![
+                "events-b:shardId-000000000001",
+                "events-b:shardId-000000000002"
+            ]
+        );
+        assert_eq!(
+            ids(&events[0].removed),
+            vec!["events-b:shardId-000000000000"]
+        );
+
+        // All streams failing leave an empty enumeration rather than an error.
+        let server = wiremock::MockServer::start().await;
+        mount_api(
+            &server,
+            "ListShards",
+            error_response("AccessDeniedException"),
+        )
+        .await;
+        let mut enumerator = KinesisSplitEnumerator::new(KinesisProperties {
+            enumerate_partial_failure: Some("skip_failed".to_string()),
+            ..mock_properties(&server)
+        })
+        .await?;
+        assert!(enumerator.list_splits().await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_localstack_pagination() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        let shards = list_shards_output(&["shardId-000000000000"])["Shards"].clone();
+        // The last page has neither shards nor a next token.
+        mount_api_matching(
+            &server,
+            "ListShards",
+            json!({ "NextToken": "page-2" }),
+            json_response(json!({ "NextToken": "" })),
+        )
+        .await;
+        mount_api(
+            &server,
+            "ListShards",
+            json_response(json!({
+                "Shards": shards,
+                "NextToken": "page-2",
+            })),
+        )
+        .await;
+
+        let mut enumerator = KinesisSplitEnumerator::new(mock_properties(&server)).await?;
+        let err = enumerator.list_splits().await.unwrap_err();
+        assert!(err.to_string().contains("mock_stream"), "{}", err);
+
+        let mut enumerator = KinesisSplitEnumerator::new(KinesisProperties {
+            endpoint_flavor: Some("localstack".to_string()),
+            ..mock_properties(&server)
+        })
+        .await?;
+        assert_eq!(enumerator.list_splits().await?.len(), 1);
+
+        // A stream listed without any shard is still handled by `on_no_shards`.
+        let server = wiremock::MockServer::start().await;
+        mount_api(&server, "ListShards", json_response(json!({}))).await;
+        let mut enumerator = KinesisSplitEnumerator::new(KinesisProperties {
+            endpoint_flavor: Some("localstack".to_string()),
+            ..mock_properties(&server)
+        })
+        .await?;
+        let no_shards = KinesisEnumerationError::NoShards {
+            stream: "mock_stream".to_string(),
+            region: "us-east-1".to_string(),
+        };
+        let err = enumerator.list_splits().await.unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&no_shards));
+        let err = enumerator
+            .list_split_pages()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&no_shards));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_list_split_pages() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        let page = |shard_ids: &[&str], next_token: Option<&str>| {
+            let mut page = list_shards_output(shard_ids);
+            page["NextToken"] = json!(next_token);
+            json_response(page)
+        };
+        mount_api_matching(
+            &server,
+            "ListShards",
+            json!({ "NextToken": "page-3" }),
+            page(&["shardId-000000000004"], None),
+        )
+        .await;
+        mount_api_matching(
+            &server,
+            "ListShards",
+            json!({ "NextToken": "page-2" }),
+            page(
+                &["shardId-000000000002", "shardId-000000000003"],
+                Some("page-3"),
+            ),
+        )
+        .await;
+        mount_api(
+            &server,
+            "ListShards",
+            page(
+                &["shardId-000000000000", "shardId-000000000001"],
+                Some("page-2"),
+            ),
+        )
+        .await;
+
+        let mut enumerator = KinesisSplitEnumerator::new(mock_properties(&server)).await?;
+        let splits = enumerator.list_splits().await?;
+        let pages = enumerator
+            .list_split_pages()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        // A page of splits per page of shards.
+        assert_eq!(
+            pages.iter().map(|page| page.len()).collect::<Vec<_>>(),
+            vec![2, 2, 1]
+        );
+        assert_eq!(pages.concat(), splits);
+        assert!(splits
+            .iter()
+            .all(|split| split.start_position == KinesisOffset::None));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_enumeration_errors() -> Result<()> {
+        async fn list_splits_error(code: &str) -> Result<Option<KinesisEnumerationError>> {
+            let server = wiremock::MockServer::start().await;
+            mount_api(&server, "ListShards", error_response(code)).await;
+            let mut enumerator = KinesisSplitEnumerator::new(mock_properties(&server)).await?;
+            let err = enumerator.list_splits().await.unwrap_err();
+            Ok(err.downcast_ref::<KinesisEnumerationError>().cloned())
+        }
+
+        assert!(matches!(
+            list_splits_error("ResourceNotFoundException").await?,
+            Some(KinesisEnumerationError::StreamNotFound { stream, region, .. })
+                if stream == "mock_stream" && region == "us-east-1"
+        ));
+        let err = list_splits_error("AccessDeniedException").await?.unwrap();
+        assert!(matches!(
+            &err,
+            KinesisEnumerationError::AccessDenied { stream, action, .. }
+                if stream == "mock_stream" && action == "kinesis:ListShards"
+        ));
+        assert!(err.to_string().contains("check that the IAM policy"));
+        assert_eq!(list_splits_error("InvalidArgumentException").await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_no_shards() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(
+            &server,
+            "ListShards",
+            SequenceResponder::new(vec![
+                json_response(json!({ "Shards": [] })),
+                json_response(json!({})),
+                json_response(list_shards_output(&["shardId-000000000000"])),
+            ]),
+        )
+        .await;
+
+        let mut enumerator = KinesisSplitEnumerator::new(mock_properties(&server)).await?;
+        let err = enumerator.list_splits().await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<KinesisEnumerationError>(),
+            Some(&KinesisEnumerationError::NoShards {
+                stream: "mock_stream".to_string(),
+                region: "us-east-1".to_string(),
+            })
+        );
+
+        // Retried at the next enumeration until the shards are listable.
+        let properties = KinesisProperties {
+            on_no_shards: Some("retry".to_string()),
+            ..mock_properties(&server)
+        };
+        let mut enumerator = KinesisSplitEnumerator::new(properties).await?;
+        assert!(enumerator.list_splits().await?.is_empty());
+        assert_eq!(enumerator.list_splits().await?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_reshard_listener() -> Result<()> {
+        #[derive(Debug, Default)]
+        struct MockListener(std::sync::Mutex<Vec<StreamResharded>>);
+
+        impl ReshardListener for MockListener {
+            fn on_resharded(&self, event: &StreamResharded) {
+                self.0.lock().unwrap().push(event.clone());
+            }
+        }
+
+        let server = wiremock::MockServer::start().await;
+        // Shard 0 is split into shards 2 and 3 between the first two listings.
+        mount_api(
+            &server,
+            "ListShards",
+            SequenceResponder::new(vec![
+                json_response(list_shards_output(&[
+                    "shardId-000000000000",
+                    "shardId-000000000001",
+                ])),
+                json_response(list_shards_output(&[
+                    "shardId-000000000001",
+                    "shardId-000000000002",
+                    "shardId-000000000003",
+                ])),
+            ]),
+        )
+        .await;
+        let listener = Arc::new(MockListener::default());
+        let mut enumerator = KinesisSplitEnumerator::new(mock_properties(&server))
+            .await?
+            .with_reshard_listener(listener.clone());
+        for _ in 0..3 {
+            enumerator.list_splits().await?;
+        }
+        let events = listener.0.lock().unwrap().clone();
+        assert_eq!(events.len(), 1);
+        let ids = |ids: &[SplitId]| ids.iter().map(|id| id.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            ids(&events[0].added),
+            vec!["shardId-000000000002", "shardId-000000000003"]
+        );
+        assert_eq!(ids(&events[0].removed), vec!["shardId-000000000000"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_affinity_key_stable() -> Result<()> {
+        let shard_ids = [
+            "shardId-000000000000",
+            "shardId-000000000001",
+            "shardId-000000000002",
+        ];
+        let mut enumerations = vec![];
+        // The same shards listed in different orders by fresh enumerators.
+        for shard_ids in [shard_ids, [shard_ids[2], shard_ids[0], shard_ids[1]]] {
+            let server = wiremock::MockServer::start().await;
+            mount_api(
+                &server,
+                "ListShards",
+                json_response(list_shards_output(&shard_ids)),
+            )
+            .await;
+            let mut enumerator = KinesisSplitEnumerator::new(mock_properties(&server)).await?;
+            let affinity = enumerator
+                .list_splits()
+                .await?
+                .into_iter()
+                .map(|split| {
+                    (
+                        split.id(),
+                        (split.affinity_key(), split.preferred_reader(2)),
+                    )
+                })
+                .collect::<std::collections::HashMap<_, _>>();
+            enumerations.push(affinity);
+        }
+        assert_eq!(enumerations[0].len(), 3);
+        assert_eq!(enumerations[0], enumerations[1]);
+
+        // Shard ids are only unique within a stream.
+        let split = KinesisSplit::new(
+            shard_ids[0].to_string().into(),
+            KinesisOffset::Earliest,
+            KinesisOffset::None,
+        );
+        assert_ne!(
+            split.affinity_key(),
+            split
+                .clone()
+                .with_stream_name("other".to_string())
+                .affinity_key()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_startup_offset() {
+        // Without startup properties, the start position is unset as before they were added.
+        assert_eq!(
+            startup_offset(&KinesisProperties::default(), 0).unwrap(),
+            KinesisOffset::None
+        );
+        let properties = KinesisProperties {
+            scan_startup_mode: Some("earliest".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            startup_offset(&properties, 0).unwrap(),
+            KinesisOffset::Earliest
+        );
+
+        let properties = KinesisProperties {
+            scan_startup_timestamp: Some("2023-01-01T00:00:00.5+00:00".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            startup_offset(&properties, 0).unwrap(),
+            KinesisOffset::Timestamp(1672531200500)
+        );
+
+        let properties = KinesisProperties {
+            scan_startup_timestamp: Some("yesterday".to_string()),
+            ..Default::default()
+        };
+        assert!(startup_offset(&properties, 0).is_err());
+
+        let now = 1672531200000;
+        for (relative, millis) in [
+            ("30s", 30_000),
+            ("15m", 15 * 60_000),
+            ("1h", 60 * 60_000),
+            ("2d", 2 * 24 * 60 * 60_000),
+        ] {
+            let properties = KinesisProperties {
+                scan_startup_relative: Some(relative.to_string()),
+                ..Default::default()
+            };
+            assert_eq!(
+                startup_offset(&properties, now).unwrap(),
+                KinesisOffset::Timestamp(now - millis)
+            );
+        }
+        for invalid in ["1 fortnight", "-1h", "h"] {
+            let properties = KinesisProperties {
+                scan_startup_relative: Some(invalid.to_string()),
+                ..Default::default()
+            };
+            assert!(startup_offset(&properties, now).is_err());
+        }
+        let properties = KinesisProperties {
+            scan_startup_relative: Some("1h".to_string()),
+            scan_startup_timestamp_millis: Some("0".to_string()),
+            ..Default::default()
+        };
+        assert!(startup_offset(&properties, now).is_err());
+
+        let properties = KinesisProperties {
+            scan_startup_mode: Some("latest".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            startup_offset(&properties, 0).unwrap(),
+            KinesisOffset::Latest
+        );
+
+        let properties = KinesisProperties {
+            scan_startup_mode: Some("tail".to_string()),
+            scan_tail_lookback: Some("1m".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            startup_offset(&properties, 100_000).unwrap(),
+            KinesisOffset::Timestamp(40_000)
+        );
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_bounded_to_latest() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(
+            &server,
+            "ListShards",
+            json_response(list_shards_output(&["shardId-000000000000"])),
+        )
+        .await;
+
+        let properties = KinesisProperties {
+            bounded_to_latest: Some("true".to_string()),
+            ..mock_properties(&server)
+        };
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        let mut enumerator = KinesisSplitEnumerator::new(properties).await?;
+        let splits = enumerator.list_splits().await?;
+        match splits[0].end_position {
+            KinesisOffset::Timestamp(end) => assert!(end >= started_at),
+            _ => panic!("expect a timestamp end position"),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_backfill_mode() -> Result<()> {
+        let properties = KinesisProperties {
+            scan_startup_mode: Some("backfill".to_string()),
+            ..Default::default()
+        };
+        assert!(startup_offset(&properties, 0).is_err());
+
+        let server = wiremock::MockServer::start().await;
+        let mut shards = list_shards_output(&[
+            "shardId-000000000000",
+            "shardId-000000000001",
+            "shardId-000000000002",
+        ]);
+        shards["Shards"][1]["SequenceNumberRange"]["EndingSequenceNumber"] = json!("2");
+        mount_api(&server, "ListShards", json_response(shards)).await;
+        mount_api_matching(
+            &server,
+            "GetShardIterator",
+            json!({ "ShardIteratorType": "AT_TIMESTAMP" }),
+            ShardIteratorResponder,
+        )
+        .await;
+        let now = TokioClock.now_millis();
+        let hour = 3_600_000;
+        // Open with a record arriving after the tip, closed, and open without records in range.
+        let in_range = vec![
+            mock_record("1", b"a", now - hour / 2),
+            mock_record("2", b"b", now - hour / 4),
+        ];
+        let mut beyond_tip = in_range.clone();
+        beyond_tip.push(mock_record("3", b"c", now + hour));
+        let outputs = [
+            get_records_output(beyond_tip, 0),
+            json!({ "Records": in_range, "MillisBehindLatest": 0 }),
+            get_records_output(vec![], 0),
+        ];
+        for (i, output) in outputs.into_iter().enumerate() {
+            mount_api_matching(
+                &server,
+                "GetRecords",
+                json!({ "ShardIterator": format!("shardId-00000000000{}/0", i) }),
+                json_response(output),
+            )
+            .await;
+        }
+
+        let properties = KinesisProperties {
+            scan_startup_mode: Some("backfill".to_string()),
+            scan_startup_relative: Some("1h".to_string()),
+            ..mock_properties(&server)
+        };
+        let mut enumerator = KinesisSplitEnumerator::new(properties.clone()).await?;
+        let splits = enumerator.list_splits().await?;
+        for split in &splits {
+            match split.start_position {
+                KinesisOffset::Timestamp(start) => assert!(start >= now - hour && start < now),
+                _ => panic!("expect a timestamp start position"),
+            }
+        }
+        match splits[0].end_position {
+            KinesisOffset::Timestamp(end) => assert!(end >= now),
+            _ => panic!("expect a timestamp end position"),
+        }
+        assert_eq!(
+            splits[1].end_position,
+            KinesisOffset::SequenceNumber("2".to_string())
+        );
+
+        let mut offsets = vec![];
+        for split in splits {
+            let mut reader = KinesisSplitReader::new(properties.clone(), split).await?;
+            let mut split_offsets = vec![];
+            while let Some(chunk) = reader.next().await? {
+                split_offsets.extend(
+                    chunk
+                        .into_iter()
+                        .filter(|msg| msg.payload.is_some())
+                        .map(|msg| msg.offset),
+                );
+            }
+            offsets.push(split_offsets);
+        }
+        assert_eq!(offsets, vec![vec!["1", "2"], vec!["1", "2"], vec![]]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_shard_limit_warning() {
+        assert!(shard_limit_warning(10, 100, 500, 0.8).is_none());
+        assert!(shard_limit_warning(400, 450, 500, 0.8).is_none());
+        assert!(shard_limit_warning(401, 450, 500, 0.8).is_some());
+        assert!(shard_limit_warning(10, 10, 0, 0.8).is_none());
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_check_shard_limit() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(
+            &server,
+            "DescribeLimits",
+            json_response(json!({
+                "ShardLimit": 4,
+                "OpenShardCount": 3,
+                "OnDemandStreamCount": 0,
+                "OnDemandStreamCountLimit": 50,
+            })),
+        )
+        .await;
+
+        let properties = KinesisProperties {
+            preflight_check_shard_limit: Some("true".to_string()),
+            ..mock_properties(&server)
+        };
+        let enumerator = KinesisSplitEnumerator::new(properties).await?;
+        assert_eq!(enumerator.shard_limit_threshold, Some(0.8));
+        assert!(enumerator.check_shard_limit(3, 0.8).await?.is_none());
+        let warning = enumerator.check_shard_limit(4, 0.8).await?.unwrap();
+        assert!(warning.contains("100% of the account shard limit 4"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_enable_enhanced_monitoring() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(
+            &server,
+            "ListShards",
+            json_response(list_shards_output(&["shardId-000000000000"])),
+        )
+        .await;
+        mount_api_matching(
+            &server,
+            "EnableEnhancedMonitoring",
+            json!({
+                "StreamName": "mock_stream",
+                "ShardLevelMetrics": ["IncomingBytes", "IncomingRecords"],
+            }),
+            json_response(json!({
+                "StreamName": "mock_stream",
+                "CurrentShardLevelMetrics": [],
+                "DesiredShardLevelMetrics": ["IncomingBytes", "IncomingRecords"],
+            })),
+        )
+        .await;
+
+        assert!(KinesisSplitEnumerator::new(KinesisProperties {
+            enhanced_monitoring_metrics: Some("IncomingBytes,Incoming".to_string()),
+            ..mock_properties(&server)
+        })
+        .await
+        .is_err());
+
+        let properties = KinesisProperties {
+            enhanced_monitoring_metrics: Some("IncomingBytes, IncomingRecords".to_string()),
+            ..mock_properties(&server)
+        };
+        let mut enumerator = KinesisSplitEnumerator::new(properties).await?;
+        assert_eq!(
+            enumerator
+                .enable_enhanced_monitoring(
+                    "mock_stream",
+                    &[MetricsName::IncomingBytes, MetricsName::IncomingRecords]
+                )
+                .await?,
+            vec![MetricsName::IncomingBytes, MetricsName::IncomingRecords]
+        );
+
+        // Enabled on the first enumeration only.
+        enumerator.list_splits().await?;
+        enumerator.list_splits().await?;
+        assert_eq!(received_calls(&server, "EnableEnhancedMonitoring").await, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_unsupported_consumer_mode() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        let properties = mock_properties(&server);
+        for mode in ["efo", "auto"] {
+            let err = KinesisSplitEnumerator::new(KinesisProperties {
+                consumer_mode: Some(mode.to_string()),
+                ..properties.clone()
+            })
+            .await
+            .err()
+            .unwrap();
+            assert!(err.to_string().contains("not supported yet"), "{}", err);
+        }
+        KinesisSplitEnumerator::new(KinesisProperties {
+            consumer_mode: Some("polling".to_string()),
+            ..properties
+        })
+        .await?;
+        Ok(())
+    }
 
     #[tokio::test]
     #[ignore]
@@ -98,6 +1665,13 @@ mod tests {
         let mut enumerator = KinesisSplitEnumerator {
             stream_name,
             client,
+            start_offset: KinesisOffset::Earliest,
+            end_offset: KinesisOffset::None,
+            stream_pattern: None,
+            discovery_interval: DEFAULT_STREAM_DISCOVERY_INTERVAL,
+            discovered_streams: None,
+            shard_limit_threshold: None,
+            retry_policy: RetryPolicy::default(),
         };
         let list_splits_resp = enumerator.list_splits().await?;
         println!("{:#?}", list_splits_resp);
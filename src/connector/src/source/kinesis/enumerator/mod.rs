@@ -13,3 +13,5 @@
 // limitations under the License.
 
 pub mod client;
+pub mod report;
+pub mod reshard;
@@ -0,0 +1,45 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use anyhow::Result;
+
+/// Which streams the last enumeration listed the shards of, and which failed with what error, see
+/// `enumerate.partial.failure`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnumerationReport {
+    pub succeeded: Vec<String>,
+    pub failed: Vec<(String, String)>,
+}
+
+impl EnumerationReport {
+    /// Takes the streams along with the outcomes of listing them, in the same order.
+    pub fn new<T>(streams: &[String], listed: &[Result<T>]) -> Self {
+        let mut report = Self::default();
+        for (stream_name, outcome) in streams.iter().zip(listed) {
+            match outcome {
+                Ok(_) => report.succeeded.push(stream_name.clone()),
+                Err(e) => report.failed.push((stream_name.clone(), e.to_string())),
+            }
+        }
+        report
+    }
+
+    /// Describes the failures as `<stream>: <error>; ...`.
+    pub fn describe_failures(&self) -> String {
+        self.failed
+            .iter()
+            .map(|(stream_name, e)| format!("{}: {}", stream_name, e))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
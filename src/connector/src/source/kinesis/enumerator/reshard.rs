@@ -0,0 +1,62 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::source::SplitId;
+
+/// The shards of the streams changed between two listings of the enumerator, e.g. as shards were
+/// split or merged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamResharded {
+    /// The splits listed for the first time, sorted.
+    pub added: Vec<SplitId>,
+    /// The splits no longer listed, e.g. closed shards past the retention period, sorted.
+    pub removed: Vec<SplitId>,
+    /// When the change was found, in milliseconds since the Unix epoch.
+    pub timestamp: i64,
+}
+
+impl StreamResharded {
+    /// Returns the change from the `previous` listing to the `current` one, or `None` if the
+    /// splits are the same.
+    pub fn between(
+        previous: &HashSet<SplitId>,
+        current: &HashSet<SplitId>,
+        timestamp: i64,
+    ) -> Option<Self> {
+        let mut added = current.difference(previous).cloned().collect::<Vec<_>>();
+        let mut removed = previous.difference(current).cloned().collect::<Vec<_>>();
+        if added.is_empty() && removed.is_empty() {
+            return None;
+        }
+        added.sort();
+        removed.sort();
+        Some(Self {
+            added,
+            removed,
+            timestamp,
+        })
+    }
+}
+
+/// Receives the reshards found by the enumerator, e.g. for downstream operators to flush state or
+/// rebalance. Called on the enumeration path, so implementations should not block.
+pub trait ReshardListener: Debug + Send + Sync {
+    fn on_resharded(&self, event: &StreamResharded);
+}
+
+pub type ReshardListenerRef = Arc<dyn ReshardListener>;
@@ -0,0 +1,141 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use aws_sdk_kinesis::types::SdkError;
+use aws_smithy_types::retry::ProvideErrorKind;
+use thiserror::Error;
+
+/// The header of the id AWS assigns to each request, which AWS support asks for.
+const REQUEST_ID_HEADER: &str = "x-amzn-RequestId";
+/// The header of the extended request id, returned by some services.
+const EXTENDED_REQUEST_ID_HEADER: &str = "x-amz-id-2";
+
+/// A failed AWS call along with the ids of its request, which the message of [`SdkError`] omits.
+#[derive(Error, Debug)]
+#[error(
+    "{source}, aws request id: {request_id}{}",
+    extended_request_id
+        .as_ref()
+        .map(|id| format!(", extended request id: {}", id))
+        .unwrap_or_default()
+)]
+pub struct AwsRequestError {
+    pub request_id: String,
+    pub extended_request_id: Option<String>,
+    source: Box<dyn std::error::Error + Send + Sync>,
+}
+
+/// Converts a failed AWS call to [`AwsRequestError`] if the response carries a request id.
+pub fn sdk_error<E>(e: SdkError<E>) -> anyhow::Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let headers = match &e {
+        SdkError::ServiceError { raw, .. } | SdkError::ResponseError { raw, .. } => {
+            raw.http().headers()
+        }
+        _ => return e.into(),
+    };
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    };
+    let extended_request_id = header(EXTENDED_REQUEST_ID_HEADER);
+    match header(REQUEST_ID_HEADER) {
+        Some(request_id) => AwsRequestError {
+            request_id,
+            extended_request_id,
+            source: Box::new(e),
+        }
+        .into(),
+        None => e.into(),
+    }
+}
+
+/// The errors of enumerating a stream which users can fix by themselves, with hints of how.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum KinesisEnumerationError {
+    #[error(
+        "kinesis stream {stream} is not found in region {region}, check the stream name and the \
+         region: {message}"
+    )]
+    StreamNotFound {
+        stream: String,
+        region: String,
+        message: String,
+    },
+    #[error(
+        "access denied to kinesis stream {stream}, check that the IAM policy of the credentials \
+         allows {action}: {message}"
+    )]
+    AccessDenied {
+        stream: String,
+        action: String,
+        message: String,
+    },
+    #[error(
+        "kinesis stream {stream} in region {region} has no listable shards, check the stream name \
+         and the region, or set on_no_shards to retry if the stream is being created"
+    )]
+    NoShards { stream: String, region: String },
+}
+
+impl KinesisEnumerationError {
+    /// Maps `ResourceNotFoundException` and `AccessDeniedException` returned by `action` on the
+    /// stream, e.g. `kinesis:ListShards`, to the variants. Returns `None` for other errors.
+    pub fn from_sdk_error<E: ProvideErrorKind + std::error::Error>(
+        e: &SdkError<E>,
+        stream: &str,
+        region: &str,
+        action: &str,
+    ) -> Option<Self> {
+        let err = match e {
+            SdkError::ServiceError { err, .. } => err,
+            _ => return None,
+        };
+        let message = err.to_string();
+        match err.code()? {
+            "ResourceNotFoundException" => Some(Self::StreamNotFound {
+                stream: stream.to_string(),
+                region: region.to_string(),
+                message,
+            }),
+            "AccessDeniedException" => Some(Self::AccessDenied {
+                stream: stream.to_string(),
+                action: action.to_string(),
+                message,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Converts an SDK error to [`KinesisEnumerationError`] if it is one, which callers can find
+/// with `downcast_ref`.
+pub fn enumeration_error<E>(
+    e: SdkError<E>,
+    stream: &str,
+    region: &str,
+    action: &str,
+) -> anyhow::Error
+where
+    E: ProvideErrorKind + std::error::Error + Send + Sync + 'static,
+{
+    match KinesisEnumerationError::from_sdk_error(&e, stream, region, action) {
+        Some(err) => err.into(),
+        None => sdk_error(e),
+    }
+}
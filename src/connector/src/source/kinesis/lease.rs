@@ -0,0 +1,290 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checkpoints in a DynamoDB lease table of the Kinesis Client Library (KCL), so that RisingWave
+//! can take over from or coexist with KCL consumers of a stream. Only the checkpoints of the
+//! leases are read and written, the leases are not taken or renewed as KCL workers do.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use aws_sdk_dynamodb::model::AttributeValue;
+use aws_sdk_dynamodb::Client;
+use http::Uri;
+
+use crate::source::kinesis::config::AwsConfigInfo;
+use crate::source::kinesis::error::sdk_error;
+use crate::source::kinesis::split::KinesisOffset;
+use crate::source::kinesis::KinesisProperties;
+
+const DEFAULT_LEASE_OWNER: &str = "risingwave";
+
+/// The attributes of a KCL lease record.
+pub const LEASE_KEY: &str = "leaseKey";
+pub const CHECKPOINT: &str = "checkpoint";
+pub const CHECKPOINT_SUB_SEQUENCE_NUMBER: &str = "checkpointSubSequenceNumber";
+pub const LEASE_OWNER: &str = "leaseOwner";
+pub const LEASE_COUNTER: &str = "leaseCounter";
+pub const OWNER_SWITCHES_SINCE_CHECKPOINT: &str = "ownerSwitchesSinceCheckpoint";
+pub const PARENT_SHARD_ID: &str = "parentShardId";
+
+/// The checkpoints KCL writes instead of a sequence number.
+const CHECKPOINT_TRIM_HORIZON: &str = "TRIM_HORIZON";
+const CHECKPOINT_LATEST: &str = "LATEST";
+const CHECKPOINT_SHARD_END: &str = "SHARD_END";
+
+/// A lease record of a shard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KclLease {
+    pub shard_id: String,
+    /// The sequence number of the last processed record, or one of `TRIM_HORIZON`, `LATEST` and
+    /// `SHARD_END`.
+    pub checkpoint: String,
+    pub checkpoint_sub_sequence_number: i64,
+    pub lease_owner: Option<String>,
+    pub lease_counter: i64,
+    pub parent_shard_ids: Vec<String>,
+}
+
+impl KclLease {
+    fn from_item(item: &HashMap<String, AttributeValue>) -> Result<Self> {
+        let string = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+        let number = |name: &str| -> Result<i64> {
+            match item.get(name).and_then(|v| v.as_n().ok()) {
+                Some(n) => n
+                    .parse()
+                    .map_err(|e| anyhow!("invalid {} '{}' of kcl lease: {}", name, n, e)),
+                None => Ok(0),
+            }
+        };
+        Ok(Self {
+            shard_id: string(LEASE_KEY)
+                .ok_or_else(|| anyhow!("kcl lease without {}", LEASE_KEY))?,
+            checkpoint: string(CHECKPOINT)
+                .ok_or_else(|| anyhow!("kcl lease without {}", CHECKPOINT))?,
+            checkpoint_sub_sequence_number: number(CHECKPOINT_SUB_SEQUENCE_NUMBER)?,
+            lease_owner: string(LEASE_OWNER),
+            lease_counter: number(LEASE_COUNTER)?,
+            parent_shard_ids: item
+                .get(PARENT_SHARD_ID)
+                .and_then(|v| v.as_ss().ok())
+                .cloned()
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Returns the offset to start the shard from, or `None` if KCL has read the shard to its end.
+    pub fn start_offset(&self) -> Option<KinesisOffset> {
+        match self.checkpoint.as_str() {
+            CHECKPOINT_TRIM_HORIZON => Some(KinesisOffset::Earliest),
+            CHECKPOINT_LATEST => Some(KinesisOffset::Latest),
+            CHECKPOINT_SHARD_END => None,
+            sequence_number => Some(KinesisOffset::SequenceNumber(sequence_number.to_string())),
+        }
+    }
+}
+
+/// The lease table named by `checkpoint.kcl.lease_table`.
+#[derive(Debug, Clone)]
+pub struct LeaseTable {
+    client: Client,
+    table_name: String,
+    owner: String,
+}
+
+impl LeaseTable {
+    /// Returns `None` if `checkpoint.kcl.lease_table` is not set.
+    pub async fn from_properties(properties: &KinesisProperties) -> Result<Option<Self>> {
+        let table_name = match &properties.checkpoint_kcl_lease_table {
+            Some(table_name) => table_name.clone(),
+            None => return Ok(None),
+        };
+        let aws_config = AwsConfigInfo::build(properties.clone())?.load().await?;
+        let mut builder = aws_sdk_dynamodb::config::Builder::from(&aws_config);
+        if let Some(endpoint) = &properties.checkpoint_kcl_endpoint {
+            let uri = endpoint
+                .parse::<Uri>()
+                .map_err(|e| anyhow!("invalid checkpoint.kcl.endpoint {}: {}", endpoint, e))?;
+            builder =
+                builder.endpoint_resolver(aws_smithy_http::endpoint::Endpoint::immutable(uri));
+        }
+        Ok(Some(Self {
+            client: Client::from_conf(builder.build()),
+            table_name,
+            owner: properties
+                .checkpoint_kcl_owner
+                .clone()
+                .unwrap_or_else(|| DEFAULT_LEASE_OWNER.to_string()),
+        }))
+    }
+
+    /// Reads the lease of the shard, which is `None` if KCL has not leased it.
+    pub async fn read(&self, shard_id: &str) -> Result<Option<KclLease>> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key(LEASE_KEY, AttributeValue::S(shard_id.to_string()))
+            .consistent_read(true)
+            .send()
+            .await
+            .map_err(sdk_error)?;
+        output.item().map(KclLease::from_item).transpose()
+    }
+
+    /// Writes the sequence number of the last record read from the shard as its checkpoint,
+    /// creating the lease if it does not exist.
+    pub async fn checkpoint(&self, shard_id: &str, sequence_number: &str) -> Result<()> {
+        self.client
+            .update_item()
+            .table_name(&self.table_name)
+            .key(LEASE_KEY, AttributeValue::S(shard_id.to_string()))
+            .update_expression(format!(
+                "SET {} = :checkpoint, {} = :sub_sequence_number, {} = :owner, {} = :zero \
+                 ADD {} :one",
+                CHECKPOINT,
+                CHECKPOINT_SUB_SEQUENCE_NUMBER,
+                LEASE_OWNER,
+                OWNER_SWITCHES_SINCE_CHECKPOINT,
+                LEASE_COUNTER
+            ))
+            .expression_attribute_values(
+                ":checkpoint",
+                AttributeValue::S(sequence_number.to_string()),
+            )
+            .expression_attribute_values(":sub_sequence_number", AttributeValue::N("0".into()))
+            .expression_attribute_values(":owner", AttributeValue::S(self.owner.clone()))
+            .expression_attribute_values(":zero", AttributeValue::N("0".into()))
+            .expression_attribute_values(":one", AttributeValue::N("1".into()))
+            .send()
+            .await
+            .map_err(sdk_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::matchers::{header, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::source::kinesis::test_utils::mock_properties;
+
+    async fn mount_dynamodb_api(server: &MockServer, operation: &str, body: serde_json::Value) {
+        let target = format!("DynamoDB_20120810.{}", operation);
+        Mock::given(method("POST"))
+            .and(header("x-amz-target", target.as_str()))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(body.to_string(), "application/x-amz-json-1.0"),
+            )
+            .mount(server)
+            .await;
+    }
+
+    async fn lease_table(server: &MockServer) -> LeaseTable {
+        let properties = KinesisProperties {
+            checkpoint_kcl_lease_table: Some("kcl_app".to_string()),
+            checkpoint_kcl_endpoint: Some(server.uri()),
+            checkpoint_kcl_owner: Some("worker-1".to_string()),
+            ..mock_properties(server)
+        };
+        LeaseTable::from_properties(&properties)
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_read_lease() -> Result<()> {
+        let server = MockServer::start().await;
+        mount_dynamodb_api(
+            &server,
+            "GetItem",
+            json!({
+                "Item": {
+                    "leaseKey": { "S": "shardId-000000000001" },
+                    "checkpoint": { "S": "49590338271490256608559692538361571095921575989136588898" },
+                    "checkpointSubSequenceNumber": { "N": "0" },
+                    "leaseOwner": { "S": "kcl-worker" },
+                    "leaseCounter": { "N": "42" },
+                    "ownerSwitchesSinceCheckpoint": { "N": "0" },
+                    "parentShardId": { "SS": ["shardId-000000000000"] },
+                },
+            }),
+        )
+        .await;
+
+        let lease = lease_table(&server)
+            .await
+            .read("shardId-000000000001")
+            .await?
+            .unwrap();
+        assert_eq!(
+            lease,
+            KclLease {
+                shard_id: "shardId-000000000001".to_string(),
+                checkpoint: "49590338271490256608559692538361571095921575989136588898".to_string(),
+                checkpoint_sub_sequence_number: 0,
+                lease_owner: Some("kcl-worker".to_string()),
+                lease_counter: 42,
+                parent_shard_ids: vec!["shardId-000000000000".to_string()],
+            }
+        );
+        assert_eq!(
+            lease.start_offset(),
+            Some(KinesisOffset::SequenceNumber(lease.checkpoint.clone()))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_checkpoint_lease() -> Result<()> {
+        let server = MockServer::start().await;
+        mount_dynamodb_api(&server, "UpdateItem", json!({})).await;
+
+        lease_table(&server)
+            .await
+            .checkpoint("shardId-000000000001", "123")
+            .await?;
+
+        let requests = server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body)?;
+        assert_eq!(body["TableName"], "kcl_app");
+        assert_eq!(
+            body["Key"],
+            json!({ "leaseKey": { "S": "shardId-000000000001" } })
+        );
+        assert_eq!(
+            body["UpdateExpression"],
+            "SET checkpoint = :checkpoint, checkpointSubSequenceNumber = :sub_sequence_number, \
+             leaseOwner = :owner, ownerSwitchesSinceCheckpoint = :zero ADD leaseCounter :one"
+        );
+        assert_eq!(
+            body["ExpressionAttributeValues"],
+            json!({
+                ":checkpoint": { "S": "123" },
+                ":sub_sequence_number": { "N": "0" },
+                ":owner": { "S": "worker-1" },
+                ":zero": { "N": "0" },
+                ":one": { "N": "1" },
+            })
+        );
+        Ok(())
+    }
+}
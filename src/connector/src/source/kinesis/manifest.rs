@@ -0,0 +1,192 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! A resume manifest, the JSON file named by `resume.manifest` which maps split ids to the
+//! positions to start them from, e.g. for surgical recovery after an incident:
+//!
+//! ```json
+//! {
+//!     "shardId-000000000000": { "SequenceNumber": "49590338271490256608559692538361571095921575989136588898" },
+//!     "shardId-000000000001": { "Timestamp": 1672531200000 },
+//!     "shardId-000000000002": "Earliest"
+//! }
+//! ```
+//!
+//! The split id is the shard id, prefixed by `<stream>:` when streams are discovered by
+//! `stream.pattern`. The positions override the startup mode of the shards when the enumerator
+//! first lists them, and are ignored afterwards: the shards being read resume from their
+//! checkpointed state, e.g. on recovery or rescaling, so the manifest can be left in place.
+
+use std::collections::HashMap;
+use std::fs;
+
+use anyhow::{anyhow, Result};
+
+use crate::source::kinesis::split::{KinesisOffset, KinesisSplit};
+use crate::source::kinesis::KinesisProperties;
+use crate::source::SplitMetaData;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResumeManifest {
+    positions: HashMap<String, KinesisOffset>,
+}
+
+impl ResumeManifest {
+    /// Returns `None` if `resume.manifest` is not set.
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Option<Self>> {
+        let path = match &properties.resume_manifest {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("failed to read resume.manifest {}: {}", path, e))?;
+        let positions = serde_json::from_str(&content)
+            .map_err(|e| anyhow!("invalid resume.manifest {}: {}", path, e))?;
+        Ok(Some(Self { positions }))
+    }
+
+    /// Checks that every split of the manifest is among `splits`, the current shards of the
+    /// streams, so that a mistyped shard id is not silently ignored.
+    pub fn validate(&self, splits: &[KinesisSplit]) -> Result<()> {
+        let mut unknown = self
+            .positions
+            .keys()
+            .filter(|id| {
+                !splits
+                    .iter()
+                    .any(|split| split.id().as_ref() == id.as_str())
+            })
+            .collect::<Vec<_>>();
+        if unknown.is_empty() {
+            return Ok(());
+        }
+        unknown.sort();
+        Err(anyhow!(
+            "resume.manifest has unknown kinesis shards {:?}",
+            unknown
+        ))
+    }
+
+    /// Starts `split` from its position in the manifest, if any.
+    pub fn apply(&self, split: KinesisSplit) -> KinesisSplit {
+        match self.positions.get(split.id().as_ref()) {
+            Some(position) => {
+                tracing::info!(
+                    "start kinesis shard {} from {:?} of resume.manifest",
+                    split.id(),
+                    position
+                );
+                KinesisSplit {
+                    start_position: position.clone(),
+                    ..split
+                }
+            }
+            None => split,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::source::kinesis::enumerator::client::KinesisSplitEnumerator;
+    use crate::source::kinesis::source::reader::KinesisMultiSplitReader;
+    use crate::source::kinesis::test_utils::*;
+    use crate::source::{SplitEnumerator, SplitImpl, SplitReader};
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_resume_manifest() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        let shard_ids = [
+            "shardId-000000000000",
+            "shardId-000000000001",
+            "shardId-000000000002",
+        ];
+        mount_api(
+            &server,
+            "ListShards",
+            json_response(list_shards_output(&shard_ids)),
+        )
+        .await;
+        mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+        mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+        let dir = tempfile::tempdir()?;
+        let manifest = |positions: serde_json::Value| -> Result<KinesisProperties> {
+            let path = dir.path().join("manifest.json");
+            fs::write(&path, positions.to_string())?;
+            Ok(KinesisProperties {
+                resume_manifest: Some(path.to_str().unwrap().to_string()),
+                ..mock_properties(&server)
+            })
+        };
+        let properties = manifest(json!({
+            "shardId-000000000000": { "SequenceNumber": "5" },
+            "shardId-000000000001": { "Timestamp": 1672531200000i64 },
+        }))?;
+
+        // The manifest overrides the startup mode of new shards.
+        let mut enumerator = KinesisSplitEnumerator::new(properties.clone()).await?;
+        let positions = enumerator
+            .list_splits()
+            .await?
+            .into_iter()
+            .map(|split| split.start_position)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            vec![
+                KinesisOffset::SequenceNumber("5".to_string()),
+                KinesisOffset::Timestamp(1672531200000),
+                KinesisOffset::Earliest,
+            ]
+        );
+
+        // Only on the first listing.
+        assert!(enumerator
+            .list_splits()
+            .await?
+            .into_iter()
+            .all(|split| split.start_position == KinesisOffset::Earliest));
+
+        // The checkpointed state of the shards being read wins over the manifest.
+        let state = shard_ids
+            .iter()
+            .map(|shard_id| SplitImpl::Kinesis(mock_split(shard_id).copy_with_offset("100".into())))
+            .collect();
+        let mut reader = KinesisMultiSplitReader::new(properties, Some(state), None).await?;
+        let mut first_offsets = HashMap::new();
+        while first_offsets.len() < shard_ids.len() {
+            let chunk = reader.next().await?.unwrap();
+            first_offsets
+                .entry(chunk[0].split_id.to_string())
+                .or_insert_with(|| chunk[0].offset.clone());
+        }
+        for shard_id in shard_ids {
+            assert_eq!(first_offsets[shard_id], "101");
+        }
+        let bodies = received_bodies(&server, "GetShardIterator").await;
+        assert!(bodies
+            .iter()
+            .all(|body| body["ShardIteratorType"] == "AFTER_SEQUENCE_NUMBER"));
+
+        // A shard unknown to the streams fails the enumeration.
+        let properties = manifest(json!({ "shardId-000000000009": "Earliest" }))?;
+        let mut enumerator = KinesisSplitEnumerator::new(properties).await?;
+        let err = enumerator.list_splits().await.unwrap_err();
+        assert!(err.to_string().contains("shardId-000000000009"), "{}", err);
+        Ok(())
+    }
+}
@@ -12,22 +12,69 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod canonical;
+pub mod client_cache;
+pub mod clock;
 pub mod config;
+pub mod credentials;
+pub mod debug;
+pub mod dry_run;
 pub mod enumerator;
+pub mod error;
+pub mod lease;
+pub mod manifest;
+pub mod retry;
 pub mod source;
 pub mod split;
+pub mod telemetry;
+#[cfg(test)]
+pub(crate) mod test_utils;
 
 pub use config::build_client;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 pub const KINESIS_CONNECTOR: &str = "kinesis";
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct KinesisProperties {
-    #[serde(rename = "stream", alias = "kinesis.stream.name")]
+    #[serde(rename = "stream", alias = "kinesis.stream.name", default)]
     pub stream_name: String,
-    #[serde(rename = "aws.region", alias = "kinesis.stream.region")]
+    /// Required unless derived from `stream.arn`.
+    #[serde(rename = "aws.region", alias = "kinesis.stream.region", default)]
     pub stream_region: String,
+    /// The ARN of the stream, `arn:aws:kinesis:<region>:<account id>:stream/<stream name>`, from
+    /// which the stream name and the region are derived unless set explicitly. An explicit
+    /// `aws.region` takes precedence, e.g. to reach the stream through an interface endpoint.
+    #[serde(rename = "stream.arn")]
+    pub stream_arn: Option<String>,
+    /// `earliest` (default), `latest`, `tail` or `backfill`. The `tail` mode reads approximately
+    /// the last `scan.tail.records` records of each shard and stops. The `backfill` mode reads
+    /// from the `scan.startup.timestamp*` or `scan.startup.relative` timestamp up to the tip when
+    /// the source starts and stops.
+    #[serde(rename = "scan.startup.mode", alias = "kinesis.scan.startup.mode")]
+    pub scan_startup_mode: Option<String>,
+    /// The number of records read from each shard in the `tail` mode.
+    #[serde(rename = "scan.tail.records")]
+    pub scan_tail_records: Option<String>,
+    /// How far back the `tail` mode looks for records, 5 minutes by default. Kinesis can not seek
+    /// to the N-th record before the tip, so the `tail` mode reads from this long ago up to the
+    /// tip and keeps the last records, missing records older than this.
+    #[serde(rename = "scan.tail.lookback")]
+    pub scan_tail_lookback: Option<String>,
+    /// Start consuming from records arriving at or after this epoch timestamp in milliseconds.
+    #[serde(rename = "scan.startup.timestamp_millis")]
+    pub scan_startup_timestamp_millis: Option<String>,
+    /// Start consuming from records arriving at or after this RFC 3339 timestamp, e.g.
+    /// `2023-01-01T00:00:00Z`.
+    #[serde(rename = "scan.startup.timestamp")]
+    pub scan_startup_timestamp: Option<String>,
+    /// Start consuming from records arriving within this duration before the source starts, e.g.
+    /// `1h` or `2d`.
+    #[serde(rename = "scan.startup.relative")]
+    pub scan_startup_relative: Option<String>,
+    /// Stop each shard at the tip when the source starts, excluding records arriving later.
+    #[serde(rename = "bounded.to_latest")]
+    pub bounded_to_latest: Option<String>,
     #[serde(rename = "endpoint", alias = "kinesis.endpoint")]
     pub endpoint: Option<String>,
     #[serde(
@@ -52,4 +99,372 @@ pub struct KinesisProperties {
         alias = "kinesis.assumerole.external_id"
     )]
     pub assume_role_external_id: Option<String>,
+
+    /// Appended to the user agent of Kinesis API calls, so that they can be attributed in
+    /// CloudTrail.
+    #[serde(rename = "client.user_agent_suffix")]
+    pub client_user_agent_suffix: Option<String>,
+    /// Whether to share one client with the other sources in the process with the same region,
+    /// endpoint and credentials, instead of building one per reader and enumerator. Defaults to
+    /// `false`.
+    #[serde(rename = "client.shared")]
+    pub client_shared: Option<String>,
+    /// How often the HTTP/2 connections of the client are pinged to keep them alive, even when
+    /// idle. Defaults to `30s`.
+    #[serde(rename = "client.http2.keep_alive.interval")]
+    pub client_keep_alive_interval: Option<String>,
+    /// How long to wait for the ack of a keep-alive ping before closing the connection. Defaults
+    /// to `20s`.
+    #[serde(rename = "client.http2.keep_alive.timeout")]
+    pub client_keep_alive_timeout: Option<String>,
+    /// The initial HTTP/2 flow control window of each stream in bytes, e.g. of a
+    /// `SubscribeToShard` push. Defaults to 4 MiB.
+    #[serde(rename = "client.http2.stream_window_size")]
+    pub client_stream_window_size: Option<String>,
+    /// The initial HTTP/2 flow control window of each connection in bytes. Defaults to 16 MiB.
+    #[serde(rename = "client.http2.connection_window_size")]
+    pub client_connection_window_size: Option<String>,
+    /// Ask for the responses of `GetRecords` compressed where the endpoint supports it, to cut the
+    /// bandwidth of busy shards. Either `none` or `gzip`. Defaults to `none`.
+    #[serde(rename = "response.compression")]
+    pub response_compression: Option<String>,
+
+    /// Emit a heartbeat message without payload when a shard has been idle for this long, e.g.
+    /// `5s`. Disabled by default.
+    #[serde(rename = "idle.heartbeat.interval")]
+    pub idle_heartbeat_interval: Option<String>,
+
+    /// Discover the streams to consume by matching their names against this regex with
+    /// `ListStreams`, instead of consuming the single `stream`.
+    #[serde(rename = "stream.pattern")]
+    pub stream_pattern: Option<String>,
+
+    /// How often to re-discover streams when `stream.pattern` is set, e.g. `1m`.
+    #[serde(rename = "stream.discovery.interval")]
+    pub stream_discovery_interval: Option<String>,
+
+    /// How many streams to list the shards of concurrently, 8 by default.
+    #[serde(rename = "stream.discovery.parallelism")]
+    pub stream_discovery_parallelism: Option<String>,
+
+    /// What to do when the shards of some streams fail to be listed, e.g. access is denied or the
+    /// stream is `UPDATING`: `fail_all` (default), `skip_failed`, which reports the failures and
+    /// enumerates the other streams, or `retry_failed`, which lists the failed streams again a few
+    /// times before failing. `on_stream_error` and `skip` are legacy names.
+    #[serde(rename = "enumerate.partial.failure", alias = "on_stream_error")]
+    pub enumerate_partial_failure: Option<String>,
+
+    /// What to do when a stream has no shards to list, unlike a stream with shards but no records
+    /// yet, which is read as idle: `error` (default), which fails the stream subject to
+    /// `enumerate.partial.failure`, or `retry`, which warns and lists the stream again at the next
+    /// enumeration.
+    #[serde(rename = "on_no_shards")]
+    pub on_no_shards: Option<String>,
+
+    /// The number of fetched batches buffered in memory before fetching pauses.
+    #[serde(rename = "buffer.capacity")]
+    pub buffer_capacity: Option<String>,
+
+    /// The maximum bytes of payload fetched across all the shards of a reader and buffered before
+    /// fetching pauses, along with `buffer.capacity`. Unlimited by default.
+    #[serde(rename = "max.inflight.bytes")]
+    pub max_inflight_bytes: Option<String>,
+
+    /// The number of batches each shard fetches ahead of the reader, up to 16, trading memory for
+    /// the throughput of shards with high latency. 1 by default.
+    #[serde(rename = "prefetch.depth")]
+    pub prefetch_depth: Option<String>,
+
+    /// What to do when a single shard fails: `fail_all` (default), `drop_shard` or `retry`.
+    #[serde(rename = "on_shard_error")]
+    pub on_shard_error: Option<String>,
+
+    /// What to do with records of empty payloads: `emit` (default) as messages, `skip`, or
+    /// `heartbeat`, which emits them as heartbeats without payloads advancing the watermark.
+    #[serde(rename = "on_empty_payload")]
+    pub on_empty_payload: Option<String>,
+
+    /// What to do with a KPL aggregated record that fails to be deaggregated, e.g. truncated or
+    /// failing its MD5 digest: `fail` (default) the shard, `passthrough` to emit it as a plain
+    /// record, or `dead_letter` to hand it off to the dead-letter sink of the reader.
+    #[serde(rename = "on_corrupted_aggregate")]
+    pub on_corrupted_aggregate: Option<String>,
+
+    /// What to do when the parent shards of a child shard are missing from its lineage, e.g.
+    /// trimmed, so that they can not be read before it: `warn` (default) and read the child,
+    /// `proceed` to read it silently, accepting records of a partition key out of order, or
+    /// `fail`.
+    #[serde(rename = "on_missing_parent")]
+    pub on_missing_parent: Option<String>,
+
+    /// Write the raw `GetRecords` responses of each shard to `<dir>/<split id>.jsonl`, for
+    /// debugging.
+    #[serde(rename = "capture.dir")]
+    pub capture_dir: Option<String>,
+
+    /// Read the responses captured by `capture.dir` in this directory instead of calling Kinesis.
+    #[serde(rename = "replay.dir")]
+    pub replay_dir: Option<String>,
+
+    /// How payloads are framed: `none` (default) or `confluent`, where the schema registry id is
+    /// extracted from the header of each payload. Records without the framing are skipped with a
+    /// warning, like payloads failing to parse.
+    #[serde(rename = "payload.framing")]
+    pub payload_framing: Option<String>,
+
+    /// Drop records duplicated by producer retries, identified by their partition key and the id
+    /// at this JSON pointer in their payload, e.g. `/id`. Disabled by default.
+    #[serde(rename = "dedup.id_path")]
+    pub dedup_id_path: Option<String>,
+
+    /// The number of recent ids remembered for deduplication, 10000 by default.
+    #[serde(rename = "dedup.window.size")]
+    pub dedup_window_size: Option<String>,
+
+    /// Transforms applied to record payloads in order, a comma separated list of `decompress`,
+    /// `deaggregate` and `strip_header`, e.g. `decompress,deaggregate`.
+    #[serde(rename = "payload.transforms")]
+    pub payload_transforms: Option<String>,
+
+    /// The length in bytes of the header dropped by the `strip_header` transform.
+    #[serde(rename = "payload.header.length")]
+    pub payload_header_length: Option<String>,
+
+    /// The maximum number of shards a single reader handles. Disabled by default.
+    #[serde(rename = "max.shards.per.reader")]
+    pub max_shards_per_reader: Option<String>,
+
+    /// What to do when a reader is assigned more than `max.shards.per.reader` shards: `error`
+    /// (default) or `warn`.
+    #[serde(rename = "max.shards.per.reader.policy")]
+    pub max_shards_per_reader_policy: Option<String>,
+
+    /// Pause polling a shard for `circuit_breaker.cooldown` after this many consecutive failures
+    /// within `circuit_breaker.window`. Only applies to the `retry` shard error policy, since the
+    /// others stop the shard on the first failure. Disabled by default.
+    #[serde(rename = "circuit_breaker.failure_threshold")]
+    pub circuit_breaker_failure_threshold: Option<String>,
+
+    /// The window in which failures are counted by the circuit breaker, 1 minute by default.
+    #[serde(rename = "circuit_breaker.window")]
+    pub circuit_breaker_window: Option<String>,
+
+    /// How long the circuit breaker pauses a shard before trying it again, 1 minute by default.
+    #[serde(rename = "circuit_breaker.cooldown")]
+    pub circuit_breaker_cooldown: Option<String>,
+
+    /// The maximum number of attempts of a Kinesis API call, 3 by default.
+    #[serde(rename = "retry.max_attempts")]
+    pub retry_max_attempts: Option<String>,
+
+    /// The delay before the first retry, doubled for each following retry. 100ms by default.
+    #[serde(rename = "retry.base_delay")]
+    pub retry_base_delay: Option<String>,
+
+    /// The upper bound of the delay between retries, 10s by default.
+    #[serde(rename = "retry.max_delay")]
+    pub retry_max_delay: Option<String>,
+
+    /// Whether to randomize the delay between retries, true by default.
+    #[serde(rename = "retry.jitter")]
+    pub retry_jitter: Option<String>,
+
+    /// Seeds the randomness of the jitter, e.g. `42`, so that the delays between retries are
+    /// reproducible. Each shard derives its own seed from it. Unseeded by default.
+    #[serde(rename = "retry.jitter.seed")]
+    pub retry_jitter_seed: Option<String>,
+
+    /// The comma separated kinds of errors to retry among `throttling`, `timeout` and
+    /// `transient`. All by default.
+    #[serde(rename = "retry.on")]
+    pub retry_on: Option<String>,
+
+    /// Check the account shard limit with `DescribeLimits` on the first enumeration, and warn if
+    /// the stream takes a large fraction of it. Disabled by default.
+    #[serde(rename = "preflight.check.shard_limit")]
+    pub preflight_check_shard_limit: Option<String>,
+
+    /// The fraction of the account shard limit above which the preflight check warns, 0.8 by
+    /// default.
+    #[serde(rename = "preflight.shard_limit.threshold")]
+    pub preflight_shard_limit_threshold: Option<String>,
+
+    /// Comma separated shard-level CloudWatch metrics to enable on the streams with
+    /// `EnableEnhancedMonitoring` on the first enumeration, e.g. `IncomingBytes,IncomingRecords`
+    /// or `ALL`. Enhanced monitoring is billed, so it is left as is by default.
+    #[serde(rename = "enhanced_monitoring.metrics")]
+    pub enhanced_monitoring_metrics: Option<String>,
+
+    /// How sequence numbers are persisted in the split state: `string` (default) or `packed`,
+    /// which is shorter. Splits in either format can be restored regardless.
+    #[serde(rename = "state.sequence_number.format")]
+    pub state_sequence_number_format: Option<String>,
+
+    /// Only `polling` (default) is supported so far, `efo` and `auto` are rejected until the
+    /// reader implements enhanced fan-out.
+    #[serde(rename = "consumer.mode")]
+    pub consumer_mode: Option<String>,
+
+    /// How long a reader waits for its streams in `CREATING` or `UPDATING` status, e.g. while
+    /// encryption is being enabled, to become `ACTIVE`. 1 minute by default.
+    #[serde(rename = "stream.active.timeout")]
+    pub stream_active_timeout: Option<String>,
+
+    /// Stop the reader after emitting this many records in total across its shards, e.g. for
+    /// sampling. Disabled by default.
+    #[serde(rename = "max.total.records")]
+    pub max_total_records: Option<String>,
+
+    /// Stop the reader once it has run this long, e.g. `10m`, reporting a partial result whose
+    /// offsets can be resumed from. A safety cap for bounded reads like wide timestamp ranges of
+    /// busy streams. Disabled by default.
+    #[serde(rename = "batch.max_runtime")]
+    pub batch_max_runtime: Option<String>,
+
+    /// When the shard iterators are acquired: `lazy` (default) on the first poll of each shard,
+    /// which spreads the `GetShardIterator` calls and speeds up startup, or `eager` for all shards
+    /// when the reader is created.
+    #[serde(rename = "iterator.acquisition")]
+    pub iterator_acquisition: Option<String>,
+
+    /// How many shard iterators are acquired concurrently in the `eager` mode, 8 by default.
+    #[serde(rename = "iterator.acquisition.parallelism")]
+    pub iterator_acquisition_parallelism: Option<String>,
+
+    /// The maximum number of messages in a chunk returned by a shard reader. Unlimited by
+    /// default.
+    #[serde(rename = "max_chunk_records")]
+    pub max_chunk_records: Option<String>,
+
+    /// The maximum bytes of payload in a chunk returned by a shard reader, for downstream channels
+    /// with a byte budget per chunk. A message larger than the budget makes a chunk of its own.
+    /// Unlimited by default.
+    #[serde(rename = "max_chunk_bytes")]
+    pub max_chunk_bytes: Option<String>,
+
+    /// Coalesce the batches of a shard fetched within this many milliseconds into one returned
+    /// batch, instead of returning each `GetRecords` response as a batch. Disabled by default.
+    #[serde(rename = "batch.window.ms")]
+    pub batch_window_ms: Option<String>,
+
+    /// Return the coalesced batch of `batch.window.ms` early once it holds this many messages.
+    /// Unlimited by default.
+    #[serde(rename = "batch.window.max.records")]
+    pub batch_window_max_records: Option<String>,
+
+    /// Surface a new offset of a shard as its state at most once per this interval, e.g. `10s`,
+    /// always the latest one, to reduce the state written for streams of many shards. Disabled
+    /// by default.
+    #[serde(rename = "checkpoint.min.interval")]
+    pub checkpoint_min_interval: Option<String>,
+
+    /// Only validate the configuration end to end, from the credentials to a single fetch, and
+    /// fail the creation of the source with the report, see [`dry_run::dry_run`].
+    #[serde(rename = "dry_run")]
+    pub dry_run: Option<String>,
+
+    /// Truncate the payload of each record to this many bytes to preview a stream, leaving the
+    /// offsets intact. Never set it for real ingestion, which would lose data.
+    #[serde(rename = "preview.truncate.bytes")]
+    pub preview_truncate_bytes: Option<String>,
+
+    /// Fail a shard whose iterator expires this many times in a row, e.g. because the downstream
+    /// stalls for longer than the 5 minute iterator lifetime between fetches. 10 by default.
+    #[serde(rename = "max.consecutive.renews")]
+    pub max_consecutive_renews: Option<String>,
+
+    /// How `GetRecords` responses are validated: `off` (default), `warn` or `strict`, which fails
+    /// the shard on out-of-order sequence numbers, a negative `MillisBehindLatest`, or a missing
+    /// next shard iterator while behind. Catches bugs of Kinesis-compatible endpoints and proxies.
+    #[serde(rename = "get_records.validation")]
+    pub get_records_validation: Option<String>,
+
+    /// The bytes to fetch per `GetRecords` call, e.g. `5242880`. When set, the record limit of
+    /// each call adapts to the average size of recent records. Unset by default, which fetches
+    /// up to 10000 records per call.
+    #[serde(rename = "get_records.target_bytes")]
+    pub get_records_target_bytes: Option<String>,
+
+    /// Whether to cache the last `GetRecords` response of each shard, so that a batch which the
+    /// downstream failed to emit is emitted again on retry instead of skipped. `false` by default.
+    #[serde(rename = "get_records.retry.cache")]
+    pub get_records_retry_cache: Option<String>,
+
+    /// Whether to detect the records skipped by a `Latest` start position, which are those
+    /// written between the listing of a shard and the acquisition of its iterator. They are
+    /// logged and counted once the first record is read. Defaults to `false`.
+    #[serde(rename = "latest.gap.detection")]
+    pub latest_gap_detection: Option<String>,
+
+    /// When a shard restored from a sequence number turns out to lag the tip by more than this,
+    /// e.g. `1h`, skip to `Latest` instead of replaying the backlog. The skipped range is logged.
+    /// Disabled by default, which never skips records.
+    #[serde(rename = "startup.skip.to.latest.if.lag.exceeds")]
+    pub startup_skip_to_latest_lag: Option<String>,
+
+    /// When a shard is not polled again within this long after returning a batch, e.g. `2m`, warn
+    /// that the downstream is stalled and renew the shard iterator every such timeout, so that
+    /// it does not expire. Disabled by default.
+    #[serde(rename = "stall.watchdog.timeout")]
+    pub stall_watchdog_timeout: Option<String>,
+
+    /// Warn when the arrival timestamps of records are ahead of the local clock by more than this,
+    /// which breaks the watermarks, `30s` by default.
+    #[serde(rename = "clock.skew.threshold")]
+    pub clock_skew_threshold: Option<String>,
+
+    /// How often each shard checks the clock skew after its first batch, `1m` by default.
+    #[serde(rename = "clock.skew.check.interval")]
+    pub clock_skew_check_interval: Option<String>,
+
+    /// Only emit the records whose hash key is within the inclusive range `<start>,<end>` of
+    /// decimal hash keys, e.g. the hash key range of a shard. The hash key of a record is its
+    /// explicit hash key, or the MD5 of its partition key. The offsets advance past the records
+    /// filtered out.
+    #[serde(rename = "hash_key.range")]
+    pub hash_key_range: Option<String>,
+
+    /// The minimum interval between progress reports of a shard to the reader's
+    /// [`source::progress::ProgressReporter`], 10s by default.
+    #[serde(rename = "progress.report.interval")]
+    pub progress_report_interval: Option<String>,
+
+    /// Only emit records that arrived at least this many milliseconds ago, withholding younger
+    /// ones until they age, to stay clear of producer-side reordering at the tip. Disabled by
+    /// default.
+    #[serde(rename = "read.safety.lag.ms")]
+    pub read_safety_lag_ms: Option<String>,
+
+    /// The lag behind the tip in milliseconds beyond which a shard breaches its freshness SLA.
+    /// A shard breaching it for longer than `freshness.sla.grace.ms` is recommended to scale out
+    /// to the reader's [`source::freshness::ScalingListener`]. Disabled by default.
+    #[serde(rename = "freshness.sla.ms")]
+    pub freshness_sla_ms: Option<String>,
+    /// How long in milliseconds a shard may breach `freshness.sla.ms` before scale-out is
+    /// recommended, 60000 by default.
+    #[serde(rename = "freshness.sla.grace.ms")]
+    pub freshness_sla_grace_ms: Option<String>,
+
+    /// The service behind `endpoint`: `aws` (default) or `localstack`, which tolerates the quirks
+    /// of its Kinesis-compatible API, see [`config::EndpointFlavor`].
+    #[serde(rename = "endpoint.flavor")]
+    pub endpoint_flavor: Option<String>,
+
+    /// The DynamoDB lease table of a Kinesis Client Library (KCL) application, to start new
+    /// shards from its checkpoints and write checkpoints back to, see [`lease::LeaseTable`].
+    #[serde(rename = "checkpoint.kcl.lease_table")]
+    pub checkpoint_kcl_lease_table: Option<String>,
+
+    /// The owner written to the KCL leases, `risingwave` by default.
+    #[serde(rename = "checkpoint.kcl.owner")]
+    pub checkpoint_kcl_owner: Option<String>,
+
+    /// The DynamoDB endpoint of the KCL lease table, e.g. for LocalStack.
+    #[serde(rename = "checkpoint.kcl.endpoint")]
+    pub checkpoint_kcl_endpoint: Option<String>,
+
+    /// The path of a JSON file mapping shards to the positions to start them from, overriding the
+    /// startup mode when the shards are first listed, see [`manifest::ResumeManifest`].
+    #[serde(rename = "resume.manifest")]
+    pub resume_manifest: Option<String>,
 }
@@ -0,0 +1,466 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::{Debug, Display, Formatter};
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use aws_sdk_kinesis::types::SdkError;
+use aws_smithy_types::retry::{ErrorKind, ProvideErrorKind};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::source::kinesis::clock::{Clock, ClockRef};
+use crate::source::kinesis::config::{parse_duration_property, parse_property};
+use crate::source::kinesis::KinesisProperties;
+
+const DEFAULT_MAX_ATTEMPTS: usize = 3;
+const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(100);
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// The kinds of errors which may succeed when retried.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum RetryKind {
+    /// The request was throttled, e.g. `ProvisionedThroughputExceededException`.
+    Throttling,
+    /// The request timed out.
+    Timeout,
+    /// A network failure or a server side error.
+    Transient,
+}
+
+impl FromStr for RetryKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("throttling") {
+            Ok(Self::Throttling)
+        } else if s.eq_ignore_ascii_case("timeout") {
+            Ok(Self::Timeout)
+        } else if s.eq_ignore_ascii_case("transient") {
+            Ok(Self::Transient)
+        } else {
+            Err(anyhow!("expect one of throttling, timeout and transient"))
+        }
+    }
+}
+
+/// Classifies an error for [`with_retry`].
+pub trait Retryable {
+    /// Returns `None` if the error is not retryable.
+    fn retry_kind(&self) -> Option<RetryKind>;
+}
+
+impl<E: ProvideErrorKind> Retryable for SdkError<E> {
+    fn retry_kind(&self) -> Option<RetryKind> {
+        match self {
+            SdkError::TimeoutError(_) => Some(RetryKind::Timeout),
+            SdkError::DispatchFailure(_) => Some(RetryKind::Transient),
+            SdkError::ServiceError { err, .. } => match err.code() {
+                Some(
+                    "ProvisionedThroughputExceededException"
+                    | "LimitExceededException"
+                    | "KMSThrottlingException",
+                ) => Some(RetryKind::Throttling),
+                _ => match err.retryable_error_kind() {
+                    Some(ErrorKind::ThrottlingError) => Some(RetryKind::Throttling),
+                    Some(ErrorKind::TransientError | ErrorKind::ServerError) => {
+                        Some(RetryKind::Transient)
+                    }
+                    _ => None,
+                },
+            },
+            _ => None,
+        }
+    }
+}
+
+/// The randomness of the jitter of retries, the thread RNG unless seeded by `retry.jitter.seed`, so
+/// that the delays are reproducible, e.g. in tests.
+#[derive(Clone, Default)]
+pub struct JitterRng(Option<(u64, Arc<Mutex<StdRng>>)>);
+
+impl JitterRng {
+    pub fn seeded(seed: u64) -> Self {
+        Self(Some((
+            seed,
+            Arc::new(Mutex::new(StdRng::seed_from_u64(seed))),
+        )))
+    }
+
+    /// Returns a generator seeded by the seed of this one and `salt`, e.g. the id of a shard, so
+    /// that the shards retrying together do not share delays. The thread RNG is returned as is.
+    pub fn derive(&self, salt: &str) -> Self {
+        match &self.0 {
+            // FNV-1a, which is stable across processes unlike the default hasher.
+            Some((seed, _)) => Self::seeded(salt.bytes().fold(*seed, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })),
+            None => Self(None),
+        }
+    }
+
+    /// Returns a factor in `[0.5, 1.0]` to scale a delay by.
+    fn gen_factor(&self) -> f64 {
+        match &self.0 {
+            Some((_, rng)) => rng.lock().unwrap().gen_range(0.5..=1.0),
+            None => rand::thread_rng().gen_range(0.5..=1.0),
+        }
+    }
+}
+
+impl Debug for JitterRng {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Some((seed, _)) => write!(f, "JitterRng(seed {})", seed),
+            None => write!(f, "JitterRng(thread)"),
+        }
+    }
+}
+
+/// Generators are equal by their seeds, regardless of the numbers generated since.
+impl PartialEq for JitterRng {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_ref().map(|(seed, _)| seed) == other.0.as_ref().map(|(seed, _)| seed)
+    }
+}
+
+impl Eq for JitterRng {}
+
+/// How AWS calls are retried, configured by the `retry.*` properties.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts including the first one.
+    pub max_attempts: usize,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Randomize each delay between half and all of it, so that shards throttled together do not
+    /// retry together.
+    pub jitter: bool,
+    pub rng: JitterRng,
+    pub retry_on: Vec<RetryKind>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            base_delay: DEFAULT_BASE_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            jitter: true,
+            rng: JitterRng::default(),
+            retry_on: vec![
+                RetryKind::Throttling,
+                RetryKind::Timeout,
+                RetryKind::Transient,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Self> {
+        let default = Self::default();
+        let max_attempts = parse_property::<usize>(
+            "retry.max_attempts",
+            properties.retry_max_attempts.as_deref(),
+        )?
+        .unwrap_or(default.max_attempts);
+        if max_attempts == 0 {
+            return Err(anyhow!("retry.max_attempts should be positive"));
+        }
+        let retry_on = match properties.retry_on.as_deref() {
+            Some(kinds) => kinds
+                .split(',')
+                .map(str::trim)
+                .filter(|kind| !kind.is_empty())
+                .map(|kind| parse_property("retry.on", Some(kind)).map(Option::unwrap))
+                .collect::<Result<Vec<_>>>()?,
+            None => default.retry_on,
+        };
+        Ok(Self {
+            max_attempts,
+            base_delay: parse_duration_property(
+                "retry.base_delay",
+                properties.retry_base_delay.as_deref(),
+            )?
+            .unwrap_or(default.base_delay),
+            max_delay: parse_duration_property(
+                "retry.max_delay",
+                properties.retry_max_delay.as_deref(),
+            )?
+            .unwrap_or(default.max_delay),
+            jitter: parse_property("retry.jitter", properties.retry_jitter.as_deref())?
+                .unwrap_or(default.jitter),
+            rng: parse_property::<u64>(
+                "retry.jitter.seed",
+                properties.retry_jitter_seed.as_deref(),
+            )?
+            .map_or(default.rng, JitterRng::seeded),
+            retry_on,
+        })
+    }
+
+    /// Returns the delay before the retry following the failed `attempt`, counting from 0,
+    /// without jitter.
+    pub fn delay(&self, attempt: usize) -> Duration {
+        let factor = 1u32.checked_shl(attempt as u32).unwrap_or(u32::MAX);
+        self.base_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    fn jittered_delay(&self, attempt: usize) -> Duration {
+        let delay = self.delay(attempt);
+        if self.jitter {
+            delay.mul_f64(self.rng.gen_factor())
+        } else {
+            delay
+        }
+    }
+}
+
+/// Calls `f` until it succeeds, fails with an error not retryable under `policy`, or runs out of
+/// attempts, sleeping on `clock` between attempts.
+pub async fn with_retry<T, E, F, Fut>(
+    policy: &RetryPolicy,
+    clock: &dyn Clock,
+    mut f: F,
+) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Retryable + Display,
+{
+    let mut attempt = 0;
+    loop {
+        let err = match f().await {
+            Ok(output) => return Ok(output),
+            Err(err) => err,
+        };
+        let retryable = err
+            .retry_kind()
+            .map_or(false, |kind| policy.retry_on.contains(&kind));
+        attempt += 1;
+        if !retryable || attempt >= policy.max_attempts {
+            return Err(err);
+        }
+        let delay = policy.jittered_delay(attempt - 1);
+        tracing::warn!(
+            kind = ?err.retry_kind(),
+            attempt,
+            delay = ?delay,
+            error = %err,
+            "kinesis api call failed, back off and retry"
+        );
+        clock.sleep(delay).await;
+    }
+}
+
+/// Shares the backoff of concurrent calls, so that once one of them backs off, the others wait as
+/// well instead of adding to the load of a throttled API. Used as the clock of [`with_retry`],
+/// with [`SharedBackoff::wait`] called before each attempt.
+#[derive(Debug)]
+pub struct SharedBackoff {
+    clock: ClockRef,
+    /// When the latest backoff ends.
+    until: Mutex<Option<Instant>>,
+}
+
+impl SharedBackoff {
+    pub fn new(clock: ClockRef) -> Self {
+        Self {
+            clock,
+            until: Mutex::new(None),
+        }
+    }
+
+    /// Waits until the latest backoff ends.
+    pub async fn wait(&self) {
+        loop {
+            let until = *self.until.lock().unwrap();
+            let now = self.clock.now();
+            match until {
+                Some(until) if until > now => self.clock.sleep(until - now).await,
+                _ => return,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Clock for SharedBackoff {
+    fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        {
+            let mut until = self.until.lock().unwrap();
+            let end = self.clock.now() + duration;
+            *until = Some(until.map_or(end, |until| until.max(end)));
+        }
+        self.wait().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::source::kinesis::clock::MockClock;
+
+    #[derive(Debug)]
+    struct MockError(Option<RetryKind>);
+
+    impl Display for MockError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "mock error {:?}", self.0)
+        }
+    }
+
+    impl Retryable for MockError {
+        fn retry_kind(&self) -> Option<RetryKind> {
+            self.0
+        }
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(30),
+            jitter: false,
+            rng: JitterRng::default(),
+            retry_on: vec![RetryKind::Throttling],
+        }
+    }
+
+    /// Fails with `error` for the first `failures` calls.
+    async fn call(
+        policy: &RetryPolicy,
+        clock: &MockClock,
+        failures: usize,
+        error: Option<RetryKind>,
+    ) -> (Result<(), MockError>, usize) {
+        let calls = &AtomicUsize::new(0);
+        let result = with_retry(policy, clock, move || async move {
+            if calls.fetch_add(1, Ordering::SeqCst) < failures {
+                Err(MockError(error))
+            } else {
+                Ok(())
+            }
+        })
+        .await;
+        (result, calls.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = policy();
+        let delays = (0..4)
+            .map(|attempt| policy.delay(attempt))
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [10, 20, 30, 30].map(Duration::from_millis).to_vec());
+        assert_eq!(policy.delay(100), policy.max_delay);
+
+        let policy = RetryPolicy {
+            jitter: true,
+            ..policy
+        };
+        for attempt in 0..4 {
+            let delay = policy.jittered_delay(attempt);
+            assert!(delay >= policy.delay(attempt) / 2 && delay <= policy.delay(attempt));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_seeded_jitter() {
+        let seeded = |seed: u64| RetryPolicy {
+            max_attempts: 6,
+            jitter: true,
+            rng: JitterRng::seeded(seed),
+            ..policy()
+        };
+        let delays = |policy: &RetryPolicy| {
+            (0..16)
+                .map(|attempt| policy.jittered_delay(attempt % 4))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(delays(&seeded(42)), delays(&seeded(42)));
+        assert_ne!(delays(&seeded(42)), delays(&seeded(43)));
+
+        // The backoff of `with_retry` is reproducible as well.
+        let sleeps = |policy: RetryPolicy| async move {
+            let clock = MockClock::new();
+            call(&policy, &clock, 5, Some(RetryKind::Throttling)).await;
+            clock.sleeps()
+        };
+        let first = sleeps(seeded(7)).await;
+        assert_eq!(first.len(), 5);
+        assert_eq!(first, sleeps(seeded(7)).await);
+
+        // Shards derive distinct but reproducible generators.
+        let shard = |id: &str| RetryPolicy {
+            rng: JitterRng::seeded(42).derive(id),
+            ..seeded(42)
+        };
+        assert_eq!(
+            delays(&shard("shardId-000000000000")),
+            delays(&shard("shardId-000000000000"))
+        );
+        assert_ne!(
+            delays(&shard("shardId-000000000000")),
+            delays(&shard("shardId-000000000001"))
+        );
+        assert_eq!(JitterRng::default().derive("shard"), JitterRng::default());
+    }
+
+    #[tokio::test]
+    async fn test_with_retry() {
+        let policy = policy();
+
+        let clock = MockClock::new();
+        let (result, calls) = call(&policy, &clock, 2, Some(RetryKind::Throttling)).await;
+        assert!(result.is_ok());
+        assert_eq!(calls, 3);
+        assert_eq!(clock.sleeps(), [10, 20].map(Duration::from_millis).to_vec());
+
+        // Gives up after the maximum attempts.
+        let clock = MockClock::new();
+        let (result, calls) = call(&policy, &clock, 10, Some(RetryKind::Throttling)).await;
+        assert!(result.is_err());
+        assert_eq!(calls, 4);
+        assert_eq!(
+            clock.sleeps(),
+            [10, 20, 30].map(Duration::from_millis).to_vec()
+        );
+
+        // Errors not retryable under the policy fail immediately.
+        let clock = MockClock::new();
+        let (result, calls) = call(&policy, &clock, 10, Some(RetryKind::Timeout)).await;
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+        let (result, calls) = call(&policy, &clock, 10, None).await;
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+        assert!(clock.sleeps().is_empty());
+    }
+}
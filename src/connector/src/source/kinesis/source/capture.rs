@@ -0,0 +1,163 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Capturing the raw `GetRecords` responses of a shard to a local file, and replaying them
+//! offline, so that an incident on live data can be turned into a repeatable test case. A capture
+//! file has a JSON encoded [`CapturedResponse`] per line.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use aws_sdk_kinesis::model::Record;
+use aws_sdk_kinesis::output::GetRecordsOutput;
+use aws_smithy_types::{base64, Blob, DateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::source::kinesis::source::message::datetime_to_millis;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapturedRecord {
+    pub sequence_number: Option<String>,
+    pub partition_key: Option<String>,
+    /// Base64 encoded data.
+    pub data: Option<String>,
+    pub approximate_arrival_timestamp_millis: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapturedResponse {
+    pub records: Vec<CapturedRecord>,
+    pub next_shard_iterator: Option<String>,
+    pub millis_behind_latest: Option<i64>,
+}
+
+impl From<&GetRecordsOutput> for CapturedResponse {
+    fn from(output: &GetRecordsOutput) -> Self {
+        Self {
+            records: output
+                .records()
+                .unwrap_or_default()
+                .iter()
+                .map(|record| CapturedRecord {
+                    sequence_number: record.sequence_number().map(String::from),
+                    partition_key: record.partition_key().map(String::from),
+                    data: record.data().map(|data| base64::encode(data.as_ref())),
+                    approximate_arrival_timestamp_millis: record
+                        .approximate_arrival_timestamp()
+                        .map(datetime_to_millis),
+                })
+                .collect(),
+            next_shard_iterator: output.next_shard_iterator().map(String::from),
+            millis_behind_latest: output.millis_behind_latest(),
+        }
+    }
+}
+
+impl TryFrom<CapturedResponse> for GetRecordsOutput {
+    type Error = anyhow::Error;
+
+    fn try_from(response: CapturedResponse) -> Result<Self> {
+        let records = response
+            .records
+            .into_iter()
+            .map(|record| {
+                let data = record
+                    .data
+                    .map(|data| base64::decode(&data).map(Blob::new))
+                    .transpose()
+                    .map_err(|e| anyhow!("invalid captured record data: {}", e))?;
+                Ok(Record::builder()
+                    .set_sequence_number(record.sequence_number)
+                    .set_partition_key(record.partition_key)
+                    .set_data(data)
+                    .set_approximate_arrival_timestamp(
+                        record
+                            .approximate_arrival_timestamp_millis
+                            .map(DateTime::from_millis),
+                    )
+                    .build())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(GetRecordsOutput::builder()
+            .set_records(Some(records))
+            .set_next_shard_iterator(response.next_shard_iterator)
+            .set_millis_behind_latest(response.millis_behind_latest)
+            .build())
+    }
+}
+
+/// Appends the `GetRecords` responses of a shard to a capture file.
+#[derive(Debug)]
+pub struct CaptureSink {
+    writer: BufWriter<File>,
+}
+
+impl CaptureSink {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::create(path.as_ref()).map_err(|e| {
+            anyhow!(
+                "failed to create capture file {}: {}",
+                path.as_ref().display(),
+                e
+            )
+        })?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    pub fn write(&mut self, output: &GetRecordsOutput) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, &CapturedResponse::from(output))?;
+        self.writer.write_all(b"\n")?;
+        // Flush every response, so that the capture survives a crash.
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Replays the `GetRecords` responses of a capture file in order. Once exhausted, it reports the
+/// shard as closed.
+#[derive(Debug)]
+pub struct ReplaySource {
+    responses: VecDeque<GetRecordsOutput>,
+}
+
+impl ReplaySource {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path.as_ref()).map_err(|e| {
+            anyhow!(
+                "failed to open capture file {}: {}",
+                path.as_ref().display(),
+                e
+            )
+        })?;
+        let responses = BufReader::new(file)
+            .lines()
+            .map(|line| {
+                let response: CapturedResponse = serde_json::from_str(&line?)?;
+                GetRecordsOutput::try_from(response)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { responses })
+    }
+
+    pub fn next_output(&mut self) -> GetRecordsOutput {
+        self.responses
+            .pop_front()
+            .unwrap_or_else(|| GetRecordsOutput::builder().build())
+    }
+}
@@ -0,0 +1,93 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::source::kinesis::config::parse_duration_property;
+use crate::source::kinesis::KinesisProperties;
+use crate::source::SourceMessage;
+
+/// Coalesces the offsets surfaced as the state of a shard, see `checkpoint.min.interval`. Within
+/// the interval since the last surfaced offset, the last record of a batch carries that offset as
+/// its `state_offset`, so the state of the shard does not change, while the offsets of the records
+/// are left as is. The latest offset is surfaced by the first batch after the interval, by
+/// [`Self::flush`] once the interval expires on an idle shard, or by the last batch of the shard.
+#[derive(Debug, Clone)]
+pub struct OffsetCoalescer {
+    interval: Duration,
+    /// The last surfaced offset and when it was surfaced.
+    surfaced: Option<(String, Instant)>,
+    /// The offset of the last record emitted since, not surfaced yet.
+    pending: Option<String>,
+}
+
+impl OffsetCoalescer {
+    /// Returns `None` if `checkpoint.min.interval` is not set or zero, which surfaces the offset
+    /// of every batch.
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Option<Self>> {
+        let interval = parse_duration_property(
+            "checkpoint.min.interval",
+            properties.checkpoint_min_interval.as_deref(),
+        )?;
+        Ok(interval
+            .filter(|interval| !interval.is_zero())
+            .map(|interval| Self {
+                interval,
+                surfaced: None,
+                pending: None,
+            }))
+    }
+
+    /// Keeps the state of the shard at the last surfaced offset if it was surfaced within the
+    /// interval before `now`, unless `last` is set for the last batch of the shard.
+    pub fn coalesce(&mut self, chunk: &mut [SourceMessage], now: Instant, last: bool) {
+        let msg = match chunk.iter_mut().rev().find(|msg| msg.payload.is_some()) {
+            Some(msg) => msg,
+            None => {
+                // The last batch of the shard without records, e.g. only its end, surfaces the
+                // pending offset.
+                if let (true, Some(msg)) = (last, chunk.last_mut()) {
+                    msg.state_offset = self.pending.take();
+                }
+                return;
+            }
+        };
+        match &self.surfaced {
+            Some((offset, surfaced_at))
+                if !last && now.duration_since(*surfaced_at) < self.interval =>
+            {
+                self.pending = Some(msg.offset.clone());
+                msg.state_offset = Some(offset.clone());
+            }
+            _ => {
+                self.surfaced = Some((msg.offset.clone(), now));
+                self.pending = None;
+            }
+        }
+    }
+
+    /// Returns the offset not surfaced yet once the interval since the last surfaced offset has
+    /// expired at `now`, so that a shard going idle after a burst surfaces its latest offset.
+    pub fn flush(&mut self, now: Instant) -> Option<String> {
+        let (_, surfaced_at) = self.surfaced.as_ref()?;
+        if now.duration_since(*surfaced_at) < self.interval {
+            return None;
+        }
+        let offset = self.pending.take()?;
+        self.surfaced = Some((offset.clone(), now));
+        Some(offset)
+    }
+}
@@ -0,0 +1,196 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+
+use crate::source::kinesis::config::parse_property;
+use crate::source::kinesis::KinesisProperties;
+use crate::source::{SourceMessage, SourceMeta, SplitId};
+
+/// Summarizes a batch of a shard for the scheduling decisions of downstream operators, see
+/// `KinesisSplitReader::next_with_meta`. Heartbeats and shard ends carry no records and are not
+/// counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchMeta {
+    pub records: usize,
+    /// The bytes of payload.
+    pub bytes: usize,
+    /// The earliest approximate arrival timestamp of the records in milliseconds.
+    pub min_arrival: Option<i64>,
+    /// The latest approximate arrival timestamp of the records in milliseconds.
+    pub max_arrival: Option<i64>,
+    /// `MillisBehindLatest` of the last `GetRecords` call of the batch.
+    pub lag_millis: Option<i64>,
+}
+
+impl BatchMeta {
+    pub fn summarize(batch: &[SourceMessage], lag_millis: Option<i64>) -> Self {
+        let mut meta = Self {
+            lag_millis,
+            ..Default::default()
+        };
+        for msg in batch {
+            let payload = match &msg.payload {
+                Some(payload) => payload,
+                None => continue,
+            };
+            meta.records += 1;
+            meta.bytes += payload.len();
+            if let SourceMeta::Kinesis(kinesis) = &msg.meta {
+                if let Some(arrival) = kinesis.timestamp {
+                    meta.min_arrival =
+                        Some(meta.min_arrival.map_or(arrival, |min| min.min(arrival)));
+                    meta.max_arrival =
+                        Some(meta.max_arrival.map_or(arrival, |max| max.max(arrival)));
+                }
+            }
+        }
+        meta
+    }
+}
+
+/// Splits the batches of a shard so that none exceeds `max_chunk_records` messages or
+/// `max_chunk_bytes` bytes of payload, whichever is hit first. A message is never split, so a
+/// single message larger than the byte budget makes a chunk of its own.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkSplitter {
+    max_records: Option<usize>,
+    max_bytes: Option<usize>,
+}
+
+impl ChunkSplitter {
+    /// Returns `None` if neither `max_chunk_records` nor `max_chunk_bytes` is set.
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Option<Self>> {
+        let max_records =
+            parse_property::<usize>("max_chunk_records", properties.max_chunk_records.as_deref())?;
+        let max_bytes =
+            parse_property::<usize>("max_chunk_bytes", properties.max_chunk_bytes.as_deref())?;
+        if max_records == Some(0) || max_bytes == Some(0) {
+            return Err(anyhow!(
+                "max_chunk_records and max_chunk_bytes should be positive"
+            ));
+        }
+        if max_records.is_none() && max_bytes.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            max_records,
+            max_bytes,
+        }))
+    }
+
+    /// Splits `chunk` in order. An empty chunk is returned as is.
+    pub fn split(&self, chunk: Vec<SourceMessage>) -> Vec<Vec<SourceMessage>> {
+        let mut chunks = vec![];
+        let mut current: Vec<SourceMessage> = vec![];
+        let mut current_bytes = 0;
+        for msg in chunk {
+            let bytes = msg.payload.as_ref().map_or(0, |payload| payload.len());
+            let full = self.max_records.map_or(false, |max| current.len() >= max)
+                || self
+                    .max_bytes
+                    .map_or(false, |max| current_bytes + bytes > max);
+            if full && !current.is_empty() {
+                chunks.push(std::mem::take(&mut current));
+                current_bytes = 0;
+            }
+            current_bytes += bytes;
+            current.push(msg);
+        }
+        if !current.is_empty() || chunks.is_empty() {
+            chunks.push(current);
+        }
+        chunks
+    }
+}
+
+/// How much the downstream can take in the next chunk of a shard, reported by [`FlowControl`].
+/// `None` is unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capacity {
+    pub records: Option<usize>,
+    /// The bytes of payload.
+    pub bytes: Option<usize>,
+}
+
+impl Capacity {
+    pub fn is_exhausted(&self) -> bool {
+        self.records == Some(0) || self.bytes == Some(0)
+    }
+
+    /// Truncates `chunk` to the messages within the capacity, returning the rest. Like
+    /// [`ChunkSplitter`], the first message is kept even if it is larger than the byte capacity,
+    /// so that the shard makes progress.
+    pub fn split_off(&self, chunk: &mut Vec<SourceMessage>) -> Vec<SourceMessage> {
+        let mut bytes = 0;
+        let end = chunk
+            .iter()
+            .enumerate()
+            .position(|(i, msg)| {
+                bytes += msg.payload.as_ref().map_or(0, |payload| payload.len());
+                i > 0
+                    && (self.records.map_or(false, |max| i >= max)
+                        || self.bytes.map_or(false, |max| bytes > max))
+            })
+            .unwrap_or(chunk.len());
+        chunk.split_off(end)
+    }
+}
+
+/// Reports the capacity of the downstream, e.g. the free space of its channel, so that a shard
+/// reader emits no more of a batch per `next` than the downstream can take and buffers the rest.
+/// Called before each chunk is returned, so implementations should not block.
+pub trait FlowControl: Debug + Send + Sync {
+    fn available(&self, split_id: &SplitId) -> Capacity;
+}
+
+pub type FlowControlRef = Arc<dyn FlowControl>;
+
+/// Coalesces the batches of a shard fetched within `batch.window.ms` into one, returned at the
+/// first fetch after the window elapses or once it holds `batch.window.max.records` messages, to
+/// reduce the per-batch overhead downstream for low-volume shards.
+#[derive(Debug, Clone, Copy)]
+pub struct BatchWindow {
+    window: Duration,
+    max_records: Option<usize>,
+}
+
+impl BatchWindow {
+    /// Returns `None` if `batch.window.ms` is not set.
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Option<Self>> {
+        let window =
+            parse_property::<u64>("batch.window.ms", properties.batch_window_ms.as_deref())?;
+        let max_records = parse_property::<usize>(
+            "batch.window.max.records",
+            properties.batch_window_max_records.as_deref(),
+        )?;
+        if max_records == Some(0) {
+            return Err(anyhow!("batch.window.max.records should be positive"));
+        }
+        Ok(window.map(|millis| Self {
+            window: Duration::from_millis(millis),
+            max_records,
+        }))
+    }
+
+    /// Returns whether the window opened at `opened` and holding `batch` is closed at `now`.
+    pub fn is_closed(&self, opened: Instant, now: Instant, batch: &[SourceMessage]) -> bool {
+        now.duration_since(opened) >= self.window
+            || self.max_records.map_or(false, |max| batch.len() >= max)
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+
+use crate::source::kinesis::config::{parse_duration_property, parse_property};
+use crate::source::kinesis::KinesisProperties;
+
+const DEFAULT_FAILURE_WINDOW: Duration = Duration::from_secs(60);
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(60);
+
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub struct CircuitBreakerConfig {
+    /// The number of consecutive failures within `window` which opens the breaker.
+    pub failure_threshold: usize,
+    pub window: Duration,
+    /// How long polls are paused once the breaker opens.
+    pub cooldown: Duration,
+}
+
+impl CircuitBreakerConfig {
+    /// Returns `None` if `circuit_breaker.failure_threshold` is not set, which disables the
+    /// breaker.
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Option<Self>> {
+        let failure_threshold = match parse_property::<usize>(
+            "circuit_breaker.failure_threshold",
+            properties.circuit_breaker_failure_threshold.as_deref(),
+        )? {
+            Some(0) => {
+                return Err(anyhow!(
+                    "circuit_breaker.failure_threshold should be positive"
+                ))
+            }
+            Some(threshold) => threshold,
+            None => return Ok(None),
+        };
+        let window = parse_duration_property(
+            "circuit_breaker.window",
+            properties.circuit_breaker_window.as_deref(),
+        )?
+        .unwrap_or(DEFAULT_FAILURE_WINDOW);
+        let cooldown = parse_duration_property(
+            "circuit_breaker.cooldown",
+            properties.circuit_breaker_cooldown.as_deref(),
+        )?
+        .unwrap_or(DEFAULT_COOLDOWN);
+        Ok(Some(Self {
+            failure_threshold,
+            window,
+            cooldown,
+        }))
+    }
+}
+
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Polls go through.
+    Closed,
+    /// Polls are paused until the cooldown elapses.
+    Open,
+    /// The cooldown has elapsed, and the next poll decides whether to close or reopen.
+    HalfOpen,
+}
+
+/// Pauses polling a shard after repeated failures, so that a persistently failing shard does not
+/// burn the API quota.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    /// Times of the consecutive failures within the window.
+    failures: VecDeque<Instant>,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            failures: VecDeque::new(),
+            opened_at: None,
+        }
+    }
+
+    pub fn state(&self, now: Instant) -> BreakerState {
+        match self.opened_at {
+            None => BreakerState::Closed,
+            Some(opened_at) if now.duration_since(opened_at) < self.config.cooldown => {
+                BreakerState::Open
+            }
+            Some(_) => BreakerState::HalfOpen,
+        }
+    }
+
+    /// Returns how long polls should still be paused.
+    pub fn remaining_cooldown(&self, now: Instant) -> Option<Duration> {
+        match self.state(now) {
+            BreakerState::Open => Some(
+                self.config
+                    .cooldown
+                    .saturating_sub(now.duration_since(self.opened_at.unwrap())),
+            ),
+            _ => None,
+        }
+    }
+
+    pub fn on_success(&mut self) {
+        self.failures.clear();
+        self.opened_at = None;
+    }
+
+    pub fn on_failure(&mut self, now: Instant) {
+        match self.state(now) {
+            // The trial poll failed.
+            BreakerState::HalfOpen => self.opened_at = Some(now),
+            BreakerState::Open => {}
+            BreakerState::Closed => {
+                while self.failures.front().map_or(false, |first| {
+                    now.duration_since(*first) > self.config.window
+                }) {
+                    self.failures.pop_front();
+                }
+                self.failures.push_back(now);
+                if self.failures.len() >= self.config.failure_threshold {
+                    self.failures.clear();
+                    self.opened_at = Some(now);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker_transitions() {
+        let mut breaker = CircuitBreaker::new(CircuitBreakerConfig {
+            failure_threshold: 3,
+            window: Duration::from_secs(10),
+            cooldown: Duration::from_secs(30),
+        });
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // Failures outside the window do not add up.
+        breaker.on_failure(at(0));
+        breaker.on_failure(at(1));
+        breaker.on_failure(at(20));
+        assert_eq!(breaker.state(at(20)), BreakerState::Closed);
+
+        breaker.on_failure(at(21));
+        breaker.on_failure(at(22));
+        assert_eq!(breaker.state(at(22)), BreakerState::Open);
+        assert_eq!(
+            breaker.remaining_cooldown(at(32)),
+            Some(Duration::from_secs(20))
+        );
+
+        // A failed trial reopens the breaker immediately.
+        assert_eq!(breaker.state(at(52)), BreakerState::HalfOpen);
+        assert_eq!(breaker.remaining_cooldown(at(52)), None);
+        breaker.on_failure(at(52));
+        assert_eq!(breaker.state(at(53)), BreakerState::Open);
+
+        // A successful trial closes it.
+        assert_eq!(breaker.state(at(82)), BreakerState::HalfOpen);
+        breaker.on_success();
+        assert_eq!(breaker.state(at(82)), BreakerState::Closed);
+        breaker.on_failure(at(83));
+        assert_eq!(breaker.state(at(83)), BreakerState::Closed);
+    }
+}
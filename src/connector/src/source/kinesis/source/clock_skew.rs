@@ -0,0 +1,66 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::source::kinesis::config::parse_duration_property;
+use crate::source::kinesis::KinesisProperties;
+
+const DEFAULT_THRESHOLD: Duration = Duration::from_secs(30);
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Compares the arrival timestamps of the newest records with the local clock on the first batch
+/// of a shard and at most once per `clock.skew.check.interval` after, to diagnose watermarks
+/// broken by a local clock behind that of AWS. A record can not arrive in the future, so arrivals
+/// ahead of the local clock by more than `clock.skew.threshold` are skew. A local clock ahead of
+/// AWS looks like lag instead, and is not detected.
+#[derive(Debug, Clone)]
+pub struct ClockSkewCheck {
+    threshold: Duration,
+    interval: Duration,
+    checked_at: Option<Instant>,
+}
+
+impl ClockSkewCheck {
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Self> {
+        Ok(Self {
+            threshold: parse_duration_property(
+                "clock.skew.threshold",
+                properties.clock_skew_threshold.as_deref(),
+            )?
+            .unwrap_or(DEFAULT_THRESHOLD),
+            interval: parse_duration_property(
+                "clock.skew.check.interval",
+                properties.clock_skew_check_interval.as_deref(),
+            )?
+            .unwrap_or(DEFAULT_INTERVAL),
+            checked_at: None,
+        })
+    }
+
+    /// Returns the skew in milliseconds if the check is due at `now` and `newest_arrival` is ahead
+    /// of `now_millis` by more than the threshold.
+    pub fn check(&mut self, now: Instant, now_millis: i64, newest_arrival: i64) -> Option<i64> {
+        if let Some(checked_at) = self.checked_at {
+            if now.duration_since(checked_at) < self.interval {
+                return None;
+            }
+        }
+        self.checked_at = Some(now);
+        let skew = newest_arrival - now_millis;
+        (skew > self.threshold.as_millis() as i64).then_some(skew)
+    }
+}
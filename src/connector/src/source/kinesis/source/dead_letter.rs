@@ -0,0 +1,51 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Records the reader can not decode, handed off to a dead-letter sink instead of being emitted
+//! or failing the shard, see `on_corrupted_aggregate`.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::source::kinesis::source::message::KinesisMessage;
+
+/// A record as read from the shard, before any payload transform, along with why it could not be
+/// decoded.
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub record: KinesisMessage,
+    pub reason: String,
+}
+
+/// Receives the dead letters of a shard. Called on the fetch path, so implementations should hand
+/// the records off without blocking.
+pub trait DeadLetterSink: Debug + Send + Sync {
+    fn send(&self, letter: DeadLetter);
+}
+
+pub type DeadLetterSinkRef = Arc<dyn DeadLetterSink>;
+
+/// Logs and drops the dead letters, used when no sink is set.
+#[derive(Debug, Default)]
+pub struct LoggingDeadLetterSink;
+
+impl DeadLetterSink for LoggingDeadLetterSink {
+    fn send(&self, letter: DeadLetter) {
+        tracing::warn!(
+            shard_id = %letter.record.shard_id,
+            sequence_number = %letter.record.sequence_number,
+            "drop kinesis dead letter: {}",
+            letter.reason
+        );
+    }
+}
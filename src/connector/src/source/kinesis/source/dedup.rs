@@ -0,0 +1,105 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashSet, VecDeque};
+
+use serde_json::Value;
+
+use crate::source::kinesis::config::DedupConfig;
+use crate::source::kinesis::source::message::KinesisMessage;
+
+/// Drops records duplicated by producer retries, which are written again with different sequence
+/// numbers. Records are identified by their partition key and the id found in their JSON payload
+/// at `dedup.id_path`. This is best-effort: only the ids of the last `dedup.window.size` records
+/// are remembered, and records without an id are never dropped.
+#[derive(Debug)]
+pub struct DedupWindow {
+    /// A JSON pointer, e.g. `/order/id`.
+    id_path: String,
+    capacity: usize,
+    ids: HashSet<(String, String)>,
+    /// The remembered ids from the oldest to the latest.
+    order: VecDeque<(String, String)>,
+}
+
+impl DedupWindow {
+    pub fn new(config: &DedupConfig) -> Self {
+        Self {
+            id_path: config.id_path.clone(),
+            capacity: config.window_size,
+            ids: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Returns whether `msg` duplicates a recent record, remembering its id otherwise.
+    pub fn is_duplicate(&mut self, msg: &KinesisMessage) -> bool {
+        let id = match serde_json::from_slice::<Value>(&msg.payload)
+            .ok()
+            .and_then(|payload| payload.pointer(&self.id_path).map(Value::to_string))
+        {
+            Some(id) => id,
+            None => return false,
+        };
+        let key = (msg.partition_key.clone(), id);
+        if self.ids.contains(&key) {
+            return true;
+        }
+        if self.order.len() == self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        self.ids.insert(key.clone());
+        self.order.push_back(key);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(partition_key: &str, payload: &str) -> KinesisMessage {
+        KinesisMessage {
+            shard_id: "shardId-000000000000".to_string().into(),
+            sequence_number: "1".to_string(),
+            partition_key: partition_key.to_string(),
+            explicit_hash_key: None,
+            sub_sequence_number: None,
+            schema_id: None,
+            payload: payload.to_string().into(),
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn test_dedup_window() {
+        let mut window = DedupWindow::new(&DedupConfig {
+            id_path: "/id".to_string(),
+            window_size: 2,
+        });
+        assert!(!window.is_duplicate(&message("a", r#"{"id": 1}"#)));
+        assert!(window.is_duplicate(&message("a", r#"{"id": 1}"#)));
+        // The same id under another partition key is another record.
+        assert!(!window.is_duplicate(&message("b", r#"{"id": 1}"#)));
+        // Records without an id are kept.
+        assert!(!window.is_duplicate(&message("a", "not json")));
+        assert!(!window.is_duplicate(&message("a", "not json")));
+
+        // The oldest id is evicted from the window.
+        assert!(!window.is_duplicate(&message("a", r#"{"id": 2}"#)));
+        assert!(!window.is_duplicate(&message("a", r#"{"id": 1}"#)));
+    }
+}
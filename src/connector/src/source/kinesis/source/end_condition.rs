@@ -0,0 +1,40 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use crate::source::SourceMessage;
+
+/// Ends a shard at the first message it matches, complementing the end position of the split for
+/// conditions only the payload tells, e.g. a sentinel record written by the producer. The matching
+/// message is the last one emitted, as with a sequence number end position.
+#[derive(Clone)]
+pub struct EndCondition(Arc<dyn Fn(&SourceMessage) -> bool + Send + Sync>);
+
+impl EndCondition {
+    pub fn new(predicate: impl Fn(&SourceMessage) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(predicate))
+    }
+
+    pub fn is_end(&self, msg: &SourceMessage) -> bool {
+        (self.0)(msg)
+    }
+}
+
+impl Debug for EndCondition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EndCondition")
+    }
+}
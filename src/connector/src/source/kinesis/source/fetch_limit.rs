@@ -0,0 +1,74 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Result;
+use aws_sdk_kinesis::model::Record;
+
+use crate::source::kinesis::config::parse_property;
+use crate::source::kinesis::KinesisProperties;
+
+/// The maximum number of records `GetRecords` returns in a call, and its default.
+const MAX_LIMIT: i32 = 10_000;
+/// The weight of the latest batch in the moving average of the record size.
+const SMOOTHING: f64 = 0.5;
+
+/// Adapts the `Limit` of `GetRecords` to the moving average of the record size, so that each call
+/// returns about `get_records.target_bytes`, instead of truncating batches of large records at
+/// the 10 MB cap of a call or under-filling batches of small ones.
+#[derive(Debug, Clone)]
+pub struct AdaptiveLimit {
+    target_bytes: f64,
+    /// `None` until a batch of records is observed.
+    average_record_bytes: Option<f64>,
+}
+
+impl AdaptiveLimit {
+    /// Returns `None` if `get_records.target_bytes` is not set, which leaves `Limit` unset.
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Option<Self>> {
+        let target_bytes = parse_property::<u64>(
+            "get_records.target_bytes",
+            properties.get_records_target_bytes.as_deref(),
+        )?;
+        Ok(target_bytes.map(|target_bytes| Self {
+            target_bytes: target_bytes as f64,
+            average_record_bytes: None,
+        }))
+    }
+
+    /// The `Limit` of the next call.
+    pub fn limit(&self) -> i32 {
+        match self.average_record_bytes {
+            Some(average) if average > 0.0 => {
+                ((self.target_bytes / average) as i32).clamp(1, MAX_LIMIT)
+            }
+            _ => MAX_LIMIT,
+        }
+    }
+
+    /// Updates the average record size with a fetched batch. Empty batches tell nothing.
+    pub fn observe(&mut self, records: &[Record]) {
+        if records.is_empty() {
+            return;
+        }
+        let bytes = records
+            .iter()
+            .map(|record| record.data().map_or(0, |data| data.as_ref().len()))
+            .sum::<usize>();
+        let batch_average = bytes as f64 / records.len() as f64;
+        self.average_record_bytes = Some(match self.average_record_bytes {
+            Some(average) => average * (1.0 - SMOOTHING) + batch_average * SMOOTHING,
+            None => batch_average,
+        });
+    }
+}
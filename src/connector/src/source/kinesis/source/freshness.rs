@@ -0,0 +1,116 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Detection of shards breaching a freshness SLA, i.e. lagging behind the tip by more than
+//! `freshness.sla.ms` for longer than `freshness.sla.grace.ms`, signalled to an autoscaling
+//! controller which may split the shard or add readers.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::source::kinesis::config::parse_property;
+use crate::source::kinesis::KinesisProperties;
+use crate::source::SplitId;
+
+const DEFAULT_GRACE_MILLIS: u64 = 60_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScalingSignal {
+    /// The lag of the shard has exceeded the SLA for longer than the grace period.
+    ScaleOutRecommended { shard_id: SplitId, lag_millis: i64 },
+    /// The lag of a shard recommended to scale out is back within the SLA.
+    Recovered { shard_id: SplitId },
+}
+
+/// Receives the scaling signals of the shards, at most one recommendation per breach. Called on
+/// the fetch path, so implementations should hand the signals off without blocking.
+pub trait ScalingListener: Debug + Send + Sync {
+    fn signal(&self, signal: ScalingSignal);
+}
+
+pub type ScalingListenerRef = Arc<dyn ScalingListener>;
+
+/// Discards the signals, used when no controller is set. The reader logs them anyway.
+#[derive(Debug, Default)]
+pub struct NoopScalingListener;
+
+impl ScalingListener for NoopScalingListener {
+    fn signal(&self, _signal: ScalingSignal) {}
+}
+
+/// Tracks the lag of a shard, `MillisBehindLatest` of its fetches, against the freshness SLA.
+#[derive(Debug, Clone)]
+pub struct FreshnessSla {
+    sla_millis: i64,
+    grace: Duration,
+    /// When the lag last went above the SLA, `None` while within it.
+    breached_since: Option<Instant>,
+    /// Whether scale-out has been recommended for the current breach.
+    recommended: bool,
+}
+
+impl FreshnessSla {
+    /// Returns `None` if `freshness.sla.ms` is not set.
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Option<Self>> {
+        let sla_millis = match parse_property::<u64>(
+            "freshness.sla.ms",
+            properties.freshness_sla_ms.as_deref(),
+        )? {
+            Some(sla_millis) => sla_millis,
+            None => return Ok(None),
+        };
+        let grace_millis = parse_property::<u64>(
+            "freshness.sla.grace.ms",
+            properties.freshness_sla_grace_ms.as_deref(),
+        )?
+        .unwrap_or(DEFAULT_GRACE_MILLIS);
+        Ok(Some(Self {
+            sla_millis: sla_millis as i64,
+            grace: Duration::from_millis(grace_millis),
+            breached_since: None,
+            recommended: false,
+        }))
+    }
+
+    /// Observes the lag of a fetch of the shard at `now`, returning the signal if the shard has
+    /// just breached the SLA for the grace period, or recovered from a breach signalled before.
+    pub fn observe(
+        &mut self,
+        shard_id: &SplitId,
+        now: Instant,
+        lag_millis: i64,
+    ) -> Option<ScalingSignal> {
+        if lag_millis <= self.sla_millis {
+            self.breached_since = None;
+            if !self.recommended {
+                return None;
+            }
+            self.recommended = false;
+            return Some(ScalingSignal::Recovered {
+                shard_id: shard_id.clone(),
+            });
+        }
+        let breached_since = *self.breached_since.get_or_insert(now);
+        if self.recommended || now.duration_since(breached_since) < self.grace {
+            return None;
+        }
+        self.recommended = true;
+        Some(ScalingSignal::ScaleOutRecommended {
+            shard_id: shard_id.clone(),
+            lag_millis,
+        })
+    }
+}
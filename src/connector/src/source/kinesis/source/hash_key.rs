@@ -0,0 +1,64 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{anyhow, Result};
+
+use crate::source::kinesis::source::message::KinesisMessage;
+use crate::source::kinesis::KinesisProperties;
+
+/// Returns the hash key which routes a record to the shard whose hash key range contains it: the
+/// explicit hash key if set, otherwise the MD5 of the partition key as a 128-bit integer.
+pub fn hash_key(partition_key: &str, explicit_hash_key: Option<&str>) -> Option<u128> {
+    match explicit_hash_key {
+        Some(explicit_hash_key) => explicit_hash_key.parse().ok(),
+        None => Some(u128::from_be_bytes(md5::compute(partition_key).0)),
+    }
+}
+
+/// The inclusive range of hash keys configured by `hash_key.range`, to read a logical slice of a
+/// shard, e.g. to reprocess the records of a child shard from its parent.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub struct HashKeyRange {
+    start: u128,
+    end: u128,
+}
+
+impl HashKeyRange {
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Option<Self>> {
+        let range = match properties.hash_key_range.as_deref() {
+            Some(range) => range,
+            None => return Ok(None),
+        };
+        let invalid = || {
+            anyhow!(
+                "invalid hash_key.range '{}', expect '<start>,<end>' of decimal hash keys",
+                range
+            )
+        };
+        let (start, end) = range.split_once(',').ok_or_else(invalid)?;
+        let start = start.trim().parse().map_err(|_| invalid())?;
+        let end = end.trim().parse().map_err(|_| invalid())?;
+        if start > end {
+            return Err(invalid());
+        }
+        Ok(Some(Self { start, end }))
+    }
+
+    /// Whether the hash key of `msg` is within the range. A message with an invalid explicit hash
+    /// key is not.
+    pub fn contains(&self, msg: &KinesisMessage) -> bool {
+        hash_key(&msg.partition_key, msg.explicit_hash_key.as_deref())
+            .map_or(false, |key| self.start <= key && key <= self.end)
+    }
+}
@@ -0,0 +1,81 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::source::SourceMessage;
+
+/// Caps the bytes of payload fetched by the shards of a multi split reader and not yet returned by
+/// `next`, see `max.inflight.bytes`. Fetching pauses on all the shards while the cap is reached.
+#[derive(Debug, Clone)]
+pub struct InflightBytes {
+    max: usize,
+    semaphore: Arc<Semaphore>,
+    buffered: Arc<AtomicUsize>,
+}
+
+impl InflightBytes {
+    /// Caps the bytes at `max`, which is at most `u32::MAX`, see
+    /// [`BatchConfig`](crate::source::kinesis::config::BatchConfig).
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            semaphore: Arc::new(Semaphore::new(max)),
+            buffered: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Waits until `chunk` fits under the cap, and returns the permit releasing its bytes when
+    /// dropped. A chunk larger than the cap waits until nothing else is buffered.
+    pub async fn acquire(&self, chunk: &[SourceMessage]) -> InflightPermit {
+        let bytes = chunk
+            .iter()
+            .map(|msg| msg.payload.as_ref().map_or(0, |payload| payload.len()))
+            .sum::<usize>();
+        let permit = self
+            .semaphore
+            .clone()
+            .acquire_many_owned(bytes.min(self.max) as u32)
+            .await
+            .expect("the semaphore is never closed");
+        self.buffered.fetch_add(bytes, Ordering::SeqCst);
+        InflightPermit {
+            _permit: permit,
+            bytes,
+            buffered: self.buffered.clone(),
+        }
+    }
+
+    /// The bytes of payload buffered now.
+    pub fn buffered(&self) -> usize {
+        self.buffered.load(Ordering::SeqCst)
+    }
+}
+
+/// The bytes of a buffered chunk, released when it is returned by `next` or discarded.
+#[derive(Debug)]
+pub struct InflightPermit {
+    _permit: OwnedSemaphorePermit,
+    bytes: usize,
+    buffered: Arc<AtomicUsize>,
+}
+
+impl Drop for InflightPermit {
+    fn drop(&mut self) {
+        self.buffered.fetch_sub(self.bytes, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of records aggregated by the Kinesis Producer Library (KPL). An aggregated record is
+//! the magic number, followed by a protobuf encoded [`AggregatedRecord`], followed by the MD5
+//! digest of the protobuf bytes.
+
+use prost::Message;
+use thiserror::Error;
+
+const KPL_MAGIC: [u8; 4] = [0xF3, 0x89, 0x9A, 0xC2];
+const DIGEST_LEN: usize = 16;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AggregatedRecord {
+    #[prost(string, repeated, tag = "1")]
+    pub partition_key_table: Vec<String>,
+    #[prost(string, repeated, tag = "2")]
+    pub explicit_hash_key_table: Vec<String>,
+    #[prost(message, repeated, tag = "3")]
+    pub records: Vec<SubRecord>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SubRecord {
+    #[prost(uint64, required, tag = "1")]
+    pub partition_key_index: u64,
+    #[prost(uint64, optional, tag = "2")]
+    pub explicit_hash_key_index: Option<u64>,
+    #[prost(bytes = "vec", required, tag = "3")]
+    pub data: Vec<u8>,
+}
+
+/// A payload starting with the magic number which is not a valid aggregated record, e.g. truncated
+/// or with a digest mismatch.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("corrupted KPL aggregated record: {0}")]
+pub struct CorruptedAggregate(pub String);
+
+/// Decodes `payload` if it is an aggregated record, or returns `None` for a plain record.
+pub fn deaggregate(payload: &[u8]) -> Result<Option<AggregatedRecord>, CorruptedAggregate> {
+    if payload.len() < KPL_MAGIC.len() + DIGEST_LEN || payload[..KPL_MAGIC.len()] != KPL_MAGIC {
+        return Ok(None);
+    }
+    let (message, digest) =
+        payload[KPL_MAGIC.len()..].split_at(payload.len() - KPL_MAGIC.len() - DIGEST_LEN);
+    if md5::compute(message).0 != digest {
+        return Err(CorruptedAggregate("digest mismatch".to_string()));
+    }
+    let record =
+        AggregatedRecord::decode(message).map_err(|e| CorruptedAggregate(e.to_string()))?;
+    for sub_record in &record.records {
+        if sub_record.partition_key_index as usize >= record.partition_key_table.len() {
+            return Err(CorruptedAggregate(format!(
+                "partition key index {} out of bounds",
+                sub_record.partition_key_index
+            )));
+        }
+        if let Some(index) = sub_record.explicit_hash_key_index {
+            if index as usize >= record.explicit_hash_key_table.len() {
+                return Err(CorruptedAggregate(format!(
+                    "explicit hash key index {} out of bounds",
+                    index
+                )));
+            }
+        }
+    }
+    Ok(Some(record))
+}
+
+/// Encodes `(partition key, data)` pairs as an aggregated record like the KPL does.
+#[cfg(test)]
+pub fn aggregate(records: &[(&str, &[u8])]) -> Vec<u8> {
+    let records = records
+        .iter()
+        .map(|(key, data)| (*key, None, *data))
+        .collect::<Vec<_>>();
+    aggregate_with_explicit_hash_keys(&records)
+}
+
+/// Encodes `(partition key, explicit hash key, data)` tuples as an aggregated record.
+#[cfg(test)]
+pub fn aggregate_with_explicit_hash_keys(records: &[(&str, Option<&str>, &[u8])]) -> Vec<u8> {
+    let explicit_hash_key_table = records
+        .iter()
+        .filter_map(|(_, hash_key, _)| hash_key.map(String::from))
+        .collect::<Vec<_>>();
+    let record = AggregatedRecord {
+        partition_key_table: records.iter().map(|(key, ..)| key.to_string()).collect(),
+        records: records
+            .iter()
+            .enumerate()
+            .map(|(i, (_, hash_key, data))| SubRecord {
+                partition_key_index: i as u64,
+                explicit_hash_key_index: hash_key.map(|hash_key| {
+                    explicit_hash_key_table
+                        .iter()
+                        .position(|key| key == hash_key)
+                        .unwrap() as u64
+                }),
+                data: data.to_vec(),
+            })
+            .collect(),
+        explicit_hash_key_table,
+    };
+    let message = record.encode_to_vec();
+    [&KPL_MAGIC[..], &message, &md5::compute(&message).0].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deaggregate() {
+        let payload = aggregate(&[("key_a", b"a"), ("key_b", b"b")]);
+        let record = deaggregate(&payload).unwrap().unwrap();
+        assert_eq!(record.records.len(), 2);
+        assert_eq!(record.records[1].data, b"b");
+        assert_eq!(
+            record.partition_key_table[record.records[1].partition_key_index as usize],
+            "key_b"
+        );
+
+        assert!(deaggregate(b"plain record").unwrap().is_none());
+
+        let mut corrupted = payload;
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xFF;
+        assert!(deaggregate(&corrupted).is_err());
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use anyhow::{anyhow, Result};
+use futures::{Stream, StreamExt};
+
+use crate::source::kinesis::config::MissingParentPolicy;
+use crate::source::SplitId;
+
+/// Merges the streams of shards across reshards. A child shard is read only once all its parent
+/// shards are closed, so that the records of a partition key stay in order, while the shards
+/// unrelated by lineage are read in turn, each as its batches arrive.
+pub struct LineageMerge<S> {
+    /// The streams being read, polled in turn from `next`.
+    active: Vec<(SplitId, S)>,
+    /// The streams waiting for their parents to close.
+    waiting: Vec<(SplitId, Vec<SplitId>, S)>,
+    closed: HashSet<SplitId>,
+    next: usize,
+}
+
+impl<S: Stream + Unpin> LineageMerge<S> {
+    /// Takes the stream of each shard with the ids of its parent shards. Parents which are not
+    /// among the shards are missing, e.g. when they have expired, and are taken as closed unless
+    /// `policy` fails.
+    pub fn new(
+        shards: Vec<(SplitId, Vec<SplitId>, S)>,
+        policy: MissingParentPolicy,
+    ) -> Result<Self> {
+        let ids = shards
+            .iter()
+            .map(|(id, ..)| id.clone())
+            .collect::<HashSet<_>>();
+        let mut waiting = Vec::with_capacity(shards.len());
+        for (id, parents, stream) in shards {
+            let (parents, missing): (Vec<_>, Vec<_>) =
+                parents.into_iter().partition(|p| ids.contains(p));
+            if !missing.is_empty() {
+                match policy {
+                    MissingParentPolicy::Proceed => {}
+                    MissingParentPolicy::Fail => {
+                        return Err(anyhow!(
+                            "parent shards {:?} of kinesis shard {} are missing, see \
+                             on_missing_parent",
+                            missing,
+                            id
+                        ));
+                    }
+                    MissingParentPolicy::Warn => tracing::warn!(
+                        "parent shards {:?} of kinesis shard {} are missing, read it anyway, \
+                         which may emit the records of a partition key out of order",
+                        missing,
+                        id
+                    ),
+                }
+            }
+            waiting.push((id, parents, stream));
+        }
+        let mut merge = Self {
+            active: vec![],
+            waiting,
+            closed: HashSet::new(),
+            next: 0,
+        };
+        merge.activate_ready();
+        Ok(merge)
+    }
+
+    /// Moves the waiting streams whose parents are all closed to the active ones.
+    fn activate_ready(&mut self) {
+        let closed = &self.closed;
+        let (ready, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.waiting)
+            .into_iter()
+            .partition(|(_, parents, _)| parents.iter().all(|p| closed.contains(p)));
+        self.waiting = waiting;
+        self.active
+            .extend(ready.into_iter().map(|(id, _, stream)| (id, stream)));
+    }
+}
+
+impl<S: Stream + Unpin> Stream for LineageMerge<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        'poll: loop {
+            let n = this.active.len();
+            for i in 0..n {
+                let index = (this.next + i) % n;
+                match this.active[index].1.poll_next_unpin(cx) {
+                    Poll::Ready(Some(item)) => {
+                        this.next = (index + 1) % n;
+                        return Poll::Ready(Some(item));
+                    }
+                    Poll::Ready(None) => {
+                        let (id, _) = this.active.remove(index);
+                        this.closed.insert(id);
+                        this.activate_ready();
+                        this.next = index;
+                        continue 'poll;
+                    }
+                    Poll::Pending => {}
+                }
+            }
+            // Streams still waiting have parents in a cycle, which can not happen for shards.
+            return if n == 0 {
+                Poll::Ready(None)
+            } else {
+                Poll::Pending
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+
+    use super::*;
+    use crate::source::kinesis::test_utils::capture_events;
+
+    fn id(id: &str) -> SplitId {
+        id.to_string().into()
+    }
+
+    #[tokio::test]
+    async fn test_lineage_merge() {
+        let shard = |name: &'static str, parents: &[&str], len: usize| {
+            (
+                id(name),
+                parents.iter().map(|p| id(p)).collect(),
+                stream::iter((0..len).map(move |i| format!("{}-{}", name, i))),
+            )
+        };
+        // Two families, each a parent split into two children, listed children first.
+        let merge = LineageMerge::new(
+            vec![
+                shard("a1", &["a0"], 1),
+                shard("a2", &["a0"], 1),
+                shard("b1", &["b0"], 2),
+                shard("a0", &[], 2),
+                shard("b0", &[], 4),
+            ],
+            MissingParentPolicy::Proceed,
+        )
+        .unwrap();
+        let order = merge.collect::<Vec<_>>().await;
+
+        let position = |item: &str| order.iter().position(|i| i == item).unwrap();
+        // Within a family, the parent is read to its end before its children.
+        assert!(position("a0-1") < position("a1-0"));
+        assert!(position("a0-1") < position("a2-0"));
+        assert!(position("b0-3") < position("b1-0"));
+        assert!(position("b1-0") < position("b1-1"));
+        // Across families, the shards are read in turn rather than one family after another.
+        assert_eq!(&order[..4], ["a0-0", "b0-0", "a0-1", "b0-1"]);
+        assert!(position("a1-0") < position("b0-3"));
+        assert_eq!(order.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_missing_parent() {
+        // The parent a0 of a1 has been trimmed, unlike the parent b0 of b1.
+        let shards = || {
+            vec![
+                (id("a1"), vec![id("a0")], stream::iter(vec!["a1-0"])),
+                (id("b1"), vec![id("b0")], stream::iter(vec!["b1-0"])),
+                (id("b0"), vec![], stream::iter(vec!["b0-0"])),
+            ]
+        };
+
+        let merge = LineageMerge::new(shards(), MissingParentPolicy::Proceed).unwrap();
+        assert_eq!(merge.collect::<Vec<_>>().await, ["a1-0", "b0-0", "b1-0"]);
+
+        let (events, _guard) = capture_events();
+        let merge = LineageMerge::new(shards(), MissingParentPolicy::Warn).unwrap();
+        assert_eq!(merge.collect::<Vec<_>>().await, ["a1-0", "b0-0", "b1-0"]);
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].contains("parent shards [\"a0\"] of kinesis shard a1 are missing"));
+
+        let err = LineageMerge::new(shards(), MissingParentPolicy::Fail)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("on_missing_parent"));
+    }
+}
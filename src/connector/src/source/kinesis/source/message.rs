@@ -12,35 +12,202 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
 use aws_sdk_kinesis::model::Record;
+use aws_smithy_types::DateTime;
 use bytes::Bytes;
 
-use crate::source::{SourceMessage, SplitId};
+use crate::source::{SourceMessage, SourceMeta, SplitId};
 
 #[derive(Clone, Debug)]
 pub struct KinesisMessage {
     pub shard_id: SplitId,
     pub sequence_number: String,
     pub partition_key: String,
+    /// Only known for sub-records of KPL aggregated records, since `GetRecords` does not return
+    /// the explicit hash key of plain records.
+    pub explicit_hash_key: Option<String>,
+    /// The index of a sub-record within its KPL aggregated record, which shares the sequence
+    /// number of the aggregated record.
+    pub sub_sequence_number: Option<u64>,
+    /// The schema registry id of a framed payload, see `payload.framing`.
+    pub schema_id: Option<u32>,
     pub payload: Bytes,
+    pub timestamp: Option<i64>,
+}
+
+/// Kinesis specific metadata carried by a [`SourceMessage`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct KinesisMeta {
+    /// Approximate arrival timestamp of the record in milliseconds. For a heartbeat message, this
+    /// is the tip timestamp of the shard derived from `MillisBehindLatest`.
+    pub timestamp: Option<i64>,
+    /// The explicit hash key used to route the record to its shard, if any.
+    pub explicit_hash_key: Option<String>,
+    /// Set for sub-records of KPL aggregated records, so that `(offset, sub_sequence_number)`
+    /// totally orders the records of a shard.
+    pub sub_sequence_number: Option<u64>,
+    /// The schema registry id from the framing of the payload.
+    pub schema_id: Option<u32>,
+    /// Set for a shard end message to the ending sequence number of the closed shard, if known.
+    pub ending_sequence_number: Option<String>,
+}
+
+/// Keys of the [`SourceMessage::attributes`] of Kinesis records. The stream name, shard id and
+/// truncation are set by the reader, which knows them.
+pub const ATTR_STREAM_NAME: &str = "stream_name";
+pub const ATTR_SHARD_ID: &str = "shard_id";
+pub const ATTR_SEQUENCE_NUMBER: &str = "sequence_number";
+pub const ATTR_PARTITION_KEY: &str = "partition_key";
+pub const ATTR_EXPLICIT_HASH_KEY: &str = "explicit_hash_key";
+pub const ATTR_SUB_SEQUENCE_NUMBER: &str = "sub_sequence_number";
+pub const ATTR_SCHEMA_ID: &str = "schema_id";
+pub const ATTR_ARRIVAL_TIMESTAMP: &str = "arrival_timestamp";
+/// Set to the original length of a payload truncated by `preview.truncate.bytes`.
+pub const ATTR_TRUNCATED_FROM: &str = "truncated_from";
+
+impl KinesisMessage {
+    /// Returns the metadata of the record as [`SourceMessage::attributes`], where numbers are
+    /// formatted in decimal.
+    pub fn attributes(&self) -> HashMap<String, Bytes> {
+        let mut attributes = HashMap::new();
+        let mut insert = |key: &str, value: String| {
+            attributes.insert(key.to_string(), Bytes::from(value));
+        };
+        insert(ATTR_SEQUENCE_NUMBER, self.sequence_number.clone());
+        insert(ATTR_PARTITION_KEY, self.partition_key.clone());
+        if let Some(explicit_hash_key) = &self.explicit_hash_key {
+            insert(ATTR_EXPLICIT_HASH_KEY, explicit_hash_key.clone());
+        }
+        if let Some(sub_sequence_number) = self.sub_sequence_number {
+            insert(ATTR_SUB_SEQUENCE_NUMBER, sub_sequence_number.to_string());
+        }
+        if let Some(schema_id) = self.schema_id {
+            insert(ATTR_SCHEMA_ID, schema_id.to_string());
+        }
+        if let Some(timestamp) = self.timestamp {
+            insert(ATTR_ARRIVAL_TIMESTAMP, timestamp.to_string());
+        }
+        attributes
+    }
 }
 
 impl From<KinesisMessage> for SourceMessage {
     fn from(msg: KinesisMessage) -> Self {
+        let attributes = msg.attributes();
         SourceMessage {
             payload: Some(msg.payload),
             offset: msg.sequence_number.clone(),
             split_id: msg.shard_id,
+            meta: SourceMeta::Kinesis(KinesisMeta {
+                timestamp: msg.timestamp,
+                explicit_hash_key: msg.explicit_hash_key,
+                sub_sequence_number: msg.sub_sequence_number,
+                schema_id: msg.schema_id,
+                ending_sequence_number: None,
+            }),
+            attributes,
+            provenance: None,
+            state_offset: None,
         }
     }
 }
 
+/// Converts a [`DateTime`] returned by the SDK to milliseconds since epoch.
+pub fn datetime_to_millis(datetime: &DateTime) -> i64 {
+    datetime.secs() * 1000 + (datetime.subsec_nanos() / 1_000_000) as i64
+}
+
+/// Builds a heartbeat message which has no payload, used to signal that an idle shard is alive
+/// and caught up to `tip_timestamp`.
+pub fn heartbeat_message(shard_id: SplitId, offset: String, tip_timestamp: i64) -> SourceMessage {
+    SourceMessage {
+        payload: None,
+        offset,
+        split_id: shard_id,
+        meta: SourceMeta::Kinesis(KinesisMeta {
+            timestamp: Some(tip_timestamp),
+            ..Default::default()
+        }),
+        attributes: Default::default(),
+        provenance: None,
+        state_offset: None,
+    }
+}
+
+/// Builds a message which has no payload and only surfaces `offset` as the state of the shard,
+/// see [`OffsetCoalescer::flush`].
+///
+/// [`OffsetCoalescer::flush`]: crate::source::kinesis::source::checkpoint::OffsetCoalescer::flush
+pub fn state_offset_message(shard_id: SplitId, offset: String) -> SourceMessage {
+    SourceMessage {
+        payload: None,
+        offset: offset.clone(),
+        split_id: shard_id,
+        meta: SourceMeta::Kinesis(KinesisMeta::default()),
+        attributes: Default::default(),
+        provenance: None,
+        state_offset: Some(offset),
+    }
+}
+
+/// Builds a shard end message which has no payload, used to signal that a closed shard has been
+/// read to its end. Unlike the offset of the last record, its `ending_sequence_number` tells that
+/// the shard is exhausted rather than caught up.
+pub fn shard_end_message(
+    shard_id: SplitId,
+    offset: String,
+    ending_sequence_number: Option<String>,
+) -> SourceMessage {
+    SourceMessage {
+        payload: None,
+        offset,
+        split_id: shard_id,
+        meta: SourceMeta::Kinesis(KinesisMeta {
+            ending_sequence_number,
+            ..Default::default()
+        }),
+        attributes: Default::default(),
+        provenance: None,
+        state_offset: None,
+    }
+}
+
+/// Maps the records fetched by the reader to messages, so that integrators can choose which fields
+/// of a record become the payload and the metadata. The payload transforms, framing and
+/// deduplication apply to the mapped messages.
+pub trait MessageMapper: Debug + Send + Sync {
+    fn map(&self, shard_id: SplitId, record: Record) -> KinesisMessage;
+}
+
+pub type MessageMapperRef = Arc<dyn MessageMapper>;
+
+/// Maps the data of a record to the payload, see [`KinesisMessage::new`].
+#[derive(Debug, Default)]
+pub struct DefaultMessageMapper;
+
+impl MessageMapper for DefaultMessageMapper {
+    fn map(&self, shard_id: SplitId, record: Record) -> KinesisMessage {
+        KinesisMessage::new(shard_id, record)
+    }
+}
+
 impl KinesisMessage {
     pub fn new(shard_id: SplitId, message: Record) -> Self {
         KinesisMessage {
             shard_id,
             sequence_number: message.sequence_number.unwrap(),
             partition_key: message.partition_key.unwrap(),
+            explicit_hash_key: None,
+            sub_sequence_number: None,
+            schema_id: None,
+            timestamp: message
+                .approximate_arrival_timestamp
+                .as_ref()
+                .map(datetime_to_millis),
             payload: message.data.unwrap().into_inner().into(),
         }
     }
@@ -0,0 +1,40 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::source::SplitId;
+
+/// Receives the metrics of the shards read, e.g. to export them as Prometheus counters. Called on
+/// the fetch path, so implementations should only update counters. Every method defaults to
+/// discarding its metric, so that an implementation picks the metrics it exports.
+pub trait ReaderMetrics: Debug + Send + Sync {
+    /// The iterator of the shard expired and was renewed, which happens when the downstream
+    /// stalls between fetches for longer than the 5 minute iterator lifetime.
+    fn iterator_renewed(&self, _shard_id: &SplitId) {}
+
+    /// A call of the API `operation` succeeded after `attempts` HTTP requests, which count the
+    /// retries within the SDK as well as those of `retry.max_attempts`. More than one attempt per
+    /// call means the shard is throttled or failing transiently under the hood.
+    fn api_call(&self, _shard_id: &SplitId, _operation: &str, _attempts: usize) {}
+}
+
+pub type ReaderMetricsRef = Arc<dyn ReaderMetrics>;
+
+/// Discards the metrics, used when no metrics are exported.
+#[derive(Debug, Default)]
+pub struct NoopReaderMetrics;
+
+impl ReaderMetrics for NoopReaderMetrics {}
@@ -12,5 +12,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-mod message;
+pub mod capture;
+pub mod checkpoint;
+pub mod chunk;
+pub mod circuit_breaker;
+pub mod clock_skew;
+pub mod dead_letter;
+pub mod dedup;
+pub mod end_condition;
+pub mod fetch_limit;
+pub mod freshness;
+pub mod hash_key;
+pub mod inflight;
+pub mod kpl;
+pub mod lineage;
+pub mod message;
+pub mod metrics;
+pub mod pause;
+pub mod progress;
 pub mod reader;
+pub mod response_cache;
+pub mod safety_lag;
+pub mod transform;
+pub mod validation;
+pub mod watchdog;
+pub mod watermark;
@@ -0,0 +1,111 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::watch;
+
+use crate::source::SplitId;
+
+#[derive(Debug)]
+struct PauseState {
+    reader_paused: bool,
+    paused_shards: HashSet<SplitId>,
+    /// Whether each shard is effectively paused. The receivers are kept so that sending never
+    /// fails before the shard readers subscribe.
+    channels: HashMap<SplitId, (watch::Sender<bool>, watch::Receiver<bool>)>,
+}
+
+/// Pauses and resumes a reader as a whole or per shard. A paused shard issues no `GetRecords`
+/// calls and keeps its iterator, so it resumes from the same position.
+#[derive(Debug, Clone)]
+pub struct PauseHandle {
+    state: Arc<Mutex<PauseState>>,
+}
+
+impl PauseHandle {
+    pub fn new(split_ids: impl IntoIterator<Item = SplitId>) -> Self {
+        let channels = split_ids
+            .into_iter()
+            .map(|split_id| (split_id, watch::channel(false)))
+            .collect();
+        Self {
+            state: Arc::new(Mutex::new(PauseState {
+                reader_paused: false,
+                paused_shards: HashSet::new(),
+                channels,
+            })),
+        }
+    }
+
+    /// Returns a receiver of whether the shard is paused.
+    pub fn subscribe(&self, split_id: &SplitId) -> watch::Receiver<bool> {
+        let mut state = self.state.lock().unwrap();
+        let paused = state.reader_paused || state.paused_shards.contains(split_id);
+        let (_, rx) = state
+            .channels
+            .entry(split_id.clone())
+            .or_insert_with(|| watch::channel(paused));
+        rx.clone()
+    }
+
+    pub fn pause(&self) {
+        self.update(|state| state.reader_paused = true);
+    }
+
+    pub fn resume(&self) {
+        self.update(|state| state.reader_paused = false);
+    }
+
+    pub fn pause_shard(&self, split_id: &SplitId) {
+        self.update(|state| {
+            state.paused_shards.insert(split_id.clone());
+        });
+    }
+
+    pub fn resume_shard(&self, split_id: &SplitId) {
+        self.update(|state| {
+            state.paused_shards.remove(split_id);
+        });
+    }
+
+    /// Whether the shard is paused, as a part of the reader or on its own.
+    pub fn is_paused(&self, split_id: &SplitId) -> bool {
+        let state = self.state.lock().unwrap();
+        state.reader_paused || state.paused_shards.contains(split_id)
+    }
+
+    fn update(&self, f: impl FnOnce(&mut PauseState)) {
+        let mut state = self.state.lock().unwrap();
+        f(&mut state);
+        for (split_id, (tx, _)) in &state.channels {
+            let paused = state.reader_paused || state.paused_shards.contains(split_id);
+            if *tx.borrow() != paused {
+                tx.send(paused).unwrap();
+            }
+        }
+    }
+}
+
+/// Waits until the shard is resumed. Returns immediately if `paused` is `None`.
+pub async fn wait_resumed(paused: &mut Option<watch::Receiver<bool>>) {
+    if let Some(paused) = paused {
+        while *paused.borrow() {
+            if paused.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::Arc;
+
+use crate::source::kinesis::split::KinesisOffset;
+use crate::source::{SourceMessage, SplitId};
+
+/// The progress of a shard reported to an external coordinator, e.g. one rebalancing shards
+/// across clusters or alerting on lag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardProgress {
+    pub shard_id: SplitId,
+    /// The sequence number of the last record read, `None` before the first one.
+    pub latest_sequence: Option<String>,
+    /// `MillisBehindLatest` of the last fetch.
+    pub lag_millis: Option<i64>,
+    /// Whether the last fetch found no records and no lag.
+    pub at_tip: bool,
+}
+
+/// The position of a split of a multi split reader inspected without consuming records, e.g. for
+/// a source status view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardPosition {
+    /// Where the split started to be read, or was reset to.
+    pub start_position: KinesisOffset,
+    /// The offset of the last record returned by `next`, `None` before the first one.
+    pub latest_sequence: Option<String>,
+    /// `MillisBehindLatest` of the last `GetRecords` call of the shard, `None` before the first
+    /// one.
+    pub lag_millis: Option<i64>,
+    /// Whether the records following the position may have aged out of the retention period of
+    /// the stream, so that resuming from it loses records.
+    pub beyond_retention: bool,
+}
+
+/// How far the multi split reader has read, see `KinesisMultiSplitReader::read_status`.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum ReadStatus {
+    Reading,
+    /// `next` returned `None` as the shards finished or `max.total.records` was reached.
+    Finished,
+    /// `next` returned `None` as `batch.max_runtime` elapsed, before the shards finished. The
+    /// offsets of the state resume the read.
+    TimeLimitReached,
+    /// `next` returned `None` after some shards were dropped by `on_shard_error` before reaching
+    /// their ends, so the read is incomplete.
+    ShardsDropped,
+}
+
+impl Display for ReadStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Reading => write!(f, "reading"),
+            Self::Finished => write!(f, "finished"),
+            Self::TimeLimitReached => write!(f, "partial result, time limit reached"),
+            Self::ShardsDropped => write!(f, "partial result, shards dropped"),
+        }
+    }
+}
+
+/// What `KinesisMultiSplitReader::next_signal` returns.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReadSignal {
+    Chunk(Vec<SourceMessage>),
+    /// Returned once, after the last chunk of a bounded read.
+    Completed(SourceCompleted),
+}
+
+/// Every split assigned to the reader has finished, so the batch reading them is done. Unlike a
+/// `None` from `next`, which a time limit also returns, it is never followed by more records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceCompleted {
+    pub splits: Vec<SplitId>,
+    /// The sequence number of the last record emitted of each split, absent for splits without
+    /// records.
+    pub latest_offsets: HashMap<SplitId, String>,
+}
+
+/// Receives the progress of the shards at most once per `progress.report.interval` per shard.
+/// Called on the fetch path, so implementations should hand the progress off without blocking.
+pub trait ProgressReporter: Debug + Send + Sync {
+    fn report(&self, progress: ShardProgress);
+}
+
+pub type ProgressReporterRef = Arc<dyn ProgressReporter>;
+
+/// Discards the progress, used when no coordinator is set.
+#[derive(Debug, Default)]
+pub struct NoopProgressReporter;
+
+impl ProgressReporter for NoopProgressReporter {
+    fn report(&self, _progress: ShardProgress) {}
+}
@@ -13,33 +13,150 @@
 // limitations under the License.
 
 use core::result::Result::Ok;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use aws_sdk_kinesis::error::GetRecordsError;
-use aws_sdk_kinesis::model::ShardIteratorType;
+use aws_sdk_kinesis::model::{Record, ShardIteratorType, StreamStatus};
 use aws_sdk_kinesis::output::GetRecordsOutput;
 use aws_sdk_kinesis::types::SdkError;
 use aws_sdk_kinesis::Client as KinesisClient;
-use futures::future::join_all;
-use futures_async_stream::{for_await, try_stream};
-use futures_concurrency::prelude::*;
-use tokio::sync::Mutex;
+use aws_smithy_types::DateTime;
+use bytes::Bytes;
+use futures::stream::{self, BoxStream};
+use futures::{StreamExt, TryStreamExt};
+use futures_async_stream::try_stream;
+use tokio::sync::{mpsc, watch};
 use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamMap;
+use tracing::Instrument;
 
-use crate::source::kinesis::source::message::KinesisMessage;
-use crate::source::kinesis::split::{KinesisOffset, KinesisSplit};
-use crate::source::kinesis::{build_client, KinesisProperties};
-use crate::source::{Column, ConnectorState, SourceMessage, SplitId, SplitImpl, SplitReader};
+use crate::source::kinesis::canonical::CanonicalKinesisConfig;
+use crate::source::kinesis::clock::{Clock, ClockRef, TokioClock};
+use crate::source::kinesis::config::{
+    BatchConfig, CorruptedAggregatePolicy, EmptyPayloadPolicy, IteratorAcquisition, ReaderConfig,
+    ResponseValidation, RetryConfig, SequenceNumberFormat, ShardCapPolicy, ShardErrorPolicy,
+    ValidationConfig,
+};
+use crate::source::kinesis::credentials::{
+    is_expired_credentials, ClientRefresherRef, PropertiesClientRefresher,
+};
+use crate::source::kinesis::error::sdk_error;
+use crate::source::kinesis::lease::LeaseTable;
+use crate::source::kinesis::retry::{with_retry, JitterRng};
+use crate::source::kinesis::source::capture::{CaptureSink, ReplaySource};
+use crate::source::kinesis::source::checkpoint::OffsetCoalescer;
+use crate::source::kinesis::source::chunk::{BatchMeta, BatchWindow, FlowControlRef};
+use crate::source::kinesis::source::circuit_breaker::CircuitBreaker;
+use crate::source::kinesis::source::clock_skew::ClockSkewCheck;
+use crate::source::kinesis::source::dead_letter::{
+    DeadLetter, DeadLetterSinkRef, LoggingDeadLetterSink,
+};
+use crate::source::kinesis::source::dedup::DedupWindow;
+use crate::source::kinesis::source::end_condition::EndCondition;
+use crate::source::kinesis::source::fetch_limit::AdaptiveLimit;
+use crate::source::kinesis::source::freshness::{
+    FreshnessSla, NoopScalingListener, ScalingListenerRef,
+};
+use crate::source::kinesis::source::hash_key::HashKeyRange;
+use crate::source::kinesis::source::inflight::{InflightBytes, InflightPermit};
+use crate::source::kinesis::source::kpl::CorruptedAggregate;
+use crate::source::kinesis::source::message::{
+    datetime_to_millis, heartbeat_message, shard_end_message, state_offset_message,
+    DefaultMessageMapper, KinesisMessage, MessageMapperRef, ATTR_SHARD_ID, ATTR_STREAM_NAME,
+    ATTR_TRUNCATED_FROM,
+};
+use crate::source::kinesis::source::metrics::{NoopReaderMetrics, ReaderMetricsRef};
+use crate::source::kinesis::source::pause::{wait_resumed, PauseHandle};
+use crate::source::kinesis::source::progress::{
+    NoopProgressReporter, ProgressReporterRef, ReadSignal, ReadStatus, ShardPosition,
+    ShardProgress, SourceCompleted,
+};
+use crate::source::kinesis::source::response_cache::ResponseCache;
+use crate::source::kinesis::source::safety_lag::SafetyLag;
+use crate::source::kinesis::source::transform::{
+    apply_transforms, PayloadFraming, PayloadTransform,
+};
+use crate::source::kinesis::source::validation::validate_get_records;
+use crate::source::kinesis::source::watchdog::StallWatchdog;
+use crate::source::kinesis::source::watermark::WatermarkTracker;
+use crate::source::kinesis::split::{
+    compare_sequence, unpack_sequence_number, KinesisOffset, KinesisSplit, SUB_SEQUENCE_SEPARATOR,
+};
+use crate::source::kinesis::telemetry::count_attempts;
+use crate::source::kinesis::{build_client, KinesisProperties, KINESIS_CONNECTOR};
+use crate::source::{
+    Column, ConnectorState, ConnectorStateV2, Provenance, SourceMessage, SplitId, SplitImpl,
+    SplitMetaData, SplitReader,
+};
+
+const STREAM_STATUS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// The interval between polls of a shard without new records.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// The interval between polls of the flow control while the downstream has no capacity.
+const FLOW_CONTROL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// The `GetRecords` calls at most to count the records skipped by a `Latest` start position.
+const MAX_LATEST_GAP_CALLS: usize = 10;
+
+type ShardStream = BoxStream<'static, Result<Vec<SourceMessage>>>;
+
+/// Changes to the shards read by the consumer task, see
+/// [`KinesisMultiSplitReader::update_splits`].
+enum SplitUpdate {
+    Add(SplitId, ShardStream),
+    Remove(SplitId),
+}
 
 pub struct KinesisMultiSplitReader {
     /// splits are not allowed to be empty, otherwise connector source should create
     /// DummySplitReader which is always idling.
     splits: Vec<KinesisSplit>,
-    properties: KinesisProperties,
-    message_cache: Arc<Mutex<Vec<SourceMessage>>>,
+    /// Shared by the shard readers.
+    config: ReaderConfig,
+    /// Shared by the shard readers.
+    client: KinesisClient,
+    /// Set for the client built from the connection properties, see
+    /// [`KinesisSplitReader::with_client_refresher`].
+    client_refresher: Option<ClientRefresherRef>,
+    /// Set by `checkpoint.kcl.lease_table`, see `commit_leases`.
+    lease_table: Option<LeaseTable>,
+    /// Batches fetched by the consumer task. The channel is bounded so that the consumer task
+    /// stops fetching when `next` falls behind.
+    message_rx: Option<mpsc::Receiver<BufferedChunk>>,
+    /// Set by `max.inflight.bytes`.
+    inflight: Option<InflightBytes>,
     consumer_handler: Option<JoinHandle<()>>,
+    /// Sends split updates to the consumer task.
+    update_tx: Option<mpsc::UnboundedSender<SplitUpdate>>,
+    /// Set when a single split is assigned, which is read in place without the consumer task.
+    single_split_stream: Option<ShardStream>,
+    /// The streams of the shards whose iterators are acquired eagerly, taken when the shards are
+    /// first read.
+    acquired_streams: HashMap<SplitId, ShardStream>,
+    /// The number of records emitted by `next`, shared with the shard readers so that they stop
+    /// fetching once `max.total.records` is reached.
+    emitted_records: Arc<AtomicUsize>,
+    /// The last `MillisBehindLatest` of each shard, recorded by the shard readers.
+    shard_lags: ShardLags,
+    /// The shards dropped by the `drop_shard` error policy, which leave the read incomplete.
+    dropped_splits: DroppedSplits,
+    started_at: Option<Instant>,
+    /// Tells the time of `batch.max_runtime`.
+    clock: ClockRef,
+    read_status: ReadStatus,
+    /// Whether `next_signal` has returned `SourceCompleted`.
+    completion_signaled: bool,
+    /// The sequence number of the last record emitted by `next` of each split.
+    latest_offsets: HashMap<SplitId, String>,
+    /// Event-time watermarks of the records emitted by `next`.
+    watermarks: WatermarkTracker,
+    pause_handle: PauseHandle,
 }
 
 impl Drop for KinesisMultiSplitReader {
@@ -50,132 +167,1466 @@ impl Drop for KinesisMultiSplitReader {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct KinesisSplitReader {
     client: KinesisClient,
+    client_refresher: Option<ClientRefresherRef>,
     stream_name: String,
     shard_id: SplitId,
+    split_id: SplitId,
     latest_offset: Option<String>,
     shard_iter: Option<String>,
     start_position: KinesisOffset,
     end_position: KinesisOffset,
+    end_condition: Option<EndCondition>,
+    heartbeat_interval: Option<Duration>,
+    transforms: Vec<PayloadTransform>,
+    framing: PayloadFraming,
+    dead_letter_sink: DeadLetterSinkRef,
+    sequence_number_format: SequenceNumberFormat,
+    retry: RetryConfig,
+    batch: BatchConfig,
+    validation: ValidationConfig,
+    adaptive_limit: Option<AdaptiveLimit>,
+    /// Set by `get_records.retry.cache`, see [`KinesisSplitReader::rewind`].
+    response_cache: Option<ResponseCache>,
+    /// Whether the shard is paused, see [`PauseHandle`].
+    paused: Option<watch::Receiver<bool>>,
+    capture: Option<CaptureSink>,
+    /// Set when replaying captured responses instead of calling Kinesis.
+    replay: Option<ReplaySource>,
+    dedup: Option<DedupWindow>,
+    hash_key_range: Option<HashKeyRange>,
+    offset_coalescer: Option<OffsetCoalescer>,
+    /// The sequence number of the KPL aggregated record the split starts within, and the index of
+    /// its last sub-record emitted before.
+    resume_sub_sequence: Option<(String, u64)>,
+    /// The chunks split from the last batch which are not returned yet.
+    split_chunks: VecDeque<Vec<SourceMessage>>,
+    flow_control: Option<FlowControlRef>,
+    /// When the shard starting at `Latest` was listed, until the records skipped since then are
+    /// counted, see `latest.gap.detection`.
+    latest_gap_since: Option<i64>,
+    /// The records skipped by the `Latest` start position, once counted.
+    skipped_at_latest: Option<usize>,
+    /// The lag beyond which a restored shard skips to `Latest`, until the first fetch.
+    skip_to_latest_lag: Option<Duration>,
+    watchdog: Option<StallWatchdog>,
+    clock_skew: ClockSkewCheck,
+    safety_lag: Option<SafetyLag>,
+    freshness_sla: Option<FreshnessSla>,
+    scaling_listener: ScalingListenerRef,
+    message_mapper: MessageMapperRef,
+    progress_reporter: ProgressReporterRef,
+    metrics: ReaderMetricsRef,
+    progress_report_interval: Duration,
+    /// When the progress was last reported.
+    progress_reported_at: Option<Instant>,
+    /// Set by `preview.truncate.bytes`, the length to truncate payloads to.
+    truncate_bytes: Option<usize>,
+    idle_since: Option<Instant>,
+    /// Set once the shard is closed or the end position is reached.
+    finished: bool,
+    /// Whether the last `GetRecords` returned no records and no lag, see `at_tip`.
+    at_tip: bool,
+    /// `MillisBehindLatest` of the last `GetRecords`.
+    lag_millis: Option<i64>,
+    /// The renewals of expired iterators in a row. A renewal follows the previous one in a row
+    /// if at most one fetch succeeded in between, which is the pattern of a downstream stalling
+    /// between fetches for longer than the iterator lifetime.
+    consecutive_renews: usize,
+    /// The fetches succeeded since the last renewal.
+    fetches_since_renew: usize,
+    clock: ClockRef,
+    /// The records emitted across shards and their cap, see `max.total.records`.
+    total_records_cap: Option<(Arc<AtomicUsize>, usize)>,
+    /// Where `MillisBehindLatest` of the shard is recorded for the multi split reader, see
+    /// `with_shard_lags`.
+    shard_lags: Option<ShardLags>,
+    /// Where the shard is recorded if dropped by the `drop_shard` error policy, see
+    /// `with_dropped_splits`.
+    dropped_splits: Option<DroppedSplits>,
+    /// Carries the stream and shard of the events logged by API calls, e.g. their retries.
+    span: tracing::Span,
+    /// Whether a record has been emitted, to log the first one.
+    emitted_first: bool,
 }
 
 impl KinesisSplitReader {
     pub async fn new(properties: KinesisProperties, split: KinesisSplit) -> Result<Self> {
-        let stream_name = properties.stream_name.clone();
-        let client = build_client(properties).await?;
+        let properties = CanonicalKinesisConfig::resolve(&properties)?;
+        let config = ReaderConfig::from_properties(&properties)?;
+        let client = build_client(properties.clone()).await?;
+        let client_refresher = Arc::new(PropertiesClientRefresher::new(properties));
+        Ok(Self::from_config(&config, split, client)?.with_client_refresher(client_refresher))
+    }
+
+    /// Creates the reader with a client built by the caller instead of from the connection
+    /// properties, e.g. by `KinesisClient::new(&sdk_config)` from an `aws_config::SdkConfig` with
+    /// custom credential providers.
+    pub fn new_with_client(
+        properties: KinesisProperties,
+        split: KinesisSplit,
+        client: KinesisClient,
+    ) -> Result<Self> {
+        let properties = CanonicalKinesisConfig::resolve(&properties)?;
+        Self::from_config(&ReaderConfig::from_properties(&properties)?, split, client)
+    }
+
+    /// Creates the reader of the split with the settings shared by the shards.
+    fn from_config(
+        config: &ReaderConfig,
+        split: KinesisSplit,
+        client: KinesisClient,
+    ) -> Result<Self> {
+        let split_id = split.id();
+        let stream_name = split
+            .stream_name
+            .clone()
+            .unwrap_or_else(|| config.stream_name.clone());
+        let mut retry = config.retry.clone();
+        retry.policy.rng = retry.policy.rng.derive(&split_id);
+        let capture_file = |dir: &str| Path::new(dir).join(format!("{}.jsonl", split_id));
+        let capture = config
+            .capture_dir
+            .as_deref()
+            .map(|dir| CaptureSink::create(capture_file(dir)))
+            .transpose()?;
+        let replay = config
+            .replay_dir
+            .as_deref()
+            .map(|dir| ReplaySource::open(capture_file(dir)))
+            .transpose()?;
+        if let Some(bytes) = config.truncate_bytes {
+            tracing::warn!(
+                "kinesis payloads of shard {} are truncated to {} bytes by \
+                 preview.truncate.bytes, which is only for previews and loses data if ingested",
+                split.shard_id,
+                bytes
+            );
+        }
+        let start_position = split.start_position.unpacked()?;
+        let resume_sub_sequence = match &start_position {
+            KinesisOffset::SubSequenceNumber(seq, index) => Some((seq.clone(), *index)),
+            _ => None,
+        };
+        // Only a shard restored from a sequence number may skip to `Latest`.
+        let skip_to_latest_lag = config.skip_to_latest_lag.filter(|_| {
+            matches!(
+                start_position,
+                KinesisOffset::SequenceNumber(_)
+                    | KinesisOffset::PackedSequenceNumber(_)
+                    | KinesisOffset::SubSequenceNumber(..)
+            )
+        });
+        let latest_gap_since = split
+            .discovered_at
+            .filter(|_| start_position == KinesisOffset::Latest && replay.is_none());
+        let span =
+            tracing::info_span!("kinesis_shard", stream = %stream_name, shard = %split.shard_id);
         Ok(Self {
             client,
+            client_refresher: None,
             stream_name,
             shard_id: split.shard_id,
+            split_id,
             shard_iter: None,
             latest_offset: None,
-            start_position: split.start_position,
-            end_position: split.end_position,
+            start_position,
+            end_position: split.end_position.unpacked()?,
+            end_condition: None,
+            heartbeat_interval: config.heartbeat_interval,
+            transforms: config.transforms.clone(),
+            framing: config.framing,
+            dead_letter_sink: Arc::new(LoggingDeadLetterSink),
+            sequence_number_format: config.sequence_number_format,
+            response_cache: retry.response_cache.then(ResponseCache::default),
+            retry,
+            batch: config.batch,
+            validation: config.validation,
+            adaptive_limit: config.adaptive_limit.clone(),
+            paused: None,
+            capture,
+            replay,
+            dedup: config.dedup.as_ref().map(DedupWindow::new),
+            hash_key_range: config.hash_key_range,
+            offset_coalescer: config.offset_coalescer.clone(),
+            resume_sub_sequence,
+            split_chunks: VecDeque::new(),
+            flow_control: None,
+            latest_gap_since,
+            skipped_at_latest: None,
+            skip_to_latest_lag,
+            watchdog: config.stall_timeout.map(StallWatchdog::new),
+            clock_skew: config.clock_skew.clone(),
+            safety_lag: config.safety_lag.clone(),
+            freshness_sla: config.freshness_sla.clone(),
+            scaling_listener: Arc::new(NoopScalingListener),
+            message_mapper: Arc::new(DefaultMessageMapper),
+            progress_reporter: Arc::new(NoopProgressReporter),
+            metrics: Arc::new(NoopReaderMetrics),
+            progress_report_interval: config.progress_report_interval,
+            progress_reported_at: None,
+            truncate_bytes: config.truncate_bytes,
+            idle_since: None,
+            finished: false,
+            at_tip: false,
+            lag_millis: None,
+            consecutive_renews: 0,
+            fetches_since_renew: 0,
+            clock: Arc::new(TokioClock),
+            total_records_cap: None,
+            shard_lags: None,
+            dropped_splits: None,
+            span,
+            emitted_first: false,
         })
     }
 
-    pub async fn next(&mut self) -> Result<Vec<SourceMessage>> {
-        if self.shard_iter.is_none() {
+    /// Draws the jitter of retries from `rng`, e.g. a seeded one for reproducible delays.
+    pub fn with_jitter_rng(mut self, rng: JitterRng) -> Self {
+        self.retry.policy.rng = rng;
+        self
+    }
+
+    /// Makes `next` return `None` once `emitted` reaches `max`.
+    pub fn with_total_records_cap(self, emitted: Arc<AtomicUsize>, max: usize) -> Self {
+        Self {
+            total_records_cap: Some((emitted, max)),
+            ..self
+        }
+    }
+
+    /// Records `MillisBehindLatest` of each `GetRecords` call of the shard into `lags`.
+    pub fn with_shard_lags(self, lags: ShardLags) -> Self {
+        Self {
+            shard_lags: Some(lags),
+            ..self
+        }
+    }
+
+    /// Records the shard into `dropped` if its stream drops it by the `drop_shard` error policy.
+    pub fn with_dropped_splits(self, dropped: DroppedSplits) -> Self {
+        Self {
+            dropped_splits: Some(dropped),
+            ..self
+        }
+    }
+
+    /// Makes the reader sleep and tell time with `clock` instead of tokio.
+    pub fn with_clock(self, clock: ClockRef) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Maps the fetched records to messages with `message_mapper` instead of
+    /// [`DefaultMessageMapper`].
+    pub fn with_message_mapper(self, message_mapper: MessageMapperRef) -> Self {
+        Self {
+            message_mapper,
+            ..self
+        }
+    }
+
+    /// Reports the progress of the shard to `progress_reporter` at most once per
+    /// `progress.report.interval`.
+    pub fn with_progress_reporter(self, progress_reporter: ProgressReporterRef) -> Self {
+        Self {
+            progress_reporter,
+            ..self
+        }
+    }
+
+    /// Signals `scaling_listener` as the shard breaches or recovers from its freshness SLA, see
+    /// `freshness.sla.ms`.
+    pub fn with_scaling_listener(self, scaling_listener: ScalingListenerRef) -> Self {
+        Self {
+            scaling_listener,
+            ..self
+        }
+    }
+
+    /// Limits each chunk returned by `next` to the capacity reported by `flow_control`, buffering
+    /// the rest of the batch for the following calls, and waits while there is no capacity.
+    pub fn with_flow_control(self, flow_control: FlowControlRef) -> Self {
+        Self {
+            flow_control: Some(flow_control),
+            ..self
+        }
+    }
+
+    /// Hands the corrupted aggregated records off to `dead_letter_sink` under
+    /// `on_corrupted_aggregate = dead_letter`.
+    pub fn with_dead_letter_sink(self, dead_letter_sink: DeadLetterSinkRef) -> Self {
+        Self {
+            dead_letter_sink,
+            ..self
+        }
+    }
+
+    /// Ends the shard at the first message matching `end_condition`, or at the end position if
+    /// reached first.
+    pub fn with_end_condition(self, end_condition: EndCondition) -> Self {
+        Self {
+            end_condition: Some(end_condition),
+            ..self
+        }
+    }
+
+    /// Exports the metrics of the shard to `metrics`.
+    pub fn with_metrics(self, metrics: ReaderMetricsRef) -> Self {
+        Self { metrics, ..self }
+    }
+
+    /// Replaces the client with one from `client_refresher` when its credentials expire, and
+    /// retries the call, instead of failing the shard. Readers created by `new` refresh the
+    /// client from the connection properties.
+    pub fn with_client_refresher(self, client_refresher: ClientRefresherRef) -> Self {
+        Self {
+            client_refresher: Some(client_refresher),
+            ..self
+        }
+    }
+
+    /// Makes `next` wait without polling while `paused` is true.
+    pub fn with_pause(self, paused: watch::Receiver<bool>) -> Self {
+        Self {
+            paused: Some(paused),
+            ..self
+        }
+    }
+
+    /// The records written between the listing of the shard and the acquisition of its `Latest`
+    /// iterator, which were skipped. `None` unless `latest.gap.detection` is enabled and the first
+    /// record has been read.
+    pub fn skipped_at_latest(&self) -> Option<usize> {
+        self.skipped_at_latest
+    }
+
+    /// Returns the next batch of messages, or `None` once the shard is closed or has reached its
+    /// end position.
+    pub async fn next(&mut self) -> Result<Option<Vec<SourceMessage>>> {
+        if let Some(shard_iter) = self.watchdog.as_mut().and_then(StallWatchdog::disarm) {
+            self.shard_iter = Some(shard_iter);
+        }
+        let chunk = self.next_chunk().await?;
+        if chunk.is_some() {
+            self.arm_watchdog();
+        }
+        Ok(chunk)
+    }
+
+    /// Like `next`, and summarizes the batch as well, so that consumers make scheduling decisions
+    /// without scanning it again.
+    pub async fn next_with_meta(&mut self) -> Result<Option<(Vec<SourceMessage>, BatchMeta)>> {
+        Ok(self.next().await?.map(|chunk| {
+            let meta = BatchMeta::summarize(&chunk, self.lag_millis);
+            (chunk, meta)
+        }))
+    }
+
+    async fn next_chunk(&mut self) -> Result<Option<Vec<SourceMessage>>> {
+        if let Some((emitted, max)) = &self.total_records_cap {
+            if emitted.load(Ordering::SeqCst) >= *max {
+                return Ok(None);
+            }
+        }
+        let chunk = match self.split_chunks.pop_front() {
+            Some(chunk) => chunk,
+            None => match self.fetch_chunk().await? {
+                Some(chunk) => chunk,
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(self.admit(chunk).await))
+    }
+
+    async fn fetch_chunk(&mut self) -> Result<Option<Vec<SourceMessage>>> {
+        let chunk = match (self.batch.tail_records, self.batch.window) {
+            (Some(n), _) => self.next_tail(n).await?,
+            (None, Some(window)) => self.next_window(window).await?,
+            (None, None) => self.next_batch().await?,
+        };
+        match (chunk, self.batch.chunk_splitter) {
+            (Some(chunk), Some(splitter)) => {
+                self.split_chunks = splitter.split(chunk).into();
+                Ok(self.split_chunks.pop_front())
+            }
+            (chunk, _) => Ok(chunk),
+        }
+    }
+
+    /// Returns as much of `chunk` as the downstream has capacity for, see `with_flow_control`,
+    /// and buffers the rest ahead of the chunks split from the batch.
+    async fn admit(&mut self, mut chunk: Vec<SourceMessage>) -> Vec<SourceMessage> {
+        let flow_control = match &self.flow_control {
+            Some(flow_control) if !chunk.is_empty() => flow_control.clone(),
+            _ => return chunk,
+        };
+        let capacity = loop {
+            let capacity = flow_control.available(&self.split_id);
+            if !capacity.is_exhausted() {
+                break capacity;
+            }
+            self.clock.sleep(FLOW_CONTROL_POLL_INTERVAL).await;
+        };
+        let rest = capacity.split_off(&mut chunk);
+        if !rest.is_empty() {
+            self.split_chunks.push_front(rest);
+        }
+        chunk
+    }
+
+    /// Watches for the downstream stalling until `next` is called again, see
+    /// `stall.watchdog.timeout`. The iterator is renewed after the last record read, so not while
+    /// records fetched are withheld.
+    fn arm_watchdog(&mut self) {
+        if self.finished
+            || self.shard_iter.is_none()
+            || self.replay.is_some()
+            || self.is_withholding()
+        {
+            return;
+        }
+        if let (Some(watchdog), Some(sequence_number)) =
+            (self.watchdog.as_mut(), self.latest_offset.clone())
+        {
+            watchdog.arm(
+                self.client.clone(),
+                self.clock.clone(),
+                self.stream_name.clone(),
+                self.shard_id.clone(),
+                sequence_number,
+            );
+        }
+    }
+
+    /// Returns whether the reader is caught up to the tip of the shard, i.e. the last fetch
+    /// returned no records and no lag, as opposed to lagging behind. A reader consistently at the
+    /// tip has spare capacity, e.g. for autoscalers to scale down.
+    pub fn at_tip(&self) -> bool {
+        self.at_tip
+    }
+
+    /// Rewinds the shard to before the last batch fetched, after the downstream failed to emit it,
+    /// so that `next` emits the batch again from the cache of `get_records.retry.cache` instead of
+    /// skipping it. Returns whether the shard was rewound, which requires the cache.
+    pub fn rewind(&mut self) -> bool {
+        let (shard_iter, latest_offset) = match self
+            .response_cache
+            .as_ref()
+            .and_then(ResponseCache::rewind_position)
+        {
+            Some(position) => position,
+            None => return false,
+        };
+        tracing::info!(
+            stream = %self.stream_name,
+            shard = %self.shard_id,
+            sequence = ?latest_offset,
+            "rewind kinesis shard to emit the last batch again"
+        );
+        self.shard_iter = Some(shard_iter);
+        self.latest_offset = latest_offset;
+        self.split_chunks.clear();
+        self.finished = false;
+        true
+    }
+
+    /// Acquires the shard iterator ahead of the first poll, see `iterator.acquisition`.
+    pub async fn acquire_shard_iter(&mut self) -> Result<()> {
+        if self.shard_iter.is_none() && !self.finished {
+            self.new_shard_iter().await?;
+        }
+        Ok(())
+    }
+
+    /// Reads up to the end position, and returns the last `n` records in a single batch.
+    async fn next_tail(&mut self, n: usize) -> Result<Option<Vec<SourceMessage>>> {
+        if self.finished {
+            return Ok(None);
+        }
+        let mut tail = VecDeque::with_capacity(n);
+        while let Some(chunk) = self.next_batch().await? {
+            for msg in chunk.into_iter().filter(|msg| msg.payload.is_some()) {
+                if tail.len() == n {
+                    tail.pop_front();
+                }
+                tail.push_back(msg);
+            }
+        }
+        Ok(Some(tail.into()))
+    }
+
+    /// Accumulates the batches fetched until `window` closes or the shard finishes.
+    async fn next_window(&mut self, window: BatchWindow) -> Result<Option<Vec<SourceMessage>>> {
+        let opened = self.clock.now();
+        let mut batch = match self.next_batch().await? {
+            Some(batch) => batch,
+            None => return Ok(None),
+        };
+        while !self.finished && !window.is_closed(opened, self.clock.now(), &batch) {
+            match self.next_batch().await? {
+                Some(chunk) => batch.extend(chunk),
+                None => break,
+            }
+        }
+        Ok(Some(batch))
+    }
+
+    async fn next_batch(&mut self) -> Result<Option<Vec<SourceMessage>>> {
+        if self.finished {
+            return Ok(None);
+        }
+        // Withheld records are released before the shard is fetched again, even if it is closed.
+        if self.shard_iter.is_none() && !self.is_withholding() {
             self.new_shard_iter().await?;
         }
-        assert!(self.shard_iter.is_some());
+        assert!(self.shard_iter.is_some() || self.is_withholding());
         loop {
+            wait_resumed(&mut self.paused).await;
             match self.get_records().await {
-                Ok(resp) => {
+                Ok(mut resp) => {
+                    if resp.millis_behind_latest.is_none()
+                        && self.validation.endpoint_flavor.absent_lag_is_zero()
+                    {
+                        resp.millis_behind_latest = Some(0);
+                    }
+                    self.validate(&resp)?;
+                    if let Some(threshold) = self.skip_to_latest_lag.take() {
+                        if let Some(lag) = resp.millis_behind_latest() {
+                            if lag > threshold.as_millis() as i64 {
+                                self.skip_to_latest(lag).await?;
+                                continue;
+                            }
+                        }
+                    }
                     self.shard_iter = resp.next_shard_iterator().map(String::from);
-                    let chunk = resp
-                        .records()
-                        .unwrap()
+                    let records = resp.records().unwrap_or_default();
+                    self.at_tip = records.is_empty() && resp.millis_behind_latest() == Some(0);
+                    if let Some(first) = records.first().and_then(|r| r.sequence_number()) {
+                        if let Some(discovered_at) = self.latest_gap_since.take() {
+                            self.check_latest_gap(discovered_at, first).await;
+                        }
+                    }
+                    self.fetches_since_renew += 1;
+                    self.check_clock_skew(records);
+                    let end = records
                         .iter()
-                        .map(|r| {
-                            SourceMessage::from(KinesisMessage::new(
-                                self.shard_id.clone(),
-                                r.clone(),
-                            ))
-                        })
-                        .collect::<Vec<SourceMessage>>();
+                        .position(|r| self.is_beyond_end_position(r))
+                        .unwrap_or(records.len());
+                    let mut chunk = Vec::with_capacity(end);
+                    // The records consumed, fewer than `end` if the end condition is met.
+                    let mut consumed = end;
+                    let ingested_at = self.clock.now_millis();
+                    'records: for (i, r) in records[..end].iter().enumerate() {
+                        let msg = self.message_mapper.map(self.split_id.clone(), r.clone());
+                        let msgs = match apply_transforms(&self.transforms, msg) {
+                            Ok(msgs) => msgs,
+                            Err(e) => match self.on_transform_error(r, e)? {
+                                Some(msgs) => msgs,
+                                None => continue,
+                            },
+                        };
+                        // Resuming within this aggregated record skips the sub-records emitted.
+                        let emitted_through = self
+                            .resume_sub_sequence
+                            .take()
+                            .filter(|(seq, _)| r.sequence_number() == Some(seq.as_str()))
+                            .map(|(_, index)| index);
+                        let last_sub_sequence = msgs.last().and_then(|msg| msg.sub_sequence_number);
+                        for msg in msgs {
+                            if let (Some(index), Some(emitted)) =
+                                (msg.sub_sequence_number, emitted_through)
+                            {
+                                if index <= emitted {
+                                    continue;
+                                }
+                            }
+                            if let Some(range) = &self.hash_key_range {
+                                if !range.contains(&msg) {
+                                    continue;
+                                }
+                            }
+                            // The offset of a sub-record before the last one of its aggregated
+                            // record carries its index, so that a restart resumes within the
+                            // aggregated record.
+                            let within_aggregate = msg
+                                .sub_sequence_number
+                                .filter(|index| Some(*index) != last_sub_sequence);
+                            let msg = match self.framing.unframe(msg) {
+                                Ok(msg) => msg,
+                                Err(e) => {
+                                    tracing::warn!("skip kinesis record: {}", e);
+                                    continue;
+                                }
+                            };
+                            if let Some(dedup) = self.dedup.as_mut() {
+                                if dedup.is_duplicate(&msg) {
+                                    continue;
+                                }
+                            }
+                            let mut msg = SourceMessage::from(msg);
+                            if msg.payload.as_ref().map_or(false, Bytes::is_empty) {
+                                match self.validation.empty_payload_policy {
+                                    EmptyPayloadPolicy::Emit => {}
+                                    EmptyPayloadPolicy::Skip => continue,
+                                    EmptyPayloadPolicy::Heartbeat => msg.payload = None,
+                                }
+                            }
+                            msg.provenance = Some(Provenance {
+                                connector: KINESIS_CONNECTOR,
+                                source: self.stream_name.clone(),
+                                split_id: self.shard_id.clone(),
+                                position: msg.offset.clone(),
+                                ingested_at,
+                            });
+                            msg.offset = self.sequence_number_format.format_offset(msg.offset)?;
+                            if let Some(index) = within_aggregate {
+                                msg.offset =
+                                    format!("{}{}{}", msg.offset, SUB_SEQUENCE_SEPARATOR, index);
+                            }
+                            msg.attributes.insert(
+                                ATTR_STREAM_NAME.to_string(),
+                                Bytes::from(self.stream_name.clone()),
+                            );
+                            msg.attributes.insert(
+                                ATTR_SHARD_ID.to_string(),
+                                Bytes::from(self.shard_id.to_string()),
+                            );
+                            if let Some(bytes) = self.truncate_bytes {
+                                truncate_payload(&mut msg, bytes);
+                            }
+                            let is_end = self
+                                .end_condition
+                                .as_ref()
+                                .map_or(false, |condition| condition.is_end(&msg));
+                            chunk.push(msg);
+                            if is_end {
+                                consumed = i + 1;
+                                break 'records;
+                            }
+                        }
+                    }
+                    // Advances past dropped duplicates as well.
+                    if let Some(last) = records[..consumed].last() {
+                        self.latest_offset = last.sequence_number().map(String::from);
+                    }
+                    self.lag_millis = resp.millis_behind_latest();
+                    if let (Some(lags), Some(lag_millis)) = (&self.shard_lags, self.lag_millis) {
+                        lags.lock()
+                            .unwrap()
+                            .insert(self.split_id.clone(), lag_millis);
+                    }
+                    self.report_progress(self.lag_millis);
+                    self.check_freshness(self.lag_millis);
+                    // A closed shard has no next iterator. A batch starting beyond the end
+                    // position finishes the shard as well, instead of yielding an empty batch.
+                    self.finished = consumed < records.len()
+                        || (self.shard_iter.is_none() && !self.is_withholding())
+                        || (records.is_empty()
+                            && resp.millis_behind_latest() == Some(0)
+                            && matches!(self.end_position, KinesisOffset::Timestamp(_)));
+                    if self.finished {
+                        self.log_finished();
+                        if self.shard_iter.is_none() {
+                            let ending_sequence_number = self.ending_sequence_number().await;
+                            chunk.push(shard_end_message(
+                                self.split_id.clone(),
+                                self.latest_offset.clone().unwrap_or_default(),
+                                ending_sequence_number,
+                            ));
+                        }
+                    }
                     if chunk.is_empty() {
-                        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+                        if self.finished {
+                            return Ok(None);
+                        }
+                        if end > 0 {
+                            // All the records are duplicates.
+                            continue;
+                        }
+                        if let Some(offset) = self.flush_offsets() {
+                            return Ok(Some(vec![state_offset_message(
+                                self.split_id.clone(),
+                                offset,
+                            )]));
+                        }
+                        if let Some(heartbeat) = self.try_heartbeat(resp.millis_behind_latest()) {
+                            return Ok(Some(vec![heartbeat]));
+                        }
+                        self.clock.sleep(IDLE_POLL_INTERVAL).await;
                         continue;
                     }
-                    self.latest_offset = Some(chunk.last().unwrap().offset.clone());
-                    return Ok(chunk);
+                    self.idle_since = None;
+                    if !self.emitted_first {
+                        self.emitted_first = true;
+                        tracing::info!(
+                            stream = %self.stream_name,
+                            shard = %self.shard_id,
+                            sequence = %chunk[0].offset,
+                            "kinesis shard emits its first record"
+                        );
+                    }
+                    self.coalesce_offsets(&mut chunk);
+                    return Ok(Some(chunk));
                 }
                 Err(e) => match e {
                     SdkError::ServiceError { err, .. } if err.is_expired_iterator_exception() => {
+                        if self.fetches_since_renew > 1 {
+                            self.consecutive_renews = 0;
+                        }
+                        self.consecutive_renews += 1;
+                        self.fetches_since_renew = 0;
+                        if self.consecutive_renews > self.retry.max_consecutive_renews {
+                            return Err(anyhow!(
+                                "kinesis shard {} iterator expired {} times in a row, the \
+                                 downstream may stall for longer than the 5 minute iterator \
+                                 lifetime between fetches, consume faster or fetch smaller \
+                                 batches, see max.consecutive.renews",
+                                self.shard_id,
+                                self.consecutive_renews
+                            ));
+                        }
+                        tracing::info!(
+                            stream = %self.stream_name,
+                            shard = %self.shard_id,
+                            sequence = ?self.latest_offset,
+                            "kinesis shard iterator expired, renew it"
+                        );
                         self.new_shard_iter().await?;
-                        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+                        self.metrics.iterator_renewed(&self.shard_id);
+                        self.clock.sleep(IDLE_POLL_INTERVAL).await;
                         continue;
                     }
-                    e => return Err(anyhow!(e)),
+                    e => return Err(sdk_error(e)),
                 },
             };
         }
     }
 
+    /// Checks `resp` according to `get_records.validation`.
+    fn validate(&self, resp: &GetRecordsOutput) -> Result<()> {
+        if self.validation.response == ResponseValidation::Off {
+            return Ok(());
+        }
+        let violations = validate_get_records(resp);
+        if violations.is_empty() {
+            return Ok(());
+        }
+        match self.validation.response {
+            ResponseValidation::Strict => Err(anyhow!(
+                "invalid GetRecords response of kinesis shard {}: {}",
+                self.shard_id,
+                violations.join("; ")
+            )),
+            _ => {
+                tracing::warn!(
+                    "invalid GetRecords response of kinesis shard {}: {}",
+                    self.shard_id,
+                    violations.join("; ")
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Handles `record` failing the payload transforms, returning the messages to emit in its
+    /// place, or `None` if it is handed off to the dead-letter sink. Only corrupted aggregated
+    /// records are handled, by `on_corrupted_aggregate`, and the other errors are returned.
+    fn on_transform_error(
+        &self,
+        record: &Record,
+        e: anyhow::Error,
+    ) -> Result<Option<Vec<KinesisMessage>>> {
+        let reason = match e.downcast_ref::<CorruptedAggregate>() {
+            Some(corrupted) => corrupted.to_string(),
+            None => return Err(e),
+        };
+        let msg = self
+            .message_mapper
+            .map(self.split_id.clone(), record.clone());
+        match self.validation.corrupted_aggregate_policy {
+            CorruptedAggregatePolicy::Fail => Err(e),
+            CorruptedAggregatePolicy::Passthrough => {
+                tracing::warn!(
+                    sequence_number = %msg.sequence_number,
+                    "emit kinesis record as a plain record: {}",
+                    reason
+                );
+                let transforms = self
+                    .transforms
+                    .iter()
+                    .copied()
+                    .filter(|transform| *transform != PayloadTransform::Deaggregate)
+                    .collect::<Vec<_>>();
+                apply_transforms(&transforms, msg).map(Some)
+            }
+            CorruptedAggregatePolicy::DeadLetter => {
+                self.dead_letter_sink.send(DeadLetter {
+                    record: msg,
+                    reason,
+                });
+                Ok(None)
+            }
+        }
+    }
+
+    /// Whether `record` is beyond the end position of the split, which is inclusive for a sequence
+    /// number. A timestamp end position excludes records arriving after it.
+    fn is_beyond_end_position(&self, record: &Record) -> bool {
+        match &self.end_position {
+            KinesisOffset::SequenceNumber(end) => record
+                .sequence_number()
+                .map_or(false, |seq| compare_sequence(seq, end).is_gt()),
+            KinesisOffset::Timestamp(end) => record
+                .approximate_arrival_timestamp()
+                .map_or(false, |ts| datetime_to_millis(ts) > *end),
+            _ => false,
+        }
+    }
+
+    /// Returns a heartbeat message if the shard has been idle for longer than the configured
+    /// heartbeat interval.
+    fn try_heartbeat(&mut self, millis_behind_latest: Option<i64>) -> Option<SourceMessage> {
+        let interval = self.heartbeat_interval?;
+        let now = self.clock.now();
+        let idle_since = *self.idle_since.get_or_insert(now);
+        if now - idle_since < interval {
+            return None;
+        }
+        self.idle_since = None;
+        Some(heartbeat_message(
+            self.split_id.clone(),
+            self.latest_offset.clone().unwrap_or_default(),
+            self.clock.now_millis() - millis_behind_latest.unwrap_or_default(),
+        ))
+    }
+
+    /// Warns if the newest of `records` arrived ahead of the local clock, see [`ClockSkewCheck`].
+    fn check_clock_skew(&mut self, records: &[Record]) {
+        let newest_arrival = match records
+            .iter()
+            .filter_map(|r| r.approximate_arrival_timestamp())
+            .map(datetime_to_millis)
+            .max()
+        {
+            Some(newest_arrival) => newest_arrival,
+            None => return,
+        };
+        let skew = self
+            .clock_skew
+            .check(self.clock.now(), self.clock.now_millis(), newest_arrival);
+        if let Some(skew) = skew {
+            tracing::warn!(
+                stream = %self.stream_name,
+                shard = %self.shard_id,
+                skew_millis = skew,
+                "kinesis records arrived ahead of the local clock, which breaks the watermarks, \
+                 check the clock synchronization of the host"
+            );
+        }
+    }
+
+    /// Signals the scaling listener as the shard breaches or recovers from its freshness SLA, see
+    /// [`FreshnessSla`].
+    fn check_freshness(&mut self, lag_millis: Option<i64>) {
+        let (freshness_sla, lag_millis) = match (self.freshness_sla.as_mut(), lag_millis) {
+            (Some(freshness_sla), Some(lag_millis)) => (freshness_sla, lag_millis),
+            _ => return,
+        };
+        if let Some(signal) = freshness_sla.observe(&self.shard_id, self.clock.now(), lag_millis) {
+            tracing::info!(
+                stream = %self.stream_name,
+                shard = %self.shard_id,
+                ?signal,
+                "kinesis shard freshness sla changed"
+            );
+            self.scaling_listener.signal(signal);
+        }
+    }
+
+    fn report_progress(&mut self, lag_millis: Option<i64>) {
+        let now = self.clock.now();
+        if let Some(reported_at) = self.progress_reported_at {
+            if now - reported_at < self.progress_report_interval {
+                return;
+            }
+        }
+        self.progress_reported_at = Some(now);
+        self.progress_reporter.report(ShardProgress {
+            shard_id: self.shard_id.clone(),
+            latest_sequence: self.latest_offset.clone(),
+            lag_millis,
+            at_tip: self.at_tip,
+        });
+    }
+
+    /// Restarts the shard from `Latest`, skipping the backlog of `lag` milliseconds after its
+    /// restored position.
+    async fn skip_to_latest(&mut self, lag: i64) -> Result<()> {
+        tracing::warn!(
+            stream = %self.stream_name,
+            shard = %self.shard_id,
+            from = ?self.start_position,
+            lag_millis = lag,
+            "kinesis shard lags beyond startup.skip.to.latest.if.lag.exceeds, skip the records \
+             from its restored position to the latest"
+        );
+        self.start_position = KinesisOffset::Latest;
+        self.latest_offset = None;
+        self.resume_sub_sequence = None;
+        self.new_shard_iter().await
+    }
+
+    /// Counts the records written since `discovered_at` before `first_sequence`, the first record
+    /// read from the `Latest` iterator, which the `Latest` start position skipped.
+    async fn check_latest_gap(&mut self, discovered_at: i64, first_sequence: &str) {
+        match self
+            .count_records_before(discovered_at, first_sequence)
+            .await
+        {
+            Ok(skipped) => {
+                if skipped > 0 {
+                    tracing::warn!(
+                        stream = %self.stream_name,
+                        shard = %self.shard_id,
+                        skipped,
+                        "kinesis shard skipped records written before its latest iterator was \
+                         acquired"
+                    );
+                }
+                self.skipped_at_latest = Some(skipped);
+            }
+            Err(e) => tracing::warn!(
+                "failed to count the records skipped at latest of kinesis shard {}: {:#}",
+                self.shard_id,
+                e
+            ),
+        }
+    }
+
+    /// Reads the shard from `since` up to `sequence`, for at most [`MAX_LATEST_GAP_CALLS`]
+    /// `GetRecords` calls, and returns the number of records before `sequence`.
+    async fn count_records_before(&self, since: i64, sequence: &str) -> Result<usize> {
+        let resp = with_retry(&self.retry.policy, self.clock.as_ref(), || {
+            self.client
+                .get_shard_iterator()
+                .stream_name(self.stream_name.clone())
+                .shard_id(self.shard_id.as_ref())
+                .shard_iterator_type(ShardIteratorType::AtTimestamp)
+                .timestamp(DateTime::from_millis(since))
+                .send()
+        })
+        .instrument(self.span.clone())
+        .await
+        .map_err(sdk_error)?;
+        let mut shard_iter = resp.shard_iterator().map(String::from);
+        let mut count = 0;
+        for _ in 0..MAX_LATEST_GAP_CALLS {
+            let iter = match shard_iter {
+                Some(iter) => iter,
+                None => break,
+            };
+            let resp = with_retry(&self.retry.policy, self.clock.as_ref(), || {
+                self.client
+                    .get_records()
+                    .shard_iterator(iter.clone())
+                    .send()
+            })
+            .instrument(self.span.clone())
+            .await
+            .map_err(sdk_error)?;
+            let records = resp.records().unwrap_or_default();
+            for record in records {
+                let seq = record.sequence_number().unwrap_or_default();
+                if compare_sequence(seq, sequence).is_ge() {
+                    return Ok(count);
+                }
+                count += 1;
+            }
+            if records.is_empty() && resp.millis_behind_latest() == Some(0) {
+                break;
+            }
+            shard_iter = resp.next_shard_iterator().map(String::from);
+        }
+        Ok(count)
+    }
+
+    fn coalesce_offsets(&mut self, chunk: &mut [SourceMessage]) {
+        if let Some(coalescer) = self.offset_coalescer.as_mut() {
+            coalescer.coalesce(chunk, self.clock.now(), self.finished);
+        }
+    }
+
+    /// Returns the coalesced offset to surface while the shard is idle, see
+    /// [`OffsetCoalescer::flush`].
+    fn flush_offsets(&mut self) -> Option<String> {
+        let now = self.clock.now();
+        self.offset_coalescer.as_mut()?.flush(now)
+    }
+
+    fn log_finished(&self) {
+        if self.shard_iter.is_none() {
+            tracing::info!(
+                stream = %self.stream_name,
+                shard = %self.shard_id,
+                sequence = ?self.latest_offset,
+                "kinesis shard closed"
+            );
+        } else {
+            tracing::info!(
+                stream = %self.stream_name,
+                shard = %self.shard_id,
+                sequence = ?self.latest_offset,
+                "kinesis shard reached its end position"
+            );
+        }
+    }
+
     async fn new_shard_iter(&mut self) -> Result<()> {
+        if self.replay.is_some() {
+            self.shard_iter = Some("replay".to_string());
+            return Ok(());
+        }
+        // Kept until the next records are read, so that renewing again before resumes after the
+        // same record rather than from the start position.
         let (starting_seq_num, iter_type) = if self.latest_offset.is_some() {
             (
-                self.latest_offset.take(),
+                self.latest_offset.clone(),
                 ShardIteratorType::AfterSequenceNumber,
             )
         } else {
             match &self.start_position {
-                KinesisOffset::Earliest => (None, ShardIteratorType::TrimHorizon),
+                KinesisOffset::Earliest | KinesisOffset::None => {
+                    (None, ShardIteratorType::TrimHorizon)
+                }
+                KinesisOffset::Latest => (None, ShardIteratorType::Latest),
                 KinesisOffset::SequenceNumber(seq) => {
                     (Some(seq.clone()), ShardIteratorType::AfterSequenceNumber)
                 }
-                _ => unreachable!(),
+                KinesisOffset::PackedSequenceNumber(packed) => (
+                    Some(unpack_sequence_number(packed)?),
+                    ShardIteratorType::AfterSequenceNumber,
+                ),
+                KinesisOffset::SubSequenceNumber(seq, _) => {
+                    (Some(seq.clone()), ShardIteratorType::AtSequenceNumber)
+                }
+                KinesisOffset::Timestamp(_) => (None, ShardIteratorType::AtTimestamp),
             }
         };
+        let timestamp = match (&iter_type, &self.start_position) {
+            (ShardIteratorType::AtTimestamp, KinesisOffset::Timestamp(millis)) => {
+                Some(DateTime::from_millis(*millis))
+            }
+            _ => None,
+        };
 
-        let resp = self
-            .client
-            .get_shard_iterator()
-            .stream_name(self.stream_name.clone())
-            .shard_id(self.shard_id.as_ref())
-            .shard_iterator_type(iter_type)
-            .set_starting_sequence_number(starting_seq_num)
-            .send()
-            .await?;
+        let mut refreshed = false;
+        let (resp, attempts) = loop {
+            let (resp, attempts) = count_attempts(
+                with_retry(&self.retry.policy, self.clock.as_ref(), || {
+                    self.client
+                        .get_shard_iterator()
+                        .stream_name(self.stream_name.clone())
+                        .shard_id(self.shard_id.as_ref())
+                        .shard_iterator_type(iter_type.clone())
+                        .set_starting_sequence_number(starting_seq_num.clone())
+                        .set_timestamp(timestamp)
+                        .send()
+                })
+                .instrument(self.span.clone()),
+            )
+            .await;
+            match resp {
+                Err(e) if !refreshed && is_expired_credentials(&e) => {
+                    if !self.refresh_client().await {
+                        break (Err(e), attempts);
+                    }
+                    refreshed = true;
+                }
+                resp => break (resp, attempts),
+            }
+        };
+        let resp = resp.map_err(sdk_error)?;
+        self.metrics
+            .api_call(&self.shard_id, "GetShardIterator", attempts);
 
         self.shard_iter = resp.shard_iterator().map(String::from);
+        tracing::info!(
+            stream = %self.stream_name,
+            shard = %self.shard_id,
+            sequence = ?starting_seq_num,
+            iterator_type = iter_type.as_str(),
+            "kinesis shard iterator acquired"
+        );
 
         Ok(())
     }
 
+    /// Replaces the client after a call is rejected for its expired credentials, see
+    /// `with_client_refresher`. Returns whether it is replaced, so that the call is retried once.
+    async fn refresh_client(&mut self) -> bool {
+        let client_refresher = match &self.client_refresher {
+            Some(client_refresher) => client_refresher.clone(),
+            None => return false,
+        };
+        tracing::info!(
+            stream = %self.stream_name,
+            shard = %self.shard_id,
+            "kinesis credentials expired, refresh them"
+        );
+        match client_refresher.refresh(&self.client).await {
+            Ok(client) => {
+                self.client = client;
+                true
+            }
+            Err(e) => {
+                tracing::error!(
+                    "failed to refresh kinesis credentials of shard {}: {}",
+                    self.shard_id,
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    /// Returns the ending sequence number of the closed shard listed by `ListShards`, or `None` if
+    /// it cannot be listed, e.g. when replaying.
+    async fn ending_sequence_number(&self) -> Option<String> {
+        if self.replay.is_some() {
+            return None;
+        }
+        let mut next_token = None;
+        loop {
+            let output = match with_retry(&self.retry.policy, self.clock.as_ref(), || {
+                self.client
+                    .list_shards()
+                    .set_next_token(next_token.clone())
+                    .stream_name(&self.stream_name)
+                    .send()
+            })
+            .instrument(self.span.clone())
+            .await
+            {
+                Ok(output) => output,
+                Err(e) => {
+                    tracing::warn!(
+                        stream = %self.stream_name,
+                        shard = %self.shard_id,
+                        error = %sdk_error(e),
+                        "failed to list the ending sequence number of the closed kinesis shard"
+                    );
+                    return None;
+                }
+            };
+            if let Some(shard) = output
+                .shards()
+                .unwrap_or_default()
+                .iter()
+                .find(|shard| shard.shard_id() == Some(self.shard_id.as_str()))
+            {
+                return shard
+                    .sequence_number_range()
+                    .and_then(|range| range.ending_sequence_number())
+                    .map(String::from);
+            }
+            next_token = Some(output.next_token?);
+        }
+    }
+
+    fn is_withholding(&self) -> bool {
+        self.safety_lag
+            .as_ref()
+            .map_or(false, |safety_lag| safety_lag.is_withholding())
+    }
+
+    /// Fetches the next records, withholding the ones younger than `read.safety.lag.ms`. While
+    /// records are withheld, waits for the oldest of them to age instead of fetching.
     async fn get_records(
         &mut self,
     ) -> core::result::Result<GetRecordsOutput, SdkError<GetRecordsError>> {
-        self.client
-            .get_records()
-            .set_shard_iterator(self.shard_iter.take())
-            .send()
-            .await
+        match &self.safety_lag {
+            None => return self.fetch_records().await,
+            Some(safety_lag) if safety_lag.is_withholding() => {
+                let wait = safety_lag.wait_duration(self.clock.now_millis());
+                self.clock.sleep(wait).await;
+            }
+            Some(_) => {
+                let output = self.fetch_records().await?;
+                if let Some(safety_lag) = self.safety_lag.as_mut() {
+                    safety_lag.withhold(output);
+                }
+            }
+        }
+        let now_millis = self.clock.now_millis();
+        match self.safety_lag.as_mut() {
+            Some(safety_lag) => Ok(safety_lag.release(now_millis)),
+            None => self.fetch_records().await,
+        }
     }
-}
 
-#[try_stream(ok = Vec<SourceMessage>, error = anyhow::Error)]
-async fn split_reader_into_stream(mut reader: KinesisSplitReader) {
-    loop {
-        match reader.next().await {
-            Ok(chunk) => yield chunk,
-            Err(e) => {
-                tracing::error!("hang up kinesis reader due to polling error: {}", e);
-                drop(reader);
-                break;
+    async fn fetch_records(
+        &mut self,
+    ) -> core::result::Result<GetRecordsOutput, SdkError<GetRecordsError>> {
+        let shard_iter = self.shard_iter.take();
+        if let Some(replay) = self.replay.as_mut() {
+            return Ok(replay.next_output());
+        }
+        if let (Some(cache), Some(shard_iter)) = (&self.response_cache, &shard_iter) {
+            if let Some(output) = cache.get(shard_iter) {
+                return Ok(output);
             }
         }
+        let limit = self.adaptive_limit.as_ref().map(AdaptiveLimit::limit);
+        let mut refreshed = false;
+        let (output, attempts) = loop {
+            let (output, attempts) = count_attempts(
+                with_retry(&self.retry.policy, self.clock.as_ref(), || {
+                    self.client
+                        .get_records()
+                        .set_shard_iterator(shard_iter.clone())
+                        .set_limit(limit)
+                        .send()
+                })
+                .instrument(self.span.clone()),
+            )
+            .await;
+            match output {
+                Err(e) if !refreshed && is_expired_credentials(&e) => {
+                    if !self.refresh_client().await {
+                        break (Err(e), attempts);
+                    }
+                    refreshed = true;
+                }
+                output => break (output, attempts),
+            }
+        };
+        let output = output?;
+        self.metrics
+            .api_call(&self.shard_id, "GetRecords", attempts);
+        if let Some(adaptive_limit) = self.adaptive_limit.as_mut() {
+            adaptive_limit.observe(output.records().unwrap_or_default());
+        }
+        if let Some(capture) = self.capture.as_mut() {
+            if let Err(e) = capture.write(&output) {
+                tracing::error!("failed to capture kinesis shard {}: {}", self.shard_id, e);
+            }
+        }
+        if let (Some(cache), Some(shard_iter)) = (self.response_cache.as_mut(), shard_iter) {
+            cache.put(shard_iter, self.latest_offset.clone(), output.clone());
+        }
+        Ok(output)
     }
 }
 
-#[async_trait]
-impl SplitReader for KinesisMultiSplitReader {
-    type Properties = KinesisProperties;
-
-    async fn new(
+/// Truncates the payload of `msg` to `bytes`, recording its original length in the attributes.
+fn truncate_payload(msg: &mut SourceMessage, bytes: usize) {
+    if let Some(payload) = msg.payload.as_mut() {
+        if payload.len() > bytes {
+            msg.attributes.insert(
+                ATTR_TRUNCATED_FROM.to_string(),
+                Bytes::from(payload.len().to_string()),
+            );
+            payload.truncate(bytes);
+        }
+    }
+}
+
+/// The maximum backoff before retrying a failed shard under [`ShardErrorPolicy::Retry`].
+const MAX_SHARD_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+#[try_stream(ok = Vec<SourceMessage>, error = anyhow::Error)]
+async fn split_reader_into_stream(
+    mut reader: KinesisSplitReader,
+    policy: ShardErrorPolicy,
+    mut breaker: Option<CircuitBreaker>,
+) {
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        if let Some(cooldown) = breaker
+            .as_ref()
+            .and_then(|breaker| breaker.remaining_cooldown(reader.clock.now()))
+        {
+            tracing::warn!(
+                "circuit breaker of kinesis shard {} is open, pause polling for {:?}",
+                reader.shard_id,
+                cooldown
+            );
+            reader.clock.sleep(cooldown).await;
+        }
+        match reader.next().await {
+            Ok(Some(chunk)) => {
+                backoff = Duration::from_secs(1);
+                if let Some(breaker) = breaker.as_mut() {
+                    breaker.on_success();
+                }
+                yield chunk;
+            }
+            Ok(None) => break,
+            Err(e) => match policy {
+                ShardErrorPolicy::FailAll => {
+                    return Err(e.context(format!("shard {} failed", reader.shard_id)));
+                }
+                ShardErrorPolicy::DropShard => {
+                    tracing::error!(
+                        "drop kinesis shard {} due to polling error: {}",
+                        reader.shard_id,
+                        e
+                    );
+                    if let Some(dropped) = &reader.dropped_splits {
+                        dropped.lock().unwrap().insert(reader.split_id.clone());
+                    }
+                    break;
+                }
+                ShardErrorPolicy::Retry => {
+                    tracing::warn!(
+                        stream = %reader.stream_name,
+                        shard = %reader.shard_id,
+                        sequence = ?reader.latest_offset,
+                        backoff = ?backoff,
+                        error = %e,
+                        "kinesis shard failed, back off and retry"
+                    );
+                    if let Some(breaker) = breaker.as_mut() {
+                        breaker.on_failure(reader.clock.now());
+                    }
+                    reader.clock.sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_SHARD_RETRY_BACKOFF);
+                    reader.shard_iter = None;
+                }
+            },
+        }
+    }
+}
+
+/// Drives `stream` in a task which fetches up to `depth` batches ahead of the consumer, queued in
+/// the order they are fetched. The task stops after an error or once the returned stream is
+/// dropped.
+fn prefetch(mut stream: ShardStream, depth: usize) -> ShardStream {
+    // One more batch waits in `send` while the queue is full.
+    let (tx, rx) = mpsc::channel(depth - 1);
+    tokio::spawn(async move {
+        while let Some(chunk) = stream.next().await {
+            let is_err = chunk.is_err();
+            if tx.send(chunk).await.is_err() || is_err {
+                break;
+            }
+        }
+    });
+    ReceiverStream::new(rx).boxed()
+}
+
+/// Checks the number of assigned shards against `max.shards.per.reader`.
+fn check_max_shards(config: &ReaderConfig, shards: usize) -> Result<()> {
+    let max_shards = match config.max_shards {
+        Some(max_shards) if shards > max_shards => max_shards,
+        _ => return Ok(()),
+    };
+    let message = format!(
+        "kinesis reader is assigned {} shards, more than max.shards.per.reader {}",
+        shards, max_shards
+    );
+    match config.max_shards_policy {
+        ShardCapPolicy::Error => Err(anyhow!(message)),
+        ShardCapPolicy::Warn => {
+            tracing::warn!("{}", message);
+            Ok(())
+        }
+    }
+}
+
+/// Waits until the stream is `ACTIVE`, polling `DescribeStreamSummary` every `interval`.
+/// `GetRecords` may fail sporadically while the stream is `CREATING` or `UPDATING`, e.g. when
+/// encryption is being enabled. Proceeds with a warning if the status can not be described, which
+/// may not be permitted.
+async fn wait_stream_active(
+    client: &KinesisClient,
+    clock: &dyn Clock,
+    stream_name: &str,
+    timeout: Duration,
+    interval: Duration,
+) -> Result<()> {
+    let started_at = clock.now();
+    loop {
+        let status = match client
+            .describe_stream_summary()
+            .stream_name(stream_name)
+            .send()
+            .await
+        {
+            Ok(output) => output
+                .stream_description_summary()
+                .and_then(|summary| summary.stream_status())
+                .cloned(),
+            Err(e) => {
+                tracing::warn!(
+                    "failed to describe kinesis stream {}, assume it is active: {}",
+                    stream_name,
+                    sdk_error(e)
+                );
+                return Ok(());
+            }
+        };
+        match status {
+            Some(StreamStatus::Active) | None => return Ok(()),
+            Some(StreamStatus::Deleting) => {
+                return Err(anyhow!("kinesis stream {} is being deleted", stream_name));
+            }
+            Some(status) => {
+                if clock.now() - started_at >= timeout {
+                    return Err(anyhow!(
+                        "timed out after {:?} waiting for kinesis stream {} in {} status to be \
+                         active, see stream.active.timeout",
+                        timeout,
+                        stream_name,
+                        status.as_str()
+                    ));
+                }
+                tracing::info!(
+                    "wait for kinesis stream {} in {} status to be active",
+                    stream_name,
+                    status.as_str()
+                );
+                clock.sleep(interval).await;
+            }
+        }
+    }
+}
+
+/// Waits until the streams of `splits` are active, see [`wait_stream_active`].
+async fn wait_streams_active(
+    client: &KinesisClient,
+    config: &ReaderConfig,
+    splits: &[KinesisSplit],
+) -> Result<()> {
+    let stream_names = splits
+        .iter()
+        .map(|split| {
+            split
+                .stream_name
+                .clone()
+                .unwrap_or_else(|| config.stream_name.clone())
+        })
+        .collect::<HashSet<_>>();
+    for stream_name in stream_names {
+        wait_stream_active(
+            client,
+            &TokioClock,
+            &stream_name,
+            config.stream_active_timeout,
+            STREAM_STATUS_POLL_INTERVAL,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl SplitReader for KinesisMultiSplitReader {
+    type Properties = KinesisProperties;
+
+    async fn new(
         properties: KinesisProperties,
         state: ConnectorState,
         _columns: Option<Vec<Column>>,
@@ -183,90 +1634,3987 @@ impl SplitReader for KinesisMultiSplitReader {
     where
         Self: Sized,
     {
-        let splits = state.unwrap();
-        Ok(Self {
-            splits: splits
+        let properties = CanonicalKinesisConfig::resolve(&properties)?;
+        let client = build_client(properties.clone()).await?;
+        let client_refresher = Arc::new(PropertiesClientRefresher::new(properties.clone()));
+        Self::build(properties, state, client, Some(client_refresher)).await
+    }
+
+    async fn next(&mut self) -> Result<Option<Vec<SourceMessage>>> {
+        if self.read_status == ReadStatus::TimeLimitReached {
+            return Ok(None);
+        }
+        if self.total_records_reached() {
+            if let Some(handler) = self.consumer_handler.as_ref() {
+                handler.abort();
+            }
+            self.read_status = ReadStatus::Finished;
+            return Ok(None);
+        }
+        let chunk = match self.config.batch.max_runtime {
+            None => self.next_chunk().await?,
+            Some(max_runtime) => {
+                let now = self.clock.now();
+                let started_at = *self.started_at.get_or_insert(now);
+                let remaining =
+                    max_runtime.saturating_sub(now.saturating_duration_since(started_at));
+                let clock = self.clock.clone();
+                let chunk = if remaining.is_zero() {
+                    None
+                } else {
+                    tokio::select! {
+                        biased;
+                        chunk = self.next_chunk() => Some(chunk),
+                        _ = clock.sleep(remaining) => None,
+                    }
+                };
+                match chunk {
+                    Some(chunk) => chunk?,
+                    None => {
+                        tracing::warn!(
+                            offsets = ?self.latest_offsets,
+                            "kinesis reader stops at batch.max_runtime {:?} with a partial result",
+                            max_runtime
+                        );
+                        self.stop_at_time_limit();
+                        return Ok(None);
+                    }
+                }
+            }
+        };
+        if chunk.is_none() {
+            let dropped = self.dropped_splits.lock().unwrap().len();
+            self.read_status = if dropped > 0 {
+                tracing::warn!(
+                    "kinesis reader stops with a partial result, {} shards dropped",
+                    dropped
+                );
+                ReadStatus::ShardsDropped
+            } else {
+                ReadStatus::Finished
+            };
+        }
+        Ok(chunk)
+    }
+
+    /// Stops consuming and returns the splits positioned after the last record emitted by `next`
+    /// on each shard, so that a restarted reader resumes without losing in-flight progress.
+    fn finalize(&mut self) -> Result<ConnectorStateV2> {
+        if let Some(handler) = self.consumer_handler.take() {
+            handler.abort();
+        }
+        self.message_rx = None;
+        self.update_tx = None;
+        self.single_split_stream = None;
+        for split in &mut self.splits {
+            if let Some(offset) = self.latest_offsets.get(&split.id()) {
+                *split = split.copy_with_offset(offset.clone());
+            }
+        }
+        Ok(ConnectorStateV2 {
+            splits: self
+                .splits
                 .iter()
-                .map(|split| match split {
-                    SplitImpl::Kinesis(ks) => Ok(ks.to_owned()),
-                    _ => Err(anyhow!(format!("expect KinesisSplit, got {:?}", split))),
-                })
-                .collect::<Result<Vec<KinesisSplit>>>()?,
-            properties,
-            message_cache: Arc::new(Mutex::new(Vec::new())),
-            consumer_handler: None,
+                .cloned()
+                .map(SplitImpl::Kinesis)
+                .collect(),
         })
     }
 
-    async fn next(&mut self) -> Result<Option<Vec<SourceMessage>>> {
-        if self.consumer_handler.is_none() {
-            let split_readers = join_all(
-                self.splits
-                    .iter()
-                    .map(|split| async {
-                        KinesisSplitReader::new(self.properties.clone(), split.to_owned())
-                            .await
-                            .unwrap()
-                    })
-                    .collect::<Vec<_>>(),
-            )
-            .await;
-            let cache = Arc::clone(&self.message_cache);
+    /// Writes the positions of the checkpointed splits to the KCL lease table if any, see
+    /// `commit_leases`.
+    async fn commit(&mut self, splits: &[SplitImpl]) -> Result<()> {
+        let splits = splits
+            .iter()
+            .filter_map(|split| split.as_kinesis())
+            .filter(|split| self.splits.iter().any(|own| own.id() == split.id()))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.commit_leases(&splits).await
+    }
+}
 
-            self.consumer_handler = Some(tokio::spawn(async move {
-                let join_stream = split_readers
-                    .iter()
-                    .map(|split| split_reader_into_stream(split.to_owned()))
-                    .collect::<Vec<_>>()
-                    .merge()
-                    .into_stream();
-                #[for_await]
-                for msg in join_stream {
-                    match msg {
-                        Ok(chunk) => {
-                            cache.lock().await.extend(chunk);
-                        }
-                        Err(e) => {
-                            tracing::error!(
-                                "split encountered error: {:?}, shutting down stream",
-                                e
-                            );
-                            break;
-                        }
+/// The last `MillisBehindLatest` of each shard, shared by the shard readers with the multi split
+/// reader.
+pub type ShardLags = Arc<std::sync::Mutex<HashMap<SplitId, i64>>>;
+
+/// The shards dropped by the `drop_shard` error policy before reaching their ends.
+pub type DroppedSplits = Arc<std::sync::Mutex<HashSet<SplitId>>>;
+
+/// A batch buffered by the consumer task, along with its bytes counted by `max.inflight.bytes`.
+type BufferedChunk = (Result<Vec<SourceMessage>>, Option<InflightPermit>);
+
+/// Forwards the batches of the shard streams to `message_tx`, adding and removing streams as
+/// requested through `updates`.
+async fn consume_splits(
+    mut streams: StreamMap<SplitId, ShardStream>,
+    mut updates: mpsc::UnboundedReceiver<SplitUpdate>,
+    message_tx: mpsc::Sender<BufferedChunk>,
+    inflight: Option<InflightBytes>,
+) {
+    loop {
+        let msg = tokio::select! {
+            update = updates.recv() => {
+                match update {
+                    Some(SplitUpdate::Add(split_id, stream)) => {
+                        streams.insert(split_id, stream);
                     }
+                    Some(SplitUpdate::Remove(split_id)) => {
+                        streams.remove(&split_id);
+                    }
+                    None => break,
                 }
-            }));
+                continue;
+            }
+            msg = streams.next() => match msg {
+                Some((_, msg)) => msg,
+                None => break,
+            },
+        };
+        let is_err = msg.is_err();
+        if let Err(e) = &msg {
+            tracing::error!("split encountered error: {:?}, shutting down stream", e);
+        }
+        // Blocks when the buffer is full until `next` drains it. No shard is polled meanwhile.
+        let permit = match (&inflight, &msg) {
+            (Some(inflight), Ok(chunk)) => Some(inflight.acquire(chunk).await),
+            _ => None,
+        };
+        if message_tx.send((msg, permit)).await.is_err() || is_err {
+            break;
+        }
+    }
+}
+
+impl KinesisMultiSplitReader {
+    /// Creates the reader with a client built by the caller instead of from the connection
+    /// properties, see [`KinesisSplitReader::new_with_client`]. The client is shared by the shards.
+    pub async fn new_with_client(
+        properties: KinesisProperties,
+        state: ConnectorState,
+        client: KinesisClient,
+    ) -> Result<Self> {
+        let properties = CanonicalKinesisConfig::resolve(&properties)?;
+        Self::build(properties, state, client, None).await
+    }
+
+    /// Creates the reader from properties resolved by [`CanonicalKinesisConfig::resolve`].
+    async fn build(
+        properties: KinesisProperties,
+        state: ConnectorState,
+        client: KinesisClient,
+        client_refresher: Option<ClientRefresherRef>,
+    ) -> Result<Self> {
+        let splits = state.unwrap();
+        let config = ReaderConfig::from_properties(&properties)?;
+        let splits = splits
+            .iter()
+            .map(|split| match split {
+                SplitImpl::Kinesis(ks) => Ok(ks.to_owned()),
+                _ => Err(anyhow!(format!("expect KinesisSplit, got {:?}", split))),
+            })
+            .collect::<Result<Vec<KinesisSplit>>>()?;
+        check_max_shards(&config, splits.len())?;
+        wait_streams_active(&client, &config, &splits).await?;
+        let lease_table = LeaseTable::from_properties(&properties).await?;
+        let watermarks = WatermarkTracker::new(splits.iter().map(|split| split.id()));
+        let pause_handle = PauseHandle::new(splits.iter().map(|split| split.id()));
+        let mut reader = Self {
+            splits,
+            inflight: config.batch.max_inflight_bytes.map(InflightBytes::new),
+            config,
+            client,
+            client_refresher,
+            lease_table,
+            message_rx: None,
+            consumer_handler: None,
+            update_tx: None,
+            single_split_stream: None,
+            acquired_streams: HashMap::new(),
+            emitted_records: Arc::new(AtomicUsize::new(0)),
+            shard_lags: ShardLags::default(),
+            dropped_splits: DroppedSplits::default(),
+            started_at: None,
+            clock: Arc::new(TokioClock),
+            read_status: ReadStatus::Reading,
+            completion_signaled: false,
+            latest_offsets: HashMap::new(),
+            watermarks,
+            pause_handle,
+        };
+        if reader.config.iterator_acquisition == IteratorAcquisition::Eager {
+            reader
+                .acquire_shard_iters(reader.config.acquisition_parallelism)
+                .await?;
+        }
+        if let [split] = reader.splits.as_slice() {
+            let split = split.clone();
+            reader.single_split_stream = Some(reader.take_shard_stream(split)?);
+        }
+        Ok(reader)
+    }
+
+    /// Acquires the iterators of all the shards up front, `parallelism` at a time.
+    async fn acquire_shard_iters(&mut self, parallelism: usize) -> Result<()> {
+        let readers = self
+            .splits
+            .iter()
+            .map(|split| self.shard_reader(split.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        let readers = stream::iter(readers)
+            .map(|mut reader| async move {
+                reader.acquire_shard_iter().await?;
+                Ok::<_, anyhow::Error>(reader)
+            })
+            .buffer_unordered(parallelism)
+            .try_collect::<Vec<_>>()
+            .await?;
+        for reader in readers {
+            let split_id = reader.split_id.clone();
+            let stream = self.reader_into_stream(reader);
+            self.acquired_streams.insert(split_id, stream);
+        }
+        Ok(())
+    }
+
+    /// Creates the reader of the split.
+    fn shard_reader(&self, split: KinesisSplit) -> Result<KinesisSplitReader> {
+        let mut reader =
+            KinesisSplitReader::from_config(&self.config, split.clone(), self.client.clone())?
+                .with_pause(self.pause_handle.subscribe(&split.id()))
+                .with_shard_lags(self.shard_lags.clone())
+                .with_dropped_splits(self.dropped_splits.clone());
+        if let Some(client_refresher) = &self.client_refresher {
+            reader = reader.with_client_refresher(client_refresher.clone());
+        }
+        if let Some(max) = self.config.batch.max_total_records {
+            reader = reader.with_total_records_cap(self.emitted_records.clone(), max);
+        }
+        Ok(reader)
+    }
+
+    fn reader_into_stream(&self, reader: KinesisSplitReader) -> ShardStream {
+        let stream = split_reader_into_stream(
+            reader,
+            self.config.retry.shard_error_policy,
+            self.config.retry.circuit_breaker.map(CircuitBreaker::new),
+        )
+        .boxed();
+        if self.config.batch.prefetch_depth > 1 {
+            prefetch(stream, self.config.batch.prefetch_depth)
+        } else {
+            stream
+        }
+    }
+
+    /// Creates the stream of batches read from the split.
+    fn shard_stream(&self, split: KinesisSplit) -> Result<ShardStream> {
+        Ok(self.reader_into_stream(self.shard_reader(split)?))
+    }
+
+    /// Takes the stream of the split if its iterator is acquired eagerly, or creates it.
+    fn take_shard_stream(&mut self, split: KinesisSplit) -> Result<ShardStream> {
+        match self.acquired_streams.remove(&split.id()) {
+            Some(stream) => Ok(stream),
+            None => self.shard_stream(split),
+        }
+    }
+
+    /// Launches the consumer task reading `streams` in the background.
+    fn spawn_consumer(&mut self, streams: Vec<(SplitId, ShardStream)>) {
+        let mut stream_map = StreamMap::new();
+        for (split_id, stream) in streams {
+            stream_map.insert(split_id, stream);
+        }
+        let (message_tx, message_rx) = mpsc::channel(self.config.batch.buffer_capacity);
+        let (update_tx, update_rx) = mpsc::unbounded_channel();
+        self.message_rx = Some(message_rx);
+        self.update_tx = Some(update_tx);
+        self.consumer_handler = Some(tokio::spawn(consume_splits(
+            stream_map,
+            update_rx,
+            message_tx,
+            self.inflight.clone(),
+        )));
+    }
+
+    /// Returns the next batch of the shards, see `next`.
+    async fn next_chunk(&mut self) -> Result<Option<Vec<SourceMessage>>> {
+        if let Some(stream) = self.single_split_stream.as_mut() {
+            let chunk = match stream.next().await {
+                Some(chunk) => chunk?,
+                None => return Ok(None),
+            };
+            let chunk = self.cap_total_records(chunk);
+            self.observe(&chunk);
+            return Ok(Some(chunk));
+        }
+        if self.consumer_handler.is_none() {
+            let streams = self
+                .splits
+                .clone()
+                .into_iter()
+                .map(|split| Ok((split.id(), self.take_shard_stream(split)?)))
+                .collect::<Result<Vec<_>>>()?;
+            self.spawn_consumer(streams);
             tracing::info!("launch kinesis reader with splits: {:?}", self.splits);
         }
         loop {
-            let mut cache_lock = self.message_cache.lock().await;
-            if cache_lock.is_empty() {
-                drop(cache_lock);
-                tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+            // The permit is released as the chunk leaves the buffer.
+            let chunk = match self.message_rx.as_mut().unwrap().recv().await {
+                Some((chunk, _permit)) => chunk?,
+                None => {
+                    tracing::warn!("all kinesis split readers exited");
+                    return Ok(None);
+                }
+            };
+            // Batches of removed splits may still be buffered.
+            if chunk
+                .first()
+                .map_or(false, |msg| !self.is_assigned(&msg.split_id))
+            {
                 continue;
             }
-            let chunk = cache_lock.clone();
-            cache_lock.clear();
-            drop(cache_lock);
+            let chunk = self.cap_total_records(chunk);
+            self.observe(&chunk);
             return Ok(Some(chunk));
         }
     }
-}
 
-impl KinesisMultiSplitReader {}
+    /// Measures `batch.max_runtime` with `clock` instead of tokio.
+    pub fn with_clock(mut self, clock: ClockRef) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Stops reading at `batch.max_runtime`, so that `next` returns `None` from now on.
+    fn stop_at_time_limit(&mut self) {
+        if let Some(handler) = self.consumer_handler.as_ref() {
+            handler.abort();
+        }
+        self.single_split_stream = None;
+        self.read_status = ReadStatus::TimeLimitReached;
+    }
+
+    /// Returns how far the reader has read, e.g. whether it stopped with a partial result at
+    /// `batch.max_runtime`.
+    pub fn read_status(&self) -> ReadStatus {
+        self.read_status
+    }
+
+    /// Like `next`, and returns `SourceCompleted` exactly once as the read finishes, i.e. all the
+    /// splits reached their end or `max.total.records` was reached, so that the executor tells a
+    /// done batch from a stopped one. `None` follows it, as well as a read stopped at
+    /// `batch.max_runtime` or with shards dropped by `on_shard_error`, which `read_status` tells.
+    pub async fn next_signal(&mut self) -> Result<Option<ReadSignal>> {
+        if let Some(chunk) = self.next().await? {
+            return Ok(Some(ReadSignal::Chunk(chunk)));
+        }
+        if self.read_status != ReadStatus::Finished || self.completion_signaled {
+            return Ok(None);
+        }
+        self.completion_signaled = true;
+        Ok(Some(ReadSignal::Completed(SourceCompleted {
+            splits: self.splits.iter().map(|split| split.id()).collect(),
+            latest_offsets: self.latest_offsets.clone(),
+        })))
+    }
+
+    fn total_records_reached(&self) -> bool {
+        self.config.batch.max_total_records.map_or(false, |max| {
+            self.emitted_records.load(Ordering::SeqCst) >= max
+        })
+    }
+
+    /// Truncates the chunk to the records left under `max.total.records` and counts them as
+    /// emitted, so that the offsets observed from it are exactly those of the emitted records.
+    fn cap_total_records(&self, mut chunk: Vec<SourceMessage>) -> Vec<SourceMessage> {
+        let max = match self.config.batch.max_total_records {
+            Some(max) => max,
+            None => return chunk,
+        };
+        let mut left = max.saturating_sub(self.emitted_records.load(Ordering::SeqCst));
+        let end = chunk
+            .iter()
+            .position(|msg| {
+                if msg.payload.is_none() {
+                    return false;
+                }
+                if left == 0 {
+                    return true;
+                }
+                left -= 1;
+                false
+            })
+            .unwrap_or(chunk.len());
+        chunk.truncate(end);
+        let records = chunk.iter().filter(|msg| msg.payload.is_some()).count();
+        self.emitted_records.fetch_add(records, Ordering::SeqCst);
+        chunk
+    }
+
+    fn is_assigned(&self, split_id: &SplitId) -> bool {
+        self.splits.iter().any(|split| &split.id() == split_id)
+    }
+
+    /// Reassigns the splits in place when the scheduler rebalances shards, without restarting the
+    /// reader. Retained splits keep their position and buffered batches, new splits are read from
+    /// their start position, and removed splits stop being read.
+    pub async fn update_splits(&mut self, splits: Vec<KinesisSplit>) -> Result<()> {
+        if splits.is_empty() {
+            return Err(anyhow!(
+                "kinesis reader should be assigned at least one split"
+            ));
+        }
+        check_max_shards(&self.config, splits.len())?;
+        let new_ids = splits
+            .iter()
+            .map(|split| split.id())
+            .collect::<HashSet<_>>();
+        let removed = self
+            .splits
+            .iter()
+            .map(|split| split.id())
+            .filter(|split_id| !new_ids.contains(split_id))
+            .collect::<Vec<_>>();
+        let added = splits
+            .iter()
+            .filter(|split| !self.is_assigned(&split.id()))
+            .cloned()
+            .collect::<Vec<_>>();
+        if added.is_empty() && removed.is_empty() {
+            return Ok(());
+        }
+        wait_streams_active(&self.client, &self.config, &added).await?;
+        let stream_name = |split: &KinesisSplit| {
+            split
+                .stream_name
+                .clone()
+                .unwrap_or_else(|| self.config.stream_name.clone())
+        };
+        for split in &self.splits {
+            if !new_ids.contains(&split.id()) {
+                tracing::info!(
+                    stream = %stream_name(split),
+                    shard = %split.shard_id,
+                    sequence = ?self.latest_offsets.get(&split.id()),
+                    "kinesis split reassigned away from the reader"
+                );
+            }
+        }
+        for split in &added {
+            tracing::info!(
+                stream = %stream_name(split),
+                shard = %split.shard_id,
+                "kinesis split reassigned to the reader"
+            );
+        }
+
+        // The iterators acquired eagerly for the removed splits are released before being read.
+        for split_id in &removed {
+            self.acquired_streams.remove(split_id);
+        }
+        // The single split read in place moves to the consumer task along with the new ones.
+        if let Some(stream) = self.single_split_stream.take() {
+            let split_id = self.splits[0].id();
+            self.spawn_consumer(vec![(split_id, stream)]);
+        }
+        // Otherwise the consumer task is launched by `next` with the updated splits.
+        if let Some(update_tx) = self.update_tx.clone() {
+            let mut updates = removed
+                .iter()
+                .cloned()
+                .map(SplitUpdate::Remove)
+                .collect::<Vec<_>>();
+            for split in &added {
+                updates.push(SplitUpdate::Add(
+                    split.id(),
+                    self.shard_stream(split.clone())?,
+                ));
+            }
+            for update in updates {
+                update_tx.send(update).map_err(|_| {
+                    anyhow!("failed to update the splits as the kinesis consumer task has exited")
+                })?;
+            }
+        }
+
+        for split_id in &removed {
+            self.latest_offsets.remove(split_id);
+            self.shard_lags.lock().unwrap().remove(split_id);
+            self.dropped_splits.lock().unwrap().remove(split_id);
+            self.watermarks.remove_split(split_id);
+        }
+        for split in &added {
+            self.watermarks.add_split(split.id());
+        }
+        self.splits.retain(|split| new_ids.contains(&split.id()));
+        self.splits.extend(added);
+        Ok(())
+    }
+
+    /// Rewinds or fast-forwards the shards to the offsets, e.g. to reprocess data after a
+    /// downstream bug, without recreating the source. A sequence number is exclusive as the start
+    /// position of a split. The shards should be paused with [`Self::pause_handle`], and read
+    /// from the offsets once resumed. The iterators of all the shards are acquired before any is
+    /// reset, so a failure leaves the reader as is. As with pausing, batches already buffered are
+    /// still returned.
+    pub async fn reset_offsets(
+        &mut self,
+        per_shard: HashMap<SplitId, KinesisOffset>,
+    ) -> Result<()> {
+        let mut retention = HashMap::new();
+        let mut streams = vec![];
+        for (split_id, offset) in &per_shard {
+            let split = self
+                .splits
+                .iter()
+                .find(|split| split.id() == *split_id)
+                .ok_or_else(|| {
+                    anyhow!("kinesis split {} is not assigned to the reader", split_id)
+                })?;
+            if !self.pause_handle.is_paused(split_id) {
+                return Err(anyhow!(
+                    "pause kinesis split {} before resetting its offset",
+                    split_id
+                ));
+            }
+            if *offset == KinesisOffset::None {
+                return Err(anyhow!("invalid offset of kinesis split {}", split_id));
+            }
+            if let KinesisOffset::Timestamp(millis) = offset {
+                let stream_name = split
+                    .stream_name
+                    .clone()
+                    .unwrap_or_else(|| self.config.stream_name.clone());
+                let hours = match retention.get(&stream_name) {
+                    Some(hours) => *hours,
+                    None => {
+                        let hours = self.retention_period_hours(&stream_name).await?;
+                        retention.insert(stream_name, hours);
+                        hours
+                    }
+                };
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_millis() as i64;
+                if *millis < now - hours as i64 * 3600 * 1000 {
+                    return Err(anyhow!(
+                        "offset of kinesis split {} is beyond the retention period of {} hours",
+                        split_id,
+                        hours
+                    ));
+                }
+            }
+            let split = KinesisSplit {
+                start_position: offset.clone(),
+                ..split.clone()
+            };
+            // Fails on sequence numbers beyond the retention or not of the shard.
+            let mut reader = self.shard_reader(split.clone())?;
+            reader.acquire_shard_iter().await?;
+            streams.push((split, self.reader_into_stream(reader)));
+        }
+
+        for (split, stream) in streams {
+            let split_id = split.id();
+            tracing::info!(
+                shard = %split.shard_id,
+                offset = ?split.start_position,
+                "reset the offset of kinesis split"
+            );
+            if self.single_split_stream.is_some() {
+                self.single_split_stream = Some(stream);
+            } else if let Some(update_tx) = self.update_tx.as_ref() {
+                let _ = update_tx.send(SplitUpdate::Remove(split_id.clone()));
+                let _ = update_tx.send(SplitUpdate::Add(split_id.clone(), stream));
+            } else {
+                self.acquired_streams.insert(split_id.clone(), stream);
+            }
+            self.latest_offsets.remove(&split_id);
+            for assigned in &mut self.splits {
+                if assigned.id() == split_id {
+                    *assigned = split.clone();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the position of each split without fetching or consuming records, from the offsets
+    /// and watermarks of the records returned by `next`, the last `MillisBehindLatest` of the
+    /// shards, and the retention period of the streams from `DescribeStreamSummary`.
+    pub async fn shard_positions(&self) -> Result<HashMap<SplitId, ShardPosition>> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        let mut retention = HashMap::new();
+        let mut positions = HashMap::new();
+        for split in &self.splits {
+            let stream_name = split
+                .stream_name
+                .clone()
+                .unwrap_or_else(|| self.config.stream_name.clone());
+            let hours = match retention.get(&stream_name) {
+                Some(hours) => *hours,
+                None => {
+                    let hours = self.retention_period_hours(&stream_name).await?;
+                    retention.insert(stream_name, hours);
+                    hours
+                }
+            };
+            let split_id = split.id();
+            let watermark = self.watermarks.shard_watermark(&split_id);
+            let position_millis = watermark.or(match split.start_position {
+                KinesisOffset::Timestamp(millis) => Some(millis),
+                _ => None,
+            });
+            positions.insert(
+                split_id.clone(),
+                ShardPosition {
+                    start_position: split.start_position.clone(),
+                    latest_sequence: self.latest_offsets.get(&split_id).cloned(),
+                    lag_millis: self.shard_lags.lock().unwrap().get(&split_id).copied(),
+                    beyond_retention: position_millis
+                        .map_or(false, |millis| millis < now - hours as i64 * 3600 * 1000),
+                },
+            );
+        }
+        Ok(positions)
+    }
+
+    async fn retention_period_hours(&self, stream_name: &str) -> Result<i32> {
+        let summary = self
+            .client
+            .describe_stream_summary()
+            .stream_name(stream_name)
+            .send()
+            .await
+            .map_err(sdk_error)?;
+        summary
+            .stream_description_summary()
+            .and_then(|summary| summary.retention_period_hours())
+            .ok_or_else(|| anyhow!("kinesis stream {} has no retention period", stream_name))
+    }
+
+    /// Records the offsets and watermarks of a chunk returned by `next`.
+    fn observe(&mut self, chunk: &[SourceMessage]) {
+        for msg in chunk.iter().filter(|msg| msg.payload.is_some()) {
+            self.latest_offsets
+                .insert(msg.split_id.clone(), msg.offset.clone());
+        }
+        self.watermarks.observe(chunk);
+    }
+
+    /// Flattens the batches returned by `next` into a stream of single messages in the same
+    /// order, keeping the batching and prefetching underneath.
+    #[try_stream(ok = SourceMessage, error = anyhow::Error)]
+    pub async fn messages(mut self) {
+        while let Some(chunk) = self.next().await? {
+            for msg in chunk {
+                yield msg;
+            }
+        }
+    }
+
+    /// Returns a handle to pause and resume the reader as a whole or per shard. Batches already
+    /// buffered are still returned by `next` while paused.
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.pause_handle.clone()
+    }
+
+    /// Returns the event-time watermark of the split, see [`WatermarkTracker`].
+    pub fn shard_watermark(&self, split_id: &SplitId) -> Option<i64> {
+        self.watermarks.shard_watermark(split_id)
+    }
+
+    /// Returns the event-time watermark of the source, which is the minimum across its splits.
+    pub fn source_watermark(&self) -> Option<i64> {
+        self.watermarks.source_watermark()
+    }
+
+    /// Writes the positions of `splits` to the KCL lease table as the checkpoints of their shards.
+    /// Call it once the positions are durable, e.g. after a checkpoint, so that KCL consumers
+    /// taking over do not skip records.
+    pub async fn commit_leases(&self, splits: &[KinesisSplit]) -> Result<()> {
+        let lease_table = match &self.lease_table {
+            Some(lease_table) => lease_table,
+            None => return Ok(()),
+        };
+        for split in splits {
+            if let KinesisOffset::SequenceNumber(sequence_number) =
+                split.start_position.unpacked()?
+            {
+                lease_table
+                    .checkpoint(&split.shard_id, &sequence_number)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Shuts the reader down gracefully, e.g. on scaling down or dropping the source: finalizes the
+    /// splits, and writes their offsets to the KCL lease table if any, see `commit_leases`.
+    /// Returns the state to restart from.
+    pub async fn shutdown(mut self) -> Result<ConnectorStateV2> {
+        let state = self.finalize()?;
+        self.commit_leases(&self.splits).await?;
+        tracing::info!(offsets = ?self.latest_offsets, "kinesis reader shut down");
+        Ok(state)
+    }
+}
 #[cfg(test)]
 mod tests {
 
     use std::iter::Iterator;
+    use std::sync::Mutex;
 
+    use futures::TryStreamExt;
     use futures_async_stream::for_await;
     use futures_concurrency::prelude::*;
+    use itertools::Itertools;
 
     use super::*;
+    use crate::source::kinesis::clock::MockClock;
+    use crate::source::kinesis::credentials::ClientRefresher;
+    use crate::source::kinesis::source::chunk::{Capacity, FlowControl};
+    use crate::source::kinesis::source::dead_letter::DeadLetterSink;
+    use crate::source::kinesis::source::freshness::{ScalingListener, ScalingSignal};
+    use crate::source::kinesis::source::kpl::{aggregate, aggregate_with_explicit_hash_keys};
+    use crate::source::kinesis::source::message::{KinesisMessage, MessageMapper};
+    use crate::source::kinesis::source::metrics::ReaderMetrics;
+    use crate::source::kinesis::source::progress::ProgressReporter;
+    use crate::source::kinesis::test_utils::*;
+    use crate::source::test_kit::assert_state_round_trip;
+    use crate::source::SourceMeta;
 
     #[tokio::test]
-    #[ignore]
-    async fn test_single_thread_kinesis_reader() -> Result<()> {
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_idle_heartbeat() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(vec![], 0)),
+        )
+        .await;
+
+        let properties = KinesisProperties {
+            idle_heartbeat_interval: Some("500ms".to_string()),
+            ..mock_properties(&server)
+        };
+        let clock = Arc::new(MockClock::new());
+        let start = clock.now_millis();
+        let mut reader = KinesisSplitReader::new(properties, mock_split("shardId-000000000000"))
+            .await?
+            .with_clock(clock.clone());
+
+        let chunk = reader.next().await?.unwrap();
+        assert!(clock.now_millis() - start >= 500);
+        assert_eq!(chunk.len(), 1);
+        assert!(chunk[0].payload.is_none());
+        // The heartbeat is stamped with the reader's clock rather than the wall clock.
+        match &chunk[0].meta {
+            SourceMeta::Kinesis(meta) => assert_eq!(meta.timestamp, Some(clock.now_millis())),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_buffer_backpressure() -> Result<()> {
+        async fn wait_pulled(pulled_rx: &mut watch::Receiver<usize>, expected: usize) {
+            while *pulled_rx.borrow() < expected {
+                pulled_rx.changed().await.unwrap();
+            }
+        }
+
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let properties = KinesisProperties {
+            buffer_capacity: Some("2".to_string()),
+            ..mock_properties(&server)
+        };
+        // A single split is read in place without buffering.
+        let splits = vec![
+            SplitImpl::Kinesis(mock_split("shardId-000000000000")),
+            SplitImpl::Kinesis(mock_split("shardId-000000000001")),
+        ];
+        let mut reader = KinesisMultiSplitReader::new(properties, Some(splits), None).await?;
+
+        // The shards fetch endlessly and count the batches pulled by the consumer task, so that
+        // the test waits for it instead of sleeping.
+        let (pulled_tx, mut pulled_rx) = watch::channel(0);
+        let pulled_tx = Arc::new(pulled_tx);
+        let streams = reader
+            .splits
+            .iter()
+            .map(|split| {
+                let pulled_tx = pulled_tx.clone();
+                let stream = stream::repeat_with(move || {
+                    pulled_tx.send_modify(|pulled| *pulled += 1);
+                    Ok(vec![])
+                });
+                (split.id(), stream.boxed())
+            })
+            .collect();
+        reader.spawn_consumer(streams);
+
+        // The buffer is full with 2 batches, and the consumer task is blocked sending the third.
+        wait_pulled(&mut pulled_rx, 3).await;
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(*pulled_rx.borrow(), 3);
+
+        // Draining a batch lets it send the blocked one and pull another.
+        assert!(reader.next().await?.is_some());
+        wait_pulled(&mut pulled_rx, 4).await;
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(*pulled_rx.borrow(), 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_timestamp_end_position() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(
+                vec![
+                    mock_record("1", b"before", 1_000),
+                    mock_record("2", b"at", 2_000),
+                    mock_record("3", b"after", 3_000),
+                ],
+                0,
+            )),
+        )
+        .await;
+
+        let split = KinesisSplit {
+            end_position: KinesisOffset::Timestamp(2_000),
+            ..mock_split("shardId-000000000000")
+        };
+        let mut reader = KinesisSplitReader::new(mock_properties(&server), split).await?;
+        let chunk = reader.next().await?.unwrap();
+        let offsets = chunk.iter().map(|msg| msg.offset.as_str()).collect_vec();
+        assert_eq!(offsets, vec!["1", "2"]);
+        assert!(reader.next().await?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_end_position_before_batch() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(
+                vec![
+                    mock_record("6", b"after", 6_000),
+                    mock_record("7", b"after", 7_000),
+                ],
+                0,
+            )),
+        )
+        .await;
+
+        for end_position in [
+            KinesisOffset::SequenceNumber("5".to_string()),
+            KinesisOffset::Timestamp(5_000),
+        ] {
+            let split = KinesisSplit {
+                end_position,
+                ..mock_split("shardId-000000000000")
+            };
+            let mut reader = KinesisSplitReader::new(mock_properties(&server), split).await?;
+            // The shard ends rather than yielding an empty batch.
+            assert!(reader.next().await?.is_none());
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_end_condition() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(
+                vec![
+                    mock_record("1", b"a", 0),
+                    mock_record("2", b"sentinel", 0),
+                    mock_record("3", b"b", 0),
+                ],
+                1_000,
+            )),
+        )
+        .await;
+
+        let reader =
+            KinesisSplitReader::new(mock_properties(&server), mock_split("shardId-000000000000"))
+                .await?
+                .with_end_condition(EndCondition::new(|msg| {
+                    msg.payload.as_deref() == Some(b"sentinel".as_slice())
+                }));
+        let messages = read_to_end(reader).await?;
+        let offsets = messages.iter().map(|msg| msg.offset.as_str()).collect_vec();
+        assert_eq!(offsets, vec!["1", "2"]);
+        assert_eq!(received_calls(&server, "GetRecords").await, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_batch_window() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let records = |seqs: &[&str]| {
+            json_response(get_records_output(
+                seqs.iter().map(|seq| mock_record(seq, b"a", 0)).collect(),
+                0,
+            ))
+        };
+        // The empty responses idle for 200ms each, so that the window of 500ms elapses by `3`.
+        mount_api(
+            &server,
+            "GetRecords",
+            SequenceResponder::new(vec![
+                records(&["1"]),
+                records(&[]),
+                records(&["2", "3"]),
+                records(&[]),
+                records(&[]),
+                records(&["4"]),
+                records(&["5"]),
+            ]),
+        )
+        .await;
+        let properties = KinesisProperties {
+            batch_window_ms: Some("500".into()),
+            ..mock_properties(&server)
+        };
+        let mut reader = KinesisSplitReader::new(properties, mock_split("shardId-000000000000"))
+            .await?
+            .with_clock(Arc::new(MockClock::new()));
+        let offsets =
+            |chunk: Vec<SourceMessage>| chunk.into_iter().map(|msg| msg.offset).collect_vec();
+        assert_eq!(
+            offsets(reader.next().await?.unwrap()),
+            vec!["1", "2", "3", "4"]
+        );
+        assert_eq!(received_calls(&server, "GetRecords").await, 6);
+
+        // The window is returned early once full.
+        let server = wiremock::MockServer::start().await;
+        mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+        mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+        let properties = KinesisProperties {
+            batch_window_ms: Some("500".into()),
+            batch_window_max_records: Some("3".into()),
+            ..mock_properties(&server)
+        };
+        let mut reader = KinesisSplitReader::new(properties, mock_split("shardId-000000000000"))
+            .await?
+            .with_clock(Arc::new(MockClock::new()));
+        assert_eq!(offsets(reader.next().await?.unwrap()), vec!["1", "2", "3"]);
+        assert_eq!(offsets(reader.next().await?.unwrap()), vec!["4", "5", "6"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_expired_credentials() -> Result<()> {
+        #[derive(Debug)]
+        struct MockRefresher {
+            client: KinesisClient,
+            refreshes: AtomicUsize,
+        }
+
+        #[async_trait]
+        impl ClientRefresher for MockRefresher {
+            async fn refresh(&self, _stale: &KinesisClient) -> Result<KinesisClient> {
+                self.refreshes.fetch_add(1, Ordering::SeqCst);
+                Ok(self.client.clone())
+            }
+        }
+
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            SequenceResponder::new(vec![
+                error_response("ExpiredTokenException"),
+                json_response(get_records_output(vec![mock_record("1", b"a", 0)], 0)),
+            ]),
+        )
+        .await;
+        let refresher = Arc::new(MockRefresher {
+            client: build_client(mock_properties(&server)).await?,
+            refreshes: AtomicUsize::new(0),
+        });
+        let mut reader =
+            KinesisSplitReader::new(mock_properties(&server), mock_split("shardId-000000000000"))
+                .await?
+                .with_client_refresher(refresher.clone());
+        let chunk = reader.next().await?.unwrap();
+        assert_eq!(
+            chunk.iter().map(|msg| msg.offset.as_str()).collect_vec(),
+            vec!["1"]
+        );
+        assert_eq!(refresher.refreshes.load(Ordering::SeqCst), 1);
+        assert_eq!(received_calls(&server, "GetRecords").await, 2);
+
+        // Without a refresher, the expired credentials fail the shard.
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            error_response("ExpiredTokenException"),
+        )
+        .await;
+        let properties = mock_properties(&server);
+        let client = build_client(properties.clone()).await?;
+        let mut reader = KinesisSplitReader::new_with_client(
+            properties,
+            mock_split("shardId-000000000000"),
+            client,
+        )?;
+        assert!(reader.next().await.is_err());
+
+        // An unrecognized client, e.g. a wrong access key, fails at once without a refresh.
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            error_response("UnrecognizedClientException"),
+        )
+        .await;
+        let refresher = Arc::new(MockRefresher {
+            client: build_client(mock_properties(&server)).await?,
+            refreshes: AtomicUsize::new(0),
+        });
+        let mut reader =
+            KinesisSplitReader::new(mock_properties(&server), mock_split("shardId-000000000000"))
+                .await?
+                .with_client_refresher(refresher.clone());
+        assert!(reader.next().await.is_err());
+        assert_eq!(refresher.refreshes.load(Ordering::SeqCst), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_empty_payload_policy() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(serde_json::json!({
+                "Records": [
+                    mock_record("1", b"a", 1_000),
+                    mock_record("2", b"", 2_000),
+                    mock_record("3", b"b", 3_000),
+                ],
+                "MillisBehindLatest": 0,
+            })),
+        )
+        .await;
+
+        let read = |policy: &str| {
+            let properties = KinesisProperties {
+                on_empty_payload: Some(policy.to_string()),
+                ..mock_properties(&server)
+            };
+            async move {
+                let reader =
+                    KinesisSplitReader::new(properties, mock_split("shardId-000000000000")).await?;
+                let mut messages = read_to_end(reader).await?;
+                // The shard end message.
+                messages.pop();
+                Ok::<_, anyhow::Error>(messages)
+            }
+        };
+        let payloads = |messages: &[SourceMessage]| {
+            messages
+                .iter()
+                .map(|msg| {
+                    let payload = msg.payload.as_ref().map(|payload| payload.to_vec());
+                    (msg.offset.clone(), payload)
+                })
+                .collect_vec()
+        };
+        assert_eq!(
+            payloads(&read("emit").await?),
+            vec![
+                ("1".to_string(), Some(b"a".to_vec())),
+                ("2".to_string(), Some(vec![])),
+                ("3".to_string(), Some(b"b".to_vec())),
+            ]
+        );
+        assert_eq!(
+            payloads(&read("skip").await?),
+            vec![
+                ("1".to_string(), Some(b"a".to_vec())),
+                ("3".to_string(), Some(b"b".to_vec())),
+            ]
+        );
+        let messages = read("heartbeat").await?;
+        assert_eq!(
+            payloads(&messages),
+            vec![
+                ("1".to_string(), Some(b"a".to_vec())),
+                ("2".to_string(), None),
+                ("3".to_string(), Some(b"b".to_vec())),
+            ]
+        );
+        // The heartbeat advances the watermark to the arrival of the record.
+        match &messages[1].meta {
+            SourceMeta::Kinesis(meta) => assert_eq!(meta.timestamp, Some(2_000)),
+            _ => panic!("expect kinesis metadata"),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_next_with_meta() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(
+                vec![
+                    mock_record("1", b"abc", 2_000),
+                    mock_record("2", b"", 1_000),
+                    mock_record("3", b"defgh", 3_000),
+                ],
+                4_000,
+            )),
+        )
+        .await;
+
+        let mut reader =
+            KinesisSplitReader::new(mock_properties(&server), mock_split("shardId-000000000000"))
+                .await?;
+        let (chunk, meta) = reader.next_with_meta().await?.unwrap();
+        assert_eq!(chunk.len(), 3);
+        assert_eq!(
+            meta,
+            BatchMeta {
+                records: 3,
+                bytes: 8,
+                min_arrival: Some(1_000),
+                max_arrival: Some(3_000),
+                lag_millis: Some(4_000),
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_clock_skew_warning() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let future = TokioClock.now_millis() + 3_600_000;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(
+                vec![mock_record("1", b"a", 0), mock_record("2", b"b", future)],
+                1_000,
+            )),
+        )
+        .await;
+
+        let (events, _guard) = capture_events();
+        let mut reader =
+            KinesisSplitReader::new(mock_properties(&server), mock_split("shardId-000000000000"))
+                .await?;
+        reader.next().await?.unwrap();
+        // Not checked again within the interval.
+        reader.next().await?.unwrap();
+        let warnings = events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| event.contains("arrived ahead of the local clock"))
+            .cloned()
+            .collect_vec();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("skew_millis="));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_latest_gap_detection() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        // Records 3 and 4 are written after the shard is listed and before its latest iterator is
+        // acquired, which starts at record 5.
+        mount_api_matching(
+            &server,
+            "GetShardIterator",
+            serde_json::json!({ "ShardIteratorType": "AT_TIMESTAMP" }),
+            json_response(serde_json::json!({ "ShardIterator": "since_discovery" })),
+        )
+        .await;
+        mount_api_matching(
+            &server,
+            "GetRecords",
+            serde_json::json!({ "ShardIterator": "since_discovery" }),
+            json_response(get_records_output(
+                vec![
+                    mock_record("3", b"a", 0),
+                    mock_record("4", b"b", 0),
+                    mock_record("5", b"c", 0),
+                ],
+                0,
+            )),
+        )
+        .await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(vec![mock_record("5", b"c", 0)], 0)),
+        )
+        .await;
+
+        let split = KinesisSplit {
+            start_position: KinesisOffset::Latest,
+            discovered_at: Some(1_000),
+            ..mock_split("shardId-000000000000")
+        };
+        let mut reader = KinesisSplitReader::new(mock_properties(&server), split).await?;
+        assert_eq!(reader.skipped_at_latest(), None);
+        let chunk = reader.next().await?.unwrap();
+        assert_eq!(chunk[0].offset, "5");
+        assert_eq!(reader.skipped_at_latest(), Some(2));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_skip_to_latest() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        // The restored position lags an hour behind, while the latest iterator is at the tip.
+        mount_api_matching(
+            &server,
+            "GetShardIterator",
+            serde_json::json!({ "ShardIteratorType": "AFTER_SEQUENCE_NUMBER" }),
+            json_response(serde_json::json!({ "ShardIterator": "restored" })),
+        )
+        .await;
+        mount_api_matching(
+            &server,
+            "GetRecords",
+            serde_json::json!({ "ShardIterator": "restored" }),
+            json_response(get_records_output(
+                vec![mock_record("2", b"backlog", 0)],
+                3_600_000,
+            )),
+        )
+        .await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(vec![mock_record("9", b"tip", 0)], 0)),
+        )
+        .await;
+
+        let split = KinesisSplit {
+            start_position: KinesisOffset::SequenceNumber("1".to_string()),
+            ..mock_split("shardId-000000000000")
+        };
+        for (threshold, expected) in [("10m", "9"), ("2h", "2")] {
+            let properties = KinesisProperties {
+                startup_skip_to_latest_lag: Some(threshold.to_string()),
+                ..mock_properties(&server)
+            };
+            let mut reader = KinesisSplitReader::new(properties, split.clone()).await?;
+            assert_eq!(reader.next().await?.unwrap()[0].offset, expected);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_provenance() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(vec![mock_record("1", b"a", 1_000)], 0)),
+        )
+        .await;
+        let properties = KinesisProperties {
+            state_sequence_number_format: Some("packed".to_string()),
+            ..mock_properties(&server)
+        };
+        let clock = Arc::new(MockClock::new());
+
+        let mut reader = KinesisSplitReader::new(properties, mock_split("shardId-000000000000"))
+            .await?
+            .with_clock(clock.clone());
+        let chunk = reader.next().await?.unwrap();
+        assert_eq!(
+            chunk[0].provenance,
+            Some(Provenance {
+                connector: "kinesis",
+                source: "mock_stream".to_string(),
+                split_id: "shardId-000000000000".to_string().into(),
+                // The sequence number rather than the packed offset.
+                position: "1".to_string(),
+                ingested_at: clock.now_millis(),
+            })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_stall_watchdog() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api_matching(
+            &server,
+            "GetShardIterator",
+            serde_json::json!({
+                "ShardIteratorType": "AFTER_SEQUENCE_NUMBER",
+                "StartingSequenceNumber": "1",
+            }),
+            json_response(serde_json::json!({ "ShardIterator": "renewed" })),
+        )
+        .await;
+        mount_api_matching(
+            &server,
+            "GetRecords",
+            serde_json::json!({ "ShardIterator": "renewed" }),
+            json_response(get_records_output(vec![mock_record("2", b"b", 0)], 0)),
+        )
+        .await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(vec![mock_record("1", b"a", 0)], 0)),
+        )
+        .await;
+        let properties = KinesisProperties {
+            stall_watchdog_timeout: Some("100ms".to_string()),
+            ..mock_properties(&server)
+        };
+
+        let mut reader =
+            KinesisSplitReader::new(properties, mock_split("shardId-000000000000")).await?;
+        assert_eq!(reader.next().await?.unwrap()[0].offset, "1");
+        assert_eq!(received_calls(&server, "GetShardIterator").await, 1);
+        // The downstream stalls, and the watchdog renews the iterator after the last record.
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        assert!(received_calls(&server, "GetShardIterator").await > 1);
+        assert_eq!(reader.next().await?.unwrap()[0].offset, "2");
+        // Disarmed while polled.
+        let renewals = received_calls(&server, "GetShardIterator").await;
+        reader.next().await?;
+        assert_eq!(received_calls(&server, "GetShardIterator").await, renewals);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_idle_empty_shard() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        // The shard exists and is open, but has no records yet.
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(vec![], 0)),
+        )
+        .await;
+
+        let mut reader =
+            KinesisSplitReader::new(mock_properties(&server), mock_split("shardId-000000000000"))
+                .await?;
+        // Keeps polling instead of failing or finishing.
+        let next = tokio::time::timeout(Duration::from_millis(500), reader.next()).await;
+        assert!(next.is_err());
+        assert!(received_calls(&server, "GetRecords").await > 1);
+        Ok(())
+    }
+
+    /// Asserts that `next` waits without calling `GetRecords`.
+    async fn assert_paused(server: &wiremock::MockServer, reader: &mut KinesisSplitReader) {
+        let calls = received_calls(server, "GetRecords").await;
+        let next = tokio::time::timeout(Duration::from_millis(300), reader.next()).await;
+        assert!(next.is_err());
+        assert_eq!(received_calls(server, "GetRecords").await, calls);
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_pause_resume() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            SequenceResponder::new(vec![
+                json_response(get_records_output(vec![mock_record("1", b"a", 0)], 0)),
+                json_response(get_records_output(vec![mock_record("2", b"b", 0)], 0)),
+            ]),
+        )
+        .await;
+
+        let split = mock_split("shardId-000000000000");
+        let handle = PauseHandle::new([split.id()]);
+        let mut reader = KinesisSplitReader::new(mock_properties(&server), split.clone())
+            .await?
+            .with_pause(handle.subscribe(&split.id()));
+        assert_eq!(reader.next().await?.unwrap()[0].offset, "1");
+
+        // Paused as a whole.
+        handle.pause();
+        assert_paused(&server, &mut reader).await;
+        handle.resume();
+
+        // Paused per shard.
+        handle.pause_shard(&split.id());
+        assert_paused(&server, &mut reader).await;
+        handle.resume_shard(&split.id());
+
+        assert_eq!(reader.next().await?.unwrap()[0].offset, "2");
+        Ok(())
+    }
+
+    /// Reads the shard until it is closed.
+    async fn read_to_end(mut reader: KinesisSplitReader) -> Result<Vec<SourceMessage>> {
+        let mut messages = vec![];
+        while let Some(chunk) = reader.next().await? {
+            messages.extend(chunk);
+        }
+        Ok(messages)
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_capture_replay() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir_path = dir.path().to_str().unwrap().to_string();
+
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let mut closed = get_records_output(vec![mock_record("3", b"c", 3_000)], 0);
+        closed.as_object_mut().unwrap().remove("NextShardIterator");
+        mount_api(
+            &server,
+            "GetRecords",
+            SequenceResponder::new(vec![
+                json_response(get_records_output(
+                    vec![mock_record("1", b"a", 1_000), mock_record("2", b"b", 2_000)],
+                    0,
+                )),
+                json_response(closed),
+            ]),
+        )
+        .await;
+        let properties = KinesisProperties {
+            capture_dir: Some(dir_path.clone()),
+            ..mock_properties(&server)
+        };
+        let reader =
+            KinesisSplitReader::new(properties, mock_split("shardId-000000000000")).await?;
+        let captured = read_to_end(reader).await?;
+        // The records and the shard end message.
+        assert_eq!(captured.len(), 4);
+
+        // Replays offline against a server without any API.
+        let offline = wiremock::MockServer::start().await;
+        let properties = KinesisProperties {
+            replay_dir: Some(dir_path),
+            ..mock_properties(&offline)
+        };
+        let reader =
+            KinesisSplitReader::new(properties, mock_split("shardId-000000000000")).await?;
+        assert_eq!(read_to_end(reader).await?, captured);
+        assert!(offline.received_requests().await.unwrap().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_dedup() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(
+                vec![
+                    mock_record("1", br#"{"id": 1}"#, 0),
+                    mock_record("2", br#"{"id": 2}"#, 0),
+                    mock_record("3", br#"{"id": 1}"#, 0),
+                ],
+                0,
+            )),
+        )
+        .await;
+
+        let properties = KinesisProperties {
+            dedup_id_path: Some("/id".to_string()),
+            ..mock_properties(&server)
+        };
+        let mut reader =
+            KinesisSplitReader::new(properties, mock_split("shardId-000000000000")).await?;
+        let chunk = reader.next().await?.unwrap();
+        let offsets = chunk.iter().map(|msg| msg.offset.as_str()).collect_vec();
+        assert_eq!(offsets, vec!["1", "2"]);
+        assert_eq!(reader.latest_offset.as_deref(), Some("3"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_rewind_from_response_cache() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let mut first = get_records_output(
+            vec![mock_record("1", b"a", 0), mock_record("2", b"b", 0)],
+            1_000,
+        );
+        first["NextShardIterator"] = serde_json::json!("iterator-2");
+        let mut second = get_records_output(vec![mock_record("3", b"c", 0)], 0);
+        second["NextShardIterator"] = serde_json::json!("iterator-3");
+        mount_api(
+            &server,
+            "GetRecords",
+            SequenceResponder::new(vec![json_response(first), json_response(second)]),
+        )
+        .await;
+
+        let properties = KinesisProperties {
+            get_records_retry_cache: Some("true".to_string()),
+            ..mock_properties(&server)
+        };
+        let mut reader =
+            KinesisSplitReader::new(properties, mock_split("shardId-000000000000")).await?;
+        let offsets =
+            |chunk: Vec<SourceMessage>| chunk.into_iter().map(|msg| msg.offset).collect_vec();
+        assert_eq!(offsets(reader.next().await?.unwrap()), vec!["1", "2"]);
+        // The downstream fails to emit the batch, which is emitted again without fetching.
+        assert!(reader.rewind());
+        assert_eq!(reader.latest_offset, None);
+        assert_eq!(offsets(reader.next().await?.unwrap()), vec!["1", "2"]);
+        assert_eq!(received_calls(&server, "GetRecords").await, 1);
+        assert_eq!(offsets(reader.next().await?.unwrap()), vec!["3"]);
+        assert_eq!(received_calls(&server, "GetRecords").await, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_shards_per_reader() {
+        let splits = (0..3)
+            .map(|i| SplitImpl::Kinesis(mock_split(&format!("shardId-00000000000{}", i))))
+            .collect_vec();
+        let properties = KinesisProperties {
+            max_shards_per_reader: Some("2".to_string()),
+            ..Default::default()
+        };
+        assert!(
+            KinesisMultiSplitReader::new(properties.clone(), Some(splits.clone()), None)
+                .await
+                .is_err()
+        );
+
+        let properties = KinesisProperties {
+            max_shards_per_reader_policy: Some("warn".to_string()),
+            ..properties
+        };
+        assert!(KinesisMultiSplitReader::new(properties, Some(splits), None)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_confluent_framing() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let framed = [&[0, 0, 0, 0, 42][..], b"payload"].concat();
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(
+                vec![
+                    mock_record("1", &framed, 0),
+                    mock_record("2", b"unframed", 0),
+                ],
+                0,
+            )),
+        )
+        .await;
+
+        let properties = KinesisProperties {
+            payload_framing: Some("confluent".to_string()),
+            ..mock_properties(&server)
+        };
+        let mut reader =
+            KinesisSplitReader::new(properties, mock_split("shardId-000000000000")).await?;
+        let chunk = reader.next().await?.unwrap();
+        assert_eq!(chunk.len(), 1);
+        assert_eq!(chunk[0].payload.as_deref(), Some(&b"payload"[..]));
+        match &chunk[0].meta {
+            SourceMeta::Kinesis(meta) => assert_eq!(meta.schema_id, Some(42)),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_message_attributes() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(vec![mock_record("1", b"a", 1_000)], 0)),
+        )
+        .await;
+
+        let mut reader =
+            KinesisSplitReader::new(mock_properties(&server), mock_split("shardId-000000000000"))
+                .await?;
+        let chunk = reader.next().await?.unwrap();
+        let attributes = chunk[0]
+            .attributes
+            .iter()
+            .map(|(key, value)| (key.as_str(), std::str::from_utf8(value).unwrap()))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(
+            attributes,
+            HashMap::from([
+                ("stream_name", "mock_stream"),
+                ("shard_id", "shardId-000000000000"),
+                ("sequence_number", "1"),
+                ("partition_key", "mock_partition_key"),
+                ("arrival_timestamp", "1000"),
+            ])
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_preview_truncate_bytes() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(
+                vec![
+                    mock_record("1", b"hello world", 0),
+                    mock_record("2", b"hi", 0),
+                ],
+                0,
+            )),
+        )
+        .await;
+        let properties = KinesisProperties {
+            preview_truncate_bytes: Some("5".to_string()),
+            ..mock_properties(&server)
+        };
+
+        let mut reader =
+            KinesisSplitReader::new(properties, mock_split("shardId-000000000000")).await?;
+        let chunk = reader.next().await?.unwrap();
+        assert_eq!(chunk[0].payload.as_deref(), Some(&b"hello"[..]));
+        assert_eq!(chunk[0].attributes[ATTR_TRUNCATED_FROM], "11");
+        assert_eq!(chunk[0].offset, "1");
+        // Short payloads are neither truncated nor flagged.
+        assert_eq!(chunk[1].payload.as_deref(), Some(&b"hi"[..]));
+        assert!(!chunk[1].attributes.contains_key(ATTR_TRUNCATED_FROM));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_at_tip() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            SequenceResponder::new(vec![
+                json_response(get_records_output(vec![mock_record("1", b"a", 0)], 1_000)),
+                json_response(get_records_output(vec![], 0)),
+            ]),
+        )
+        .await;
+        // Heartbeats return from `next` while the shard is idle.
+        let properties = KinesisProperties {
+            idle_heartbeat_interval: Some("200ms".to_string()),
+            ..mock_properties(&server)
+        };
+
+        let mut reader = KinesisSplitReader::new(properties, mock_split("shardId-000000000000"))
+            .await?
+            .with_clock(Arc::new(MockClock::new()));
+        assert!(!reader.at_tip());
+        let chunk = reader.next().await?.unwrap();
+        assert!(chunk[0].payload.is_some());
+        assert!(!reader.at_tip());
+        // No records and no lag.
+        let chunk = reader.next().await?.unwrap();
+        assert!(chunk[0].payload.is_none());
+        assert!(reader.at_tip());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_get_records_validation() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        // Out-of-order sequence numbers and a negative lag.
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(
+                vec![mock_record("2", b"a", 0), mock_record("1", b"b", 0)],
+                -1,
+            )),
+        )
+        .await;
+
+        // Trusted by default.
+        let mut reader =
+            KinesisSplitReader::new(mock_properties(&server), mock_split("shardId-000000000000"))
+                .await?;
+        assert_eq!(reader.next().await?.unwrap().len(), 2);
+
+        let properties = KinesisProperties {
+            get_records_validation: Some("warn".to_string()),
+            ..mock_properties(&server)
+        };
+        let mut reader =
+            KinesisSplitReader::new(properties.clone(), mock_split("shardId-000000000000")).await?;
+        assert_eq!(reader.next().await?.unwrap().len(), 2);
+
+        let mut reader = KinesisSplitReader::new(
+            KinesisProperties {
+                get_records_validation: Some("strict".to_string()),
+                ..properties
+            },
+            mock_split("shardId-000000000000"),
+        )
+        .await?;
+        let err = reader.next().await.unwrap_err().to_string();
+        assert!(
+            err.contains("sequence number 1 does not follow 2"),
+            "{}",
+            err
+        );
+        assert!(err.contains("negative millis behind latest -1"), "{}", err);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_freshness_sla() -> Result<()> {
+        /// Records the signals along with the virtual time they are sent at.
+        #[derive(Debug)]
+        struct RecordingListener {
+            clock: Arc<MockClock>,
+            signals: Mutex<Vec<(Instant, ScalingSignal)>>,
+        }
+
+        impl ScalingListener for RecordingListener {
+            fn signal(&self, signal: ScalingSignal) {
+                let now = self.clock.now();
+                self.signals.lock().unwrap().push((now, signal));
+            }
+        }
+
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        // Each empty batch takes an idle poll of 200ms.
+        let lagging = json_response(get_records_output(vec![], 10_000));
+        mount_api(
+            &server,
+            "GetRecords",
+            SequenceResponder::new(vec![
+                lagging.clone(),
+                lagging.clone(),
+                lagging,
+                json_response(get_records_output(vec![mock_record("1", b"a", 0)], 10_000)),
+                json_response(get_records_output(vec![mock_record("2", b"b", 0)], 10_000)),
+                json_response(get_records_output(vec![mock_record("3", b"c", 0)], 500)),
+            ]),
+        )
+        .await;
+        let properties = KinesisProperties {
+            freshness_sla_ms: Some("1000".to_string()),
+            freshness_sla_grace_ms: Some("500".to_string()),
+            ..mock_properties(&server)
+        };
+        let clock = Arc::new(MockClock::new());
+        let start = clock.now();
+        let listener = Arc::new(RecordingListener {
+            clock: clock.clone(),
+            signals: Mutex::new(vec![]),
+        });
+
+        let mut reader = KinesisSplitReader::new(properties, mock_split("shardId-000000000000"))
+            .await?
+            .with_clock(clock.clone())
+            .with_scaling_listener(listener.clone());
+        let shard_id: SplitId = "shardId-000000000000".to_string().into();
+        // Recommended once the lag stays beyond the SLA for the grace period, at the 4th fetch.
+        reader.next().await?.unwrap();
+        {
+            let signals = listener.signals.lock().unwrap();
+            assert_eq!(signals.len(), 1);
+            assert_eq!(signals[0].0 - start, Duration::from_millis(600));
+            assert_eq!(
+                signals[0].1,
+                ScalingSignal::ScaleOutRecommended {
+                    shard_id: shard_id.clone(),
+                    lag_millis: 10_000,
+                }
+            );
+        }
+        // Not again while the breach lasts.
+        reader.next().await?.unwrap();
+        assert_eq!(listener.signals.lock().unwrap().len(), 1);
+        // Cleared as the lag recovers.
+        reader.next().await?.unwrap();
+        let signals = listener.signals.lock().unwrap();
+        assert_eq!(signals.len(), 2);
+        assert_eq!(signals[1].1, ScalingSignal::Recovered { shard_id });
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_progress_reporter() -> Result<()> {
+        /// Records the progress along with the virtual time it is reported at.
+        #[derive(Debug)]
+        struct RecordingReporter {
+            clock: Arc<MockClock>,
+            reports: Mutex<Vec<(Instant, ShardProgress)>>,
+        }
+
+        impl ProgressReporter for RecordingReporter {
+            fn report(&self, progress: ShardProgress) {
+                let now = self.clock.now();
+                self.reports.lock().unwrap().push((now, progress));
+            }
+        }
+
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            SequenceResponder::new(vec![
+                json_response(get_records_output(vec![mock_record("1", b"a", 0)], 5_000)),
+                json_response(get_records_output(vec![mock_record("2", b"b", 0)], 0)),
+                json_response(get_records_output(vec![], 0)),
+            ]),
+        )
+        .await;
+        // Each heartbeat takes an idle poll of 200ms.
+        let properties = KinesisProperties {
+            idle_heartbeat_interval: Some("200ms".to_string()),
+            progress_report_interval: Some("1s".to_string()),
+            ..mock_properties(&server)
+        };
+        let clock = Arc::new(MockClock::new());
+        let start = clock.now();
+        let reporter = Arc::new(RecordingReporter {
+            clock: clock.clone(),
+            reports: Mutex::new(vec![]),
+        });
+
+        let mut reader = KinesisSplitReader::new(properties, mock_split("shardId-000000000000"))
+            .await?
+            .with_clock(clock.clone())
+            .with_progress_reporter(reporter.clone());
+        // Two batches of records and 10 heartbeats, i.e. 2s.
+        for _ in 0..12 {
+            reader.next().await?.unwrap();
+        }
+
+        let reports = reporter.reports.lock().unwrap().clone();
+        let progress = |latest_sequence: &str, lag_millis, at_tip| ShardProgress {
+            shard_id: "shardId-000000000000".to_string().into(),
+            latest_sequence: Some(latest_sequence.to_string()),
+            lag_millis: Some(lag_millis),
+            at_tip,
+        };
+        assert_eq!(
+            reports,
+            vec![
+                (start, progress("1", 5_000, false)),
+                (start + Duration::from_secs(1), progress("2", 0, true)),
+                (start + Duration::from_secs(2), progress("2", 0, true)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_read_safety_lag() -> Result<()> {
+        let clock = Arc::new(MockClock::new());
+        let now = clock.now_millis();
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            SequenceResponder::new(vec![
+                json_response(get_records_output(
+                    vec![
+                        mock_record("1", b"a", now - 10_000),
+                        mock_record("2", b"b", now - 500),
+                        mock_record("3", b"c", now - 200),
+                    ],
+                    0,
+                )),
+                json_response(get_records_output(vec![], 0)),
+            ]),
+        )
+        .await;
+        let properties = KinesisProperties {
+            read_safety_lag_ms: Some("2000".to_string()),
+            ..mock_properties(&server)
+        };
+
+        let mut reader = KinesisSplitReader::new(properties, mock_split("shardId-000000000000"))
+            .await?
+            .with_clock(clock.clone());
+        let offsets =
+            |chunk: Vec<SourceMessage>| chunk.into_iter().map(|msg| msg.offset).collect::<Vec<_>>();
+        assert_eq!(offsets(reader.next().await?.unwrap()), vec!["1"]);
+        assert!(!reader.at_tip());
+        // Released as they age, without fetching the shard further.
+        assert_eq!(offsets(reader.next().await?.unwrap()), vec!["2"]);
+        assert!(clock.now_millis() - now >= 1_400);
+        assert_eq!(offsets(reader.next().await?.unwrap()), vec!["3"]);
+        assert!(clock.now_millis() - now >= 1_700);
+        assert_eq!(received_calls(&server, "GetRecords").await, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_localstack_absent_lag() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(serde_json::json!({
+                "Records": [],
+                "NextShardIterator": MOCK_SHARD_ITERATOR,
+            })),
+        )
+        .await;
+        let properties = KinesisProperties {
+            idle_heartbeat_interval: Some("200ms".to_string()),
+            ..mock_properties(&server)
+        };
+
+        // The lag is unknown, so the shard is not known to be at the tip.
+        let mut reader =
+            KinesisSplitReader::new(properties.clone(), mock_split("shardId-000000000000"))
+                .await?
+                .with_clock(Arc::new(MockClock::new()));
+        reader.next().await?.unwrap();
+        assert!(!reader.at_tip());
+
+        let mut reader = KinesisSplitReader::new(
+            KinesisProperties {
+                endpoint_flavor: Some("localstack".to_string()),
+                ..properties
+            },
+            mock_split("shardId-000000000000"),
+        )
+        .await?
+        .with_clock(Arc::new(MockClock::new()));
+        reader.next().await?.unwrap();
+        assert!(reader.at_tip());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_flow_control() -> Result<()> {
+        /// Reports the capacities in order, then an unlimited one.
+        #[derive(Debug)]
+        struct Budget(Mutex<VecDeque<Capacity>>);
+
+        impl FlowControl for Budget {
+            fn available(&self, _split_id: &SplitId) -> Capacity {
+                self.0.lock().unwrap().pop_front().unwrap_or_default()
+            }
+        }
+
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let records = (1..=6)
+            .map(|i| mock_record(&i.to_string(), &[b'x'; 10], 0))
+            .collect();
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(records, 0)),
+        )
+        .await;
+        let capacity = |records, bytes| Capacity { records, bytes };
+        let budget = Arc::new(Budget(Mutex::new(
+            vec![
+                capacity(None, Some(25)),
+                // The downstream is full, so the reader waits.
+                capacity(Some(0), None),
+                capacity(Some(1), None),
+                capacity(Some(3), Some(5)),
+            ]
+            .into(),
+        )));
+
+        let mut reader =
+            KinesisSplitReader::new(mock_properties(&server), mock_split("shardId-000000000000"))
+                .await?
+                .with_flow_control(budget.clone());
+        let mut offsets = vec![];
+        for _ in 0..4 {
+            let chunk = reader.next().await?.unwrap();
+            offsets.push(chunk.iter().map(|msg| msg.offset.clone()).collect_vec());
+        }
+        assert_eq!(
+            offsets,
+            vec![
+                vec!["1", "2"],
+                vec!["3"],
+                // A message larger than the byte capacity is emitted alone.
+                vec!["4"],
+                vec!["5", "6"],
+            ]
+        );
+        assert!(budget.0.lock().unwrap().is_empty());
+        assert_eq!(received_calls(&server, "GetRecords").await, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_max_chunk_bytes() -> Result<()> {
+        let sizes = [10, 50, 30, 70, 5, 100, 1, 1, 1];
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let records = sizes
+            .iter()
+            .enumerate()
+            .map(|(i, size)| mock_record(&(i + 1).to_string(), &vec![b'x'; *size], 0))
+            .collect();
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(records, 0)),
+        )
+        .await;
+        let properties = KinesisProperties {
+            max_chunk_records: Some("2".to_string()),
+            max_chunk_bytes: Some("80".to_string()),
+            ..mock_properties(&server)
+        };
+
+        let mut reader =
+            KinesisSplitReader::new(properties, mock_split("shardId-000000000000")).await?;
+        let mut chunks = vec![];
+        for _ in 0..6 {
+            let chunk = reader.next().await?.unwrap();
+            chunks.push(
+                chunk
+                    .iter()
+                    .map(|msg| msg.payload.as_ref().unwrap().len())
+                    .collect::<Vec<_>>(),
+            );
+        }
+        // Within both limits, except the message larger than the byte budget.
+        assert_eq!(
+            chunks,
+            vec![
+                vec![10, 50],
+                vec![30],
+                vec![70, 5],
+                vec![100],
+                vec![1, 1],
+                vec![1]
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_request_id_in_error() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            error_response("InvalidArgumentException")
+                .insert_header("x-amzn-RequestId", "mock-request-id")
+                .insert_header("x-amz-id-2", "mock-extended-request-id"),
+        )
+        .await;
+
+        let mut reader =
+            KinesisSplitReader::new(mock_properties(&server), mock_split("shardId-000000000000"))
+                .await?;
+        let err = reader.next().await.unwrap_err().to_string();
+        assert!(err.contains("aws request id: mock-request-id"), "{}", err);
+        assert!(
+            err.contains("extended request id: mock-extended-request-id"),
+            "{}",
+            err
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_adaptive_limit() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let batch = |record_bytes: usize| {
+            let records = (1..=10)
+                .map(|seq| mock_record(&seq.to_string(), &vec![b'x'; record_bytes], 0))
+                .collect();
+            json_response(get_records_output(records, 0))
+        };
+        // The records grow from 1KB to 100KB.
+        mount_api(
+            &server,
+            "GetRecords",
+            SequenceResponder::new(vec![batch(1_000), batch(100_000)]),
+        )
+        .await;
+        let properties = KinesisProperties {
+            get_records_target_bytes: Some("1000000".to_string()),
+            ..mock_properties(&server)
+        };
+
+        let mut reader =
+            KinesisSplitReader::new(properties, mock_split("shardId-000000000000")).await?;
+        for _ in 0..6 {
+            reader.next().await?.unwrap();
+        }
+        let limits = received_bodies(&server, "GetRecords")
+            .await
+            .iter()
+            .map(|body| body["Limit"].as_i64().unwrap())
+            .collect_vec();
+        // Moves toward 1MB / 100KB = 10 records per call as the average size catches up.
+        assert_eq!(limits, vec![10_000, 1_000, 19, 13, 11, 10]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_shared_client() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        let shared = KinesisProperties {
+            client_shared: Some("true".to_string()),
+            ..mock_properties(&server)
+        };
+        let reader = |properties: KinesisProperties, shard_id: &str| {
+            KinesisSplitReader::new(properties, mock_split(shard_id))
+        };
+        let a = reader(shared.clone(), "shardId-000000000000").await?;
+        let b = reader(shared.clone(), "shardId-000000000001").await?;
+        assert!(std::ptr::eq(a.client.conf(), b.client.conf()));
+
+        // Different credentials or an unshared client build another one.
+        let other_credentials = KinesisProperties {
+            credentials_access_key: Some("other_access_key".to_string()),
+            ..shared
+        };
+        let c = reader(other_credentials, "shardId-000000000000").await?;
+        assert!(!std::ptr::eq(a.client.conf(), c.client.conf()));
+        let d = reader(mock_properties(&server), "shardId-000000000000").await?;
+        assert!(!std::ptr::eq(a.client.conf(), d.client.conf()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_iterator_renewed_metric() -> Result<()> {
+        /// Counts the renewals per shard.
+        #[derive(Debug, Default)]
+        struct RenewalCounter {
+            renewals: Mutex<HashMap<SplitId, usize>>,
+        }
+
+        impl ReaderMetrics for RenewalCounter {
+            fn iterator_renewed(&self, shard_id: &SplitId) {
+                *self
+                    .renewals
+                    .lock()
+                    .unwrap()
+                    .entry(shard_id.clone())
+                    .or_default() += 1;
+            }
+        }
+
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let batch =
+            |seq: &str| json_response(get_records_output(vec![mock_record(seq, b"", 0)], 0));
+        let expired = || error_response("ExpiredIteratorException");
+        mount_api(
+            &server,
+            "GetRecords",
+            SequenceResponder::new(vec![
+                expired(),
+                batch("1"),
+                expired(),
+                expired(),
+                batch("2"),
+            ]),
+        )
+        .await;
+        let metrics = Arc::new(RenewalCounter::default());
+
+        let split = mock_split("shardId-000000000000");
+        let mut reader = KinesisSplitReader::new(mock_properties(&server), split.clone())
+            .await?
+            .with_clock(Arc::new(MockClock::new()))
+            .with_metrics(metrics.clone());
+        let renewals = || metrics.renewals.lock().unwrap().get(&split.id()).copied();
+        assert_eq!(reader.next().await?.unwrap()[0].offset, "1");
+        assert_eq!(renewals(), Some(1));
+        assert_eq!(reader.next().await?.unwrap()[0].offset, "2");
+        assert_eq!(renewals(), Some(3));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_api_call_attempts() -> Result<()> {
+        /// Records the attempts of each call.
+        #[derive(Debug, Default)]
+        struct AttemptRecorder {
+            calls: Mutex<Vec<(String, usize)>>,
+        }
+
+        impl ReaderMetrics for AttemptRecorder {
+            fn api_call(&self, _shard_id: &SplitId, operation: &str, attempts: usize) {
+                self.calls
+                    .lock()
+                    .unwrap()
+                    .push((operation.to_string(), attempts));
+            }
+        }
+
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        // Throttled once, which the SDK retries by itself before the call returns.
+        mount_api(
+            &server,
+            "GetRecords",
+            SequenceResponder::new(vec![
+                error_response("ProvisionedThroughputExceededException"),
+                json_response(get_records_output(vec![mock_record("1", b"a", 0)], 0)),
+            ]),
+        )
+        .await;
+        let metrics = Arc::new(AttemptRecorder::default());
+
+        let mut reader =
+            KinesisSplitReader::new(mock_properties(&server), mock_split("shardId-000000000000"))
+                .await?
+                .with_metrics(metrics.clone());
+        assert_eq!(reader.next().await?.unwrap()[0].offset, "1");
+        assert_eq!(received_calls(&server, "GetRecords").await, 2);
+        assert_eq!(
+            *metrics.calls.lock().unwrap(),
+            vec![
+                ("GetShardIterator".to_string(), 1),
+                ("GetRecords".to_string(), 2)
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_max_consecutive_renews() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        // Each renewed iterator serves a single batch and expires at the next fetch.
+        let batch =
+            |seq: &str| json_response(get_records_output(vec![mock_record(seq, b"", 0)], 0));
+        let expired = || error_response("ExpiredIteratorException");
+        mount_api(
+            &server,
+            "GetRecords",
+            SequenceResponder::new(vec![
+                batch("1"),
+                expired(),
+                batch("2"),
+                expired(),
+                batch("3"),
+                expired(),
+            ]),
+        )
+        .await;
+        let properties = KinesisProperties {
+            max_consecutive_renews: Some("2".to_string()),
+            ..mock_properties(&server)
+        };
+
+        let mut reader = KinesisSplitReader::new(properties, mock_split("shardId-000000000000"))
+            .await?
+            .with_clock(Arc::new(MockClock::new()));
+        assert_eq!(reader.next().await?.unwrap()[0].offset, "1");
+        assert_eq!(reader.next().await?.unwrap()[0].offset, "2");
+        assert_eq!(reader.next().await?.unwrap()[0].offset, "3");
+        let err = reader.next().await.unwrap_err();
+        assert!(
+            err.to_string().contains("expired 3 times in a row"),
+            "{}",
+            err
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_message_mapper() -> Result<()> {
+        /// Prefixes the payload with the partition key.
+        #[derive(Debug)]
+        struct KeyedMapper;
+
+        impl MessageMapper for KeyedMapper {
+            fn map(&self, shard_id: SplitId, record: Record) -> KinesisMessage {
+                let mut msg = KinesisMessage::new(shard_id, record);
+                msg.payload = format!(
+                    "{}:{}",
+                    msg.partition_key,
+                    std::str::from_utf8(&msg.payload).unwrap()
+                )
+                .into();
+                msg
+            }
+        }
+
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(vec![mock_record("1", b"a", 0)], 0)),
+        )
+        .await;
+
+        let mut reader =
+            KinesisSplitReader::new(mock_properties(&server), mock_split("shardId-000000000000"))
+                .await?
+                .with_message_mapper(Arc::new(KeyedMapper));
+        let chunk = reader.next().await?.unwrap();
+        assert_eq!(
+            chunk[0].payload.as_deref(),
+            Some(&b"mock_partition_key:a"[..])
+        );
+        assert_eq!(chunk[0].offset, "1");
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_wait_stream_active() -> Result<()> {
+        let summary = |status: &str| {
+            json_response(serde_json::json!({
+                "StreamDescriptionSummary": {
+                    "StreamName": "mock_stream",
+                    "StreamStatus": status,
+                },
+            }))
+        };
+        let server = wiremock::MockServer::start().await;
+        mount_api(
+            &server,
+            "DescribeStreamSummary",
+            SequenceResponder::new(vec![
+                summary("UPDATING"),
+                summary("UPDATING"),
+                summary("ACTIVE"),
+            ]),
+        )
+        .await;
+        let client = build_client(mock_properties(&server)).await?;
+        let clock = MockClock::new();
+        let interval = Duration::from_secs(1);
+        wait_stream_active(
+            &client,
+            &clock,
+            "mock_stream",
+            Duration::from_secs(10),
+            interval,
+        )
+        .await?;
+        assert_eq!(received_calls(&server, "DescribeStreamSummary").await, 3);
+        assert_eq!(clock.sleeps(), vec![interval; 2]);
+
+        let server = wiremock::MockServer::start().await;
+        mount_api(&server, "DescribeStreamSummary", summary("UPDATING")).await;
+        let client = build_client(mock_properties(&server)).await?;
+        let clock = MockClock::new();
+        let err = wait_stream_active(
+            &client,
+            &clock,
+            "mock_stream",
+            Duration::from_secs(5),
+            interval,
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+        assert_eq!(clock.sleeps(), vec![interval; 5]);
+        Ok(())
+    }
+
+    /// Reads `n` batches, appending the offsets of each split to `offsets`.
+    async fn read_offsets(
+        reader: &mut KinesisMultiSplitReader,
+        n: usize,
+        offsets: &mut HashMap<SplitId, Vec<u64>>,
+    ) -> Result<()> {
+        for _ in 0..n {
+            for msg in reader.next().await?.unwrap() {
+                offsets
+                    .entry(msg.split_id)
+                    .or_default()
+                    .push(msg.offset.parse().unwrap());
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_iterator_acquisition() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+        mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+        let splits = || {
+            ["shardId-000000000000", "shardId-000000000001"]
+                .map(|shard_id| SplitImpl::Kinesis(mock_split(shard_id)))
+                .to_vec()
+        };
+
+        let mut reader =
+            KinesisMultiSplitReader::new(mock_properties(&server), Some(splits()), None).await?;
+        assert_eq!(received_calls(&server, "GetShardIterator").await, 0);
+        reader.next().await?.unwrap();
+        assert!(received_calls(&server, "GetShardIterator").await > 0);
+        drop(reader);
+
+        server.reset().await;
+        mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+        mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+        let properties = KinesisProperties {
+            iterator_acquisition: Some("eager".to_string()),
+            iterator_acquisition_parallelism: Some("1".to_string()),
+            ..mock_properties(&server)
+        };
+        let mut reader = KinesisMultiSplitReader::new(properties, Some(splits()), None).await?;
+        assert_eq!(received_calls(&server, "GetShardIterator").await, 2);
+        reader.next().await?.unwrap();
+        assert_eq!(received_calls(&server, "GetShardIterator").await, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_max_inflight_bytes() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let data = vec![b'a'; 1000];
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(
+                vec![mock_record("1", &data, 0), mock_record("2", &data, 0)],
+                1_000,
+            )),
+        )
+        .await;
+        let properties = KinesisProperties {
+            buffer_capacity: Some("100".into()),
+            max_inflight_bytes: Some("5000".into()),
+            ..mock_properties(&server)
+        };
+        let splits = (0..3)
+            .map(|i| SplitImpl::Kinesis(mock_split(&format!("shardId-00000000000{}", i))))
+            .collect_vec();
+        let mut reader = KinesisMultiSplitReader::new(properties, Some(splits), None).await?;
+        let mut max_buffered = 0;
+        for _ in 0..10 {
+            reader.next().await?.unwrap();
+            // Lets the consumer task fill the buffer.
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let buffered = reader.inflight.as_ref().unwrap().buffered();
+            assert!(buffered <= 5000, "{} bytes are buffered", buffered);
+            max_buffered = max_buffered.max(buffered);
+        }
+        // Two batches of 2000 bytes are buffered, and the third waits for them to drain.
+        assert_eq!(max_buffered, 4000);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_prefetch_depth() -> Result<()> {
+        for (depth, fetched) in [(None, 1), (Some("4"), 5)] {
+            let server = wiremock::MockServer::start().await;
+            mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+            mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+            let properties = KinesisProperties {
+                prefetch_depth: depth.map(String::from),
+                ..mock_properties(&server)
+            };
+            let splits = vec![SplitImpl::Kinesis(mock_split("shardId-000000000000"))];
+            let mut reader = KinesisMultiSplitReader::new(properties, Some(splits), None).await?;
+            let mut offsets = reader
+                .next()
+                .await?
+                .unwrap()
+                .into_iter()
+                .map(|msg| msg.offset)
+                .collect_vec();
+            // Lets the shard fetch ahead.
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            // The batch returned, along with those queued.
+            assert_eq!(received_calls(&server, "GetRecords").await, fetched);
+
+            for _ in 0..9 {
+                offsets.extend(
+                    reader
+                        .next()
+                        .await?
+                        .unwrap()
+                        .into_iter()
+                        .map(|msg| msg.offset),
+                );
+            }
+            assert_eq!(offsets, (1..=10).map(|seq| seq.to_string()).collect_vec());
+        }
+
+        let server = wiremock::MockServer::start().await;
+        let properties = KinesisProperties {
+            prefetch_depth: Some("17".to_string()),
+            ..mock_properties(&server)
+        };
+        let splits = vec![SplitImpl::Kinesis(mock_split("shardId-000000000000"))];
+        assert!(KinesisMultiSplitReader::new(properties, Some(splits), None)
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_shard_positions() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+        mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+        mount_api(
+            &server,
+            "DescribeStreamSummary",
+            json_response(serde_json::json!({
+                "StreamDescriptionSummary": {
+                    "StreamName": "mock_stream",
+                    "StreamStatus": "ACTIVE",
+                    "RetentionPeriodHours": 24,
+                },
+            })),
+        )
+        .await;
+        let split = mock_split("shardId-000000000000");
+        let split_id = split.id();
+        let mut reader = KinesisMultiSplitReader::new(
+            mock_properties(&server),
+            Some(vec![SplitImpl::Kinesis(split)]),
+            None,
+        )
+        .await?;
+
+        let positions = reader.shard_positions().await?;
+        assert_eq!(
+            positions[&split_id],
+            ShardPosition {
+                start_position: KinesisOffset::Earliest,
+                latest_sequence: None,
+                lag_millis: None,
+                beyond_retention: false,
+            }
+        );
+
+        for _ in 0..3 {
+            reader.next().await?.unwrap();
+        }
+        let fetched = received_calls(&server, "GetRecords").await;
+        let position = reader.shard_positions().await?.remove(&split_id).unwrap();
+        assert_eq!(received_calls(&server, "GetRecords").await, fetched);
+        assert_eq!(
+            position.latest_sequence,
+            reader.latest_offsets.get(&split_id).cloned()
+        );
+        assert_eq!(position.latest_sequence.as_deref(), Some("3"));
+        // The lag is the last `MillisBehindLatest` of the shard, although the mock records
+        // arrived at the epoch.
+        assert_eq!(position.lag_millis, Some(0));
+        assert!(position.beyond_retention);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_state_round_trip() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+        mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+        let splits = ["shardId-000000000000", "shardId-000000000001"]
+            .map(|shard_id| SplitImpl::Kinesis(mock_split(shard_id)))
+            .to_vec();
+
+        assert_state_round_trip::<KinesisMultiSplitReader, _>(
+            mock_properties(&server),
+            Some(splits),
+            |reader| Ok(reader.finalize()?.into_state()),
+            compare_sequence,
+            10,
+            10,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_batch_max_runtime() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+        mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+        // Bounded far beyond the records, so that only the time limit stops the read.
+        let split = KinesisSplit::new(
+            "shardId-000000000000".into(),
+            KinesisOffset::Earliest,
+            KinesisOffset::Timestamp(TokioClock.now_millis() + 3_600_000),
+        );
+        let properties = KinesisProperties {
+            batch_max_runtime: Some("1s".to_string()),
+            ..mock_properties(&server)
+        };
+        let clock = Arc::new(MockClock::manual());
+        let mut reader = KinesisMultiSplitReader::new(
+            properties.clone(),
+            Some(vec![SplitImpl::Kinesis(split.clone())]),
+            None,
+        )
+        .await?
+        .with_clock(clock.clone());
+
+        // The time limit counts from the first `next`.
+        assert!(reader.next().await?.is_some());
+        clock.advance(Duration::from_millis(999));
+        let last_emitted = reader.next().await?.unwrap().last().unwrap().offset.clone();
+        assert_eq!(reader.read_status(), ReadStatus::Reading);
+        clock.advance(Duration::from_millis(1));
+        assert!(reader.next().await?.is_none());
+        assert_eq!(reader.read_status(), ReadStatus::TimeLimitReached);
+        assert_eq!(
+            reader.read_status().to_string(),
+            "partial result, time limit reached"
+        );
+        assert!(reader.next().await?.is_none());
+
+        // The read resumes after the last record emitted.
+        let last_emitted = last_emitted.parse::<u64>().unwrap();
+        let state = reader.finalize()?.into_state();
+        let mut resumed =
+            KinesisMultiSplitReader::new(mock_properties(&server), state, None).await?;
+        let chunk = resumed.next().await?.unwrap();
+        assert_eq!(chunk[0].offset, (last_emitted + 1).to_string());
+
+        // A read waiting for records stops at the time limit as well.
+        let stalled = wiremock::MockServer::start().await;
+        mount_api(&stalled, "GetShardIterator", ShardIteratorResponder).await;
+        mount_api(
+            &stalled,
+            "GetRecords",
+            json_response(get_records_output(vec![], 0)).set_delay(Duration::from_secs(3600)),
+        )
+        .await;
+        let clock = Arc::new(MockClock::manual());
+        let mut reader = KinesisMultiSplitReader::new(
+            KinesisProperties {
+                batch_max_runtime: Some("1s".to_string()),
+                ..mock_properties(&stalled)
+            },
+            Some(vec![SplitImpl::Kinesis(split)]),
+            None,
+        )
+        .await?
+        .with_clock(clock.clone());
+        let next = reader.next();
+        futures::pin_mut!(next);
+        assert!(futures::poll!(next.as_mut()).is_pending());
+        clock.advance(Duration::from_secs(1));
+        assert!(next.await?.is_none());
+        assert_eq!(reader.read_status(), ReadStatus::TimeLimitReached);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_source_completed() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+        mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+        let splits = [("shardId-000000000000", "3"), ("shardId-000000000001", "5")]
+            .into_iter()
+            .map(|(shard_id, end)| {
+                SplitImpl::Kinesis(KinesisSplit::new(
+                    shard_id.into(),
+                    KinesisOffset::Earliest,
+                    KinesisOffset::SequenceNumber(end.to_string()),
+                ))
+            })
+            .collect();
+        let mut reader =
+            KinesisMultiSplitReader::new(mock_properties(&server), Some(splits), None).await?;
+        let mut records = 0;
+        let mut completions = vec![];
+        for _ in 0..20 {
+            match reader.next_signal().await? {
+                Some(ReadSignal::Chunk(chunk)) => {
+                    assert!(completions.is_empty());
+                    records += chunk.iter().filter(|msg| msg.payload.is_some()).count();
+                }
+                Some(ReadSignal::Completed(completed)) => completions.push(completed),
+                None => {}
+            }
+        }
+        assert_eq!(records, 8);
+        assert_eq!(completions.len(), 1);
+        let completed = &completions[0];
+        assert_eq!(completed.splits.len(), 2);
+        assert_eq!(completed.latest_offsets["shardId-000000000000"], "3");
+        assert_eq!(completed.latest_offsets["shardId-000000000001"], "5");
+
+        // A shard dropped before its end leaves the read incomplete rather than completed.
+        let server = wiremock::MockServer::start().await;
+        mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+        mount_api_matching(
+            &server,
+            "GetRecords",
+            serde_json::json!({ "ShardIterator": "shardId-000000000001/2" }),
+            error_response("KMSAccessDeniedException"),
+        )
+        .await;
+        mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+        let properties = KinesisProperties {
+            on_shard_error: Some("drop_shard".to_string()),
+            ..mock_properties(&server)
+        };
+        let splits = ["shardId-000000000000", "shardId-000000000001"]
+            .into_iter()
+            .map(|shard_id| {
+                SplitImpl::Kinesis(KinesisSplit::new(
+                    shard_id.into(),
+                    KinesisOffset::Earliest,
+                    KinesisOffset::SequenceNumber("3".to_string()),
+                ))
+            })
+            .collect();
+        let mut reader = KinesisMultiSplitReader::new(properties, Some(splits), None).await?;
+        let mut records = 0;
+        for _ in 0..20 {
+            match reader.next_signal().await? {
+                Some(ReadSignal::Chunk(chunk)) => {
+                    records += chunk.iter().filter(|msg| msg.payload.is_some()).count();
+                }
+                Some(ReadSignal::Completed(_)) => panic!("completed with a dropped shard"),
+                None => {}
+            }
+        }
+        assert_eq!(records, 5);
+        assert_eq!(reader.read_status(), ReadStatus::ShardsDropped);
+        Ok(())
+    }
+
+    /// Asserts that `chunk` is not empty and only has records after `sequence_number`.
+    fn assert_after(chunk: &[SourceMessage], sequence_number: &str) {
+        assert!(!chunk.is_empty());
+        for msg in chunk {
+            assert_eq!(
+                compare_sequence(&msg.offset, sequence_number),
+                std::cmp::Ordering::Greater,
+                "{} is emitted again after {}",
+                msg.offset,
+                sequence_number
+            );
+        }
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_resume_after_last_emitted() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+        mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+        let split = mock_split("shardId-000000000000");
+        let mut reader = KinesisMultiSplitReader::new(
+            mock_properties(&server),
+            Some(vec![SplitImpl::Kinesis(split.clone())]),
+            None,
+        )
+        .await?;
+        for _ in 0..3 {
+            reader.next().await?.unwrap();
+        }
+        let state = reader.finalize()?.into_state().unwrap();
+        let snapshot = match &state[..] {
+            [SplitImpl::Kinesis(split)] => split.clone(),
+            _ => unreachable!(),
+        };
+        let last_emitted = match &snapshot.start_position {
+            KinesisOffset::SequenceNumber(seq) => seq.clone(),
+            position => panic!("unexpected position {:?}", position),
+        };
+        assert_eq!(last_emitted, "3");
+
+        let mut resumed = KinesisSplitReader::new(mock_properties(&server), snapshot).await?;
+        assert_after(&resumed.next().await?.unwrap(), &last_emitted);
+        let bodies = received_bodies(&server, "GetShardIterator").await;
+        let request = bodies.last().unwrap();
+        assert_eq!(request["ShardIteratorType"], "AFTER_SEQUENCE_NUMBER");
+        assert_eq!(request["StartingSequenceNumber"], last_emitted.as_str());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_resume_after_last_emitted_across_renewals() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+        // The iterator after the first record expires twice in a row before reading further.
+        mount_api_matching(
+            &server,
+            "GetRecords",
+            serde_json::json!({ "ShardIterator": "shardId-000000000000/1" }),
+            SequenceResponder::new(vec![
+                error_response("ExpiredIteratorException"),
+                error_response("ExpiredIteratorException"),
+                json_response(serde_json::json!({
+                    "Records": [mock_record("2", b"payload", 0)],
+                    "NextShardIterator": "shardId-000000000000/2",
+                    "MillisBehindLatest": 0,
+                })),
+            ]),
+        )
+        .await;
+        mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+
+        let mut reader =
+            KinesisSplitReader::new(mock_properties(&server), mock_split("shardId-000000000000"))
+                .await?
+                .with_clock(Arc::new(MockClock::new()));
+        let chunk = reader.next().await?.unwrap();
+        assert_eq!(chunk.last().unwrap().offset, "1");
+        assert_after(&reader.next().await?.unwrap(), "1");
+        let renewals = received_bodies(&server, "GetShardIterator").await;
+        assert_eq!(renewals.len(), 3);
+        for request in &renewals[1..] {
+            assert_eq!(request["ShardIteratorType"], "AFTER_SEQUENCE_NUMBER");
+            assert_eq!(request["StartingSequenceNumber"], "1");
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_reset_offsets() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+        mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+        mount_api(
+            &server,
+            "DescribeStreamSummary",
+            json_response(serde_json::json!({
+                "StreamDescriptionSummary": {
+                    "StreamName": "mock_stream",
+                    "StreamStatus": "ACTIVE",
+                    "RetentionPeriodHours": 24,
+                },
+            })),
+        )
+        .await;
+        let split = mock_split("shardId-000000000000");
+        let mut reader = KinesisMultiSplitReader::new(
+            mock_properties(&server),
+            Some(vec![SplitImpl::Kinesis(split.clone())]),
+            None,
+        )
+        .await?;
+        let mut offsets = vec![];
+        for _ in 0..5 {
+            offsets.extend(
+                reader
+                    .next()
+                    .await?
+                    .unwrap()
+                    .into_iter()
+                    .map(|msg| msg.offset),
+            );
+        }
+        assert_eq!(offsets, vec!["1", "2", "3", "4", "5"]);
+
+        let rewind =
+            || HashMap::from([(split.id(), KinesisOffset::SequenceNumber("2".to_string()))]);
+        assert!(reader.reset_offsets(rewind()).await.is_err());
+        reader.pause_handle().pause();
+        // Beyond the retention period of 24 hours.
+        let expired = HashMap::from([(split.id(), KinesisOffset::Timestamp(0))]);
+        assert!(reader.reset_offsets(expired).await.is_err());
+        reader.reset_offsets(rewind()).await?;
+        reader.pause_handle().resume();
+
+        // Re-delivered after the sequence number.
+        let chunk = reader.next().await?.unwrap();
+        assert_eq!(chunk[0].offset, "3");
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_update_splits() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+        mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+
+        let [shard_0, shard_1, shard_2] = [
+            "shardId-000000000000",
+            "shardId-000000000001",
+            "shardId-000000000002",
+        ]
+        .map(mock_split);
+        let properties = KinesisProperties {
+            buffer_capacity: Some("2".to_string()),
+            ..mock_properties(&server)
+        };
+        let splits = vec![
+            SplitImpl::Kinesis(shard_0.clone()),
+            SplitImpl::Kinesis(shard_1.clone()),
+        ];
+        let mut reader = KinesisMultiSplitReader::new(properties, Some(splits), None).await?;
+        let mut offsets = HashMap::new();
+        read_offsets(&mut reader, 20, &mut offsets).await?;
+
+        reader
+            .update_splits(vec![shard_0.clone(), shard_2.clone()])
+            .await?;
+        offsets.remove(&shard_1.id());
+        read_offsets(&mut reader, 40, &mut offsets).await?;
+
+        // The retained shard continues without gaps or duplicates.
+        let retained = &offsets[&shard_0.id()];
+        assert!(retained.len() > 10);
+        assert_eq!(*retained, (1..=retained.len() as u64).collect_vec());
+        // The new shard starts from its start position, the removed shard is no longer read.
+        assert_eq!(offsets[&shard_2.id()][0], 1);
+        assert!(!offsets.contains_key(&shard_1.id()));
+
+        assert!(reader.update_splits(vec![]).await.is_err());
+
+        // The splits can no longer be updated once the consumer task has exited.
+        let consumer = reader.consumer_handler.take().unwrap();
+        consumer.abort();
+        let _ = consumer.await;
+        let err = reader
+            .update_splits(vec![shard_0.clone(), shard_1.clone()])
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("consumer task has exited"),
+            "{}",
+            err
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_update_splits_before_read() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+        mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+
+        let [shard_0, shard_1] = ["shardId-000000000000", "shardId-000000000001"].map(mock_split);
+        let properties = KinesisProperties {
+            iterator_acquisition: Some("eager".to_string()),
+            ..mock_properties(&server)
+        };
+        let splits = vec![
+            SplitImpl::Kinesis(shard_0.clone()),
+            SplitImpl::Kinesis(shard_1.clone()),
+        ];
+        let mut reader = KinesisMultiSplitReader::new(properties, Some(splits), None).await?;
+        assert_eq!(reader.acquired_streams.len(), 2);
+
+        // The iterator acquired for the removed split is released without being read.
+        reader.update_splits(vec![shard_0.clone()]).await?;
+        assert_eq!(
+            reader.acquired_streams.keys().collect_vec(),
+            vec![&shard_0.id()]
+        );
+        let chunk = reader.next().await?.unwrap();
+        assert!(chunk.iter().all(|msg| msg.split_id == shard_0.id()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_max_total_records() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+        mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+
+        let properties = KinesisProperties {
+            max_total_records: Some("25".to_string()),
+            ..mock_properties(&server)
+        };
+        let splits = (0..3)
+            .map(|i| SplitImpl::Kinesis(mock_split(&format!("shardId-00000000000{}", i))))
+            .collect_vec();
+        let mut reader = KinesisMultiSplitReader::new(properties, Some(splits), None).await?;
+        let mut offsets = HashMap::new();
+        while let Some(chunk) = reader.next().await? {
+            for msg in chunk {
+                offsets
+                    .entry(msg.split_id)
+                    .or_insert_with(Vec::new)
+                    .push(msg.offset);
+            }
+        }
+        assert_eq!(offsets.values().map(Vec::len).sum::<usize>(), 25);
+        assert!(reader.next().await?.is_none());
+
+        // Resumes right after the last emitted record of each shard.
+        for split in reader.finalize()?.into_state().unwrap() {
+            let split = split.into_kinesis().unwrap();
+            match offsets.get(&split.id()) {
+                Some(emitted) => assert_eq!(
+                    split.start_position,
+                    KinesisOffset::SequenceNumber(emitted.len().to_string())
+                ),
+                None => assert_eq!(split.start_position, KinesisOffset::Earliest),
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_messages() -> Result<()> {
+        async fn reader() -> Result<(wiremock::MockServer, KinesisMultiSplitReader)> {
+            let server = wiremock::MockServer::start().await;
+            mount_shard_iterator(&server).await;
+            let batches = [vec!["1", "2"], vec!["3"], vec!["4", "5", "6"]];
+            let mut responses = batches
+                .iter()
+                .map(|batch| {
+                    let records = batch.iter().map(|seq| mock_record(seq, b"payload", 0));
+                    json_response(get_records_output(records.collect(), 0))
+                })
+                .collect_vec();
+            // The shard is closed.
+            responses.push(json_response(
+                serde_json::json!({ "Records": [], "MillisBehindLatest": 0 }),
+            ));
+            mount_api(&server, "GetRecords", SequenceResponder::new(responses)).await;
+            let splits = vec![SplitImpl::Kinesis(mock_split("shardId-000000000000"))];
+            let reader =
+                KinesisMultiSplitReader::new(mock_properties(&server), Some(splits), None).await?;
+            Ok((server, reader))
+        }
+
+        let (_server, mut reader) = reader().await?;
+        let mut batched = vec![];
+        while let Some(chunk) = reader.next().await? {
+            batched.extend(chunk);
+        }
+
+        let (_server, reader) = reader().await?;
+        let messages = reader.messages().try_collect::<Vec<_>>().await?;
+        // The records and the shard end message, which are read at different times.
+        let without_provenance = |messages: Vec<SourceMessage>| {
+            messages
+                .into_iter()
+                .map(|msg| SourceMessage {
+                    provenance: None,
+                    ..msg
+                })
+                .collect_vec()
+        };
+        assert_eq!(messages.len(), 7);
+        assert_eq!(without_provenance(messages), without_provenance(batched));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_single_split_fast_path() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(vec![mock_record("1", b"a", 0)], 0)),
+        )
+        .await;
+
+        let splits = vec![SplitImpl::Kinesis(mock_split("shardId-000000000000"))];
+        let mut reader =
+            KinesisMultiSplitReader::new(mock_properties(&server), Some(splits), None).await?;
+        assert!(reader.single_split_stream.is_some());
+        let chunk = reader.next().await?.unwrap();
+        assert_eq!(chunk[0].offset, "1");
+        assert!(reader.consumer_handler.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_tail_mode() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let records = (1..=5)
+            .map(|i| mock_record(&i.to_string(), b"payload", i * 1_000))
+            .collect_vec();
+        mount_api(
+            &server,
+            "GetRecords",
+            SequenceResponder::new(vec![
+                json_response(get_records_output(records, 0)),
+                json_response(get_records_output(vec![], 0)),
+            ]),
+        )
+        .await;
+
+        let properties = KinesisProperties {
+            scan_startup_mode: Some("tail".to_string()),
+            scan_tail_records: Some("3".to_string()),
+            ..mock_properties(&server)
+        };
+        let split = KinesisSplit {
+            start_position: KinesisOffset::Timestamp(0),
+            end_position: KinesisOffset::Timestamp(10_000),
+            ..mock_split("shardId-000000000000")
+        };
+        let mut reader = KinesisSplitReader::new(properties, split).await?;
+        let chunk = reader.next().await?.unwrap();
+        let offsets = chunk.iter().map(|msg| msg.offset.as_str()).collect_vec();
+        assert_eq!(offsets, vec!["3", "4", "5"]);
+        assert!(reader.next().await?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_hash_key_range() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let aggregated = aggregate_with_explicit_hash_keys(&[
+            ("key_a", Some("100"), b"a"),
+            ("key_b", Some("200"), b"b"),
+            ("key_c", Some("300"), b"c"),
+        ]);
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(
+                vec![
+                    mock_record("1", &aggregated, 0),
+                    // Hashed from its partition key far beyond the range.
+                    mock_record("2", b"plain", 0),
+                ],
+                0,
+            )),
+        )
+        .await;
+
+        let properties = KinesisProperties {
+            payload_transforms: Some("deaggregate".to_string()),
+            hash_key_range: Some("150, 300".to_string()),
+            ..mock_properties(&server)
+        };
+        let mut reader =
+            KinesisSplitReader::new(properties, mock_split("shardId-000000000000")).await?;
+        let chunk = reader.next().await?.unwrap();
+        let payloads = chunk
+            .iter()
+            .map(|msg| msg.payload.as_deref().unwrap())
+            .collect_vec();
+        assert_eq!(payloads, vec![b"b".as_slice(), b"c".as_slice()]);
+        assert_eq!(reader.latest_offset.as_deref(), Some("2"));
+
+        let invalid = KinesisProperties {
+            hash_key_range: Some("300,150".to_string()),
+            ..mock_properties(&server)
+        };
+        assert!(
+            KinesisSplitReader::new(invalid, mock_split("shardId-000000000000"))
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_corrupted_aggregate() -> Result<()> {
+        #[derive(Debug, Default)]
+        struct CollectingSink(Mutex<Vec<DeadLetter>>);
+
+        impl DeadLetterSink for CollectingSink {
+            fn send(&self, letter: DeadLetter) {
+                self.0.lock().unwrap().push(letter);
+            }
+        }
+
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let mut corrupted = aggregate(&[("key_a", b"a"), ("key_b", b"b")]);
+        // Breaks the MD5 digest trailing the protobuf.
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xFF;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(
+                vec![
+                    mock_record("1", &corrupted, 0),
+                    mock_record("2", &aggregate(&[("key_c", b"c")]), 0),
+                ],
+                0,
+            )),
+        )
+        .await;
+        let reader = |policy: &str| {
+            let properties = KinesisProperties {
+                payload_transforms: Some("deaggregate".to_string()),
+                on_corrupted_aggregate: Some(policy.to_string()),
+                ..mock_properties(&server)
+            };
+            KinesisSplitReader::new(properties, mock_split("shardId-000000000000"))
+        };
+        let payloads = |chunk: &[SourceMessage]| {
+            chunk
+                .iter()
+                .map(|msg| msg.payload.clone().unwrap())
+                .collect_vec()
+        };
+
+        let err = reader("fail").await?.next().await.unwrap_err();
+        assert!(err.to_string().contains("digest mismatch"), "{}", err);
+
+        let chunk = reader("passthrough").await?.next().await?.unwrap();
+        assert_eq!(
+            payloads(&chunk),
+            vec![Bytes::from(corrupted.clone()), Bytes::from_static(b"c")]
+        );
+
+        let sink = Arc::new(CollectingSink::default());
+        let mut dead_letter = reader("dead_letter")
+            .await?
+            .with_dead_letter_sink(sink.clone());
+        let chunk = dead_letter.next().await?.unwrap();
+        assert_eq!(payloads(&chunk), vec![Bytes::from_static(b"c")]);
+        assert_eq!(dead_letter.latest_offset.as_deref(), Some("2"));
+        let letters = sink.0.lock().unwrap();
+        assert_eq!(letters.len(), 1);
+        assert_eq!(letters[0].record.sequence_number, "1");
+        assert_eq!(letters[0].record.payload, Bytes::from(corrupted));
+        assert!(letters[0].reason.contains("digest mismatch"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_explicit_hash_key() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let aggregated = aggregate_with_explicit_hash_keys(&[
+            ("key_a", Some("123"), b"a"),
+            ("key_b", None, b"b"),
+        ]);
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(
+                vec![
+                    mock_record("1", &aggregated, 0),
+                    mock_record("2", b"plain", 0),
+                ],
+                0,
+            )),
+        )
+        .await;
+
+        let properties = KinesisProperties {
+            payload_transforms: Some("deaggregate".to_string()),
+            ..mock_properties(&server)
+        };
+        let mut reader =
+            KinesisSplitReader::new(properties, mock_split("shardId-000000000000")).await?;
+        let chunk = reader.next().await?.unwrap();
+        let hash_keys = chunk
+            .iter()
+            .map(|msg| match &msg.meta {
+                SourceMeta::Kinesis(meta) => meta.explicit_hash_key.as_deref(),
+                _ => unreachable!(),
+            })
+            .collect_vec();
+        assert_eq!(hash_keys, vec![Some("123"), None, None]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_resume_within_aggregate() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let aggregated = aggregate(&[("a", b"a"), ("b", b"b"), ("c", b"c"), ("d", b"d")]);
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(
+                vec![mock_record("1", &aggregated, 0), mock_record("2", b"e", 0)],
+                0,
+            )),
+        )
+        .await;
+        let properties = KinesisProperties {
+            payload_transforms: Some("deaggregate".to_string()),
+            max_chunk_records: Some("2".to_string()),
+            ..mock_properties(&server)
+        };
+        let read = |chunk: Vec<SourceMessage>| {
+            chunk
+                .into_iter()
+                .map(|msg| (msg.offset, msg.payload.unwrap()))
+                .collect_vec()
+        };
+
+        let split = mock_split("shardId-000000000000");
+        let mut reader = KinesisSplitReader::new(properties.clone(), split.clone()).await?;
+        let first = read(reader.next().await?.unwrap());
+        assert_eq!(
+            first,
+            vec![
+                ("1#0".to_string(), Bytes::from("a")),
+                ("1#1".to_string(), Bytes::from("b"))
+            ]
+        );
+
+        // Restarted from the state after the first chunk.
+        let split = split.copy_with_offset(first[1].0.clone());
+        let mut reader = KinesisSplitReader::new(properties, split).await?;
+        let mut rest = read(reader.next().await?.unwrap());
+        rest.extend(read(reader.next().await?.unwrap()));
+        assert_eq!(
+            rest,
+            vec![
+                ("1#2".to_string(), Bytes::from("c")),
+                ("1".to_string(), Bytes::from("d")),
+                ("2".to_string(), Bytes::from("e"))
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_finalize() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            SequenceResponder::new(vec![
+                json_response(get_records_output(
+                    vec![mock_record("1", b"a", 0), mock_record("2", b"b", 0)],
+                    0,
+                )),
+                json_response(get_records_output(vec![mock_record("3", b"c", 0)], 0)),
+                json_response(get_records_output(vec![], 0)),
+            ]),
+        )
+        .await;
+
+        let splits = vec![SplitImpl::Kinesis(mock_split("shardId-000000000000"))];
+        let mut reader =
+            KinesisMultiSplitReader::new(mock_properties(&server), Some(splits), None).await?;
+        let chunk = reader.next().await?.unwrap();
+        assert_eq!(chunk.last().unwrap().offset, "2");
+
+        // The record fetched but not emitted yet is not persisted.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let state = reader.finalize()?.into_state().unwrap();
+        assert_eq!(state.len(), 1);
+        assert_eq!(
+            state[0].as_kinesis().unwrap().start_position,
+            KinesisOffset::SequenceNumber("2".to_string())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_shutdown() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_api(&server, "GetShardIterator", ShardIteratorResponder).await;
+        mount_api(&server, "GetRecords", EndlessRecordsResponder).await;
+        let splits = vec![SplitImpl::Kinesis(mock_split("shardId-000000000000"))];
+        let mut reader =
+            KinesisMultiSplitReader::new(mock_properties(&server), Some(splits), None).await?;
+        let mut last_emitted = String::new();
+        for _ in 0..3 {
+            last_emitted = reader.next().await?.unwrap().last().unwrap().offset.clone();
+        }
+
+        // The state returned on shutdown resumes right after the last record emitted.
+        let splits = reader.shutdown().await?.splits;
+        assert_eq!(splits.len(), 1);
+        assert_eq!(
+            splits[0].as_kinesis().unwrap().start_position,
+            KinesisOffset::SequenceNumber(last_emitted.clone())
+        );
+        let mut resumed =
+            KinesisMultiSplitReader::new(mock_properties(&server), Some(splits), None).await?;
+        let chunk = resumed.next().await?.unwrap();
+        assert_eq!(
+            chunk[0].offset,
+            (last_emitted.parse::<u64>().unwrap() + 1).to_string()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_commit_leases() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(
+                vec![mock_record("1", b"a", 0), mock_record("2", b"b", 0)],
+                0,
+            )),
+        )
+        .await;
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .and(wiremock::matchers::header(
+                "x-amz-target",
+                "DynamoDB_20120810.UpdateItem",
+            ))
+            .respond_with(
+                wiremock::ResponseTemplate::new(200)
+                    .set_body_raw("{}", "application/x-amz-json-1.0"),
+            )
+            .mount(&server)
+            .await;
+
+        let properties = KinesisProperties {
+            checkpoint_kcl_lease_table: Some("kcl_app".to_string()),
+            checkpoint_kcl_endpoint: Some(server.uri()),
+            ..mock_properties(&server)
+        };
+        let split = SplitImpl::Kinesis(mock_split("shardId-000000000000"));
+        let mut reader = crate::source::SplitReaderImpl::create(
+            crate::source::ConnectorProperties::Kinesis(properties),
+            Some(vec![split.clone()]),
+            None,
+        )
+        .await?;
+        let chunk = reader.next().await?.unwrap();
+        let offset = chunk.last().unwrap().offset.clone();
+
+        // The checkpointed splits are committed once the barrier completes. Splits read by other
+        // readers are ignored.
+        reader
+            .commit(&[
+                split.update(offset),
+                SplitImpl::Kinesis(mock_split("shardId-000000000001")).update("9".to_string()),
+            ])
+            .await?;
+
+        let requests = server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|request| {
+                request.headers.iter().any(|(name, values)| {
+                    name.as_str() == "x-amz-target"
+                        && values
+                            .iter()
+                            .any(|v| v.as_str() == "DynamoDB_20120810.UpdateItem")
+                })
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(requests.len(), 1);
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body)?;
+        assert_eq!(body["TableName"], "kcl_app");
+        assert_eq!(body["Key"]["leaseKey"]["S"], "shardId-000000000000");
+        assert_eq!(body["ExpressionAttributeValues"][":checkpoint"]["S"], "2");
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_packed_sequence_number_state() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(vec![mock_record("256", b"a", 0)], 0)),
+        )
+        .await;
+        let properties = KinesisProperties {
+            state_sequence_number_format: Some("packed".to_string()),
+            ..mock_properties(&server)
+        };
+        let splits = vec![SplitImpl::Kinesis(mock_split("shardId-000000000000"))];
+        let mut reader = KinesisMultiSplitReader::new(properties, Some(splits), None).await?;
+        let chunk = reader.next().await?.unwrap();
+        assert_eq!(chunk[0].offset, "packed:AQA=");
+        let state = reader.finalize()?.into_state().unwrap();
+        let split = state[0].as_kinesis().unwrap().clone();
+        assert_eq!(
+            split.start_position,
+            KinesisOffset::PackedSequenceNumber("AQA=".to_string())
+        );
+
+        // A reader restored from the packed state resumes after the plain sequence number.
+        let server = wiremock::MockServer::start().await;
+        mount_api_matching(
+            &server,
+            "GetShardIterator",
+            serde_json::json!({
+                "ShardIteratorType": "AFTER_SEQUENCE_NUMBER",
+                "StartingSequenceNumber": "256",
+            }),
+            json_response(serde_json::json!({ "ShardIterator": MOCK_SHARD_ITERATOR })),
+        )
+        .await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(vec![mock_record("257", b"b", 0)], 0)),
+        )
+        .await;
+        let mut reader = KinesisSplitReader::new(mock_properties(&server), split).await?;
+        let chunk = reader.next().await?.unwrap();
+        assert_eq!(chunk[0].offset, "257");
+        Ok(())
+    }
+
+    const BAD_SHARD: &str = "shardId-000000000001";
+    const BAD_SHARD_ITERATOR: &str = "bad_shard_iterator";
+
+    /// Mounts a healthy shard and a shard [`BAD_SHARD`] whose `GetRecords` responds with
+    /// `bad_responses`, and returns a multi reader over both.
+    async fn reader_with_bad_shard(
+        server: &wiremock::MockServer,
+        policy: &str,
+        bad_responses: Vec<wiremock::ResponseTemplate>,
+    ) -> Result<KinesisMultiSplitReader> {
+        mount_api_matching(
+            server,
+            "GetShardIterator",
+            serde_json::json!({ "ShardId": BAD_SHARD }),
+            json_response(serde_json::json!({ "ShardIterator": BAD_SHARD_ITERATOR })),
+        )
+        .await;
+        mount_api_matching(
+            server,
+            "GetRecords",
+            serde_json::json!({ "ShardIterator": BAD_SHARD_ITERATOR }),
+            SequenceResponder::new(bad_responses),
+        )
+        .await;
+        mount_shard_iterator(server).await;
+        mount_api(
+            server,
+            "GetRecords",
+            json_response(get_records_output(vec![mock_record("1", b"payload", 0)], 0)),
+        )
+        .await;
+
+        let properties = KinesisProperties {
+            on_shard_error: Some(policy.to_string()),
+            ..mock_properties(server)
+        };
+        let splits = vec![
+            SplitImpl::Kinesis(mock_split("shardId-000000000000")),
+            SplitImpl::Kinesis(mock_split(BAD_SHARD)),
+        ];
+        KinesisMultiSplitReader::new(properties, Some(splits), None).await
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_shard_error_fail_all() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        let mut reader = reader_with_bad_shard(
+            &server,
+            "fail_all",
+            vec![error_response("KMSAccessDeniedException")],
+        )
+        .await?;
+
+        let mut failed = false;
+        for _ in 0..100 {
+            if reader.next().await.is_err() {
+                failed = true;
+                break;
+            }
+        }
+        assert!(failed);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_shard_error_drop_shard() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        let mut reader = reader_with_bad_shard(
+            &server,
+            "drop_shard",
+            vec![error_response("KMSAccessDeniedException")],
+        )
+        .await?;
+
+        for _ in 0..20 {
+            let chunk = reader.next().await?.unwrap();
+            assert!(chunk
+                .iter()
+                .all(|msg| msg.split_id.as_str() == "shardId-000000000000"));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_shard_error_retry() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        let mut reader = reader_with_bad_shard(
+            &server,
+            "retry",
+            vec![
+                error_response("KMSAccessDeniedException"),
+                json_response(get_records_output(
+                    vec![mock_record("2", b"recovered", 0)],
+                    0,
+                )),
+            ],
+        )
+        .await?;
+
+        let recovered = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let chunk = reader.next().await.unwrap().unwrap();
+                if chunk.iter().any(|msg| msg.split_id.as_str() == BAD_SHARD) {
+                    break;
+                }
+            }
+        })
+        .await;
+        assert!(recovered.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_shard_retry_backoff_with_mock_clock() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let mut responses = vec![error_response("KMSAccessDeniedException"); 6];
+        responses.push(json_response(get_records_output(
+            vec![mock_record("1", b"recovered", 0)],
+            0,
+        )));
+        mount_api(&server, "GetRecords", SequenceResponder::new(responses)).await;
+
+        let clock = Arc::new(MockClock::new());
+        let reader =
+            KinesisSplitReader::new(mock_properties(&server), mock_split("shardId-000000000000"))
+                .await?
+                .with_clock(clock.clone());
+        let started_at = Instant::now();
+        let mut stream = split_reader_into_stream(reader, ShardErrorPolicy::Retry, None).boxed();
+        let chunk = stream.next().await.unwrap()?;
+        assert_eq!(chunk[0].offset, "1");
+
+        // The backoff doubles up to its maximum, without waiting for real.
+        assert_eq!(
+            clock.sleeps(),
+            [1, 2, 4, 8, 16, 30].map(Duration::from_secs).to_vec()
+        );
+        assert!(started_at.elapsed() < Duration::from_secs(1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_split_lifecycle_events() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let mut closed = get_records_output(vec![mock_record("3", b"c", 0)], 0);
+        closed.as_object_mut().unwrap().remove("NextShardIterator");
+        mount_api(
+            &server,
+            "GetRecords",
+            SequenceResponder::new(vec![
+                error_response("ProvisionedThroughputExceededException"),
+                json_response(get_records_output(
+                    vec![mock_record("1", b"a", 0), mock_record("2", b"b", 0)],
+                    0,
+                )),
+                error_response("ExpiredIteratorException"),
+                json_response(closed),
+            ]),
+        )
+        .await;
+
+        let (events, _guard) = capture_events();
+        let reader =
+            KinesisSplitReader::new(mock_properties(&server), mock_split("shardId-000000000000"))
+                .await?
+                .with_clock(Arc::new(MockClock::new()));
+        assert_eq!(read_to_end(reader).await?.len(), 4);
+
+        let events = events.lock().unwrap().clone();
+        let position = |message: &str| {
+            events
+                .iter()
+                .position(|event| event.starts_with(message))
+                .unwrap_or_else(|| panic!("no event {:?} in {:#?}", message, events))
+        };
+        let acquired = position("kinesis shard iterator acquired");
+        let throttled = position("kinesis api call failed, back off and retry");
+        let first = position("kinesis shard emits its first record");
+        let expired = position("kinesis shard iterator expired, renew it");
+        let closed = position("kinesis shard closed");
+        assert!(acquired < throttled && throttled < first && first < expired && expired < closed);
+
+        // Every transition names its shard, including the retries logged inside the span.
+        for index in [acquired, throttled, first, expired, closed] {
+            assert!(
+                events[index].contains("stream=mock_stream"),
+                "{}",
+                events[index]
+            );
+            assert!(
+                events[index].contains("shard=shardId-000000000000"),
+                "{}",
+                events[index]
+            );
+        }
+        assert!(events[throttled].contains("kind=Some(Throttling)"));
+        assert!(events[first].contains("sequence=1"));
+        assert!(events[expired].contains("sequence=Some(\"2\")"));
+        assert!(events[closed].contains("sequence=Some(\"3\")"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_checkpoint_min_interval() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let batch =
+            |seq: &str| json_response(get_records_output(vec![mock_record(seq, b"", 0)], 0));
+        let mut closed = get_records_output(vec![mock_record("4", b"", 0)], 0);
+        closed.as_object_mut().unwrap().remove("NextShardIterator");
+        let mut responses = vec![batch("1"), batch("2")];
+        // Idle polls for a second in total, and once more.
+        responses.extend(vec![json_response(get_records_output(vec![], 0)); 6]);
+        responses.extend([batch("3"), json_response(closed)]);
+        mount_api(&server, "GetRecords", SequenceResponder::new(responses)).await;
+
+        let properties = KinesisProperties {
+            checkpoint_min_interval: Some("1s".to_string()),
+            ..mock_properties(&server)
+        };
+        let mut reader = KinesisSplitReader::new(properties, mock_split("shardId-000000000000"))
+            .await?
+            .with_clock(Arc::new(MockClock::new()));
+        // The offset the source executor records as the state of the shard after each chunk.
+        let state_offset = |chunk: &[SourceMessage]| {
+            chunk.iter().rev().find_map(|msg| match &msg.state_offset {
+                Some(offset) => Some(offset.clone()),
+                None => msg.payload.as_ref().map(|_| msg.offset.clone()),
+            })
+        };
+        let mut states = vec![];
+        let mut offsets = vec![];
+        while let Some(chunk) = reader.next().await? {
+            states.push(state_offset(&chunk).unwrap());
+            offsets.extend(
+                chunk
+                    .iter()
+                    .filter(|msg| msg.payload.is_some())
+                    .map(|msg| msg.offset.clone()),
+            );
+        }
+        // The second batch keeps the offset surfaced within the interval until the interval
+        // expires while the shard is idle, and the last batch of the shard surfaces the latest
+        // offset regardless of the interval. The records keep their own offsets.
+        assert_eq!(states, ["1", "1", "2", "2", "4"]);
+        assert_eq!(offsets, ["1", "2", "3", "4"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_shard_end_message() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        let mut closed = get_records_output(vec![mock_record("3", b"c", 0)], 0);
+        closed.as_object_mut().unwrap().remove("NextShardIterator");
+        mount_api(&server, "GetRecords", json_response(closed)).await;
+        let mut shards = list_shards_output(&["shardId-000000000000", "shardId-000000000001"]);
+        shards["Shards"][0]["SequenceNumberRange"]["EndingSequenceNumber"] = "5".into();
+        mount_api(&server, "ListShards", json_response(shards)).await;
+
+        let reader =
+            KinesisSplitReader::new(mock_properties(&server), mock_split("shardId-000000000000"))
+                .await?;
+        let messages = read_to_end(reader).await?;
+        assert_eq!(messages.len(), 2);
+        // The shard end message carries the ending sequence number of the shard, beyond the
+        // last record.
+        let end = &messages[1];
+        assert!(end.payload.is_none());
+        assert_eq!(end.offset, "3");
+        match &end.meta {
+            SourceMeta::Kinesis(meta) => {
+                assert_eq!(meta.ending_sequence_number.as_deref(), Some("5"))
+            }
+            meta => panic!("unexpected meta {:?}", meta),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(madsim, ignore)] // MockServer is not supported in simulation.
+    async fn test_new_with_client() -> Result<()> {
+        let server = wiremock::MockServer::start().await;
+        mount_shard_iterator(&server).await;
+        mount_api(
+            &server,
+            "GetRecords",
+            json_response(get_records_output(vec![mock_record("1", b"a", 0)], 0)),
+        )
+        .await;
+        let config = aws_sdk_kinesis::Config::builder()
+            .region(aws_types::region::Region::new("us-east-1"))
+            .credentials_provider(aws_types::Credentials::from_keys(
+                "embedded_key",
+                "embedded_secret",
+                None,
+            ))
+            .endpoint_resolver(aws_smithy_http::endpoint::Endpoint::immutable(
+                server.uri().parse().unwrap(),
+            ))
+            .app_name(aws_types::app_name::AppName::new("embedding-app").unwrap())
+            .build();
+
+        // Neither the endpoint nor the credentials are in the properties, which are canonicalized
+        // as well.
+        let properties = KinesisProperties {
+            stream_name: " mock_stream ".to_string(),
+            stream_region: "us-east-1".to_string(),
+            ..Default::default()
+        };
+        let splits = vec![SplitImpl::Kinesis(mock_split("shardId-000000000000"))];
+        let mut reader = KinesisMultiSplitReader::new_with_client(
+            properties,
+            Some(splits),
+            KinesisClient::from_conf(config),
+        )
+        .await?;
+        let chunk = reader.next().await?.unwrap();
+        assert_eq!(chunk[0].offset, "1");
+        assert_eq!(
+            received_bodies(&server, "GetShardIterator").await[0]["StreamName"],
+            "mock_stream"
+        );
+
+        let requests = server.received_requests().await.unwrap();
+        assert!(!requests.is_empty());
+        let has_header = |request: &wiremock::Request, name: &str, pattern: &str| {
+            request.headers.iter().any(|(header, values)| {
+                header.as_str() == name && values.iter().any(|v| v.as_str().contains(pattern))
+            })
+        };
+        for request in &requests {
+            assert!(has_header(
+                request,
+                "authorization",
+                "Credential=embedded_key/"
+            ));
+            assert!(has_header(request, "x-amz-user-agent", "app/embedding-app"));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_single_thread_kinesis_reader() -> Result<()> {
         let properties = KinesisProperties {
             assume_role_arn: None,
             credentials_access_key: None,
@@ -276,18 +5624,18 @@ mod tests {
             endpoint: None,
             session_token: None,
             assume_role_external_id: None,
+            ..Default::default()
         };
 
-        let mut trim_horizen_reader = KinesisSplitReader::new(
-            properties.clone(),
-            KinesisSplit {
-                shard_id: "shardId-000000000001".to_string().into(),
-                start_position: KinesisOffset::Earliest,
-                end_position: KinesisOffset::None,
-            },
-        )
-        .await?;
-        let stream_reader = trim_horizen_reader.clone();
+        let trim_horizen_split = KinesisSplit {
+            shard_id: "shardId-000000000001".to_string().into(),
+            start_position: KinesisOffset::Earliest,
+            end_position: KinesisOffset::None,
+            stream_name: None,
+            discovered_at: None,
+        };
+        let mut trim_horizen_reader =
+            KinesisSplitReader::new(properties.clone(), trim_horizen_split.clone()).await?;
         println!("{:?}", trim_horizen_reader.next().await?);
 
         let mut offset_reader = KinesisSplitReader::new(
@@ -298,13 +5646,23 @@ mod tests {
                     "49629139817504901062972448413535783695568426186596941842".to_string(),
                 ),
                 end_position: KinesisOffset::None,
+                stream_name: None,
+                discovered_at: None,
             },
         )
         .await?;
         println!("{:?}", offset_reader.next().await?);
 
-        let stream1 = split_reader_into_stream(stream_reader.clone());
-        let stream2 = split_reader_into_stream(stream_reader);
+        let stream1 = split_reader_into_stream(
+            KinesisSplitReader::new(properties.clone(), trim_horizen_split.clone()).await?,
+            ShardErrorPolicy::FailAll,
+            None,
+        );
+        let stream2 = split_reader_into_stream(
+            KinesisSplitReader::new(properties.clone(), trim_horizen_split).await?,
+            ShardErrorPolicy::FailAll,
+            None,
+        );
         let stream = vec![stream1, stream2].merge().into_stream();
         #[for_await]
         for msg in stream {
@@ -327,6 +5685,7 @@ mod tests {
             endpoint: None,
             session_token: None,
             assume_role_external_id: None,
+            ..Default::default()
         };
 
         let splits = vec!["shardId-000000000000", "shardId-000000000001"]
@@ -336,6 +5695,8 @@ mod tests {
                     shard_id: split.to_string().into(),
                     start_position: KinesisOffset::Earliest,
                     end_position: KinesisOffset::None,
+                    stream_name: None,
+                    discovered_at: None,
                 })
             })
             .collect::<Vec<_>>();
@@ -0,0 +1,64 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use aws_sdk_kinesis::output::GetRecordsOutput;
+
+/// Caches the last `GetRecords` response of a shard keyed by the iterator which fetched it, see
+/// `get_records.retry.cache`. Fetching with that iterator again returns the cached response
+/// instead of calling Kinesis, so that a batch the downstream failed to emit is emitted again
+/// rather than skipped by the iterator having advanced past it.
+#[derive(Debug, Default)]
+pub struct ResponseCache {
+    entry: Option<CachedResponse>,
+}
+
+#[derive(Debug)]
+struct CachedResponse {
+    shard_iter: String,
+    /// The sequence number read before the response.
+    latest_offset: Option<String>,
+    output: GetRecordsOutput,
+}
+
+impl ResponseCache {
+    /// Returns the response fetched by `shard_iter`, if it is the last one.
+    pub fn get(&self, shard_iter: &str) -> Option<GetRecordsOutput> {
+        self.entry
+            .as_ref()
+            .filter(|entry| entry.shard_iter == shard_iter)
+            .map(|entry| entry.output.clone())
+    }
+
+    /// Replaces the cached response with `output`, fetched by `shard_iter` after reading up to
+    /// `latest_offset`.
+    pub fn put(
+        &mut self,
+        shard_iter: String,
+        latest_offset: Option<String>,
+        output: GetRecordsOutput,
+    ) {
+        self.entry = Some(CachedResponse {
+            shard_iter,
+            latest_offset,
+            output,
+        });
+    }
+
+    /// Returns the iterator of the cached response and the sequence number read before it, to
+    /// rewind the shard to.
+    pub fn rewind_position(&self) -> Option<(String, Option<String>)> {
+        self.entry
+            .as_ref()
+            .map(|entry| (entry.shard_iter.clone(), entry.latest_offset.clone()))
+    }
+}
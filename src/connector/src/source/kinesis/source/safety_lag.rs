@@ -0,0 +1,101 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use anyhow::Result;
+use aws_sdk_kinesis::model::Record;
+use aws_sdk_kinesis::output::GetRecordsOutput;
+
+use crate::source::kinesis::config::parse_property;
+use crate::source::kinesis::source::message::datetime_to_millis;
+use crate::source::kinesis::KinesisProperties;
+
+/// Withholds the records that arrived within `read.safety.lag.ms` of now, releasing them once
+/// they age past the lag. The next shard iterator and the lag of the response the records came
+/// from are held along with them, so that the shard is not fetched further until all of them are
+/// released.
+#[derive(Debug, Clone)]
+pub struct SafetyLag {
+    lag_millis: i64,
+    withheld: VecDeque<Record>,
+    next_shard_iterator: Option<String>,
+    millis_behind_latest: Option<i64>,
+}
+
+impl SafetyLag {
+    /// Returns `None` if `read.safety.lag.ms` is not set or zero.
+    pub fn from_properties(properties: &KinesisProperties) -> Result<Option<Self>> {
+        let lag_millis = parse_property::<u64>(
+            "read.safety.lag.ms",
+            properties.read_safety_lag_ms.as_deref(),
+        )?;
+        Ok(lag_millis.filter(|lag| *lag > 0).map(|lag_millis| Self {
+            lag_millis: lag_millis as i64,
+            withheld: VecDeque::new(),
+            next_shard_iterator: None,
+            millis_behind_latest: None,
+        }))
+    }
+
+    /// Whether records are withheld, in which case the shard should not be fetched.
+    pub fn is_withholding(&self) -> bool {
+        !self.withheld.is_empty()
+    }
+
+    /// Withholds all the records of a fetched `output`, to be released by [`Self::release`].
+    pub fn withhold(&mut self, output: GetRecordsOutput) {
+        self.withheld.extend(output.records.unwrap_or_default());
+        self.next_shard_iterator = output.next_shard_iterator;
+        self.millis_behind_latest = output.millis_behind_latest;
+    }
+
+    /// How long until the oldest withheld record ages past the lag at `now_millis`.
+    pub fn wait_duration(&self, now_millis: i64) -> Duration {
+        let wait = self.withheld.front().map_or(0, |record| {
+            arrival_millis(record) + self.lag_millis - now_millis
+        });
+        Duration::from_millis(wait.max(0) as u64)
+    }
+
+    /// Releases the withheld records older than the lag at `now_millis`. While some records are
+    /// still withheld, the output is at least a millisecond behind the latest, so that the reader
+    /// does not consider the shard caught up.
+    pub fn release(&mut self, now_millis: i64) -> GetRecordsOutput {
+        let cutoff = now_millis - self.lag_millis;
+        let aged = self
+            .withheld
+            .iter()
+            .position(|record| arrival_millis(record) > cutoff)
+            .unwrap_or(self.withheld.len());
+        let records = self.withheld.drain(..aged).collect::<Vec<_>>();
+        let millis_behind_latest = match self.withheld.front() {
+            Some(record) => Some((now_millis - arrival_millis(record)).max(1)),
+            None => self.millis_behind_latest,
+        };
+        GetRecordsOutput::builder()
+            .set_records(Some(records))
+            .set_next_shard_iterator(self.next_shard_iterator.clone())
+            .set_millis_behind_latest(millis_behind_latest)
+            .build()
+    }
+}
+
+/// Records without an arrival timestamp are never withheld.
+fn arrival_millis(record: &Record) -> i64 {
+    record
+        .approximate_arrival_timestamp()
+        .map_or(i64::MIN / 2, datetime_to_millis)
+}
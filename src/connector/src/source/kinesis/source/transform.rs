@@ -0,0 +1,350 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::Read;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use flate2::read::GzDecoder;
+
+use crate::source::kinesis::config::parse_property;
+use crate::source::kinesis::source::kpl::deaggregate;
+use crate::source::kinesis::source::message::KinesisMessage;
+use crate::source::kinesis::KinesisProperties;
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const CONFLUENT_MAGIC_BYTE: u8 = 0;
+/// The magic byte followed by a 4 bytes big endian schema id.
+const CONFLUENT_HEADER_LEN: usize = 5;
+
+/// A step of the payload pipeline configured by `payload.transforms`.
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
+pub enum PayloadTransform {
+    /// Gunzips payloads starting with the gzip magic number. Others are passed through.
+    Decompress,
+    /// Splits KPL aggregated records into their sub-records, which share the sequence number of
+    /// the aggregated record. Plain records are passed through.
+    Deaggregate,
+    /// Drops a fixed length header of `payload.header.length` bytes.
+    StripHeader(usize),
+}
+
+impl FromStr for PayloadTransform {
+    type Err = anyhow::Error;
+
+    /// Parses a transform other than `strip_header`, whose length comes from another property.
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("decompress") {
+            Ok(Self::Decompress)
+        } else if s.eq_ignore_ascii_case("deaggregate") {
+            Ok(Self::Deaggregate)
+        } else {
+            Err(anyhow!(
+                "expect one of decompress, deaggregate and strip_header"
+            ))
+        }
+    }
+}
+
+impl PayloadTransform {
+    fn apply(&self, msg: KinesisMessage) -> Result<Vec<KinesisMessage>> {
+        match self {
+            Self::Decompress => {
+                if !msg.payload.starts_with(&GZIP_MAGIC) {
+                    return Ok(vec![msg]);
+                }
+                let mut payload = Vec::new();
+                GzDecoder::new(msg.payload.as_ref())
+                    .read_to_end(&mut payload)
+                    .map_err(|e| {
+                        anyhow!("failed to decompress record {}: {}", msg.sequence_number, e)
+                    })?;
+                Ok(vec![KinesisMessage {
+                    payload: payload.into(),
+                    ..msg
+                }])
+            }
+            Self::Deaggregate => {
+                let record = match deaggregate(&msg.payload)? {
+                    Some(record) => record,
+                    None => return Ok(vec![msg]),
+                };
+                Ok(record
+                    .records
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, sub_record)| KinesisMessage {
+                        partition_key: record.partition_key_table
+                            [sub_record.partition_key_index as usize]
+                            .clone(),
+                        explicit_hash_key: sub_record
+                            .explicit_hash_key_index
+                            .map(|index| record.explicit_hash_key_table[index as usize].clone()),
+                        sub_sequence_number: Some(index as u64),
+                        payload: sub_record.data.into(),
+                        ..msg.clone()
+                    })
+                    .collect())
+            }
+            Self::StripHeader(len) => {
+                if msg.payload.len() < *len {
+                    return Err(anyhow!(
+                        "record {} is shorter than the {} bytes header",
+                        msg.sequence_number,
+                        len
+                    ));
+                }
+                Ok(vec![KinesisMessage {
+                    payload: msg.payload.slice(*len..),
+                    ..msg
+                }])
+            }
+        }
+    }
+}
+
+/// How payloads are framed by the producer, configured by `payload.framing`.
+#[derive(Copy, Default, Debug, Clone, PartialEq, Eq)]
+pub enum PayloadFraming {
+    #[default]
+    None,
+    /// Confluent schema registry framing: the magic byte `0x00` and a 4 bytes big endian schema
+    /// id before the serialized payload.
+    Confluent,
+}
+
+impl FromStr for PayloadFraming {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("none") {
+            Ok(Self::None)
+        } else if s.eq_ignore_ascii_case("confluent") {
+            Ok(Self::Confluent)
+        } else {
+            Err(anyhow!("expect one of none or confluent"))
+        }
+    }
+}
+
+impl PayloadFraming {
+    /// Strips the framing from the payload of `msg`, and records the schema id.
+    pub fn unframe(&self, msg: KinesisMessage) -> Result<KinesisMessage> {
+        match self {
+            Self::None => Ok(msg),
+            Self::Confluent => {
+                if msg.payload.len() < CONFLUENT_HEADER_LEN
+                    || msg.payload[0] != CONFLUENT_MAGIC_BYTE
+                {
+                    return Err(anyhow!(
+                        "record {} is not framed by the confluent schema registry",
+                        msg.sequence_number
+                    ));
+                }
+                let schema_id =
+                    u32::from_be_bytes(msg.payload[1..CONFLUENT_HEADER_LEN].try_into()?);
+                let payload = msg.payload.slice(CONFLUENT_HEADER_LEN..);
+                Ok(KinesisMessage {
+                    schema_id: Some(schema_id),
+                    payload,
+                    ..msg
+                })
+            }
+        }
+    }
+}
+
+/// Parses `payload.transforms`, a comma separated list of transforms applied in order, e.g.
+/// `decompress,deaggregate`.
+pub fn parse_transforms(properties: &KinesisProperties) -> Result<Vec<PayloadTransform>> {
+    let transforms = match properties.payload_transforms.as_deref() {
+        Some(transforms) => transforms,
+        None => return Ok(vec![]),
+    };
+    transforms
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            if name.eq_ignore_ascii_case("strip_header") {
+                parse_property::<usize>(
+                    "payload.header.length",
+                    properties.payload_header_length.as_deref(),
+                )?
+                .map(PayloadTransform::StripHeader)
+                .ok_or_else(|| anyhow!("payload.header.length is required by strip_header"))
+            } else {
+                name.parse()
+                    .map_err(|e| anyhow!("invalid payload.transforms '{}': {}", name, e))
+            }
+        })
+        .collect()
+}
+
+/// Applies `transforms` in order to the payload of `msg`, which may be split into several
+/// messages.
+pub fn apply_transforms(
+    transforms: &[PayloadTransform],
+    msg: KinesisMessage,
+) -> Result<Vec<KinesisMessage>> {
+    let mut msgs = vec![msg];
+    for transform in transforms {
+        let mut transformed = Vec::with_capacity(msgs.len());
+        for msg in msgs {
+            transformed.extend(transform.apply(msg)?);
+        }
+        msgs = transformed;
+    }
+    Ok(msgs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::*;
+    use crate::source::kinesis::source::kpl::aggregate;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn message(payload: Vec<u8>) -> KinesisMessage {
+        KinesisMessage {
+            shard_id: "shardId-000000000000".to_string().into(),
+            sequence_number: "1".to_string(),
+            partition_key: "key".to_string(),
+            explicit_hash_key: None,
+            sub_sequence_number: None,
+            schema_id: None,
+            payload: payload.into(),
+            timestamp: None,
+        }
+    }
+
+    fn transform(transforms: &str, header_length: Option<&str>, payload: Vec<u8>) -> Vec<Bytes> {
+        let properties = KinesisProperties {
+            payload_transforms: Some(transforms.to_string()),
+            payload_header_length: header_length.map(String::from),
+            ..Default::default()
+        };
+        let transforms = parse_transforms(&properties).unwrap();
+        apply_transforms(&transforms, message(payload))
+            .unwrap()
+            .into_iter()
+            .map(|msg| msg.payload)
+            .collect()
+    }
+
+    #[test]
+    fn test_decompress_then_deaggregate() {
+        let payload = gzip(&aggregate(&[("key_a", b"a"), ("key_b", b"b")]));
+        assert_eq!(
+            transform("decompress,deaggregate", None, payload),
+            vec![Bytes::from_static(b"a"), Bytes::from_static(b"b")]
+        );
+    }
+
+    #[test]
+    fn test_deaggregate_then_decompress() {
+        let payload = aggregate(&[("key_a", &gzip(b"a")), ("key_b", b"b")]);
+        assert_eq!(
+            transform("deaggregate,decompress", None, payload),
+            vec![Bytes::from_static(b"a"), Bytes::from_static(b"b")]
+        );
+    }
+
+    #[test]
+    fn test_sub_sequence_number() {
+        let properties = KinesisProperties {
+            payload_transforms: Some("deaggregate".to_string()),
+            ..Default::default()
+        };
+        let transforms = parse_transforms(&properties).unwrap();
+        let payload = aggregate(&[("key_a", b"a"), ("key_b", b"b"), ("key_c", b"c")]);
+        let msgs = apply_transforms(&transforms, message(payload)).unwrap();
+        let sub_sequence_numbers = msgs
+            .iter()
+            .map(|msg| msg.sub_sequence_number)
+            .collect::<Vec<_>>();
+        assert_eq!(sub_sequence_numbers, vec![Some(0), Some(1), Some(2)]);
+        assert!(msgs.iter().all(|msg| msg.sequence_number == "1"));
+
+        let msgs = apply_transforms(&transforms, message(b"plain".to_vec())).unwrap();
+        assert_eq!(msgs[0].sub_sequence_number, None);
+    }
+
+    #[test]
+    fn test_strip_header() {
+        let payload = [&b"HDR"[..], &gzip(b"a")].concat();
+        assert_eq!(
+            transform("strip_header,decompress", Some("3"), payload),
+            vec![Bytes::from_static(b"a")]
+        );
+
+        let payload = gzip(&[&b"HDR"[..], b"a"].concat());
+        assert_eq!(
+            transform("decompress,strip_header", Some("3"), payload),
+            vec![Bytes::from_static(b"a")]
+        );
+
+        let payload = aggregate(&[("key_a", b"HDRa"), ("key_b", b"HDRb")]);
+        assert_eq!(
+            transform("deaggregate,strip_header", Some("3"), payload),
+            vec![Bytes::from_static(b"a"), Bytes::from_static(b"b")]
+        );
+    }
+
+    #[test]
+    fn test_confluent_framing() {
+        let payload = [&[0, 0, 0, 1, 0x2A][..], b"payload"].concat();
+        let msg = PayloadFraming::Confluent.unframe(message(payload)).unwrap();
+        assert_eq!(msg.schema_id, Some(298));
+        assert_eq!(msg.payload, Bytes::from_static(b"payload"));
+
+        assert!(PayloadFraming::Confluent
+            .unframe(message(b"payload".to_vec()))
+            .is_err());
+        assert!(PayloadFraming::Confluent
+            .unframe(message(vec![0, 0, 1]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_transforms() {
+        let properties = KinesisProperties {
+            payload_transforms: Some("strip_header".to_string()),
+            ..Default::default()
+        };
+        assert!(parse_transforms(&properties).is_err());
+
+        let properties = KinesisProperties {
+            payload_transforms: Some("decompress,unzip".to_string()),
+            ..Default::default()
+        };
+        assert!(parse_transforms(&properties).is_err());
+
+        // Plain records pass through the whole pipeline.
+        assert_eq!(
+            transform("decompress,deaggregate", None, b"plain".to_vec()),
+            vec![Bytes::from_static(b"plain")]
+        );
+    }
+}
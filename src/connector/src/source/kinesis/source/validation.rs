@@ -0,0 +1,58 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use aws_sdk_kinesis::output::GetRecordsOutput;
+
+use crate::source::kinesis::split::compare_sequence;
+
+/// Checks a `GetRecords` response for inconsistencies that Kinesis itself never returns, which
+/// point to a bug of the SDK, a proxy or a Kinesis-compatible endpoint, see
+/// `get_records.validation`. Returns the violations found.
+pub fn validate_get_records(resp: &GetRecordsOutput) -> Vec<String> {
+    let mut violations = vec![];
+    let records = resp.records().unwrap_or_default();
+    let mut last: Option<&str> = None;
+    for record in records {
+        let sequence_number = match record.sequence_number() {
+            Some(sequence_number) => sequence_number,
+            None => {
+                violations.push("record without sequence number".to_string());
+                continue;
+            }
+        };
+        if let Some(last) = last {
+            if !compare_sequence(sequence_number, last).is_gt() {
+                violations.push(format!(
+                    "sequence number {} does not follow {}",
+                    sequence_number, last
+                ));
+            }
+        }
+        last = Some(sequence_number);
+    }
+    match resp.millis_behind_latest() {
+        Some(millis) if millis < 0 => {
+            violations.push(format!("negative millis behind latest {}", millis))
+        }
+        // A closed shard has been read to its end, so nothing is behind.
+        Some(millis) if millis > 0 && resp.next_shard_iterator().is_none() => {
+            violations.push(format!(
+                "no next shard iterator while {} millis behind latest",
+                millis
+            ))
+        }
+        _ => {}
+    }
+    violations
+}
@@ -0,0 +1,109 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use aws_sdk_kinesis::model::ShardIteratorType;
+use aws_sdk_kinesis::Client as KinesisClient;
+use tokio::task::JoinHandle;
+
+use crate::source::kinesis::clock::ClockRef;
+use crate::source::kinesis::error::sdk_error;
+use crate::source::SplitId;
+
+/// Watches for the downstream stalling after a batch is returned, i.e. `next` not being called
+/// again within `stall.watchdog.timeout`. Meanwhile the iterator of the shard would expire after
+/// its 5 minute lifetime, so the watchdog warns and renews it every timeout until `next` is
+/// called.
+#[derive(Debug)]
+pub struct StallWatchdog {
+    timeout: Duration,
+    task: Option<JoinHandle<()>>,
+    /// The iterator renewed by the task, taken by `disarm`.
+    renewed: Arc<Mutex<Option<String>>>,
+}
+
+impl StallWatchdog {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            task: None,
+            renewed: Default::default(),
+        }
+    }
+
+    /// Starts watching once a batch is returned, renewing the iterator after `sequence_number`,
+    /// the last record read.
+    pub fn arm(
+        &mut self,
+        client: KinesisClient,
+        clock: ClockRef,
+        stream_name: String,
+        shard_id: SplitId,
+        sequence_number: String,
+    ) {
+        self.disarm();
+        let timeout = self.timeout;
+        let renewed = self.renewed.clone();
+        self.task = Some(tokio::spawn(async move {
+            let mut stalled = Duration::ZERO;
+            loop {
+                clock.sleep(timeout).await;
+                stalled += timeout;
+                tracing::warn!(
+                    stream = %stream_name,
+                    shard = %shard_id,
+                    stalled = ?stalled,
+                    "kinesis shard is not polled since its last batch, the downstream may be \
+                     stalled, renew its iterator"
+                );
+                let resp = client
+                    .get_shard_iterator()
+                    .stream_name(stream_name.clone())
+                    .shard_id(shard_id.as_ref())
+                    .shard_iterator_type(ShardIteratorType::AfterSequenceNumber)
+                    .starting_sequence_number(sequence_number.clone())
+                    .send()
+                    .await;
+                match resp {
+                    Ok(resp) => {
+                        *renewed.lock().unwrap() = resp.shard_iterator().map(String::from);
+                    }
+                    Err(e) => tracing::warn!(
+                        "failed to renew the iterator of stalled kinesis shard {}: {}",
+                        shard_id,
+                        sdk_error(e)
+                    ),
+                }
+            }
+        }));
+    }
+
+    /// Stops watching as `next` is called, returning the iterator renewed meanwhile if any.
+    pub fn disarm(&mut self) -> Option<String> {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        self.renewed.lock().unwrap().take()
+    }
+}
+
+impl Drop for StallWatchdog {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
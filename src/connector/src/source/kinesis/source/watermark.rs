@@ -0,0 +1,141 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use crate::source::{SourceMessage, SourceMeta, SplitId};
+
+/// Tracks event-time watermarks derived from the approximate arrival timestamps of Kinesis
+/// records.
+///
+/// The watermark of a shard is the minimum arrival timestamp of the latest batch emitted from it,
+/// and never moves backwards. Heartbeats carry the tip timestamp of an idle shard, so its
+/// watermark keeps advancing without new records. The source watermark is the minimum over all
+/// shards.
+#[derive(Debug, Default)]
+pub struct WatermarkTracker {
+    watermarks: HashMap<SplitId, Option<i64>>,
+}
+
+impl WatermarkTracker {
+    pub fn new(split_ids: impl IntoIterator<Item = SplitId>) -> Self {
+        Self {
+            watermarks: split_ids.into_iter().map(|id| (id, None)).collect(),
+        }
+    }
+
+    /// Starts tracking a newly assigned split.
+    pub fn add_split(&mut self, split_id: SplitId) {
+        self.watermarks.entry(split_id).or_default();
+    }
+
+    /// Stops tracking a split no longer assigned, which no longer holds back the source watermark.
+    pub fn remove_split(&mut self, split_id: &SplitId) {
+        self.watermarks.remove(split_id);
+    }
+
+    /// Advances the watermarks of the shards present in an emitted `chunk`.
+    pub fn observe(&mut self, chunk: &[SourceMessage]) {
+        let mut batch_min: HashMap<&SplitId, i64> = HashMap::new();
+        for msg in chunk {
+            let ts = match &msg.meta {
+                SourceMeta::Kinesis(meta) => meta.timestamp,
+                _ => None,
+            };
+            if let Some(ts) = ts {
+                batch_min
+                    .entry(&msg.split_id)
+                    .and_modify(|min| *min = (*min).min(ts))
+                    .or_insert(ts);
+            }
+        }
+        for (split_id, ts) in batch_min {
+            let watermark = self.watermarks.entry(split_id.clone()).or_default();
+            *watermark = Some(watermark.map_or(ts, |wm| wm.max(ts)));
+        }
+    }
+
+    /// Returns the watermark of the shard, or `None` if nothing has been emitted from it.
+    pub fn shard_watermark(&self, split_id: &SplitId) -> Option<i64> {
+        self.watermarks.get(split_id).copied().flatten()
+    }
+
+    /// Returns the minimum watermark across shards, or `None` until every shard has one.
+    pub fn source_watermark(&self) -> Option<i64> {
+        self.watermarks
+            .values()
+            .copied()
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::source::kinesis::source::message::{heartbeat_message, KinesisMeta};
+
+    fn message(split_id: &SplitId, timestamp: i64) -> SourceMessage {
+        SourceMessage {
+            payload: Some(Bytes::from_static(b"payload")),
+            offset: timestamp.to_string(),
+            split_id: split_id.clone(),
+            meta: SourceMeta::Kinesis(KinesisMeta {
+                timestamp: Some(timestamp),
+                ..Default::default()
+            }),
+            attributes: Default::default(),
+            provenance: None,
+            state_offset: None,
+        }
+    }
+
+    #[test]
+    fn test_watermark_advances_monotonically() {
+        let shard_0: SplitId = "shardId-000000000000".to_string().into();
+        let shard_1: SplitId = "shardId-000000000001".to_string().into();
+        let mut tracker = WatermarkTracker::new([shard_0.clone(), shard_1.clone()]);
+        assert_eq!(tracker.source_watermark(), None);
+
+        let batches = [
+            (vec![1000, 1200, 1100], Some(1000)),
+            (vec![1500, 1300], Some(1300)),
+            // Late records never move the watermark backwards.
+            (vec![900], Some(1300)),
+            (vec![2000], Some(2000)),
+        ];
+        let mut last = None;
+        for (timestamps, expected) in batches {
+            let chunk = timestamps
+                .into_iter()
+                .map(|ts| message(&shard_0, ts))
+                .collect::<Vec<_>>();
+            tracker.observe(&chunk);
+            let watermark = tracker.shard_watermark(&shard_0);
+            assert_eq!(watermark, expected);
+            assert!(watermark >= last);
+            last = watermark;
+        }
+        // The idle shard holds back the source watermark.
+        assert_eq!(tracker.shard_watermark(&shard_1), None);
+        assert_eq!(tracker.source_watermark(), None);
+
+        tracker.observe(&[heartbeat_message(shard_1.clone(), String::new(), 1500)]);
+        assert_eq!(tracker.shard_watermark(&shard_1), Some(1500));
+        assert_eq!(tracker.source_watermark(), Some(1500));
+    }
+}
@@ -12,7 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::cmp::Ordering;
+
 use anyhow::anyhow;
+use aws_smithy_types::base64;
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 
@@ -22,21 +25,141 @@ use crate::source::{SplitId, SplitMetaData};
 pub enum KinesisOffset {
     Earliest,
     Latest,
+    /// After the record with the sequence number, exclusively. The state of a shard persists the
+    /// sequence number of the last record emitted, or dropped on purpose, e.g. as a duplicate, so
+    /// that it resumes with `AfterSequenceNumber` from the first record never emitted.
     SequenceNumber(String),
+    /// A sequence number in the packed format, see [`pack_sequence_number`].
+    #[serde(rename = "Packed")]
+    PackedSequenceNumber(String),
+    /// After the sub-record at the index within the KPL aggregated record with the sequence
+    /// number, whose later sub-records are yet to be read. The sequence number may be packed.
+    SubSequenceNumber(String, u64),
+    /// Milliseconds since epoch.
     Timestamp(i64),
     None,
 }
 
+/// Compares two sequence numbers by their values. They are decimal strings too long for any
+/// integer type, so they are compared by their lengths and then their digits, after leading zeros
+/// are stripped.
+pub fn compare_sequence(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+/// The prefix of message offsets in the packed format, which are stored as
+/// [`KinesisOffset::PackedSequenceNumber`].
+pub const PACKED_OFFSET_PREFIX: &str = "packed:";
+/// Separates the sequence number of a KPL aggregated record from the index of a sub-record in the
+/// offset of a sub-record which is not the last one, see [`KinesisOffset::SubSequenceNumber`].
+pub const SUB_SEQUENCE_SEPARATOR: char = '#';
+
+/// Packs a decimal sequence number into the base64 encoded big endian bytes of its value, which
+/// takes 32 instead of 56 characters for a typical sequence number.
+pub fn pack_sequence_number(sequence_number: &str) -> anyhow::Result<String> {
+    if sequence_number.is_empty() || !sequence_number.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(anyhow!("invalid sequence number '{}'", sequence_number));
+    }
+    // Repeatedly divides the decimal digits by 256, collecting the remainders.
+    let mut digits = sequence_number
+        .bytes()
+        .map(|b| (b - b'0') as u32)
+        .collect::<Vec<_>>();
+    let mut bytes = vec![];
+    while !digits.is_empty() {
+        let mut remainder = 0;
+        let mut quotient = Vec::with_capacity(digits.len());
+        for digit in digits {
+            let current = remainder * 10 + digit;
+            if !quotient.is_empty() || current >= 256 {
+                quotient.push(current / 256);
+            }
+            remainder = current % 256;
+        }
+        bytes.push(remainder as u8);
+        digits = quotient;
+    }
+    bytes.reverse();
+    Ok(base64::encode(&bytes))
+}
+
+/// The inverse of [`pack_sequence_number`].
+pub fn unpack_sequence_number(packed: &str) -> anyhow::Result<String> {
+    let bytes = base64::decode(packed)
+        .map_err(|e| anyhow!("invalid packed sequence number '{}': {}", packed, e))?;
+    // Repeatedly divides the bytes by 10, collecting the remainders.
+    let mut value = bytes
+        .into_iter()
+        .map(u32::from)
+        .skip_while(|b| *b == 0)
+        .collect::<Vec<_>>();
+    let mut digits = vec![];
+    while !value.is_empty() {
+        let mut remainder = 0;
+        let mut quotient = Vec::with_capacity(value.len());
+        for b in value {
+            let current = remainder * 256 + b;
+            if !quotient.is_empty() || current >= 10 {
+                quotient.push(current / 10);
+            }
+            remainder = current % 10;
+        }
+        digits.push(b'0' + remainder as u8);
+        value = quotient;
+    }
+    if digits.is_empty() {
+        digits.push(b'0');
+    }
+    digits.reverse();
+    Ok(String::from_utf8(digits).unwrap())
+}
+
+impl KinesisOffset {
+    /// Converts a packed sequence number to a plain one, leaving other offsets as is.
+    pub fn unpacked(self) -> anyhow::Result<Self> {
+        match self {
+            KinesisOffset::PackedSequenceNumber(packed) => Ok(KinesisOffset::SequenceNumber(
+                unpack_sequence_number(&packed)?,
+            )),
+            KinesisOffset::SubSequenceNumber(sequence_number, index) => {
+                match sequence_number.strip_prefix(PACKED_OFFSET_PREFIX) {
+                    Some(packed) => Ok(KinesisOffset::SubSequenceNumber(
+                        unpack_sequence_number(packed)?,
+                        index,
+                    )),
+                    None => Ok(KinesisOffset::SubSequenceNumber(sequence_number, index)),
+                }
+            }
+            offset => Ok(offset),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Hash)]
 pub struct KinesisSplit {
     pub(crate) shard_id: SplitId,
     pub(crate) start_position: KinesisOffset,
     pub(crate) end_position: KinesisOffset,
+    /// The stream the shard belongs to, only set when streams are discovered by pattern. Otherwise
+    /// the stream in properties is used.
+    #[serde(default)]
+    pub(crate) stream_name: Option<String>,
+    /// When the shard was listed with a `Latest` start position, in milliseconds since epoch. Only
+    /// set with `latest.gap.detection`, to detect the records written before the `Latest` iterator
+    /// is acquired.
+    #[serde(default)]
+    pub(crate) discovered_at: Option<i64>,
 }
 
 impl SplitMetaData for KinesisSplit {
     fn id(&self) -> SplitId {
-        self.shard_id.clone()
+        match &self.stream_name {
+            // Shard ids are only unique within a stream.
+            Some(stream_name) => format!("{}:{}", stream_name, self.shard_id).into(),
+            None => self.shard_id.clone(),
+        }
     }
 
     fn encode_to_bytes(&self) -> Bytes {
@@ -58,19 +181,217 @@ impl KinesisSplit {
             shard_id,
             start_position,
             end_position,
+            stream_name: None,
+            discovered_at: None,
         }
     }
 
+    pub fn with_stream_name(mut self, stream_name: String) -> Self {
+        self.stream_name = Some(stream_name);
+        self
+    }
+
+    /// A hash of the split id, which is stable across enumerations and restarts, so that the
+    /// scheduler can assign the same shard to the same reader while the shard set is unchanged and
+    /// keep its iterator warm.
+    pub fn affinity_key(&self) -> u64 {
+        let digest = md5::compute(self.id().as_bytes());
+        u64::from_be_bytes(digest.0[..8].try_into().unwrap())
+    }
+
+    /// The index of the reader among `num_readers` that the split prefers to be assigned to.
+    pub fn preferred_reader(&self, num_readers: usize) -> usize {
+        assert!(num_readers > 0);
+        (self.affinity_key() % num_readers as u64) as usize
+    }
+
+    /// Like [`Self::preferred_reader`], but by jump consistent hashing, so that when a reader is
+    /// added, only about 1 / `num_readers` of the splits move, all to the new reader, instead of
+    /// most of them.
+    pub fn consistent_reader(&self, num_readers: usize) -> usize {
+        assert!(num_readers > 0);
+        jump_consistent_hash(self.affinity_key(), num_readers)
+    }
+
     pub fn copy_with_offset(&self, start_offset: String) -> Self {
+        let sub_sequence = start_offset.rsplit_once(SUB_SEQUENCE_SEPARATOR).and_then(
+            |(sequence_number, index)| {
+                Some((sequence_number.to_string(), index.parse::<u64>().ok()?))
+            },
+        );
         let start_offset = if start_offset.is_empty() {
             KinesisOffset::Earliest
+        } else if let Some((sequence_number, index)) = sub_sequence {
+            KinesisOffset::SubSequenceNumber(sequence_number, index)
+        } else if let Some(packed) = start_offset.strip_prefix(PACKED_OFFSET_PREFIX) {
+            KinesisOffset::PackedSequenceNumber(packed.to_string())
         } else {
             KinesisOffset::SequenceNumber(start_offset)
         };
-        Self::new(
-            self.shard_id.clone(),
-            start_offset,
-            self.end_position.clone(),
-        )
+        Self {
+            start_position: start_offset,
+            ..self.clone()
+        }
+    }
+}
+
+/// Maps a key to one of `num_buckets` buckets, moving only the keys which go to the new bucket when
+/// one is added, see "A Fast, Minimal Memory, Consistent Hash Algorithm" by Lamping and Veach.
+fn jump_consistent_hash(mut key: u64, num_buckets: usize) -> usize {
+    let mut bucket: i64 = -1;
+    let mut next: i64 = 0;
+    while next < num_buckets as i64 {
+        bucket = next;
+        key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+        next = ((bucket + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    bucket as usize
+}
+
+/// Assigns the splits to `num_readers` reader slots by [`KinesisSplit::consistent_reader`], which
+/// is deterministic across enumerators and restarts, and stable as readers are added or removed.
+pub fn assign_splits(splits: &[KinesisSplit], num_readers: usize) -> Vec<Vec<KinesisSplit>> {
+    let mut assignment = vec![vec![]; num_readers];
+    for split in splits {
+        assignment[split.consistent_reader(num_readers)].push(split.clone());
+    }
+    assignment
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use num_bigint::BigUint;
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_compare_sequence() {
+        assert_eq!(compare_sequence("10", "9"), Ordering::Greater);
+        assert_eq!(compare_sequence("009", "10"), Ordering::Less);
+        assert_eq!(compare_sequence("0010", "10"), Ordering::Equal);
+        assert_eq!(compare_sequence("0", "000"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_compare_sequence_agrees_with_bignum() {
+        let mut rng = rand::thread_rng();
+        let mut sequence = |digits: u8| {
+            let zeros = "0".repeat(rng.gen_range(0..3));
+            let len = rng.gen_range(1..60);
+            let value = (0..len)
+                .map(|_| char::from(b'0' + rng.gen_range(0..digits)))
+                .collect::<String>();
+            zeros + &value
+        };
+        for i in 0..10_000 {
+            // Few distinct digits make equal values and long common prefixes likely.
+            let digits = if i % 2 == 0 { 2 } else { 10 };
+            let (a, b) = (sequence(digits), sequence(digits));
+            let expected = BigUint::parse_bytes(a.as_bytes(), 10)
+                .unwrap()
+                .cmp(&BigUint::parse_bytes(b.as_bytes(), 10).unwrap());
+            assert_eq!(compare_sequence(&a, &b), expected, "{} vs {}", a, b);
+            assert_eq!(compare_sequence(&a, &a), Ordering::Equal);
+        }
+    }
+
+    #[test]
+    fn test_pack_sequence_number() {
+        for sequence_number in [
+            "0",
+            "255",
+            "256",
+            "49629139817504901062972448413535783695568426186596941842",
+        ] {
+            let packed = pack_sequence_number(sequence_number).unwrap();
+            assert_eq!(unpack_sequence_number(&packed).unwrap(), sequence_number);
+        }
+        assert_eq!(
+            pack_sequence_number("256").unwrap(),
+            base64::encode(&[1, 0])
+        );
+        assert!(pack_sequence_number("12a").is_err());
+    }
+
+    #[test]
+    fn test_assign_splits() {
+        let splits = (0..1000)
+            .map(|i| {
+                KinesisSplit::new(
+                    format!("shardId-{:012}", i).into(),
+                    KinesisOffset::Earliest,
+                    KinesisOffset::None,
+                )
+            })
+            .collect::<Vec<_>>();
+        let slots = |num_readers| {
+            assign_splits(&splits, num_readers)
+                .into_iter()
+                .enumerate()
+                .flat_map(|(slot, splits)| splits.into_iter().map(move |split| (split.id(), slot)))
+                .collect::<HashMap<_, _>>()
+        };
+
+        let before = slots(10);
+        assert_eq!(before, slots(10));
+        assert!((0..10).all(|slot| before.values().filter(|s| **s == slot).count() > 50));
+
+        // Adding a reader moves about 1 / 11 of the splits, all to the new reader.
+        let after = slots(11);
+        let moved = before
+            .iter()
+            .filter(|(id, slot)| after[*id] != **slot)
+            .map(|(id, _)| after[id])
+            .collect::<Vec<_>>();
+        assert!(moved.len() > 50 && moved.len() < 150, "{}", moved.len());
+        assert!(moved.iter().all(|slot| *slot == 10));
+    }
+
+    #[test]
+    fn test_split_state_round_trip() {
+        let sequence_number = "49629139817504901062972448413535783695568426186596941842";
+        let split = KinesisSplit::new(
+            "shardId-000000000000".to_string().into(),
+            KinesisOffset::Earliest,
+            KinesisOffset::None,
+        );
+
+        let string = split.copy_with_offset(sequence_number.to_string());
+        let restored = KinesisSplit::restore_from_bytes(&string.encode_to_bytes()).unwrap();
+        assert_eq!(restored, string);
+        assert_eq!(
+            restored.start_position.unpacked().unwrap(),
+            KinesisOffset::SequenceNumber(sequence_number.to_string())
+        );
+
+        let packed = split.copy_with_offset(format!(
+            "{}{}",
+            PACKED_OFFSET_PREFIX,
+            pack_sequence_number(sequence_number).unwrap()
+        ));
+        let encoded = packed.encode_to_bytes();
+        assert!(encoded.len() < string.encode_to_bytes().len());
+        let restored = KinesisSplit::restore_from_bytes(&encoded).unwrap();
+        assert_eq!(restored, packed);
+        assert_eq!(
+            restored.start_position.unpacked().unwrap(),
+            KinesisOffset::SequenceNumber(sequence_number.to_string())
+        );
+
+        // Within a KPL aggregated record.
+        let sub_sequence = split.copy_with_offset(format!(
+            "{}{}{}2",
+            PACKED_OFFSET_PREFIX,
+            pack_sequence_number(sequence_number).unwrap(),
+            SUB_SEQUENCE_SEPARATOR
+        ));
+        let restored = KinesisSplit::restore_from_bytes(&sub_sequence.encode_to_bytes()).unwrap();
+        assert_eq!(
+            restored.start_position.unpacked().unwrap(),
+            KinesisOffset::SubSequenceNumber(sequence_number.to_string(), 2)
+        );
     }
 }
@@ -0,0 +1,140 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::Future;
+use std::io::Read;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use aws_smithy_client::conns::NativeTls;
+use aws_smithy_client::hyper_ext::Adapter;
+use aws_smithy_http::body::SdkBody;
+use aws_smithy_http::result::ConnectorError;
+use flate2::read::GzDecoder;
+use http::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH};
+use http::HeaderValue;
+use tower::Service;
+
+use crate::source::kinesis::config::{HttpSettings, ResponseCompression};
+
+tokio::task_local! {
+    /// The HTTP attempts of the API call being counted by [`count_attempts`].
+    static ATTEMPTS: Arc<AtomicUsize>;
+}
+
+/// Runs an API call, returning its output along with the HTTP requests it took, which include
+/// the retries within the SDK that are otherwise invisible, e.g. of throttled requests. Only the
+/// requests of clients built by [`crate::source::kinesis::build_client`] are counted.
+pub async fn count_attempts<F: Future>(call: F) -> (F::Output, usize) {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let output = ATTEMPTS.scope(attempts.clone(), call).await;
+    (output, attempts.load(Ordering::Relaxed))
+}
+
+/// The HTTP connector of the clients, which counts the requests sent within [`count_attempts`],
+/// and asks for the responses of `GetRecords` compressed under `response.compression`.
+#[derive(Clone, Debug)]
+pub struct CountingConnector<C> {
+    inner: C,
+    settings: HttpSettings,
+}
+
+impl CountingConnector<Adapter<NativeTls>> {
+    /// Wraps the default HTTPS connector of the SDK, with its connections configured by
+    /// `settings`.
+    pub fn https(settings: HttpSettings) -> Self {
+        Self {
+            inner: Adapter::builder()
+                .hyper_builder(settings.hyper_builder())
+                .build(aws_smithy_client::conns::native_tls()),
+            settings,
+        }
+    }
+}
+
+impl<C> CountingConnector<C> {
+    pub fn settings(&self) -> HttpSettings {
+        self.settings
+    }
+}
+
+impl<C> Service<http::Request<SdkBody>> for CountingConnector<C>
+where
+    C: Service<http::Request<SdkBody>, Response = http::Response<SdkBody>, Error = ConnectorError>,
+    C::Future: Send + 'static,
+{
+    type Error = ConnectorError;
+    #[allow(clippy::type_complexity)]
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+    type Response = http::Response<SdkBody>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<SdkBody>) -> Self::Future {
+        let _ = ATTEMPTS.try_with(|attempts| attempts.fetch_add(1, Ordering::Relaxed));
+        // Only `GetRecords` is asked for, as the event stream of `SubscribeToShard` can not be
+        // buffered whole to be decompressed.
+        if self.settings.response_compression == ResponseCompression::Gzip
+            && is_get_records(&request)
+        {
+            request
+                .headers_mut()
+                .insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip"));
+        }
+        let response = self.inner.call(request);
+        Box::pin(async move { decompress(response.await?).await })
+    }
+}
+
+fn is_get_records(request: &http::Request<SdkBody>) -> bool {
+    request
+        .headers()
+        .get("x-amz-target")
+        .and_then(|target| target.to_str().ok())
+        .map_or(false, |target| target.ends_with(".GetRecords"))
+}
+
+/// Decompresses the body of a response encoded with gzip, leaving other responses untouched.
+async fn decompress(
+    response: http::Response<SdkBody>,
+) -> Result<http::Response<SdkBody>, ConnectorError> {
+    let gzip = response
+        .headers()
+        .get(CONTENT_ENCODING)
+        .map_or(false, |encoding| {
+            encoding.as_bytes().eq_ignore_ascii_case(b"gzip")
+        });
+    if !gzip {
+        return Ok(response);
+    }
+    let (mut parts, body) = response.into_parts();
+    let compressed = hyper::body::to_bytes(body)
+        .await
+        .map_err(ConnectorError::io)?;
+    let mut decompressed = Vec::new();
+    GzDecoder::new(compressed.as_ref())
+        .read_to_end(&mut decompressed)
+        .map_err(|e| ConnectorError::io(Box::new(e)))?;
+    parts.headers.remove(CONTENT_ENCODING);
+    parts.headers.remove(CONTENT_LENGTH);
+    Ok(http::Response::from_parts(
+        parts,
+        SdkBody::from(decompressed),
+    ))
+}
@@ -0,0 +1,291 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A mock Kinesis service built on [`wiremock`], speaking the JSON protocol used by the SDK.
+
+use std::fmt::{Debug, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use aws_smithy_types::base64;
+use serde_json::{json, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::subscriber::DefaultGuard;
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+use wiremock::matchers::{body_partial_json, header, method};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+use crate::source::kinesis::split::{KinesisOffset, KinesisSplit};
+use crate::source::kinesis::KinesisProperties;
+
+const KINESIS_TARGET_PREFIX: &str = "Kinesis_20131202";
+const KINESIS_CONTENT_TYPE: &str = "application/x-amz-json-1.1";
+
+pub const MOCK_SHARD_ITERATOR: &str = "mock_shard_iterator";
+
+pub fn mock_properties(server: &MockServer) -> KinesisProperties {
+    KinesisProperties {
+        stream_name: "mock_stream".to_string(),
+        stream_region: "us-east-1".to_string(),
+        endpoint: Some(server.uri()),
+        credentials_access_key: Some("mock_access_key".to_string()),
+        credentials_secret_access_key: Some("mock_secret_key".to_string()),
+        ..Default::default()
+    }
+}
+
+pub fn mock_split(shard_id: &str) -> KinesisSplit {
+    KinesisSplit::new(
+        shard_id.to_string().into(),
+        KinesisOffset::Earliest,
+        KinesisOffset::None,
+    )
+}
+
+/// Mounts `responder` for the Kinesis API `operation`, e.g. `GetRecords`.
+pub async fn mount_api(server: &MockServer, operation: &str, responder: impl Respond + 'static) {
+    let target = format!("{}.{}", KINESIS_TARGET_PREFIX, operation);
+    Mock::given(method("POST"))
+        .and(header("x-amz-target", target.as_str()))
+        .respond_with(responder)
+        .mount(server)
+        .await;
+}
+
+/// Mounts `responder` for the Kinesis API `operation` whose request body contains `body`. Mount
+/// it before any catch-all [`mount_api`] of the same operation, which would take precedence.
+pub async fn mount_api_matching(
+    server: &MockServer,
+    operation: &str,
+    body: Value,
+    responder: impl Respond + 'static,
+) {
+    let target = format!("{}.{}", KINESIS_TARGET_PREFIX, operation);
+    Mock::given(method("POST"))
+        .and(header("x-amz-target", target.as_str()))
+        .and(body_partial_json(body))
+        .respond_with(responder)
+        .mount(server)
+        .await;
+}
+
+/// Mounts a `GetShardIterator` which always returns [`MOCK_SHARD_ITERATOR`].
+pub async fn mount_shard_iterator(server: &MockServer) {
+    mount_api(
+        server,
+        "GetShardIterator",
+        json_response(json!({ "ShardIterator": MOCK_SHARD_ITERATOR })),
+    )
+    .await;
+}
+
+/// Returns how many times `operation` has been called on the mock server.
+pub async fn received_calls(server: &MockServer, operation: &str) -> usize {
+    let target = format!("{}.{}", KINESIS_TARGET_PREFIX, operation);
+    server
+        .received_requests()
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter(|request| {
+            request.headers.iter().any(|(name, values)| {
+                name.as_str() == "x-amz-target" && values.iter().any(|v| v.as_str() == target)
+            })
+        })
+        .count()
+}
+
+/// Returns the JSON bodies of the requests of the Kinesis API `operation` received so far, in
+/// order.
+pub async fn received_bodies(server: &MockServer, operation: &str) -> Vec<Value> {
+    let target = format!("{}.{}", KINESIS_TARGET_PREFIX, operation);
+    server
+        .received_requests()
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter(|request| {
+            request.headers.iter().any(|(name, values)| {
+                name.as_str() == "x-amz-target" && values.iter().any(|v| v.as_str() == target)
+            })
+        })
+        .map(|request| serde_json::from_slice(&request.body).unwrap())
+        .collect()
+}
+
+pub fn json_response(body: Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_raw(body.to_string(), KINESIS_CONTENT_TYPE)
+}
+
+pub fn error_response(error_type: &str) -> ResponseTemplate {
+    ResponseTemplate::new(400).set_body_raw(
+        json!({ "__type": error_type, "message": "mock error" }).to_string(),
+        KINESIS_CONTENT_TYPE,
+    )
+}
+
+pub fn mock_record(sequence_number: &str, data: &[u8], arrival_millis: i64) -> Value {
+    json!({
+        "SequenceNumber": sequence_number,
+        "ApproximateArrivalTimestamp": arrival_millis as f64 / 1000.0,
+        "Data": base64::encode(data),
+        "PartitionKey": "mock_partition_key",
+    })
+}
+
+pub fn get_records_output(records: Vec<Value>, millis_behind_latest: i64) -> Value {
+    json!({
+        "Records": records,
+        "NextShardIterator": MOCK_SHARD_ITERATOR,
+        "MillisBehindLatest": millis_behind_latest,
+    })
+}
+
+pub fn list_shards_output(shard_ids: &[&str]) -> Value {
+    let shards = shard_ids
+        .iter()
+        .map(|shard_id| {
+            json!({
+                "ShardId": shard_id,
+                "HashKeyRange": {
+                    "StartingHashKey": "0",
+                    "EndingHashKey": "340282366920938463463374607431768211455",
+                },
+                "SequenceNumberRange": {
+                    "StartingSequenceNumber": "0",
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({ "Shards": shards })
+}
+
+/// Replies with the given responses in order, repeating the last one once exhausted.
+pub struct SequenceResponder {
+    responses: Vec<ResponseTemplate>,
+    counter: AtomicUsize,
+}
+
+impl SequenceResponder {
+    pub fn new(responses: Vec<ResponseTemplate>) -> Self {
+        assert!(!responses.is_empty());
+        Self {
+            responses,
+            counter: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Respond for SequenceResponder {
+    fn respond(&self, _request: &Request) -> ResponseTemplate {
+        let index = self
+            .counter
+            .fetch_add(1, Ordering::SeqCst)
+            .min(self.responses.len() - 1);
+        self.responses[index].clone()
+    }
+}
+
+/// Replies to `GetShardIterator` with an iterator `<shard id>/<sequence number>` positioned after
+/// the starting sequence number, or at `0` for other iterator types. Used with
+/// [`EndlessRecordsResponder`].
+pub struct ShardIteratorResponder;
+
+impl Respond for ShardIteratorResponder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        let shard_id = body["ShardId"].as_str().unwrap();
+        let sequence_number = body["StartingSequenceNumber"].as_str().unwrap_or("0");
+        json_response(json!({
+            "ShardIterator": format!("{}/{}", shard_id, sequence_number),
+        }))
+    }
+}
+
+/// Replies to `GetRecords` with the record following the sequence number in the iterator of
+/// [`ShardIteratorResponder`], so that each shard yields records `1, 2, 3, ...` endlessly.
+pub struct EndlessRecordsResponder;
+
+impl Respond for EndlessRecordsResponder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        let (shard_id, sequence_number) = body["ShardIterator"]
+            .as_str()
+            .unwrap()
+            .rsplit_once('/')
+            .unwrap();
+        let next = sequence_number.parse::<u64>().unwrap() + 1;
+        json_response(json!({
+            "Records": [mock_record(&next.to_string(), b"payload", 0)],
+            "NextShardIterator": format!("{}/{}", shard_id, next),
+            "MillisBehindLatest": 0,
+        }))
+    }
+}
+
+/// Captures the tracing events of the current thread until the guard is dropped, each as a line of
+/// `message field=value ...` followed by the fields of its spans.
+pub fn capture_events() -> (Arc<Mutex<Vec<String>>>, DefaultGuard) {
+    let events = Arc::new(Mutex::new(vec![]));
+    let subscriber = tracing_subscriber::registry().with(EventCapture(events.clone()));
+    (events, tracing::subscriber::set_default(subscriber))
+}
+
+struct EventCapture(Arc<Mutex<Vec<String>>>);
+
+/// The recorded fields of a span, kept in its extensions.
+struct SpanFields(String);
+
+#[derive(Default)]
+struct FieldRecorder(String);
+
+impl Visit for FieldRecorder {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.0.insert_str(0, &format!("{:?}", value));
+        } else {
+            write!(self.0, " {}={:?}", field.name(), value).unwrap();
+        }
+    }
+}
+
+impl<S> Layer<S> for EventCapture
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = FieldRecorder::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(fields.0));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut fields = FieldRecorder::default();
+        event.record(&mut fields);
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(span_fields) = span.extensions().get::<SpanFields>() {
+                    fields.0.push_str(&span_fields.0);
+                }
+            }
+        }
+        self.0.lock().unwrap().push(fields.0);
+    }
+}
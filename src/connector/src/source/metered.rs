@@ -0,0 +1,209 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! A [`SplitReader`] decorator recording the batches read by any connector, so that the metrics
+//! of every connector are uniform and readers need not thread metrics themselves.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::source::{Column, ConnectorState, SourceMessage, SplitReader};
+
+/// Receives the metrics of the batches read by a [`MeteredReader`], e.g. to export them as
+/// Prometheus counters and histograms. Called on the read path, so implementations should only
+/// update counters.
+pub trait BatchMetrics: Debug + Send + Sync {
+    /// A call of `next` returned a batch of `messages` messages with `bytes` bytes of payloads
+    /// after `latency`.
+    fn batch_read(&self, messages: usize, bytes: usize, latency: Duration);
+
+    /// A call of `next` failed after `latency`.
+    fn read_failed(&self, _latency: Duration) {}
+}
+
+pub type BatchMetricsRef = Arc<dyn BatchMetrics>;
+
+/// Discards the metrics, used until a sink is set.
+#[derive(Debug, Default)]
+pub struct NoopBatchMetrics;
+
+impl BatchMetrics for NoopBatchMetrics {
+    fn batch_read(&self, _messages: usize, _bytes: usize, _latency: Duration) {}
+}
+
+/// Wraps a reader, recording the batches returned by its `next` to a [`BatchMetrics`] sink and
+/// forwarding them unchanged.
+#[derive(Debug)]
+pub struct MeteredReader<R> {
+    inner: R,
+    metrics: BatchMetricsRef,
+}
+
+impl<R: SplitReader> MeteredReader<R> {
+    pub fn wrap(inner: R, metrics: BatchMetricsRef) -> Self {
+        Self { inner, metrics }
+    }
+
+    pub fn with_metrics(self, metrics: BatchMetricsRef) -> Self {
+        Self { metrics, ..self }
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<R> SplitReader for MeteredReader<R>
+where
+    R: SplitReader + Send,
+    R::Properties: Send + 'static,
+{
+    type Properties = R::Properties;
+
+    /// Builds the inner reader, whose metrics are discarded until a sink is set by
+    /// `with_metrics`.
+    async fn new(
+        properties: Self::Properties,
+        state: ConnectorState,
+        columns: Option<Vec<Column>>,
+    ) -> Result<Self> {
+        let inner = R::new(properties, state, columns).await?;
+        Ok(Self::wrap(inner, Arc::new(NoopBatchMetrics)))
+    }
+
+    async fn next(&mut self) -> Result<Option<Vec<SourceMessage>>> {
+        let started = Instant::now();
+        let chunk = self.inner.next().await;
+        let latency = started.elapsed();
+        match &chunk {
+            Ok(Some(chunk)) => {
+                let bytes = chunk
+                    .iter()
+                    .filter_map(|msg| msg.payload.as_ref())
+                    .map(|payload| payload.len())
+                    .sum();
+                self.metrics.batch_read(chunk.len(), bytes, latency);
+            }
+            Ok(None) => {}
+            Err(_) => self.metrics.read_failed(latency),
+        }
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::Mutex;
+
+    use anyhow::anyhow;
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::source::SourceMeta;
+
+    /// Returns the batches of its properties in order, failing on an empty one.
+    struct MockReader {
+        batches: VecDeque<Vec<SourceMessage>>,
+    }
+
+    #[async_trait]
+    impl SplitReader for MockReader {
+        type Properties = Vec<Vec<SourceMessage>>;
+
+        async fn new(
+            properties: Self::Properties,
+            _state: ConnectorState,
+            _columns: Option<Vec<Column>>,
+        ) -> Result<Self> {
+            Ok(Self {
+                batches: properties.into(),
+            })
+        }
+
+        async fn next(&mut self) -> Result<Option<Vec<SourceMessage>>> {
+            match self.batches.pop_front() {
+                Some(batch) if batch.is_empty() => Err(anyhow!("mock failure")),
+                batch => Ok(batch),
+            }
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct RecordingMetrics {
+        batches: Mutex<Vec<(usize, usize)>>,
+        failures: Mutex<usize>,
+    }
+
+    impl BatchMetrics for RecordingMetrics {
+        fn batch_read(&self, messages: usize, bytes: usize, _latency: Duration) {
+            self.batches.lock().unwrap().push((messages, bytes));
+        }
+
+        fn read_failed(&self, _latency: Duration) {
+            *self.failures.lock().unwrap() += 1;
+        }
+    }
+
+    fn message(offset: &str, payload: Option<&'static [u8]>) -> SourceMessage {
+        SourceMessage {
+            payload: payload.map(Bytes::from_static),
+            offset: offset.to_string(),
+            split_id: Arc::new("split".to_string()),
+            meta: SourceMeta::Empty,
+            attributes: HashMap::new(),
+            provenance: None,
+            state_offset: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_metered_reader() -> Result<()> {
+        let batches = vec![
+            vec![message("1", Some(b"abc")), message("2", Some(b"de"))],
+            // A heartbeat without payload.
+            vec![message("3", None)],
+            vec![],
+            vec![message("4", Some(b"f"))],
+        ];
+        let metrics = Arc::new(RecordingMetrics::default());
+        let inner = MockReader::new(batches.clone(), None, None).await?;
+        let mut reader = MeteredReader::wrap(inner, metrics.clone());
+
+        assert_eq!(reader.next().await?, Some(batches[0].clone()));
+        assert_eq!(reader.next().await?, Some(batches[1].clone()));
+        assert!(reader.next().await.is_err());
+        assert_eq!(reader.next().await?, Some(batches[3].clone()));
+        assert_eq!(reader.next().await?, None);
+
+        assert_eq!(
+            *metrics.batches.lock().unwrap(),
+            vec![(2, 5), (1, 0), (1, 1)]
+        );
+        assert_eq!(*metrics.failures.lock().unwrap(), 1);
+        Ok(())
+    }
+}
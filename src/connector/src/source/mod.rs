@@ -15,11 +15,15 @@
 pub mod base;
 pub mod datagen;
 pub mod dummy_connector;
+pub mod dynamodb_streams;
 pub mod filesystem;
 pub mod kafka;
 pub mod kinesis;
+pub mod metered;
 pub mod nexmark;
 pub mod pulsar;
+#[cfg(test)]
+pub(crate) mod test_kit;
 pub use base::*;
 pub use kafka::KAFKA_CONNECTOR;
 pub use kinesis::KINESIS_CONNECTOR;
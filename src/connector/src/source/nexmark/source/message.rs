@@ -15,7 +15,7 @@
 use bytes::Bytes;
 
 use crate::source::nexmark::source::event::Event;
-use crate::source::{SourceMessage, SplitId};
+use crate::source::{SourceMessage, SourceMeta, SplitId};
 
 #[derive(Clone, Debug)]
 pub struct NexmarkMessage {
@@ -30,6 +30,10 @@ impl From<NexmarkMessage> for SourceMessage {
             payload: Some(msg.payload),
             offset: msg.sequence_number.clone(),
             split_id: msg.split_id,
+            meta: SourceMeta::Empty,
+            attributes: Default::default(),
+            provenance: None,
+            state_offset: None,
         }
     }
 }
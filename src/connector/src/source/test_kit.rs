@@ -0,0 +1,100 @@
+// Copyright 2022 Singularity Data
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A conformance test of the state of split readers, which any connector reader with a state to
+//! restore from should pass.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::source::{ConnectorState, SplitId, SplitReader};
+
+/// Reads the messages with payloads from `reader` until at least `count` are read or it ends,
+/// returning their offsets per split.
+async fn read_offsets<R: SplitReader>(
+    reader: &mut R,
+    count: usize,
+    offsets: &mut HashMap<SplitId, Vec<String>>,
+) -> Result<()> {
+    let mut read = 0;
+    while read < count {
+        let chunk = match reader.next().await? {
+            Some(chunk) => chunk,
+            None => break,
+        };
+        for msg in chunk.into_iter().filter(|msg| msg.payload.is_some()) {
+            offsets.entry(msg.split_id).or_default().push(msg.offset);
+            read += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Reads `before` messages from a reader created from `state`, snapshots its state with
+/// `snapshot`, drops it, and reads `after` more messages from a reader restored from the
+/// snapshot. Asserts that the offsets of each split read across the restart are strictly
+/// increasing by `compare`, i.e. without duplicates, and agree with those of a reader which is not
+/// restarted, i.e. without gaps. Messages without payloads, e.g. heartbeats, are ignored.
+pub async fn assert_state_round_trip<R, F>(
+    properties: R::Properties,
+    state: ConnectorState,
+    mut snapshot: F,
+    compare: fn(&str, &str) -> Ordering,
+    before: usize,
+    after: usize,
+) -> Result<()>
+where
+    R: SplitReader,
+    R::Properties: Clone,
+    F: FnMut(&mut R) -> Result<ConnectorState>,
+{
+    // Reads further than the restarted readers, so that every split covers them.
+    let mut expected = HashMap::new();
+    let mut reader = R::new(properties.clone(), state.clone(), None).await?;
+    read_offsets(&mut reader, (before + after) * 2, &mut expected).await?;
+    drop(reader);
+
+    let mut offsets = HashMap::new();
+    let mut reader = R::new(properties.clone(), state, None).await?;
+    read_offsets(&mut reader, before, &mut offsets).await?;
+    let restored = snapshot(&mut reader)?;
+    drop(reader);
+    let mut reader = R::new(properties, restored, None).await?;
+    read_offsets(&mut reader, after, &mut offsets).await?;
+
+    assert!(!offsets.is_empty(), "no messages are read");
+    for (split_id, offsets) in &offsets {
+        for pair in offsets.windows(2) {
+            assert_eq!(
+                compare(&pair[0], &pair[1]),
+                Ordering::Less,
+                "split {} reads {} and then {} across the restart",
+                split_id,
+                pair[0],
+                pair[1]
+            );
+        }
+        let expected = &expected[split_id];
+        let len = offsets.len().min(expected.len());
+        assert_eq!(
+            offsets[..len],
+            expected[..len],
+            "split {} reads other messages across the restart",
+            split_id
+        );
+    }
+    Ok(())
+}
@@ -22,8 +22,8 @@ use risingwave_common::array::StreamChunk;
 use risingwave_common::catalog::{ColumnId, TableId};
 use risingwave_common::error::{internal_error, Result, ToRwResult};
 use risingwave_connector::source::{
-    Column, ConnectorProperties, ConnectorState, SourceMessage, SplitId, SplitMetaData,
-    SplitReaderImpl,
+    Column, ConnectorProperties, ConnectorState, ConnectorStateV2, SourceMessage, SplitId,
+    SplitImpl, SplitMetaData, SplitReaderImpl,
 };
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::{mpsc, oneshot};
@@ -63,9 +63,18 @@ struct InnerConnectorSourceReader {
 
 struct InnerConnectorSourceReaderHandle {
     stop_tx: oneshot::Sender<()>,
+    command_tx: mpsc::UnboundedSender<ReaderCommand>,
     join_handle: JoinHandle<()>,
 }
 
+/// Commands sent to a running [`InnerConnectorSourceReader`].
+enum ReaderCommand {
+    /// Finalizes the split reader and stops it, replying with the positions of its splits.
+    Finalize(oneshot::Sender<anyhow::Result<ConnectorStateV2>>),
+    /// Commits the positions of the checkpointed splits.
+    Commit(Vec<SplitImpl>),
+}
+
 const CONNECTOR_MESSAGE_BUFFER_SIZE: usize = 512;
 
 /// [`ConnectorSource`] serves as a bridge between external components and streaming or
@@ -131,6 +140,7 @@ impl InnerConnectorSourceReader {
     async fn run(
         &mut self,
         mut stop: oneshot::Receiver<()>,
+        mut commands: mpsc::UnboundedReceiver<ReaderCommand>,
         output: mpsc::Sender<Result<Vec<SourceMessage>>>,
     ) {
         let actor_id = self.context.actor_id.to_string();
@@ -150,6 +160,20 @@ impl InnerConnectorSourceReader {
                     break;
                 }
 
+                Some(command) = commands.recv() => match command {
+                    ReaderCommand::Finalize(reply) => {
+                        tracing::debug!("connector reader {} finalizing", id);
+                        reply.send(self.reader.finalize()).ok();
+                        break;
+                    }
+                    ReaderCommand::Commit(splits) => {
+                        if let Err(e) = self.reader.commit(&splits).await {
+                            tracing::warn!("connector reader {} failed to commit: {}", id, e);
+                        }
+                        continue;
+                    }
+                },
+
                 c = self.reader.next() => {
                     chunk = c;
                 }
@@ -195,15 +219,24 @@ impl ConnectorSourceReader {
         let mut split_offset_mapping: HashMap<SplitId, String> = HashMap::new();
 
         for msg in batch {
-            if let Some(content) = msg.payload {
-                split_offset_mapping.insert(msg.split_id, msg.offset);
-                match self.parser.parse(content.as_ref(), &self.columns) {
-                    Err(e) => {
-                        tracing::warn!("message parsing failed {}, skipping", e.to_string());
-                        continue;
+            let content = match msg.payload {
+                Some(content) => content,
+                None => {
+                    // A message without payload may still move the state, e.g. a coalesced
+                    // offset surfaced while the split is idle.
+                    if let Some(offset) = msg.state_offset {
+                        split_offset_mapping.insert(msg.split_id, offset);
                     }
-                    Ok(result) => events.push(result),
+                    continue;
                 }
+            };
+            split_offset_mapping.insert(msg.split_id, msg.state_offset.unwrap_or(msg.offset));
+            match self.parser.parse(content.as_ref(), &self.columns) {
+                Err(e) => {
+                    tracing::warn!("message parsing failed {}, skipping", e.to_string());
+                    continue;
+                }
+                Ok(result) => events.push(result),
             }
         }
 
@@ -217,6 +250,65 @@ impl ConnectorSourceReader {
     }
 }
 
+/// Controls the split readers of a [`ConnectorSourceReader`], and can be kept after the reader is
+/// moved into a stream.
+#[derive(Clone)]
+pub struct ConnectorSourceReaderController {
+    command_txs: HashMap<SplitId, mpsc::UnboundedSender<ReaderCommand>>,
+}
+
+impl ConnectorSourceReaderController {
+    /// Finalizes the split readers and stops them, returning the positions of their splits after
+    /// the last messages they sent. Split readers may be blocked on sending messages, so the
+    /// [`ConnectorSourceReader`] should be consumed until this returns. Readers which have already
+    /// stopped are skipped.
+    pub async fn finalize(&self) -> Result<ConnectorStateV2> {
+        let replies = self
+            .command_txs
+            .values()
+            .filter_map(|command_tx| {
+                let (reply_tx, reply_rx) = oneshot::channel();
+                command_tx
+                    .send(ReaderCommand::Finalize(reply_tx))
+                    .ok()
+                    .map(|_| reply_rx)
+            })
+            .collect_vec();
+
+        let mut splits = vec![];
+        for reply in replies {
+            // The reply is dropped if the reader stops before receiving the command.
+            if let Ok(state) = reply.await {
+                splits.extend(state.map_err(|e| internal_error(e.to_string()))?.splits);
+            }
+        }
+        Ok(ConnectorStateV2 { splits })
+    }
+
+    /// Commits the positions of the checkpointed splits to the split readers reading them, without
+    /// waiting for the commits. Failed commits are logged.
+    pub fn commit(&self, splits: impl IntoIterator<Item = SplitImpl>) {
+        for split in splits {
+            if let Some(command_tx) = self.command_txs.get(&split.id()) {
+                command_tx.send(ReaderCommand::Commit(vec![split])).ok();
+            }
+        }
+    }
+}
+
+impl ConnectorSourceReader {
+    pub fn controller(&self) -> ConnectorSourceReaderController {
+        ConnectorSourceReaderController {
+            command_txs: self
+                .handles
+                .iter()
+                .flat_map(|handles| handles.iter())
+                .map(|(split_id, handle)| (split_id.clone(), handle.command_tx.clone()))
+                .collect(),
+        }
+    }
+}
+
 impl Drop for ConnectorSourceReader {
     fn drop(&mut self) {
         let handles = self.handles.take().unwrap();
@@ -242,14 +334,17 @@ impl ConnectorSourceReader {
                 )
                 .await?;
                 let (stop_tx, stop_rx) = oneshot::channel();
+                let (command_tx, command_rx) = mpsc::unbounded_channel();
                 let sender = self.message_tx.clone();
-                let join_handle = tokio::spawn(async move { reader.run(stop_rx, sender).await });
+                let join_handle =
+                    tokio::spawn(async move { reader.run(stop_rx, command_rx, sender).await });
 
                 if let Some(handles) = self.handles.as_mut() {
                     handles.insert(
                         split_id,
                         InnerConnectorSourceReaderHandle {
                             stop_tx,
+                            command_tx,
                             join_handle,
                         },
                     );
@@ -344,13 +439,16 @@ impl ConnectorSource {
                 None => DEFAULT_SPLIT_ID.clone(),
             };
             let (stop_tx, stop_rx) = oneshot::channel();
+            let (command_tx, command_rx) = mpsc::unbounded_channel();
             let sender = tx.clone();
-            let join_handle = tokio::spawn(async move { reader.run(stop_rx, sender).await });
+            let join_handle =
+                tokio::spawn(async move { reader.run(stop_rx, command_rx, sender).await });
 
             handles.insert(
                 split_id,
                 InnerConnectorSourceReaderHandle {
                     stop_tx,
+                    command_tx,
                     join_handle,
                 },
             );
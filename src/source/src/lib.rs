@@ -42,7 +42,9 @@ use risingwave_common::error::Result;
 use risingwave_connector::source::SplitId;
 pub use table_v2::*;
 
-use crate::connector_source::{ConnectorSource, ConnectorSourceReader};
+use crate::connector_source::{
+    ConnectorSource, ConnectorSourceReader, ConnectorSourceReaderController,
+};
 
 pub mod parser;
 
@@ -82,6 +84,14 @@ impl SourceStreamReaderImpl {
             SourceStreamReaderImpl::Connector(c) => c.next().await,
         }
     }
+
+    /// Returns the controller of the split readers, which only the connector source has.
+    pub fn controller(&self) -> Option<ConnectorSourceReaderController> {
+        match self {
+            SourceStreamReaderImpl::TableV2(_) => None,
+            SourceStreamReaderImpl::Connector(c) => Some(c.controller()),
+        }
+    }
 }
 
 /// [`StreamChunkWithState`] returns stream chunk together with offset for each split. In the
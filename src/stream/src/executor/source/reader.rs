@@ -113,6 +113,26 @@ impl SourceReaderStream {
         let _ = self.paused.insert(source_chunk_reader);
     }
 
+    /// Whether the source stream is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+
+    /// Receive the next chunk from the source chunk reader only, even if it is paused. Used to
+    /// drain the source reader on stop, when no more barrier is expected.
+    pub async fn next_source_chunk(
+        &mut self,
+    ) -> Option<StreamExecutorResult<StreamChunkWithState>> {
+        let source_chunk_reader = match self.paused.as_mut() {
+            Some(paused) => paused,
+            None => self.inner.get_mut().1,
+        };
+        source_chunk_reader
+            .next()
+            .await
+            .map(|msg| msg.right().expect("source chunk reader yields chunks only"))
+    }
+
     /// Resume the source stream, panic if the source is not paused before.
     pub fn resume_source(&mut self) {
         let source_chunk_reader = self.paused.take().expect("not paused");
@@ -16,7 +16,7 @@ use std::fmt::{Debug, Formatter};
 use std::sync::Arc;
 
 use either::Either;
-use futures::StreamExt;
+use futures::{pin_mut, FutureExt, StreamExt};
 use futures_async_stream::try_stream;
 use risingwave_common::array::column::Column;
 use risingwave_common::array::stream_chunk::Ops;
@@ -237,6 +237,56 @@ impl<S: StateStore> SourceExecutor<S> {
         Ok(Box::new(reader))
     }
 
+    /// Updates the state cache with the offsets of the chunk and refills its row id column.
+    async fn process_chunk(
+        &mut self,
+        chunk_with_state: StreamChunkWithState,
+    ) -> StreamExecutorResult<StreamChunk> {
+        let StreamChunkWithState {
+            mut chunk,
+            split_offset_mapping,
+        } = chunk_with_state;
+
+        if let Some(mapping) = split_offset_mapping {
+            let state: HashMap<_, _> = mapping
+                .iter()
+                .map(|(split, offset)| {
+                    let origin_split_impl = self
+                        .stream_source_splits
+                        .iter()
+                        .filter(|origin_split| &origin_split.id() == split)
+                        .collect_vec();
+
+                    if origin_split_impl.is_empty() {
+                        bail!(
+                            "cannot find split: {:?} in stream_source_splits: {:?}",
+                            split,
+                            self.stream_source_splits
+                        )
+                    } else {
+                        Ok::<_, StreamExecutorError>((
+                            split.clone(),
+                            origin_split_impl[0].update(offset.clone()),
+                        ))
+                    }
+                })
+                .try_collect()?;
+            self.state_cache.extend(state);
+        }
+
+        // Refill row id column for source.
+        chunk = match self.source_desc.source.as_ref() {
+            SourceImpl::Connector(_) => self.refill_row_id_column(chunk, true).await,
+            SourceImpl::TableV2(_) => self.refill_row_id_column(chunk, false).await,
+        };
+
+        self.metrics
+            .source_output_row_count
+            .with_label_values(&[self.source_identify.as_str()])
+            .inc_by(chunk.cardinality() as u64);
+        Ok(chunk)
+    }
+
     #[try_stream(ok = Message, error = StreamExecutorError)]
     async fn into_stream(mut self) {
         let mut barrier_receiver = self.barrier_receiver.take().unwrap();
@@ -274,6 +324,7 @@ impl<S: StateStore> SourceExecutor<S> {
             .build_stream_source_reader(recover_state)
             .stack_trace("source_build_reader")
             .await?;
+        let mut controller = source_chunk_reader.controller();
 
         // Merge the chunks from source and the barriers into a single stream.
         let mut stream = SourceReaderStream::new(barrier_receiver, source_chunk_reader);
@@ -286,7 +337,60 @@ impl<S: StateStore> SourceExecutor<S> {
                 Either::Left(barrier) => {
                     let barrier = barrier?;
                     let epoch = barrier.epoch.prev;
+
+                    // Finalize the split readers before the last snapshot of a stopped actor, so
+                    // that it covers every message read.
+                    if barrier.is_stop_or_update_drop_actor(self.ctx.id) {
+                        if let Some(controller) = controller.take() {
+                            let finalize = controller.finalize();
+                            pin_mut!(finalize);
+                            // The split readers may be blocked on sending messages, which are
+                            // consumed until all of them are finalized.
+                            let state = loop {
+                                let chunk_with_state = tokio::select! {
+                                    biased;
+                                    state = &mut finalize => break state,
+                                    chunk_with_state = stream.next_source_chunk() => chunk_with_state,
+                                };
+                                // Messages drained while paused are dropped, and read again
+                                // from the last snapshot.
+                                if let Some(chunk_with_state) = chunk_with_state {
+                                    if !stream.is_paused() {
+                                        let chunk = self.process_chunk(chunk_with_state?).await?;
+                                        yield Message::Chunk(chunk);
+                                    }
+                                }
+                            };
+                            // Then the messages sent before the finalization.
+                            while let Some(Some(chunk_with_state)) =
+                                stream.next_source_chunk().now_or_never()
+                            {
+                                if !stream.is_paused() {
+                                    let chunk = self.process_chunk(chunk_with_state?).await?;
+                                    yield Message::Chunk(chunk);
+                                }
+                            }
+
+                            let splits =
+                                state.map_err(StreamExecutorError::connector_error)?.splits;
+                            tracing::info!(
+                                "actor {:?} finalized source splits {:?}",
+                                self.ctx.id,
+                                splits
+                            );
+                            if !stream.is_paused() {
+                                self.state_cache
+                                    .extend(splits.into_iter().map(|split| (split.id(), split)));
+                            }
+                        }
+                    }
+
                     self.take_snapshot(epoch).await?;
+                    if barrier.checkpoint {
+                        if let Some(controller) = &controller {
+                            controller.commit(self.state_cache.values().cloned());
+                        }
+                    }
 
                     if let Some(mutation) = barrier.mutation.as_deref() {
                         match mutation {
@@ -304,6 +408,7 @@ impl<S: StateStore> SourceExecutor<S> {
                                         let reader = self
                                             .build_stream_source_reader(Some(target_state.clone()))
                                             .await?;
+                                        controller = reader.controller();
                                         stream.replace_source_chunk_reader(reader);
 
                                         self.stream_source_splits = target_state;
@@ -335,48 +440,7 @@ impl<S: StateStore> SourceExecutor<S> {
                 }
 
                 Either::Right(chunk_with_state) => {
-                    let StreamChunkWithState {
-                        mut chunk,
-                        split_offset_mapping,
-                    } = chunk_with_state?;
-
-                    if let Some(mapping) = split_offset_mapping {
-                        let state: HashMap<_, _> = mapping
-                            .iter()
-                            .map(|(split, offset)| {
-                                let origin_split_impl = self
-                                    .stream_source_splits
-                                    .iter()
-                                    .filter(|origin_split| &origin_split.id() == split)
-                                    .collect_vec();
-
-                                if origin_split_impl.is_empty() {
-                                    bail!(
-                                        "cannot find split: {:?} in stream_source_splits: {:?}",
-                                        split,
-                                        self.stream_source_splits
-                                    )
-                                } else {
-                                    Ok::<_, StreamExecutorError>((
-                                        split.clone(),
-                                        origin_split_impl[0].update(offset.clone()),
-                                    ))
-                                }
-                            })
-                            .try_collect()?;
-                        self.state_cache.extend(state);
-                    }
-
-                    // Refill row id column for source.
-                    chunk = match self.source_desc.source.as_ref() {
-                        SourceImpl::Connector(_) => self.refill_row_id_column(chunk, true).await,
-                        SourceImpl::TableV2(_) => self.refill_row_id_column(chunk, false).await,
-                    };
-
-                    self.metrics
-                        .source_output_row_count
-                        .with_label_values(&[self.source_identify.as_str()])
-                        .inc_by(chunk.cardinality() as u64);
+                    let chunk = self.process_chunk(chunk_with_state?).await?;
                     yield Message::Chunk(chunk);
                 }
             }
@@ -415,10 +479,11 @@ impl<S: StateStore> Debug for SourceExecutor<S> {
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
+    use std::time::Duration;
 
     use bytes::Bytes;
     use futures::StreamExt;
-    use maplit::hashmap;
+    use maplit::{hashmap, hashset};
     use risingwave_common::array::stream_chunk::StreamChunkTestExt;
     use risingwave_common::array::StreamChunk;
     use risingwave_common::catalog::{ColumnDesc, Field, Schema};
@@ -880,4 +945,104 @@ mod tests {
         barrier_tx.send(pause_barrier).unwrap();
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_stop_finalizes_readers() -> Result<()> {
+        let source_table_id = TableId::default();
+        let source_manager = Arc::new(MemSourceManager::default());
+        source_manager
+            .create_source(&source_table_id, mock_stream_source_info())
+            .await?;
+        let source_desc = source_manager.get_source(&source_table_id)?;
+
+        let keyspace = Keyspace::table_root(MemoryStateStore::new(), &TableId::from(0x2333));
+        let column_ids = vec![ColumnId::from(0), ColumnId::from(1)];
+        let schema = Schema::new(vec![
+            Field::unnamed(DataType::Int64),
+            Field::unnamed(DataType::Int32),
+        ]);
+        let (barrier_tx, barrier_rx) = unbounded_channel::<Barrier>();
+        let vnodes = Bitmap::from_bytes(Bytes::from_static(&[0b11111111]));
+
+        let source_exec = SourceExecutor::new(
+            ActorContext::create(0),
+            source_table_id,
+            source_desc,
+            vnodes,
+            keyspace.clone(),
+            column_ids,
+            schema,
+            vec![0],
+            barrier_rx,
+            1,
+            1,
+            "SourceExecutor".to_string(),
+            Arc::new(StreamingMetrics::unused()),
+            u64::MAX,
+        )?;
+        let mut executor = Box::new(source_exec).execute();
+
+        let split = SplitImpl::Datagen(DatagenSplit {
+            split_index: 0,
+            split_num: 1,
+            start_offset: None,
+        });
+        let curr_epoch = 1919;
+        let init_barrier = Barrier::new_test_barrier(curr_epoch).with_mutation(Mutation::Add {
+            adds: HashMap::new(),
+            splits: hashmap! {
+                ActorId::default() => vec![split.clone()],
+            },
+        });
+        barrier_tx.send(init_barrier).unwrap();
+        executor
+            .next()
+            .await
+            .unwrap()
+            .unwrap()
+            .into_barrier()
+            .unwrap();
+
+        let mut rows = executor
+            .next()
+            .await
+            .unwrap()?
+            .into_chunk()
+            .unwrap()
+            .cardinality();
+
+        let stop_barrier = Barrier::new_test_barrier(curr_epoch + 1)
+            .with_mutation(Mutation::Stop(hashset! { ActorId::default() }));
+        barrier_tx.send(stop_barrier).unwrap();
+        // The chunks read before the readers are finalized are delivered ahead of the barrier.
+        let barrier = loop {
+            match executor.next().await.unwrap().unwrap() {
+                Message::Chunk(chunk) => rows += chunk.cardinality(),
+                Message::Barrier(barrier) => break barrier,
+            }
+        };
+
+        // The readers are stopped, so no more chunk is read.
+        assert!(
+            tokio::time::timeout(Duration::from_secs(2), executor.next())
+                .await
+                .is_err()
+        );
+
+        // The last snapshot is positioned after the last delivered row.
+        let state = SourceStateHandler::new(keyspace)
+            .try_recover_from_state_store(&split, barrier.epoch.prev)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            state,
+            SplitImpl::Datagen(DatagenSplit {
+                split_index: 0,
+                split_num: 1,
+                start_offset: Some(rows as u64 - 1),
+            })
+        );
+        Ok(())
+    }
 }